@@ -7,17 +7,20 @@ use fitsio::sys::{
     FLEN_KEYWORD, FLEN_VALUE,
 };
 use fitsio::FitsFile;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::path::Path;
 
+use crate::fits_header::{normalize_hierarch, parse_card};
+
 /// A single FITS header card.
 ///
 /// This is the canonical, lossless(ish) representation used by the metadata layer.
 /// The exact raw card text is preserved when it is available from an on-disk FITS file.
-#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct FitsHeaderCard {
     /// Zero-based HDU index containing this card.
     pub hdu_index: usize,
@@ -33,6 +36,32 @@ pub struct FitsHeaderCard {
     pub raw_card: Option<String>,
 }
 
+/// A logical header keyword assembled from one or more physical cards.
+///
+/// Long strings split with the OGIP `CONTINUE` convention are joined into a
+/// single value, and `HIERARCH` keywords use their normalized name
+/// (e.g. `HIERARCH ESO DET TEMP`). The physical cards are kept for lossless output.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct LogicalHeaderCard {
+    /// Zero-based HDU index containing this keyword.
+    pub hdu_index: usize,
+    /// Keyword name, normalized for `HIERARCH` keywords.
+    pub keyword: String,
+    /// Full value with any `CONTINUE` fragments joined.
+    pub value: Option<String>,
+    /// Comment text, joined across `CONTINUE` cards.
+    pub comment: Option<String>,
+    /// Physical cards this keyword was assembled from, in header order.
+    pub physical_cards: Vec<FitsHeaderCard>,
+}
+
+impl LogicalHeaderCard {
+    /// Whether this is an ESO `HIERARCH` keyword.
+    pub fn is_hierarch(&self) -> bool {
+        self.keyword.starts_with("HIERARCH ")
+    }
+}
+
 /// Read a FITS file and return its pixel data, width, and height.
 pub fn load_fits(path: &Path) -> Result<(Vec<f32>, usize, usize)> {
     // Open the FITS file.
@@ -117,15 +146,68 @@ pub fn read_all_header_cards(fits_file: &mut FitsFile) -> Result<Vec<FitsHeaderC
     Ok(cards)
 }
 
+/// Group physical header cards into logical keywords.
+///
+/// A `CONTINUE` card extends the preceding keyword when its value ends with
+/// `&`; the `&` is dropped and the fragments are concatenated.
+pub fn logical_header_cards(cards: &[FitsHeaderCard]) -> Vec<LogicalHeaderCard> {
+    let mut logical: Vec<LogicalHeaderCard> = Vec::new();
+
+    for card in cards {
+        let (keyword, value, comment) = match &card.raw_card {
+            Some(raw) => parse_card(raw),
+            None => (
+                card.keyword.clone(),
+                card.value.clone(),
+                card.comment.clone(),
+            ),
+        };
+
+        if keyword == "CONTINUE" {
+            let previous = logical.last_mut().filter(|previous| {
+                previous.hdu_index == card.hdu_index
+                    && previous
+                        .value
+                        .as_deref()
+                        .is_some_and(|value| value.ends_with('&'))
+            });
+            if let Some(previous) = previous {
+                if let Some(joined) = previous.value.as_mut() {
+                    joined.pop();
+                    joined.push_str(value.as_deref().unwrap_or_default());
+                }
+                previous.comment = match (previous.comment.take(), comment) {
+                    (Some(first), Some(next)) => Some(format!("{first} {next}")),
+                    (first, next) => first.or(next),
+                };
+                previous.physical_cards.push(card.clone());
+                continue;
+            }
+        }
+
+        logical.push(LogicalHeaderCard {
+            hdu_index: card.hdu_index,
+            keyword,
+            value,
+            comment,
+            physical_cards: vec![card.clone()],
+        });
+    }
+
+    logical
+}
+
 /// Build a compatibility header map from lossless header cards.
 ///
-/// If duplicate keywords are present, the last value wins.
+/// Values are taken from the logical header, so long strings are complete and
+/// `HIERARCH` keywords appear under their normalized name. If duplicate
+/// keywords are present, the last value wins.
 pub fn header_cards_to_map(cards: &[FitsHeaderCard]) -> HashMap<String, String> {
     let mut headers = HashMap::new();
 
-    for card in cards {
-        if let Some(value) = &card.value {
-            headers.insert(card.keyword.clone(), value.clone());
+    for card in logical_header_cards(cards) {
+        if let Some(value) = card.value {
+            headers.insert(card.keyword, value);
         }
     }
 
@@ -187,6 +269,11 @@ fn read_card_fields(
     card_index: i32,
     raw_card: &str,
 ) -> (String, Option<String>, Option<String>) {
+    // CFITSIO has no notion of these conventions, so parse the raw card
+    if raw_card.starts_with("HIERARCH ") || raw_card.starts_with("CONTINUE") {
+        return parse_card(raw_card);
+    }
+
     let mut status = 0;
     let mut keyword = vec![0 as c_char; FLEN_KEYWORD as usize];
     let mut value = vec![0 as c_char; FLEN_VALUE as usize];
@@ -249,7 +336,7 @@ fn parse_keyword_from_raw_card(raw_card: &str) -> String {
 
     if trimmed.starts_with("HIERARCH") {
         if let Some((keyword, _)) = trimmed.split_once('=') {
-            return keyword.trim().to_string();
+            return normalize_hierarch(keyword);
         }
 
         return "HIERARCH".to_string();
@@ -403,6 +490,54 @@ mod tests {
         assert_eq!(headers.get("DUPKEY"), Some(&"two".to_string()));
     }
 
+    #[test]
+    fn test_logical_cards_join_continue_and_normalize_hierarch() {
+        let raw = [
+            "OBJECT  = 'NGC 7000 North America and Pelican nebulae, wide-field mosaic pa&'",
+            "CONTINUE  'nel 3&'                / part one",
+            "CONTINUE  ' of 4'                 / part two",
+            "HIERARCH eso  det temp = -120.5 / detector temperature",
+            "CONTINUE  'orphan'",
+        ];
+        let cards: Vec<FitsHeaderCard> = raw
+            .iter()
+            .enumerate()
+            .map(|(index, raw)| FitsHeaderCard {
+                card_index: index + 1,
+                raw_card: Some(raw.to_string()),
+                ..Default::default()
+            })
+            .collect();
+
+        let logical = logical_header_cards(&cards);
+        assert_eq!(logical.len(), 3);
+        assert_eq!(
+            logical[0].value.as_deref(),
+            Some("NGC 7000 North America and Pelican nebulae, wide-field mosaic panel 3 of 4")
+        );
+        assert_eq!(logical[0].comment.as_deref(), Some("part one part two"));
+        assert_eq!(logical[0].physical_cards.len(), 3);
+        assert_eq!(logical[1].keyword, "HIERARCH ESO DET TEMP");
+        assert!(logical[1].is_hierarch());
+        assert_eq!(logical[1].value.as_deref(), Some("-120.5"));
+        assert_eq!(logical[2].keyword, "CONTINUE");
+
+        let headers = header_cards_to_map(&cards);
+        assert_eq!(headers.get("OBJECT"), logical[0].value.as_ref());
+        assert_eq!(
+            headers.get("HIERARCH ESO DET TEMP").map(String::as_str),
+            Some("-120.5")
+        );
+    }
+
+    #[test]
+    fn test_parse_keyword_from_raw_card_normalizes_hierarch() {
+        assert_eq!(
+            parse_keyword_from_raw_card("HIERARCH ESO  INS filt1 NAME = 'Ha'"),
+            "HIERARCH ESO INS FILT1 NAME"
+        );
+    }
+
     // Helper function to test dimension extraction logic.
     fn extract_dimensions_from_shape(shape: &[usize]) -> (usize, usize) {
         let h = shape[0];
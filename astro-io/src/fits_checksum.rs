@@ -0,0 +1,357 @@
+//! FITS `DATASUM` / `CHECKSUM` verification and generation.
+//!
+//! Implements the FITS checksum convention: `DATASUM` is the 32-bit ones'
+//! complement sum of the data unit as an unsigned decimal string, and
+//! `CHECKSUM` is a 16-character ASCII encoding chosen so the ones' complement
+//! sum of the whole HDU (header plus data) is negative zero.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::fits_header::{read_header_layouts, HeaderEditor, HeaderLayout, CARD_LEN};
+
+/// Placeholder `CHECKSUM` value used while the HDU sum is computed.
+pub(crate) const CHECKSUM_PLACEHOLDER: &str = "0000000000000000";
+
+/// Outcome of checking one checksum keyword.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChecksumStatus {
+    /// The stored value matches the file contents.
+    Pass,
+    /// The stored value does not match the file contents.
+    Fail,
+    /// The keyword is not present.
+    Missing,
+}
+
+/// Checksum results for a single HDU.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HduChecksumReport {
+    /// Zero-based HDU index.
+    pub hdu_index: usize,
+    /// Result of checking `DATASUM` against the data unit.
+    pub datasum: ChecksumStatus,
+    /// Result of checking `CHECKSUM` against the whole HDU.
+    pub checksum: ChecksumStatus,
+    /// `DATASUM` value stored in the header, if present and numeric.
+    pub stored_datasum: Option<u32>,
+    /// Checksum computed from the data unit.
+    pub computed_datasum: u32,
+}
+
+/// Checksum results for every HDU in a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumReport {
+    /// File that was checked.
+    pub path: PathBuf,
+    /// Per-HDU results, in file order.
+    pub hdus: Vec<HduChecksumReport>,
+}
+
+impl ChecksumReport {
+    /// Overall status: `Fail` if any check failed, else `Missing` if any
+    /// keyword is absent, else `Pass`.
+    pub fn status(&self) -> ChecksumStatus {
+        let statuses = self.hdus.iter().flat_map(|hdu| [hdu.datasum, hdu.checksum]);
+        let mut overall = ChecksumStatus::Pass;
+        for status in statuses {
+            match status {
+                ChecksumStatus::Fail => return ChecksumStatus::Fail,
+                ChecksumStatus::Missing => overall = ChecksumStatus::Missing,
+                ChecksumStatus::Pass => {}
+            }
+        }
+        overall
+    }
+
+    /// Whether no check failed (missing keywords are not failures).
+    pub fn is_intact(&self) -> bool {
+        self.status() != ChecksumStatus::Fail
+    }
+}
+
+/// Verify `DATASUM` and `CHECKSUM` for every HDU in a FITS file.
+pub fn verify_checksums(path: &Path) -> Result<ChecksumReport> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open FITS file {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let layouts = read_header_layouts(&mut reader)?;
+
+    let mut hdus = Vec::with_capacity(layouts.len());
+    for (hdu_index, layout) in layouts.iter().enumerate() {
+        let header = read_bytes(
+            &mut reader,
+            layout.offset,
+            layout.data_offset() - layout.offset,
+        )
+        .with_context(|| format!("Failed to read header of HDU {hdu_index}"))?;
+        let computed_datasum = data_checksum(&mut reader, layout)
+            .with_context(|| format!("Failed to read data unit of HDU {hdu_index}"))?;
+
+        let card_value = |keyword: &str| {
+            layout
+                .cards
+                .iter()
+                .find(|card| card.keyword == keyword)
+                .map(|card| card.value.clone().unwrap_or_default())
+        };
+
+        let stored_datasum = card_value("DATASUM").and_then(|value| value.trim().parse().ok());
+        let datasum = match card_value("DATASUM") {
+            None => ChecksumStatus::Missing,
+            Some(_) if stored_datasum == Some(computed_datasum) => ChecksumStatus::Pass,
+            Some(_) => ChecksumStatus::Fail,
+        };
+
+        let checksum = match card_value("CHECKSUM") {
+            None => ChecksumStatus::Missing,
+            Some(_) => {
+                let total = ones_complement_add(checksum(&header), computed_datasum);
+                // Both representations of ones' complement zero are accepted
+                if total == u32::MAX || total == 0 {
+                    ChecksumStatus::Pass
+                } else {
+                    ChecksumStatus::Fail
+                }
+            }
+        };
+
+        hdus.push(HduChecksumReport {
+            hdu_index,
+            datasum,
+            checksum,
+            stored_datasum,
+            computed_datasum,
+        });
+    }
+
+    Ok(ChecksumReport {
+        path: path.to_path_buf(),
+        hdus,
+    })
+}
+
+/// Write fresh `DATASUM` and `CHECKSUM` cards to every HDU in a FITS file.
+pub fn write_checksums(path: &Path) -> Result<()> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open FITS file {}", path.display()))?;
+    let hdu_count = read_header_layouts(&mut BufReader::new(file))?.len();
+
+    for hdu_index in 0..hdu_count {
+        let mut editor = HeaderEditor::from_path(path, hdu_index)?;
+        editor.update_checksums(true);
+        editor
+            .write(path)
+            .with_context(|| format!("Failed to write checksums for HDU {hdu_index}"))?;
+    }
+
+    Ok(())
+}
+
+/// 32-bit ones' complement sum of big-endian words.
+///
+/// The length must be a multiple of 4, which holds for whole FITS blocks.
+pub fn checksum(bytes: &[u8]) -> u32 {
+    let mut sum: u64 = bytes
+        .as_chunks::<4>()
+        .0
+        .iter()
+        .map(|word| u64::from(u32::from_be_bytes(*word)))
+        .sum();
+    while sum >> 32 != 0 {
+        sum = (sum & 0xFFFF_FFFF) + (sum >> 32);
+    }
+    sum as u32
+}
+
+/// Encode a checksum as the 16-character ASCII `CHECKSUM` value.
+///
+/// Pass the complement of the HDU sum to produce a value that makes the
+/// sum of the sealed HDU negative zero.
+pub fn encode_checksum(value: u32) -> String {
+    // Punctuation between the digits and letters is avoided
+    const EXCLUDE: [u8; 13] = [
+        0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f, 0x40, 0x5b, 0x5c, 0x5d, 0x5e, 0x5f, 0x60,
+    ];
+    const OFFSET: u8 = 0x30;
+
+    let mut ascii = [0u8; 16];
+    for (byte_index, byte) in value.to_be_bytes().into_iter().enumerate() {
+        let mut chars = [byte / 4 + OFFSET; 4];
+        chars[0] += byte % 4;
+
+        let mut adjusted = true;
+        while adjusted {
+            adjusted = false;
+            for excluded in EXCLUDE {
+                for pair in [0, 2] {
+                    if chars[pair] == excluded || chars[pair + 1] == excluded {
+                        chars[pair] += 1;
+                        chars[pair + 1] -= 1;
+                        adjusted = true;
+                    }
+                }
+            }
+        }
+
+        for (slot, c) in chars.into_iter().enumerate() {
+            ascii[4 * slot + byte_index] = c;
+        }
+    }
+
+    // Rotate one place right to account for the value starting in column 12
+    (0..16).map(|i| ascii[(i + 15) % 16] as char).collect()
+}
+
+/// Fill the `CHECKSUM` card of a serialized header in place.
+///
+/// The header must already contain `CHECKSUM` set to the placeholder and
+/// be padded to its final length.
+pub(crate) fn seal_header(header: &mut [u8], datasum: u32) -> Result<()> {
+    let position = header
+        .chunks(CARD_LEN)
+        .position(|card| card.starts_with(b"CHECKSUM= '"))
+        .context("Header has no CHECKSUM card to seal")?;
+    let start = position * CARD_LEN + 11;
+    let value = &mut header[start..start + 16];
+    if value != CHECKSUM_PLACEHOLDER.as_bytes() {
+        bail!("CHECKSUM card must hold the placeholder before sealing");
+    }
+
+    let sum = ones_complement_add(checksum(header), datasum);
+    header[start..start + 16].copy_from_slice(encode_checksum(!sum).as_bytes());
+    Ok(())
+}
+
+/// Checksum of an HDU's data unit, including block padding.
+pub(crate) fn data_checksum<R: Read + Seek>(reader: &mut R, layout: &HeaderLayout) -> Result<u32> {
+    let data = read_bytes(reader, layout.data_offset(), layout.padded_data_len())?;
+    Ok(checksum(&data))
+}
+
+fn read_bytes<R: Read + Seek>(reader: &mut R, offset: u64, len: u64) -> Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut bytes = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut bytes)
+        .context("File is shorter than its headers describe")?;
+    Ok(bytes)
+}
+
+fn ones_complement_add(a: u32, b: u32) -> u32 {
+    let (sum, carry) = a.overflowing_add(b);
+    sum + u32::from(carry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fits_header::tests::{fits_bytes, unique_temp_fits_path};
+    use crate::fits_header::BLOCK_LEN;
+    use std::fs;
+
+    const PRIMARY: &[&str] = &[
+        "SIMPLE  =                    T",
+        "BITPIX  =                   16",
+        "NAXIS   =                    2",
+        "NAXIS1  =                    3",
+        "NAXIS2  =                    2",
+        "EXTEND  =                    T",
+        "OBJECT  = 'M42     '",
+    ];
+
+    const EXTENSION: &[&str] = &[
+        "XTENSION= 'IMAGE   '",
+        "BITPIX  =                    8",
+        "NAXIS   =                    1",
+        "NAXIS1  =                    4",
+        "PCOUNT  =                    0",
+        "GCOUNT  =                    1",
+    ];
+
+    fn write_sample(prefix: &str) -> Result<PathBuf> {
+        let path = unique_temp_fits_path(prefix);
+        fs::write(
+            &path,
+            fits_bytes(&[
+                (PRIMARY, &[0, 1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6]),
+                (EXTENSION, &[1, 2, 3, 4]),
+            ]),
+        )?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_checksum_wraps_carries() {
+        assert_eq!(checksum(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 2]), 2);
+        assert_eq!(checksum(&[0x12, 0x34, 0x56, 0x78]), 0x1234_5678);
+    }
+
+    #[test]
+    fn test_encode_checksum_known_values() {
+        assert_eq!(encode_checksum(0), "0000000000000000");
+        // Worked example from the FITS checksum proposal (Seaman, Pence & Rots):
+        // an HDU summing to 868229149 is sealed with its complement
+        assert_eq!(encode_checksum(!868_229_149), "hcHjjc9ghcEghc9g");
+        assert!(encode_checksum(0x1234_5678)
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_missing_checksums_are_reported() -> Result<()> {
+        let path = write_sample("checksum-missing")?;
+
+        let report = verify_checksums(&path)?;
+        assert_eq!(report.hdus.len(), 2);
+        assert_eq!(report.hdus[0].datasum, ChecksumStatus::Missing);
+        assert_eq!(report.hdus[1].checksum, ChecksumStatus::Missing);
+        assert_eq!(report.status(), ChecksumStatus::Missing);
+        assert!(report.is_intact());
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_generated_checksums_verify_and_detect_corruption() -> Result<()> {
+        let path = write_sample("checksum-roundtrip")?;
+
+        write_checksums(&path)?;
+        let report = verify_checksums(&path)?;
+        assert_eq!(report.status(), ChecksumStatus::Pass);
+        assert_eq!(report.hdus[1].stored_datasum, Some(0x0102_0304));
+
+        // Flip one data byte in the primary HDU
+        let mut bytes = fs::read(&path)?;
+        bytes[BLOCK_LEN + 1] ^= 0xFF;
+        fs::write(&path, &bytes)?;
+
+        let report = verify_checksums(&path)?;
+        assert_eq!(report.hdus[0].datasum, ChecksumStatus::Fail);
+        assert_eq!(report.hdus[0].checksum, ChecksumStatus::Fail);
+        assert_eq!(report.hdus[1].checksum, ChecksumStatus::Pass);
+        assert_eq!(report.status(), ChecksumStatus::Fail);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_edit_refreshes_existing_checksum() -> Result<()> {
+        let path = write_sample("checksum-edit")?;
+        write_checksums(&path)?;
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        editor.set("OBJECT", "M43", None)?;
+        editor.write(&path)?;
+
+        assert_eq!(verify_checksums(&path)?.status(), ChecksumStatus::Pass);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
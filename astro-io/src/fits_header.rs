@@ -0,0 +1,1282 @@
+//! FITS header editing and in-place header writes.
+//!
+//! Headers are edited as `FitsHeaderCard` collections and written back with
+//! plain file I/O rather than CFITSIO, so untouched cards keep their exact raw
+//! text. When the edited header still fits in the HDU's existing 2880-byte
+//! blocks it is overwritten in place; otherwise the file is rewritten with the
+//! header grown by whole blocks and the data units copied unchanged.
+//!
+//! String values too long for one card are written with the OGIP `CONTINUE`
+//! convention, and keywords longer than 8 characters become `HIERARCH` cards.
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::fits::FitsHeaderCard;
+use crate::fits_checksum;
+
+/// Length of a FITS header card in bytes.
+pub const CARD_LEN: usize = 80;
+
+/// Length of a FITS block in bytes.
+pub const BLOCK_LEN: usize = 2880;
+
+const CARDS_PER_BLOCK: usize = BLOCK_LEN / CARD_LEN;
+
+/// Keywords that describe the HDU structure and must not be edited.
+const STRUCTURAL_KEYWORDS: &[&str] = &[
+    "SIMPLE", "XTENSION", "BITPIX", "NAXIS", "EXTEND", "PCOUNT", "GCOUNT", "GROUPS", "END",
+];
+
+/// A typed value for a new or updated header card.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderValue {
+    /// Quoted character string.
+    Text(String),
+    /// Logical `T` / `F`.
+    Logical(bool),
+    /// Integer.
+    Integer(i64),
+    /// Floating-point number.
+    Float(f64),
+}
+
+impl HeaderValue {
+    /// Value text as written into the card image.
+    fn formatted(&self) -> String {
+        match self {
+            HeaderValue::Text(text) => {
+                let escaped = text.replace('\'', "''");
+                format!("'{escaped:<8}'")
+            }
+            HeaderValue::Logical(true) => "T".to_string(),
+            HeaderValue::Logical(false) => "F".to_string(),
+            HeaderValue::Integer(value) => value.to_string(),
+            HeaderValue::Float(value) => {
+                let formatted = format!("{value:?}").to_uppercase();
+                if formatted.contains(['.', 'E', 'N', 'I']) {
+                    formatted
+                } else {
+                    format!("{formatted}.0")
+                }
+            }
+        }
+    }
+}
+
+impl From<&str> for HeaderValue {
+    fn from(value: &str) -> Self {
+        HeaderValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for HeaderValue {
+    fn from(value: String) -> Self {
+        HeaderValue::Text(value)
+    }
+}
+
+impl From<bool> for HeaderValue {
+    fn from(value: bool) -> Self {
+        HeaderValue::Logical(value)
+    }
+}
+
+impl From<i32> for HeaderValue {
+    fn from(value: i32) -> Self {
+        HeaderValue::Integer(value.into())
+    }
+}
+
+impl From<i64> for HeaderValue {
+    fn from(value: i64) -> Self {
+        HeaderValue::Integer(value)
+    }
+}
+
+impl From<f32> for HeaderValue {
+    fn from(value: f32) -> Self {
+        HeaderValue::Float(value.into())
+    }
+}
+
+impl From<f64> for HeaderValue {
+    fn from(value: f64) -> Self {
+        HeaderValue::Float(value)
+    }
+}
+
+/// Kind of change between the original and edited header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Card was added.
+    Added,
+    /// Card was removed.
+    Removed,
+    /// Card text changed.
+    Modified,
+}
+
+/// A single card-level difference produced by `HeaderEditor::diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderChange {
+    /// What happened to the card.
+    pub kind: ChangeKind,
+    /// Card before the edit, if it existed.
+    pub before: Option<FitsHeaderCard>,
+    /// Card after the edit, if it still exists.
+    pub after: Option<FitsHeaderCard>,
+}
+
+impl fmt::Display for HeaderChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        if let Some(before) = &self.before {
+            lines.push(format!("- {}", card_image(before).trim_end()));
+        }
+        if let Some(after) = &self.after {
+            lines.push(format!("+ {}", card_image(after).trim_end()));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// How a header was written back to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// The header fit in its existing blocks and was overwritten in place.
+    InPlace,
+    /// The header grew and the file was rewritten.
+    Rewritten,
+    /// There was nothing to write.
+    Unchanged,
+}
+
+/// Edits a single HDU header while preserving untouched cards verbatim.
+#[derive(Debug, Clone)]
+pub struct HeaderEditor {
+    hdu_index: usize,
+    original: Vec<FitsHeaderCard>,
+    cards: Vec<FitsHeaderCard>,
+    /// Index into `original` for each card in `cards`, `None` for new cards.
+    origins: Vec<Option<usize>>,
+    /// Explicit checksum choice; `None` refreshes them only if the header had them.
+    update_checksums: Option<bool>,
+}
+
+impl HeaderEditor {
+    /// Start editing cards read from a single HDU.
+    ///
+    /// A trailing `END` card, if present, is dropped; it is re-added on write.
+    pub fn new(cards: Vec<FitsHeaderCard>) -> Self {
+        let hdu_index = cards.first().map_or(0, |card| card.hdu_index);
+        let original: Vec<FitsHeaderCard> = cards
+            .into_iter()
+            .filter(|card| card.hdu_index == hdu_index && card.keyword != "END")
+            .collect();
+        let origins = (0..original.len()).map(Some).collect();
+
+        Self {
+            hdu_index,
+            cards: original.clone(),
+            original,
+            origins,
+            update_checksums: None,
+        }
+    }
+
+    /// Read the header of an HDU directly from disk and start editing it.
+    pub fn from_path(path: &Path, hdu_index: usize) -> Result<Self> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open FITS file {}", path.display()))?;
+        let layout = locate_header(&mut file, hdu_index)?;
+        Ok(Self::new(layout.cards))
+    }
+
+    /// Zero-based index of the HDU being edited.
+    pub fn hdu_index(&self) -> usize {
+        self.hdu_index
+    }
+
+    /// Current state of the edited cards.
+    pub fn cards(&self) -> &[FitsHeaderCard] {
+        &self.cards
+    }
+
+    /// Set a keyword's value, updating the first matching card or appending a new one.
+    ///
+    /// The existing comment is kept when `comment` is `None`.
+    pub fn set(
+        &mut self,
+        keyword: &str,
+        value: impl Into<HeaderValue>,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let keyword = checked_keyword(keyword)?;
+        let value = value.into();
+
+        match self.position(&keyword) {
+            Some(position) => {
+                let span = self.span(position);
+                let comment = comment
+                    .map(str::to_string)
+                    .or_else(|| self.cards[position + span - 1].comment.clone());
+                let cards = build_cards(&keyword, &value, comment.as_deref())?;
+                self.splice(position, span, cards);
+            }
+            None => {
+                let cards = build_cards(&keyword, &value, comment)?;
+                self.splice(self.cards.len(), 0, cards);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Insert a new card directly after the first card with `anchor`.
+    pub fn insert_after(
+        &mut self,
+        anchor: &str,
+        keyword: &str,
+        value: impl Into<HeaderValue>,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let anchor = self.anchor_position(anchor)?;
+        let position = anchor + self.span(anchor);
+        let cards = build_cards(&checked_keyword(keyword)?, &value.into(), comment)?;
+        self.splice(position, 0, cards);
+        Ok(())
+    }
+
+    /// Insert a new card directly before the first card with `anchor`.
+    pub fn insert_before(
+        &mut self,
+        anchor: &str,
+        keyword: &str,
+        value: impl Into<HeaderValue>,
+        comment: Option<&str>,
+    ) -> Result<()> {
+        let position = self.anchor_position(anchor)?;
+        if position == 0 {
+            bail!("Cannot insert before the first card of the header");
+        }
+        let cards = build_cards(&checked_keyword(keyword)?, &value.into(), comment)?;
+        self.splice(position, 0, cards);
+        Ok(())
+    }
+
+    /// Delete every card with `keyword` and return how many keywords were removed.
+    ///
+    /// `CONTINUE` cards belonging to a deleted long string are removed with it.
+    pub fn delete(&mut self, keyword: &str) -> Result<usize> {
+        let keyword = checked_keyword(keyword)?;
+        let mut removed = 0;
+
+        let mut position = 0;
+        while position < self.cards.len() {
+            if self.cards[position].keyword == keyword {
+                let span = self.span(position);
+                self.splice(position, span, Vec::new());
+                removed += 1;
+            } else {
+                position += 1;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Rename every card with `from` to `to`, keeping values and comments.
+    ///
+    /// Returns how many cards were renamed.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<usize> {
+        let from = checked_keyword(from)?;
+        let to = checked_keyword(to)?;
+        let mut renamed = 0;
+
+        for card in self.cards.iter_mut().filter(|card| card.keyword == from) {
+            let image = card_image(card);
+            // Keep everything from the value indicator onwards verbatim
+            let rest = if from.starts_with("HIERARCH ") {
+                let field = image.split_once('=').map_or("", |(_, field)| field);
+                format!("= {}", field.trim_start())
+            } else {
+                image.get(8..).unwrap_or_default().to_string()
+            };
+            let renamed_image = if to.starts_with("HIERARCH ") {
+                format!("{to} {}", rest.trim_start())
+            } else {
+                format!("{to:<8}{rest}")
+            };
+            let renamed_image = renamed_image.trim_end();
+            if renamed_image.len() > CARD_LEN {
+                bail!("Renaming {from} to {to} does not fit in an 80-character card");
+            }
+
+            let (hdu_index, card_index) = (card.hdu_index, card.card_index);
+            *card = card_from_image(renamed_image);
+            card.hdu_index = hdu_index;
+            card.card_index = card_index;
+            renamed += 1;
+        }
+
+        Ok(renamed)
+    }
+
+    /// Append `HISTORY` cards, wrapping long text across several cards.
+    pub fn add_history(&mut self, text: &str) -> Result<()> {
+        for line in wrap_commentary(text) {
+            let card = FitsHeaderCard {
+                hdu_index: self.hdu_index,
+                keyword: "HISTORY".to_string(),
+                comment: Some(line.clone()),
+                raw_card: Some(format!("HISTORY {line}")),
+                ..Default::default()
+            };
+            self.push(self.cards.len(), card);
+        }
+        Ok(())
+    }
+
+    /// Choose whether `write` generates `DATASUM` and `CHECKSUM` cards.
+    ///
+    /// By default they are refreshed only when the header already had a
+    /// `CHECKSUM` card. Enabling this also writes an otherwise unmodified header.
+    pub fn update_checksums(&mut self, enabled: bool) {
+        self.update_checksums = Some(enabled);
+    }
+
+    /// Whether any card differs from the original header.
+    pub fn is_modified(&self) -> bool {
+        !self.diff().is_empty()
+    }
+
+    /// Card-level differences between the original and edited header.
+    ///
+    /// This is the dry-run view: nothing is written to disk.
+    pub fn diff(&self) -> Vec<HeaderChange> {
+        let mut changes = Vec::new();
+        let mut next_original = 0;
+
+        for (card, origin) in self.cards.iter().zip(&self.origins) {
+            match origin {
+                Some(index) => {
+                    changes.extend(self.removed_between(next_original, *index));
+                    next_original = index + 1;
+
+                    let before = &self.original[*index];
+                    if card_image(before).trim_end() != card_image(card).trim_end() {
+                        changes.push(HeaderChange {
+                            kind: ChangeKind::Modified,
+                            before: Some(before.clone()),
+                            after: Some(card.clone()),
+                        });
+                    }
+                }
+                None => changes.push(HeaderChange {
+                    kind: ChangeKind::Added,
+                    before: None,
+                    after: Some(card.clone()),
+                }),
+            }
+        }
+
+        changes.extend(self.removed_between(next_original, self.original.len()));
+        changes
+    }
+
+    /// Write the edited header back to the file it was read from.
+    ///
+    /// The on-disk header must still match the cards the editor started
+    /// from. See `update_checksums` for how `CHECKSUM` cards are handled.
+    pub fn write(&self, path: &Path) -> Result<WriteMode> {
+        let had_checksum = self.original.iter().any(|card| card.keyword == "CHECKSUM");
+        let seal = self.update_checksums.unwrap_or(had_checksum);
+        if !self.is_modified() && self.update_checksums != Some(true) {
+            return Ok(WriteMode::Unchanged);
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open FITS file {}", path.display()))?;
+        let layout = locate_header(&mut file, self.hdu_index)?;
+        self.check_unchanged_on_disk(&layout.cards)?;
+
+        let mut sealed = self.clone();
+        let datasum = if seal {
+            let datasum = fits_checksum::data_checksum(&mut file, &layout)?;
+            sealed.set("DATASUM", datasum.to_string(), Some("data unit checksum"))?;
+            sealed.set(
+                "CHECKSUM",
+                fits_checksum::CHECKSUM_PLACEHOLDER,
+                Some("HDU checksum"),
+            )?;
+            Some(datasum)
+        } else {
+            None
+        };
+
+        let mut header = serialize_header(&sealed.cards);
+        let old_len = layout.block_count * BLOCK_LEN;
+        let in_place = header.len() <= old_len;
+        if in_place {
+            header.resize(old_len, b' ');
+        }
+        if let Some(datasum) = datasum {
+            fits_checksum::seal_header(&mut header, datasum)?;
+        }
+
+        if in_place {
+            file.seek(SeekFrom::Start(layout.offset))?;
+            file.write_all(&header)
+                .context("Failed to write FITS header in place")?;
+            file.sync_all()?;
+            return Ok(WriteMode::InPlace);
+        }
+
+        rewrite_with_header(file, path, layout.offset, old_len as u64, &header)?;
+        Ok(WriteMode::Rewritten)
+    }
+
+    fn push(&mut self, position: usize, card: FitsHeaderCard) {
+        self.splice(position, 0, vec![card]);
+    }
+
+    /// Replace `remove` cards at `position` with `cards`.
+    ///
+    /// Replacement cards inherit the origins of the cards they overwrite so
+    /// the diff reports them as modified rather than removed and added.
+    fn splice(&mut self, position: usize, remove: usize, cards: Vec<FitsHeaderCard>) {
+        let old_origins: Vec<Option<usize>> =
+            self.origins.drain(position..position + remove).collect();
+        let new_origins: Vec<Option<usize>> = (0..cards.len())
+            .map(|index| old_origins.get(index).copied().flatten())
+            .collect();
+
+        let hdu_index = self.hdu_index;
+        self.cards.splice(
+            position..position + remove,
+            cards.into_iter().map(|mut card| {
+                card.hdu_index = hdu_index;
+                card
+            }),
+        );
+        self.origins.splice(position..position, new_origins);
+        self.renumber();
+    }
+
+    /// Number of cards holding the keyword at `position`, including `CONTINUE` cards.
+    fn span(&self, position: usize) -> usize {
+        1 + self.cards[position + 1..]
+            .iter()
+            .take_while(|card| card.keyword == "CONTINUE")
+            .count()
+    }
+
+    fn renumber(&mut self) {
+        for (index, card) in self.cards.iter_mut().enumerate() {
+            card.card_index = index + 1;
+        }
+    }
+
+    fn position(&self, keyword: &str) -> Option<usize> {
+        self.cards.iter().position(|card| card.keyword == keyword)
+    }
+
+    fn anchor_position(&self, anchor: &str) -> Result<usize> {
+        let anchor = anchor.trim().to_ascii_uppercase();
+        self.position(&anchor)
+            .with_context(|| format!("Keyword {anchor} not found in header"))
+    }
+
+    fn removed_between(&self, start: usize, end: usize) -> Vec<HeaderChange> {
+        (start..end)
+            .filter(|index| !self.origins.contains(&Some(*index)))
+            .map(|index| HeaderChange {
+                kind: ChangeKind::Removed,
+                before: Some(self.original[index].clone()),
+                after: None,
+            })
+            .collect()
+    }
+
+    fn check_unchanged_on_disk(&self, on_disk: &[FitsHeaderCard]) -> Result<()> {
+        let matches = on_disk.len() == self.original.len()
+            && on_disk.iter().zip(&self.original).all(|(disk, original)| {
+                card_image(disk).trim_end() == card_image(original).trim_end()
+            });
+        if !matches {
+            bail!(
+                "Header of HDU {} changed on disk since it was read",
+                self.hdu_index
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Position and contents of an HDU header within a FITS file.
+#[derive(Debug, Clone)]
+pub(crate) struct HeaderLayout {
+    /// Byte offset of the first header block.
+    pub offset: u64,
+    /// Number of 2880-byte blocks the header occupies.
+    pub block_count: usize,
+    /// Cards before `END`, with exact raw text.
+    pub cards: Vec<FitsHeaderCard>,
+}
+
+impl HeaderLayout {
+    /// Byte offset of the data unit following this header.
+    pub fn data_offset(&self) -> u64 {
+        self.offset + (self.block_count * BLOCK_LEN) as u64
+    }
+
+    /// Size of the data unit in bytes, including padding to whole blocks.
+    pub fn padded_data_len(&self) -> u64 {
+        let value = |keyword: &str| -> Option<i64> {
+            self.cards
+                .iter()
+                .find(|card| card.keyword == keyword)
+                .and_then(|card| card.value.as_deref())
+                .and_then(|value| value.trim().parse().ok())
+        };
+
+        let bitpix = value("BITPIX").unwrap_or(8).unsigned_abs();
+        let naxis = value("NAXIS").unwrap_or(0);
+        if naxis <= 0 {
+            return 0;
+        }
+
+        let axes: Vec<u64> = (1..=naxis)
+            .map(|axis| value(&format!("NAXIS{axis}")).unwrap_or(0).max(0) as u64)
+            .collect();
+        // Random groups headers set NAXIS1 = 0 and exclude it from the product
+        let random_groups = axes[0] == 0 && axes.len() > 1;
+        let elements: u64 = if random_groups {
+            axes[1..].iter().product()
+        } else {
+            axes.iter().product()
+        };
+        let pcount = value("PCOUNT").unwrap_or(0).max(0) as u64;
+        let gcount = value("GCOUNT").unwrap_or(1).max(0) as u64;
+
+        let bytes = bitpix / 8 * gcount * (pcount + elements);
+        bytes.div_ceil(BLOCK_LEN as u64) * BLOCK_LEN as u64
+    }
+}
+
+/// Parse the header of every HDU in a file.
+///
+/// Trailing bytes shorter than a block are ignored.
+pub(crate) fn read_header_layouts<R: Read + Seek>(reader: &mut R) -> Result<Vec<HeaderLayout>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let mut layouts: Vec<HeaderLayout> = Vec::new();
+    let mut offset = 0u64;
+
+    while offset + BLOCK_LEN as u64 <= file_len {
+        let layout = read_header_at(reader, offset, layouts.len())?;
+        offset = layout.data_offset() + layout.padded_data_len();
+        layouts.push(layout);
+    }
+
+    Ok(layouts)
+}
+
+/// Find and parse the header of an HDU by walking the file's blocks.
+pub(crate) fn locate_header<R: Read + Seek>(
+    reader: &mut R,
+    hdu_index: usize,
+) -> Result<HeaderLayout> {
+    let mut offset = 0u64;
+
+    for index in 0..=hdu_index {
+        let layout = read_header_at(reader, offset, index)?;
+        if index == hdu_index {
+            return Ok(layout);
+        }
+        offset = layout.data_offset() + layout.padded_data_len();
+    }
+
+    unreachable!("loop always returns at hdu_index")
+}
+
+fn read_header_at<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    hdu_index: usize,
+) -> Result<HeaderLayout> {
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut cards = Vec::new();
+    let mut block = [0u8; BLOCK_LEN];
+    let mut block_count = 0;
+
+    loop {
+        reader
+            .read_exact(&mut block)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => {
+                    anyhow::anyhow!("HDU {hdu_index} not found or header has no END card")
+                }
+                _ => err.into(),
+            })?;
+        block_count += 1;
+
+        for (slot, chunk) in block.chunks(CARD_LEN).enumerate() {
+            let raw = String::from_utf8_lossy(chunk).into_owned();
+            if block_count == 1 && slot == 0 {
+                let first = raw.get(..8).unwrap_or_default().trim_end();
+                let expected = if hdu_index == 0 { "SIMPLE" } else { "XTENSION" };
+                if first != expected {
+                    bail!("HDU {hdu_index} does not start with {expected}");
+                }
+            }
+            if raw
+                .get(..8)
+                .is_some_and(|keyword| keyword.trim_end() == "END")
+            {
+                return Ok(HeaderLayout {
+                    offset,
+                    block_count,
+                    cards,
+                });
+            }
+
+            let (keyword, value, comment) = parse_card(&raw);
+            cards.push(FitsHeaderCard {
+                hdu_index,
+                card_index: (block_count - 1) * CARDS_PER_BLOCK + slot + 1,
+                keyword,
+                value,
+                comment,
+                raw_card: Some(raw),
+            });
+        }
+    }
+}
+
+/// Split a raw card image into keyword, value and comment.
+///
+/// `HIERARCH` keywords are normalized (see `normalize_hierarch`) and
+/// `CONTINUE` cards report their string fragment as the value.
+pub(crate) fn parse_card(raw: &str) -> (String, Option<String>, Option<String>) {
+    if let Some((keyword, field)) = split_hierarch(raw) {
+        let (value, comment) = split_value_comment(field);
+        return (keyword, value, comment);
+    }
+
+    let keyword = raw.get(..8).unwrap_or(raw).trim().to_string();
+    let value_field = if raw.get(8..10) == Some("= ") {
+        raw.get(10..)
+    } else if keyword == "CONTINUE" {
+        // CONTINUE carries a string value without the `= ` indicator
+        raw.get(8..)
+    } else {
+        None
+    };
+
+    match value_field {
+        Some(field) => {
+            let (value, comment) = split_value_comment(field);
+            (keyword, value, comment)
+        }
+        None => {
+            let text = raw.get(8..).unwrap_or_default().trim_end();
+            let comment = (!text.trim().is_empty()).then(|| text.trim_start().to_string());
+            (keyword, None, comment)
+        }
+    }
+}
+
+/// Split a `HIERARCH` card into its normalized keyword and value field.
+pub(crate) fn split_hierarch(raw: &str) -> Option<(String, &str)> {
+    let rest = raw.strip_prefix("HIERARCH ")?;
+    let (name, field) = rest.split_once('=')?;
+    Some((normalize_hierarch(name), field))
+}
+
+/// Canonical form of an ESO `HIERARCH` keyword.
+///
+/// Words are uppercased and separated by single spaces, so
+/// `HIERARCH eso  det temp` becomes `HIERARCH ESO DET TEMP`.
+pub fn normalize_hierarch(name: &str) -> String {
+    let mut words = name.split_whitespace().peekable();
+    if words
+        .peek()
+        .is_some_and(|word| word.eq_ignore_ascii_case("HIERARCH"))
+    {
+        words.next();
+    }
+
+    let words: Vec<String> = words.map(|word| word.to_ascii_uppercase()).collect();
+    format!("HIERARCH {}", words.join(" "))
+}
+
+/// Split the value field of a card into a cleaned value and optional comment.
+pub(crate) fn split_value_comment(field: &str) -> (Option<String>, Option<String>) {
+    let trimmed = field.trim_start();
+
+    let (value, rest) = if let Some(quoted) = trimmed.strip_prefix('\'') {
+        let mut value = String::new();
+        let mut chars = quoted.char_indices().peekable();
+        let mut end = quoted.len();
+        while let Some((index, c)) = chars.next() {
+            if c == '\'' {
+                if chars.peek().is_some_and(|(_, next)| *next == '\'') {
+                    chars.next();
+                    value.push('\'');
+                    continue;
+                }
+                end = index + 1;
+                break;
+            }
+            value.push(c);
+        }
+        (Some(value.trim_end().to_string()), &quoted[end..])
+    } else {
+        let (value, rest) = trimmed.split_at(trimmed.find('/').unwrap_or(trimmed.len()));
+        let value = value.trim();
+        ((!value.is_empty()).then(|| value.to_string()), rest)
+    };
+
+    let comment = rest
+        .trim_start()
+        .strip_prefix('/')
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty())
+        .map(str::to_string);
+
+    (value, comment)
+}
+
+/// Build the card images for a keyword value.
+///
+/// Strings too long for one card are split using the OGIP `CONTINUE`
+/// long-string convention, and `HIERARCH` keywords use free-format layout.
+/// String values and comments must be printable ASCII, as FITS requires.
+fn build_cards(
+    keyword: &str,
+    value: &HeaderValue,
+    comment: Option<&str>,
+) -> Result<Vec<FitsHeaderCard>> {
+    let printable = |text: &str| text.chars().all(|c| matches!(c, ' '..='~'));
+    if let HeaderValue::Text(text) = value {
+        if !printable(text) {
+            bail!("Value for {keyword} must be printable ASCII: {text:?}");
+        }
+    }
+    if let Some(comment) = comment.filter(|comment| !printable(comment)) {
+        bail!("Comment for {keyword} must be printable ASCII: {comment:?}");
+    }
+    let hierarch = keyword.starts_with("HIERARCH ");
+    let prefix = if hierarch {
+        format!("{keyword} = ")
+    } else {
+        format!("{keyword:<8}= ")
+    };
+
+    let mut images = match value {
+        HeaderValue::Text(text) => string_card_images(&prefix, text)
+            .with_context(|| format!("Keyword {keyword} is too long for a string value"))?,
+        _ => {
+            // Fixed format right-justifies non-string values to column 30
+            let formatted = value.formatted();
+            let image = if hierarch {
+                format!("{prefix}{formatted}")
+            } else {
+                format!("{prefix}{formatted:>20}")
+            };
+            if image.len() > CARD_LEN {
+                bail!("Value for {keyword} does not fit in an 80-character card");
+            }
+            vec![image]
+        }
+    };
+
+    if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+        let last = images.last_mut().expect("at least one card image");
+        if last.len() + 3 < CARD_LEN {
+            last.push_str(" / ");
+            last.push_str(comment);
+            last.truncate(CARD_LEN);
+        }
+    }
+
+    Ok(images.iter().map(|image| card_from_image(image)).collect())
+}
+
+/// Card images for a string value, continued across `CONTINUE` cards if needed.
+fn string_card_images(prefix: &str, text: &str) -> Option<Vec<String>> {
+    let escaped = text.replace('\'', "''");
+    // Room between the quotes on the first card
+    let first_room = CARD_LEN.checked_sub(prefix.len() + 2)?;
+    if escaped.len() <= first_room {
+        return Some(vec![format!("{prefix}'{escaped:<8}'")]);
+    }
+
+    // Every fragment except the last ends with `&`; never split a doubled quote
+    const CONTINUE_ROOM: usize = CARD_LEN - 10 - 2;
+    let mut fragments = vec![String::new()];
+    let mut room = first_room.checked_sub(1).filter(|room| *room > 0)?;
+    for c in text.chars() {
+        let piece = if c == '\'' {
+            "''".to_string()
+        } else {
+            c.to_string()
+        };
+        if fragments.last().expect("non-empty").len() + piece.len() > room {
+            fragments.push(String::new());
+            room = CONTINUE_ROOM - 1;
+        }
+        fragments.last_mut().expect("non-empty").push_str(&piece);
+    }
+
+    let count = fragments.len();
+    Some(
+        fragments
+            .into_iter()
+            .enumerate()
+            .map(|(index, fragment)| {
+                let amp = if index + 1 < count { "&" } else { "" };
+                if index == 0 {
+                    format!("{prefix}'{fragment}{amp}'")
+                } else {
+                    format!("CONTINUE  '{fragment}{amp}'")
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Parse a card image into a `FitsHeaderCard`.
+fn card_from_image(image: &str) -> FitsHeaderCard {
+    let (keyword, value, comment) = parse_card(image);
+    FitsHeaderCard {
+        keyword,
+        value,
+        comment,
+        raw_card: Some(image.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Validate and normalize a keyword for editing.
+///
+/// Names longer than 8 characters or containing spaces become `HIERARCH` keywords.
+fn checked_keyword(keyword: &str) -> Result<String> {
+    let trimmed = keyword.trim();
+    let hierarch = trimmed.len() > 8 || trimmed.contains(' ');
+    let keyword = if hierarch {
+        normalize_hierarch(trimmed)
+    } else {
+        trimmed.to_ascii_uppercase()
+    };
+    let name = keyword.strip_prefix("HIERARCH ").unwrap_or(&keyword);
+
+    if name.is_empty() {
+        bail!("Keyword must not be empty");
+    }
+    if !name.chars().all(|c| {
+        c.is_ascii_uppercase()
+            || c.is_ascii_digit()
+            || c == '-'
+            || c == '_'
+            || (hierarch && c == ' ')
+    }) {
+        bail!("Keyword {keyword:?} contains characters not allowed in FITS keywords");
+    }
+    let structural = STRUCTURAL_KEYWORDS.contains(&keyword.as_str())
+        || keyword
+            .strip_prefix("NAXIS")
+            .is_some_and(|axis| axis.chars().all(|c| c.is_ascii_digit()));
+    if structural {
+        bail!("Keyword {keyword} describes the HDU structure and cannot be edited");
+    }
+
+    Ok(keyword)
+}
+
+/// Split commentary text into chunks that fit after an 8-character keyword.
+fn wrap_commentary(text: &str) -> Vec<String> {
+    const WIDTH: usize = CARD_LEN - 8;
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > WIDTH {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+        while line.len() > WIDTH {
+            let rest = line.split_off(WIDTH);
+            lines.push(std::mem::replace(&mut line, rest));
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The 80-character image of a card, formatting it if no raw text is available.
+pub(crate) fn card_image(card: &FitsHeaderCard) -> String {
+    card_images(card).swap_remove(0)
+}
+
+/// Card images for a card; cards without raw text may need `CONTINUE` cards.
+fn card_images(card: &FitsHeaderCard) -> Vec<String> {
+    let images = match (&card.raw_card, &card.value) {
+        (Some(raw), _) => vec![raw.clone()],
+        (None, Some(value)) => build_cards(
+            &card.keyword,
+            &HeaderValue::Text(value.clone()),
+            card.comment.as_deref(),
+        )
+        .map(|cards| cards.into_iter().filter_map(|card| card.raw_card).collect())
+        .unwrap_or_default(),
+        (None, None) => vec![format!(
+            "{:<8}{}",
+            card.keyword,
+            card.comment.as_deref().unwrap_or_default()
+        )],
+    };
+
+    let mut images: Vec<String> = images
+        .into_iter()
+        .map(|image| format!("{image:<width$.width$}", width = CARD_LEN))
+        .collect();
+    if images.is_empty() {
+        images.push(" ".repeat(CARD_LEN));
+    }
+    images
+}
+
+/// Serialize cards plus `END`, padded to whole blocks.
+pub(crate) fn serialize_header(cards: &[FitsHeaderCard]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = cards
+        .iter()
+        .flat_map(card_images)
+        .flat_map(String::into_bytes)
+        .collect();
+    bytes.extend(format!("{:<width$}", "END", width = CARD_LEN).into_bytes());
+    let padded = bytes.len().div_ceil(BLOCK_LEN) * BLOCK_LEN;
+    bytes.resize(padded, b' ');
+    bytes
+}
+
+/// Rewrite the file with a larger header via a temporary file and rename.
+fn rewrite_with_header(
+    mut file: File,
+    path: &Path,
+    header_offset: u64,
+    old_header_len: u64,
+    header: &[u8],
+) -> Result<()> {
+    let temp_path = temp_sibling(path);
+    let result = (|| -> Result<()> {
+        let mut temp = File::create(&temp_path)
+            .with_context(|| format!("Failed to create {}", temp_path.display()))?;
+
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut file).take(header_offset), &mut temp)?;
+        temp.write_all(header)?;
+        file.seek(SeekFrom::Start(header_offset + old_header_len))?;
+        io::copy(&mut file, &mut temp)?;
+        temp.sync_all()?;
+        Ok(())
+    })();
+
+    // Close the original before replacing it; Windows cannot rename over an open file
+    drop(file);
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.context("Failed to rewrite FITS file"));
+    }
+
+    fs::rename(&temp_path, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+fn temp_sibling(path: &Path) -> PathBuf {
+    let mut name = path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Build a minimal FITS file from card images and a data payload.
+    pub(crate) fn fits_bytes(hdus: &[(&[&str], &[u8])]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (cards, data) in hdus {
+            for card in *cards {
+                bytes.extend(format!("{card:<80}").into_bytes());
+            }
+            bytes.extend(format!("{:<80}", "END").into_bytes());
+            bytes.resize(bytes.len().div_ceil(BLOCK_LEN) * BLOCK_LEN, b' ');
+            bytes.extend_from_slice(data);
+            bytes.resize(bytes.len().div_ceil(BLOCK_LEN) * BLOCK_LEN, 0);
+        }
+        bytes
+    }
+
+    pub(crate) fn unique_temp_fits_path(prefix: &str) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+
+        std::env::temp_dir().join(format!(
+            "astro-io-{prefix}-{}-{timestamp}.fits",
+            std::process::id()
+        ))
+    }
+
+    const PRIMARY: &[&str] = &[
+        "SIMPLE  =                    T / conforms to FITS standard",
+        "BITPIX  =                   16",
+        "NAXIS   =                    2",
+        "NAXIS1  =                    2",
+        "NAXIS2  =                    2",
+        "EXTEND  =                    T",
+        "OBJECT  = 'M 42    '           / wrong target",
+        "FOCALLEN=                 800. / [mm]",
+        "COMMENT   Keep   this   spacing",
+    ];
+
+    const EXTENSION: &[&str] = &[
+        "XTENSION= 'IMAGE   '",
+        "BITPIX  =                    8",
+        "NAXIS   =                    1",
+        "NAXIS1  =                    3",
+        "PCOUNT  =                    0",
+        "GCOUNT  =                    1",
+        "EXTNAME = 'MASK    '",
+    ];
+
+    fn write_sample(prefix: &str) -> Result<PathBuf> {
+        let path = unique_temp_fits_path(prefix);
+        fs::write(
+            &path,
+            fits_bytes(&[
+                (PRIMARY, &[1, 2, 3, 4, 5, 6, 7, 8]),
+                (EXTENSION, &[9, 9, 9]),
+            ]),
+        )?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_parse_card_fields() {
+        assert_eq!(
+            parse_card("OBJECT  = 'O''Brien '           / odd name"),
+            (
+                "OBJECT".to_string(),
+                Some("O'Brien".to_string()),
+                Some("odd name".to_string())
+            )
+        );
+        assert_eq!(
+            parse_card("FOCALLEN=                 800. / [mm]"),
+            (
+                "FOCALLEN".to_string(),
+                Some("800.".to_string()),
+                Some("[mm]".to_string())
+            )
+        );
+        assert_eq!(
+            parse_card("HISTORY calibrated"),
+            ("HISTORY".to_string(), None, Some("calibrated".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_edits_in_place_preserve_untouched_cards() -> Result<()> {
+        let path = write_sample("edit-in-place")?;
+        let original = fs::read(&path)?;
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        editor.set("OBJECT", "M42", None)?;
+        editor.set("FILTER", "Ha", Some("filter name"))?;
+        editor.set("FOCALLEN", 640.0, Some("[mm] with 0.8x reducer"))?;
+        editor.add_history("Fixed OBJECT and FOCALLEN")?;
+
+        let changes = editor.diff();
+        assert_eq!(changes.len(), 4);
+        assert_eq!(
+            changes[0].to_string(),
+            "- OBJECT  = 'M 42    '           / wrong target\n+ OBJECT  = 'M42     ' / wrong target"
+        );
+        assert_eq!(changes[2].kind, ChangeKind::Added);
+
+        assert_eq!(editor.write(&path)?, WriteMode::InPlace);
+        let written = fs::read(&path)?;
+        assert_eq!(written.len(), original.len());
+        assert_eq!(&written[BLOCK_LEN..], &original[BLOCK_LEN..]);
+
+        let reread = HeaderEditor::from_path(&path, 0)?;
+        let cards = reread.cards();
+        assert_eq!(
+            cards[8].raw_card.as_deref().map(str::trim_end),
+            Some("COMMENT   Keep   this   spacing")
+        );
+        assert_eq!(cards[9].keyword, "FILTER");
+        assert_eq!(cards[9].value.as_deref(), Some("Ha"));
+        assert_eq!(cards[7].value.as_deref(), Some("640.0"));
+        assert_eq!(
+            cards[10].comment.as_deref(),
+            Some("Fixed OBJECT and FOCALLEN")
+        );
+        assert!(!reread.is_modified());
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_growing_header_rewrites_file() -> Result<()> {
+        let path = write_sample("edit-rewrite")?;
+        let original = fs::read(&path)?;
+
+        let mut editor = HeaderEditor::from_path(&path, 1)?;
+        editor.rename("EXTNAME", "HDUNAME")?;
+        for index in 0..40 {
+            editor.set(&format!("KEY{index}"), index, None)?;
+        }
+        assert_eq!(editor.write(&path)?, WriteMode::Rewritten);
+
+        let written = fs::read(&path)?;
+        assert_eq!(written.len(), original.len() + BLOCK_LEN);
+        assert_eq!(&written[..2 * BLOCK_LEN], &original[..2 * BLOCK_LEN]);
+        assert_eq!(&written[4 * BLOCK_LEN..], &original[3 * BLOCK_LEN..]);
+
+        let reread = HeaderEditor::from_path(&path, 1)?;
+        assert!(reread.cards().iter().any(|card| card.keyword == "HDUNAME"
+            && card.raw_card.as_deref().map(str::trim_end) == Some("HDUNAME = 'MASK    '")));
+        assert_eq!(reread.cards().len(), EXTENSION.len() + 40);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_and_dry_run_leave_file_untouched() -> Result<()> {
+        let path = write_sample("edit-dry-run")?;
+        let original = fs::read(&path)?;
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        assert_eq!(editor.delete("object")?, 1);
+        editor.insert_after("FOCALLEN", "APTDIA", 72, Some("[mm]"))?;
+        assert!(editor.delete("NAXIS1").is_err());
+        assert!(editor.set("BITPIX", 32, None).is_err());
+
+        let kinds: Vec<ChangeKind> = editor.diff().iter().map(|change| change.kind).collect();
+        assert_eq!(kinds, vec![ChangeKind::Removed, ChangeKind::Added]);
+        assert_eq!(fs::read(&path)?, original);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_strings_and_hierarch_keywords() -> Result<()> {
+        let path = write_sample("edit-long")?;
+        let long_path =
+            "D:/Astro/2024-09-04/NGC7000/LIGHT/Ha/NGC7000_2024-09-04_21-14-03_Ha_300.00s_0001.fits";
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        editor.set("FILENAME", long_path, Some("original path"))?;
+        editor.set(
+            "hierarch eso det temp",
+            -120.5,
+            Some("detector temperature"),
+        )?;
+        editor.write(&path)?;
+
+        let reread = HeaderEditor::from_path(&path, 0)?;
+        let continuations = reread
+            .cards()
+            .iter()
+            .filter(|card| card.keyword == "CONTINUE")
+            .count();
+        assert_eq!(continuations, 1);
+        assert!(reread
+            .cards()
+            .iter()
+            .all(|card| card_image(card).len() == CARD_LEN));
+
+        let headers = crate::fits::header_cards_to_map(reread.cards());
+        assert_eq!(headers.get("FILENAME").map(String::as_str), Some(long_path));
+        assert_eq!(
+            headers.get("HIERARCH ESO DET TEMP").map(String::as_str),
+            Some("-120.5")
+        );
+
+        // Replacing or deleting a long string takes its CONTINUE cards along
+        let mut editor = reread;
+        editor.set("FILENAME", "short.fits", None)?;
+        assert!(editor.cards().iter().all(|card| card.keyword != "CONTINUE"));
+        editor.rename("HIERARCH ESO DET TEMP", "CCD-TEMP")?;
+        assert!(editor
+            .cards()
+            .iter()
+            .any(|card| card.keyword == "CCD-TEMP" && card.value.as_deref() == Some("-120.5")));
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_ascii_values_and_comments_are_rejected() -> Result<()> {
+        let path = write_sample("edit-ascii")?;
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        let before = editor.cards().to_vec();
+        assert!(editor.set("OBSERVER", "Zoë", None).is_err());
+        // Byte 80 of the card would fall inside a two-byte character
+        let comment = "é".repeat(40);
+        assert!(editor.set("OBJECT", "M42", Some(&comment)).is_err());
+        assert!(editor
+            .insert_after("OBJECT", "OBSERVER", "Zoe", None)
+            .is_ok());
+        assert!(editor
+            .insert_after("OBJECT", "NOTE", "cold", Some("-10 °C"))
+            .is_err());
+        assert_eq!(editor.cards().len(), before.len() + 1);
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_rejects_stale_header() -> Result<()> {
+        let path = write_sample("edit-stale")?;
+
+        let mut editor = HeaderEditor::from_path(&path, 0)?;
+        editor.set("OBJECT", "M42", None)?;
+
+        let mut other = HeaderEditor::from_path(&path, 0)?;
+        other.set("OBJECT", "M43", None)?;
+        other.write(&path)?;
+
+        assert!(editor.write(&path).is_err());
+
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
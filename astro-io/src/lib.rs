@@ -1,4 +1,6 @@
 //! I/O operations for astronomical image formats
 
 pub mod fits;
+pub mod fits_checksum;
+pub mod fits_header;
 pub mod xisf;
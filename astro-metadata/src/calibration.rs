@@ -0,0 +1,706 @@
+//! Matching light frames to calibration frames
+//!
+//! `CalibrationMatcher` pairs each light frame with the darks, flats and bias
+//! frames (individual or master) that can calibrate it:
+//!
+//! - darks: camera, gain, offset, exposure time, binning, sensor temperature
+//!   and readout mode
+//! - flats: camera, binning, filter, rotator angle, focuser position and
+//!   session
+//! - bias frames: camera, gain, offset and binning
+//!
+//! Values missing on either side are not compared. Accepted candidates are
+//! scored by how close the soft criteria (temperature, exposure, rotator,
+//! focuser, age) are, and every rejection carries its reasons.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+
+use super::frame_kind::FrameKind;
+use super::types::AstroMetadata;
+
+/// Kind of calibration frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CalibrationKind {
+    /// Dark frame or master dark
+    Dark,
+    /// Flat field or master flat
+    Flat,
+    /// Bias frame or master bias
+    Bias,
+}
+
+/// Limits beyond which a candidate is rejected
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchTolerances {
+    /// Largest relative exposure time difference for darks (0.01 = 1%)
+    pub exposure_fraction: f32,
+    /// Largest sensor temperature difference for darks in °C
+    pub temperature: f32,
+    /// Largest rotator angle difference for flats in degrees
+    pub rotator_angle: f32,
+    /// Largest focuser position difference for flats in steps
+    pub focuser_steps: i32,
+    /// Largest age difference for darks and bias frames in days
+    pub max_age_days: Option<f64>,
+    /// Largest session date difference for flats in days (0 = same session)
+    pub flat_session_days: i64,
+}
+
+/// Relative weights of the soft criteria in the score
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScoreWeights {
+    /// Sensor temperature difference
+    pub temperature: f32,
+    /// Exposure time difference
+    pub exposure: f32,
+    /// Rotator angle difference
+    pub rotator: f32,
+    /// Focuser position difference
+    pub focuser: f32,
+    /// Time between the light and the candidate
+    pub age: f32,
+}
+
+/// Tolerances and scoring for the matcher
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MatchConfig {
+    /// Hard limits
+    pub tolerances: MatchTolerances,
+    /// Score weights
+    pub weights: ScoreWeights,
+}
+
+/// Why a candidate cannot calibrate a light frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    /// Different camera
+    Camera { light: String, candidate: String },
+    /// Different gain setting
+    Gain { light: f32, candidate: f32 },
+    /// Different offset
+    Offset { light: i32, candidate: i32 },
+    /// Different binning
+    Binning {
+        light: (usize, usize),
+        candidate: (usize, usize),
+    },
+    /// Exposure time outside tolerance
+    ExposureTime { light: f32, candidate: f32 },
+    /// Sensor temperature outside tolerance
+    Temperature { light: f32, candidate: f32 },
+    /// Different readout mode
+    ReadoutMode { light: String, candidate: String },
+    /// Different filter
+    Filter { light: String, candidate: String },
+    /// Rotator angle outside tolerance
+    RotatorAngle { light: f32, candidate: f32 },
+    /// Focuser position outside tolerance
+    FocuserPosition { light: i32, candidate: i32 },
+    /// Flat from another session
+    Session { days: i64 },
+    /// Taken too long before or after the light
+    Age { days: f64 },
+}
+
+/// A calibration frame that can calibrate a light frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationMatch {
+    /// Path of the calibration frame
+    pub path: PathBuf,
+    /// Kind of the frame as read from its header
+    pub frame_kind: FrameKind,
+    /// Closeness between 0 and 1 (1 = identical soft criteria)
+    pub score: f32,
+}
+
+/// A calibration frame that was rejected, with all reasons
+#[derive(Debug, Clone, PartialEq)]
+pub struct RejectedCandidate {
+    /// Path of the calibration frame
+    pub path: PathBuf,
+    /// Kind of calibration
+    pub kind: CalibrationKind,
+    /// Every failed criterion
+    pub reasons: Vec<Rejection>,
+}
+
+/// Calibration frames matched to one light frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightCalibration {
+    /// Path of the light frame
+    pub light: PathBuf,
+    /// Matching darks, best first
+    pub darks: Vec<CalibrationMatch>,
+    /// Matching flats, best first
+    pub flats: Vec<CalibrationMatch>,
+    /// Matching bias frames, best first
+    pub biases: Vec<CalibrationMatch>,
+    /// Candidates that did not match
+    pub rejected: Vec<RejectedCandidate>,
+}
+
+/// Calibration frames matched to every light frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CalibrationPlan {
+    /// One entry per light frame, in input order
+    pub lights: Vec<LightCalibration>,
+}
+
+/// Matches light frames to calibration frames
+#[derive(Debug, Clone, Default)]
+pub struct CalibrationMatcher {
+    config: MatchConfig,
+}
+
+impl Default for MatchTolerances {
+    fn default() -> Self {
+        MatchTolerances {
+            exposure_fraction: 0.01,
+            temperature: 2.0,
+            rotator_angle: 1.0,
+            focuser_steps: 200,
+            max_age_days: Some(365.0),
+            flat_session_days: 0,
+        }
+    }
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            temperature: 2.0,
+            exposure: 1.0,
+            rotator: 1.0,
+            focuser: 1.0,
+            age: 1.0,
+        }
+    }
+}
+
+impl CalibrationKind {
+    /// Calibration kind of a frame kind, `None` for lights and unknown frames
+    pub fn from_frame_kind(kind: FrameKind) -> Option<Self> {
+        match kind.base() {
+            FrameKind::Dark => Some(CalibrationKind::Dark),
+            FrameKind::Flat => Some(CalibrationKind::Flat),
+            FrameKind::Bias => Some(CalibrationKind::Bias),
+            _ => None,
+        }
+    }
+}
+
+impl CalibrationMatcher {
+    /// Create a matcher with tolerances and weights
+    pub fn new(config: MatchConfig) -> Self {
+        CalibrationMatcher { config }
+    }
+
+    /// The active configuration
+    pub fn config(&self) -> &MatchConfig {
+        &self.config
+    }
+
+    /// Match every light frame to the calibration frames in the same set
+    ///
+    /// Frames are sorted by `Exposure::frame_kind`; lights and master lights
+    /// are matched, dark flats and unknown frames are ignored.
+    pub fn plan(&self, frames: &[(PathBuf, AstroMetadata)]) -> CalibrationPlan {
+        let candidates: Vec<_> = frames
+            .iter()
+            .filter_map(|(path, metadata)| {
+                CalibrationKind::from_frame_kind(metadata.exposure.frame_kind())
+                    .map(|kind| (kind, path, metadata))
+            })
+            .collect();
+
+        let lights = frames
+            .iter()
+            .filter(|(_, metadata)| metadata.exposure.frame_kind().base() == FrameKind::Light)
+            .map(|(path, light)| {
+                let mut calibration = LightCalibration {
+                    light: path.clone(),
+                    darks: Vec::new(),
+                    flats: Vec::new(),
+                    biases: Vec::new(),
+                    rejected: Vec::new(),
+                };
+
+                for &(kind, candidate_path, candidate) in &candidates {
+                    match self.evaluate(light, kind, candidate) {
+                        Ok(score) => {
+                            let matched = CalibrationMatch {
+                                path: candidate_path.clone(),
+                                frame_kind: candidate.exposure.frame_kind(),
+                                score,
+                            };
+                            match kind {
+                                CalibrationKind::Dark => calibration.darks.push(matched),
+                                CalibrationKind::Flat => calibration.flats.push(matched),
+                                CalibrationKind::Bias => calibration.biases.push(matched),
+                            }
+                        }
+                        Err(reasons) => calibration.rejected.push(RejectedCandidate {
+                            path: candidate_path.clone(),
+                            kind,
+                            reasons,
+                        }),
+                    }
+                }
+
+                for matches in [
+                    &mut calibration.darks,
+                    &mut calibration.flats,
+                    &mut calibration.biases,
+                ] {
+                    // Best first; masters before individual frames on a tie
+                    matches.sort_by(|a, b| {
+                        b.score
+                            .total_cmp(&a.score)
+                            .then(b.frame_kind.is_master().cmp(&a.frame_kind.is_master()))
+                    });
+                }
+                calibration
+            })
+            .collect();
+
+        CalibrationPlan { lights }
+    }
+
+    /// Score a candidate for a light frame, or list why it does not match
+    pub fn evaluate(
+        &self,
+        light: &AstroMetadata,
+        kind: CalibrationKind,
+        candidate: &AstroMetadata,
+    ) -> Result<f32, Vec<Rejection>> {
+        let tolerances = &self.config.tolerances;
+        let weights = &self.config.weights;
+        let (ld, cd) = (&light.detector, &candidate.detector);
+        let mut reasons = Vec::new();
+        let mut penalties: Vec<(f32, f32)> = Vec::new();
+
+        if let (Some(l), Some(c)) = (ld.camera_name.as_deref(), cd.camera_name.as_deref()) {
+            if !l.trim().eq_ignore_ascii_case(c.trim()) {
+                reasons.push(Rejection::Camera {
+                    light: l.to_string(),
+                    candidate: c.to_string(),
+                });
+            }
+        }
+        if (ld.binning_x, ld.binning_y) != (cd.binning_x, cd.binning_y) {
+            reasons.push(Rejection::Binning {
+                light: (ld.binning_x, ld.binning_y),
+                candidate: (cd.binning_x, cd.binning_y),
+            });
+        }
+
+        if matches!(kind, CalibrationKind::Dark | CalibrationKind::Bias) {
+            if let (Some(l), Some(c)) = (ld.gain_setting, cd.gain_setting) {
+                if l != c {
+                    reasons.push(Rejection::Gain {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+            }
+            if let (Some(l), Some(c)) = (ld.offset, cd.offset) {
+                if l != c {
+                    reasons.push(Rejection::Offset {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+            }
+            if let Some(days) = days_between(light.exposure.date_obs, candidate.exposure.date_obs) {
+                if let Some(max_age) = tolerances.max_age_days {
+                    if days > max_age {
+                        reasons.push(Rejection::Age { days });
+                    }
+                    penalties.push((weights.age, (days / max_age.max(1.0)) as f32));
+                }
+            }
+        }
+
+        if kind == CalibrationKind::Dark {
+            if let (Some(l), Some(c)) = (
+                light.exposure.exposure_time,
+                candidate.exposure.exposure_time,
+            ) {
+                let fraction = (l - c).abs() / l.abs().max(f32::EPSILON);
+                if fraction > tolerances.exposure_fraction {
+                    reasons.push(Rejection::ExposureTime {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+                penalties.push((
+                    weights.exposure,
+                    ratio(fraction, tolerances.exposure_fraction),
+                ));
+            }
+            if let (Some(l), Some(c)) = (ld.temperature, cd.temperature) {
+                let difference = (l - c).abs();
+                if difference > tolerances.temperature {
+                    reasons.push(Rejection::Temperature {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+                penalties.push((
+                    weights.temperature,
+                    ratio(difference, tolerances.temperature),
+                ));
+            }
+            if let (Some(l), Some(c)) = (ld.readout_mode.as_deref(), cd.readout_mode.as_deref()) {
+                if !l.trim().eq_ignore_ascii_case(c.trim()) {
+                    reasons.push(Rejection::ReadoutMode {
+                        light: l.to_string(),
+                        candidate: c.to_string(),
+                    });
+                }
+            }
+        }
+
+        if kind == CalibrationKind::Flat {
+            if let (Some(l), Some(c)) = (filter_key(light), filter_key(candidate)) {
+                if !l.eq_ignore_ascii_case(c) {
+                    reasons.push(Rejection::Filter {
+                        light: l.to_string(),
+                        candidate: c.to_string(),
+                    });
+                }
+            }
+            if let (Some(l), Some(c)) = (ld.rotator_angle, cd.rotator_angle) {
+                let difference = angle_difference(l, c);
+                if difference > tolerances.rotator_angle {
+                    reasons.push(Rejection::RotatorAngle {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+                penalties.push((weights.rotator, ratio(difference, tolerances.rotator_angle)));
+            }
+            if let (Some(l), Some(c)) = (
+                light.equipment.focuser_position,
+                candidate.equipment.focuser_position,
+            ) {
+                let difference = (l - c).abs();
+                if difference > tolerances.focuser_steps {
+                    reasons.push(Rejection::FocuserPosition {
+                        light: l,
+                        candidate: c,
+                    });
+                }
+                penalties.push((
+                    weights.focuser,
+                    ratio(difference as f32, tolerances.focuser_steps as f32),
+                ));
+            }
+            if let (Some(l), Some(c)) = (
+                light.exposure.session_local_date,
+                candidate.exposure.session_local_date,
+            ) {
+                let days = (l - c).num_days().abs();
+                if days > tolerances.flat_session_days {
+                    reasons.push(Rejection::Session { days });
+                }
+                penalties.push((
+                    weights.age,
+                    ratio(days as f32, tolerances.flat_session_days.max(1) as f32),
+                ));
+            }
+        }
+
+        if !reasons.is_empty() {
+            return Err(reasons);
+        }
+
+        let total: f32 = penalties.iter().map(|(weight, _)| weight).sum();
+        if total <= 0.0 {
+            return Ok(1.0);
+        }
+        let penalty: f32 = penalties
+            .iter()
+            .map(|(weight, penalty)| weight * penalty.min(1.0))
+            .sum();
+        Ok(1.0 - penalty / total)
+    }
+}
+
+impl LightCalibration {
+    /// Best matching frame of a kind
+    pub fn best(&self, kind: CalibrationKind) -> Option<&CalibrationMatch> {
+        match kind {
+            CalibrationKind::Dark => self.darks.first(),
+            CalibrationKind::Flat => self.flats.first(),
+            CalibrationKind::Bias => self.biases.first(),
+        }
+    }
+
+    /// Calibration kinds without any match
+    pub fn missing(&self) -> Vec<CalibrationKind> {
+        [
+            CalibrationKind::Dark,
+            CalibrationKind::Flat,
+            CalibrationKind::Bias,
+        ]
+        .into_iter()
+        .filter(|kind| self.best(*kind).is_none())
+        .collect()
+    }
+}
+
+impl CalibrationPlan {
+    /// Light frames lacking at least one calibration kind
+    pub fn incomplete(&self) -> impl Iterator<Item = &LightCalibration> {
+        self.lights
+            .iter()
+            .filter(|light| !light.missing().is_empty())
+    }
+}
+
+impl fmt::Display for CalibrationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CalibrationKind::Dark => "dark",
+            CalibrationKind::Flat => "flat",
+            CalibrationKind::Bias => "bias",
+        })
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Camera { light, candidate } => {
+                write!(f, "camera {candidate} differs from {light}")
+            }
+            Rejection::Gain { light, candidate } => {
+                write!(f, "gain {candidate} differs from {light}")
+            }
+            Rejection::Offset { light, candidate } => {
+                write!(f, "offset {candidate} differs from {light}")
+            }
+            Rejection::Binning { light, candidate } => write!(
+                f,
+                "binning {}x{} differs from {}x{}",
+                candidate.0, candidate.1, light.0, light.1
+            ),
+            Rejection::ExposureTime { light, candidate } => {
+                write!(f, "exposure {candidate} s differs from {light} s")
+            }
+            Rejection::Temperature { light, candidate } => {
+                write!(f, "sensor at {candidate:.1} °C, light at {light:.1} °C")
+            }
+            Rejection::ReadoutMode { light, candidate } => {
+                write!(f, "readout mode {candidate} differs from {light}")
+            }
+            Rejection::Filter { light, candidate } => {
+                write!(f, "filter {candidate} differs from {light}")
+            }
+            Rejection::RotatorAngle { light, candidate } => {
+                write!(f, "rotator at {candidate:.1}°, light at {light:.1}°")
+            }
+            Rejection::FocuserPosition { light, candidate } => {
+                write!(f, "focuser at {candidate}, light at {light}")
+            }
+            Rejection::Session { days } => write!(f, "taken {days} session(s) apart"),
+            Rejection::Age { days } => write!(f, "taken {days:.0} days apart"),
+        }
+    }
+}
+
+impl fmt::Display for CalibrationPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for light in &self.lights {
+            writeln!(f, "{}", light.light.display())?;
+            for (kind, matches) in [
+                (CalibrationKind::Dark, &light.darks),
+                (CalibrationKind::Flat, &light.flats),
+                (CalibrationKind::Bias, &light.biases),
+            ] {
+                match matches.first() {
+                    Some(best) => writeln!(
+                        f,
+                        "  {kind}: {} match(es), best {} (score {:.2})",
+                        matches.len(),
+                        best.path.display(),
+                        best.score
+                    )?,
+                    None => writeln!(f, "  {kind}: none")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Canonical band, falling back to the raw filter name
+fn filter_key(metadata: &AstroMetadata) -> Option<&str> {
+    metadata
+        .filter
+        .band
+        .as_deref()
+        .or(metadata.filter.name.as_deref())
+}
+
+/// Absolute difference between two angles in degrees, within 0..=180
+fn angle_difference(a: f32, b: f32) -> f32 {
+    let difference = (a - b).rem_euclid(360.0);
+    difference.min(360.0 - difference)
+}
+
+/// Days between two timestamps
+fn days_between(a: Option<DateTime<Utc>>, b: Option<DateTime<Utc>>) -> Option<f64> {
+    let (a, b) = (a?, b?);
+    Some((a - b).num_seconds().abs() as f64 / 86_400.0)
+}
+
+/// Difference as a fraction of its tolerance
+fn ratio(difference: f32, tolerance: f32) -> f32 {
+    if tolerance > 0.0 {
+        difference / tolerance
+    } else if difference > 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn frame(kind: &str, exposure: f32, temperature: f32) -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.frame_type = Some(kind.to_string());
+        metadata.exposure.exposure_time = Some(exposure);
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(2024, 9, 4, 1, 0, 0).unwrap());
+        metadata.exposure.session_local_date = NaiveDate::from_ymd_opt(2024, 9, 3);
+        metadata.detector.camera_name = Some("ZWO ASI2600MM Pro".to_string());
+        metadata.detector.gain_setting = Some(100.0);
+        metadata.detector.offset = Some(50);
+        metadata.detector.binning_x = 1;
+        metadata.detector.binning_y = 1;
+        metadata.detector.temperature = Some(temperature);
+        metadata.filter.name = Some("Ha 3nm".to_string());
+        metadata.filter.band = Some("Ha".to_string());
+        metadata
+    }
+
+    #[test]
+    fn test_plan_matches_and_explains_rejections() {
+        let mut wrong_gain = frame("Dark", 300.0, -10.0);
+        wrong_gain.detector.gain_setting = Some(0.0);
+        let mut other_filter = frame("Flat", 2.0, -10.0);
+        other_filter.filter.band = Some("OIII".to_string());
+        let mut old_flat = frame("Flat", 2.0, -10.0);
+        old_flat.exposure.session_local_date = NaiveDate::from_ymd_opt(2024, 8, 1);
+
+        let frames = vec![
+            (PathBuf::from("light.fits"), frame("Light", 300.0, -10.0)),
+            (PathBuf::from("dark_warm.fits"), frame("Dark", 300.0, -8.5)),
+            (
+                PathBuf::from("master_dark.xisf"),
+                frame("Master Dark", 300.0, -10.0),
+            ),
+            (
+                PathBuf::from("dark_short.fits"),
+                frame("Dark", 120.0, -10.0),
+            ),
+            (PathBuf::from("dark_gain0.fits"), wrong_gain),
+            (PathBuf::from("flat.fits"), frame("Flat", 2.0, -10.0)),
+            (PathBuf::from("flat_oiii.fits"), other_filter),
+            (PathBuf::from("flat_old.fits"), old_flat),
+        ];
+
+        let plan = CalibrationMatcher::default().plan(&frames);
+        assert_eq!(plan.lights.len(), 1);
+        let light = &plan.lights[0];
+
+        let darks: Vec<_> = light
+            .darks
+            .iter()
+            .map(|m| m.path.to_str().unwrap())
+            .collect();
+        assert_eq!(darks, ["master_dark.xisf", "dark_warm.fits"]);
+        assert_eq!(light.darks[0].score, 1.0);
+        assert!(light.darks[1].score < 1.0);
+        assert_eq!(light.flats.len(), 1);
+        assert_eq!(light.missing(), [CalibrationKind::Bias]);
+        assert_eq!(plan.incomplete().count(), 1);
+
+        let reasons = |name: &str| {
+            light
+                .rejected
+                .iter()
+                .find(|rejected| rejected.path.as_path() == std::path::Path::new(name))
+                .map(|rejected| rejected.reasons.clone())
+                .unwrap()
+        };
+        assert!(matches!(
+            reasons("dark_short.fits")[..],
+            [Rejection::ExposureTime { .. }]
+        ));
+        assert!(matches!(
+            reasons("dark_gain0.fits")[..],
+            [Rejection::Gain { .. }]
+        ));
+        assert!(matches!(
+            reasons("flat_oiii.fits")[..],
+            [Rejection::Filter { .. }]
+        ));
+        assert_eq!(reasons("flat_old.fits"), [Rejection::Session { days: 33 }]);
+        assert_eq!(
+            Rejection::Gain {
+                light: 100.0,
+                candidate: 0.0
+            }
+            .to_string(),
+            "gain 0 differs from 100"
+        );
+    }
+
+    #[test]
+    fn test_tolerances_are_configurable() {
+        let light = frame("Light", 300.0, -10.0);
+        let bias = frame("Bias", 0.0, -5.0);
+        let dark = frame("Dark", 300.0, -6.0);
+        let mut flat = frame("Flat", 2.0, -10.0);
+        flat.detector.rotator_angle = Some(359.5);
+        let mut rotated_light = light.clone();
+        rotated_light.detector.rotator_angle = Some(0.2);
+
+        let matcher = CalibrationMatcher::default();
+        assert_eq!(
+            matcher.evaluate(&light, CalibrationKind::Bias, &bias),
+            Ok(1.0)
+        );
+        assert!(matcher
+            .evaluate(&light, CalibrationKind::Dark, &dark)
+            .is_err());
+        assert!(matcher
+            .evaluate(&rotated_light, CalibrationKind::Flat, &flat)
+            .is_ok());
+
+        let loose = CalibrationMatcher::new(MatchConfig {
+            tolerances: MatchTolerances {
+                temperature: 5.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let score = loose
+            .evaluate(&light, CalibrationKind::Dark, &dark)
+            .unwrap();
+        assert!((0.0..1.0).contains(&score));
+    }
+}
@@ -0,0 +1,566 @@
+//! Capture software detection and per-program keyword profiles
+//!
+//! Capture programs disagree on header conventions: MaxIm DL writes
+//! `FOCUSPOS`/`FOCUSTEM` where NINA writes `FOCPOS`/`FOCTEMP`, SharpCap puts
+//! the camera offset in `BLKLEVEL`, and so on.
+//! This module detects the originating program from `SWCREATE`, `CREATOR` or
+//! the XISF creator property and adjusts the keyword registry accordingly.
+//!
+//! A profile also pins the keywords its program is known to write ahead of
+//! any alias a caller registered, so an alias added for one rig's files does
+//! not change how NINA or ASIAIR frames are read.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::keywords::{
+    get_header_value, AliasPrecedence, KeywordAlias, KeywordRegistry, MetadataField,
+};
+use super::types::AstroMetadata;
+
+/// Known capture software vendors
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum CaptureSoftware {
+    /// Nighttime Imaging 'N' Astronomy
+    Nina,
+    /// Sequence Generator Pro
+    SequenceGeneratorPro,
+    /// KStars/Ekos
+    Ekos,
+    /// ZWO ASIAIR
+    Asiair,
+    /// Diffraction Limited MaxIm DL
+    MaximDl,
+    /// SharpCap
+    SharpCap,
+    /// Astro Photography Tool
+    Apt,
+    /// Voyager
+    Voyager,
+    /// Any other program, identified by the raw creator string
+    Other(String),
+}
+
+/// The program that created an image, as detected from its headers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceSoftware {
+    /// Detected vendor
+    pub vendor: CaptureSoftware,
+    /// Raw creator string the vendor was detected from
+    pub name: String,
+    /// Version string, if one could be extracted
+    pub version: Option<String>,
+}
+
+/// A single keyword registry adjustment applied by a capture profile
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileRule {
+    /// Check this keyword before the built-in aliases for a field
+    Prefer(MetadataField, &'static str),
+    /// Check this keyword after the built-in aliases for a field
+    Fallback(MetadataField, &'static str),
+    /// Never read a field from this keyword
+    Ignore(MetadataField, &'static str),
+}
+
+impl CaptureSoftware {
+    /// Identify the vendor from a creator string such as `SWCREATE`
+    ///
+    /// INDI drivers are shared by many clients, so an INDI creator alone does
+    /// not identify Ekos.
+    pub fn from_creator(creator: &str) -> Self {
+        let lower = creator.to_ascii_lowercase();
+        let has_word = |word: &str| {
+            lower
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .any(|token| token == word)
+        };
+
+        if lower.contains("n.i.n.a") || has_word("nina") {
+            CaptureSoftware::Nina
+        } else if lower.contains("sequence generator") || has_word("sgpro") || has_word("sgp") {
+            CaptureSoftware::SequenceGeneratorPro
+        } else if has_word("ekos") || has_word("kstars") {
+            CaptureSoftware::Ekos
+        } else if lower.contains("asiair") {
+            CaptureSoftware::Asiair
+        } else if lower.contains("maxim") {
+            CaptureSoftware::MaximDl
+        } else if lower.contains("sharpcap") {
+            CaptureSoftware::SharpCap
+        } else if lower.contains("astro photography tool") || has_word("apt") {
+            CaptureSoftware::Apt
+        } else if has_word("voyager") {
+            CaptureSoftware::Voyager
+        } else {
+            CaptureSoftware::Other(creator.trim().to_string())
+        }
+    }
+
+    /// Keyword registry adjustments for this program
+    pub fn profile_rules(&self) -> Vec<ProfileRule> {
+        use MetadataField::*;
+        use ProfileRule::*;
+
+        match self {
+            // GAIN is the camera setting and EGAIN the conversion gain; local
+            // time is in DATE-LOC. Focuser keywords are written twice, in the
+            // NINA and MaxIm spellings.
+            CaptureSoftware::Nina => vec![
+                Prefer(GainSetting, "GAIN"),
+                Prefer(ElectronsPerAdu, "EGAIN"),
+                Prefer(Offset, "OFFSET"),
+                Prefer(FocuserPosition, "FOCPOS"),
+                Prefer(FocuserTemperature, "FOCTEMP"),
+                Fallback(FocuserTemperature, "FOCUSTEM"),
+                Prefer(DateLoc, "DATE-LOC"),
+            ],
+            // As NINA, with the cooler setpoint in SET-TEMP; the MaxIm
+            // focuser spellings are still accepted after SGP's own
+            CaptureSoftware::SequenceGeneratorPro => vec![
+                Prefer(GainSetting, "GAIN"),
+                Prefer(ElectronsPerAdu, "EGAIN"),
+                Prefer(FocuserPosition, "FOCPOS"),
+                Fallback(FocuserPosition, "FOCUSPOS"),
+                Prefer(FocuserTemperature, "FOCTEMP"),
+                Fallback(FocuserTemperature, "FOCUSTEM"),
+                Prefer(SensorTemperature, "CCD-TEMP"),
+                Prefer(TemperatureSetpoint, "SET-TEMP"),
+                Prefer(DateLoc, "DATE-LOC"),
+            ],
+            // GAIN is the camera setting and there is no EGAIN, so the
+            // conversion gain comes from the sensor table. DATE-OBS is UTC
+            // and no local time is written, so the session date comes from
+            // the site. The offset is OFFSET, with BLKLEVEL accepted too.
+            CaptureSoftware::Asiair => vec![
+                Prefer(GainSetting, "GAIN"),
+                Prefer(Offset, "OFFSET"),
+                Fallback(Offset, "BLKLEVEL"),
+                Prefer(DateObs, "DATE-OBS"),
+                Fallback(FocuserPosition, "FOCUSPOS"),
+            ],
+            CaptureSoftware::Apt => vec![Fallback(Offset, "BLKLEVEL")],
+            CaptureSoftware::Ekos => vec![
+                Prefer(FocuserPosition, "FOCUSPOS"),
+                Prefer(FocuserTemperature, "FOCUSTEM"),
+            ],
+            CaptureSoftware::MaximDl => vec![
+                Prefer(FocuserPosition, "FOCUSPOS"),
+                Prefer(FocuserTemperature, "FOCUSTEM"),
+                Fallback(Offset, "BLKLEVEL"),
+            ],
+            CaptureSoftware::SharpCap => vec![Prefer(Offset, "BLKLEVEL")],
+            CaptureSoftware::Voyager => vec![
+                Fallback(FocuserPosition, "FOCUSPOS"),
+                Fallback(FocuserTemperature, "FOCUSTEM"),
+            ],
+            CaptureSoftware::Other(_) => Vec::new(),
+        }
+    }
+
+    /// Return a registry with this program's profile rules applied
+    pub fn customize_registry<'a>(
+        &self,
+        registry: &'a KeywordRegistry,
+    ) -> Cow<'a, KeywordRegistry> {
+        let rules = self.profile_rules();
+        if rules.is_empty() {
+            return Cow::Borrowed(registry);
+        }
+
+        let mut customized = registry.clone();
+        for rule in rules {
+            match rule {
+                ProfileRule::Prefer(field, keyword) => customized.register_alias(
+                    field,
+                    KeywordAlias::new(keyword),
+                    AliasPrecedence::Highest,
+                ),
+                ProfileRule::Fallback(field, keyword) => customized.register_alias(
+                    field,
+                    KeywordAlias::new(keyword),
+                    AliasPrecedence::Lowest,
+                ),
+                ProfileRule::Ignore(field, keyword) => {
+                    customized.remove_alias(field, keyword);
+                }
+            }
+        }
+
+        Cow::Owned(customized)
+    }
+}
+
+/// Detect the originating software from FITS keywords and an optional XISF creator
+///
+/// Dedicated version keywords (`NINA-VERSION`, `EKOS-VERSION`) win, then
+/// `SWCREATE`, `CREATOR` and `PROGRAM`, then the XISF creator application.
+/// FITS keywords come first because an XISF written by a processing tool
+/// still carries the capture program's `SWCREATE`.
+pub fn detect_source_software(
+    headers: &HashMap<String, String>,
+    xisf_creator: Option<&str>,
+) -> Option<SourceSoftware> {
+    if let Some(version) = get_header_value(headers, "NINA-VERSION").filter(|v| !v.is_empty()) {
+        return Some(SourceSoftware {
+            vendor: CaptureSoftware::Nina,
+            name: "N.I.N.A.".to_string(),
+            version: Some(version.to_string()),
+        });
+    }
+
+    if let Some(version) = get_header_value(headers, "EKOS-VERSION").filter(|v| !v.is_empty()) {
+        return Some(SourceSoftware {
+            vendor: CaptureSoftware::Ekos,
+            name: "Ekos".to_string(),
+            version: Some(version.to_string()),
+        });
+    }
+
+    ["SWCREATE", "CREATOR", "PROGRAM"]
+        .iter()
+        .filter_map(|keyword| get_header_value(headers, keyword))
+        .chain(xisf_creator)
+        .map(str::trim)
+        .find(|creator| !creator.is_empty())
+        .map(|creator| SourceSoftware {
+            vendor: CaptureSoftware::from_creator(creator),
+            name: creator.to_string(),
+            version: extract_version(creator),
+        })
+}
+
+/// Detect the source software and populate metadata with its keyword profile
+///
+/// This is the shared entry point used by the FITS and XISF parsers.
+pub fn apply_headers(
+    registry: &KeywordRegistry,
+    headers: &HashMap<String, String>,
+    xisf_creator: Option<&str>,
+    metadata: &mut AstroMetadata,
+) {
+    let source_software = detect_source_software(headers, xisf_creator);
+
+    match &source_software {
+        Some(software) => software
+            .vendor
+            .customize_registry(registry)
+            .apply(headers, metadata),
+        None => registry.apply(headers, metadata),
+    }
+
+    metadata.source_software = source_software;
+}
+
+/// Pull a version number out of a creator string
+///
+/// Handles forms like `N.I.N.A. 3.0.0.2017 (x64)`, `Sequence Generator Pro v4.1.0`
+/// and `MaxIm DL Version 6.24 200613 01A0K`.
+fn extract_version(creator: &str) -> Option<String> {
+    let candidates: Vec<&str> = creator
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '(' || c == ')')
+        .map(|token| {
+            token
+                .trim_start_matches(['v', 'V'])
+                .trim_end_matches(['.', ',', ';'])
+        })
+        .filter(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+        .collect();
+
+    candidates
+        .iter()
+        .find(|token| token.contains('.'))
+        .or_else(|| candidates.first())
+        .map(|token| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sensor_db::SensorDatabase;
+    use chrono::NaiveDateTime;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn parse(pairs: &[(&str, &str)]) -> AstroMetadata {
+        parse_with(KeywordRegistry::builtin(), pairs)
+    }
+
+    /// Apply the headers and fill the detector from the sensor table, as the
+    /// parsers do
+    fn parse_with(registry: &KeywordRegistry, pairs: &[(&str, &str)]) -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        apply_headers(registry, &headers(pairs), None, &mut metadata);
+        SensorDatabase::builtin().fill_detector(&mut metadata.detector);
+        metadata
+    }
+
+    fn local(value: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").expect("local time"))
+    }
+
+    const NINA_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "N.I.N.A. 3.0.0.2017 (x64)"),
+        ("INSTRUME", "ZWO ASI2600MM Pro"),
+        ("GAIN", "100"),
+        ("EGAIN", "0.25"),
+        ("OFFSET", "50"),
+        ("FOCPOS", "31200"),
+        ("FOCUSPOS", "31200"),
+        ("FOCTEMP", "8.4"),
+        ("FOCUSTEM", "8.4"),
+        ("DATE-LOC", "2024-09-04T01:39:13.204"),
+    ];
+
+    const SGP_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "Sequence Generator Pro v4.1.0.1050"),
+        ("GAIN", "139"),
+        ("EGAIN", "1.00"),
+        ("OFFSET", "21"),
+        ("FOCPOS", "7800"),
+        ("FOCUSTEM", "4.5"),
+        ("CCD-TEMP", "-9.8"),
+        ("SET-TEMP", "-10.0"),
+        ("DATE-LOC", "2024-03-10T22:05:00"),
+    ];
+
+    const EKOS_HEADERS: &[(&str, &str)] = &[
+        ("EKOS-VERSION", "3.6.7"),
+        ("GAIN", "120"),
+        ("OFFSET", "30"),
+        ("FOCUSPOS", "45000"),
+        ("FOCPOS", "1"),
+        ("FOCUSTEM", "5.5"),
+    ];
+
+    const ASIAIR_HEADERS: &[(&str, &str)] = &[
+        ("CREATOR", "ZWO ASIAIR"),
+        ("INSTRUME", "ZWO ASI2600MC Pro"),
+        ("GAIN", "100"),
+        ("OFFSET", "50"),
+        ("FOCUSPOS", "2310"),
+        ("DATE-OBS", "2024-09-04T07:39:13.204000"),
+    ];
+
+    const MAXIM_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "MaxIm DL Version 6.24 200613 01A0K"),
+        ("EGAIN", "1.42"),
+        ("BLKLEVEL", "1024"),
+        ("FOCUSPOS", "12000"),
+        ("FOCUSTEM", "2.25"),
+    ];
+
+    const SHARPCAP_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "SharpCap v4.1.11234.0, 64 bit"),
+        ("GAIN", "200"),
+        ("BLKLEVEL", "40"),
+        ("OFFSET", "10"),
+    ];
+
+    const APT_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "APT - Astro Photography Tool 4.10"),
+        ("GAIN", "76"),
+        ("EGAIN", "0.8"),
+        ("BLKLEVEL", "25"),
+    ];
+
+    const VOYAGER_HEADERS: &[(&str, &str)] = &[
+        ("SWCREATE", "Voyager 2.3.12"),
+        ("GAIN", "100"),
+        ("EGAIN", "0.21"),
+        ("FOCUSPOS", "22000"),
+    ];
+
+    #[test]
+    fn test_nina_profile() {
+        let metadata = parse(NINA_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::Nina);
+        assert_eq!(software.version.as_deref(), Some("3.0.0.2017"));
+        assert_eq!(metadata.detector.gain_setting, Some(100.0));
+        assert_eq!(metadata.detector.electrons_per_adu, Some(0.25));
+        assert_eq!(metadata.detector.offset, Some(50));
+        assert_eq!(metadata.equipment.focuser_position, Some(31200));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(8.4));
+        assert_eq!(metadata.exposure.date_loc, local("2024-09-04T01:39:13.204"));
+    }
+
+    #[test]
+    fn test_sgp_profile() {
+        let metadata = parse(SGP_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::SequenceGeneratorPro);
+        assert_eq!(software.version.as_deref(), Some("4.1.0.1050"));
+        assert_eq!(metadata.detector.gain_setting, Some(139.0));
+        assert_eq!(metadata.detector.electrons_per_adu, Some(1.0));
+        assert_eq!(metadata.detector.offset, Some(21));
+        assert_eq!(metadata.equipment.focuser_position, Some(7800));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(4.5));
+        assert_eq!(metadata.detector.temperature, Some(-9.8));
+        assert_eq!(metadata.detector.temp_setpoint, Some(-10.0));
+        assert_eq!(metadata.exposure.date_loc, local("2024-03-10T22:05:00"));
+    }
+
+    #[test]
+    fn test_ekos_profile() {
+        let metadata = parse(EKOS_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::Ekos);
+        assert_eq!(software.version.as_deref(), Some("3.6.7"));
+        assert_eq!(metadata.detector.gain_setting, Some(120.0));
+        assert_eq!(metadata.detector.electrons_per_adu, None);
+        assert_eq!(metadata.detector.offset, Some(30));
+        assert_eq!(metadata.equipment.focuser_position, Some(45000));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(5.5));
+        assert_eq!(metadata.exposure.date_loc, None);
+    }
+
+    #[test]
+    fn test_asiair_profile() {
+        let metadata = parse(ASIAIR_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::Asiair);
+        assert_eq!(software.version, None);
+        assert_eq!(metadata.detector.gain_setting, Some(100.0));
+        // No EGAIN, so the sensor table's value at gain 100
+        assert_eq!(metadata.detector.electrons_per_adu, Some(0.25));
+        assert_eq!(metadata.detector.offset, Some(50));
+        assert_eq!(metadata.equipment.focuser_position, Some(2310));
+        assert_eq!(metadata.exposure.date_loc, None);
+        assert_eq!(
+            metadata.exposure.date_obs.map(|date| date.to_rfc3339()),
+            Some("2024-09-04T07:39:13.204+00:00".to_string())
+        );
+
+        let metadata = parse(&[("CREATOR", "ZWO ASIAIR"), ("GAIN", "0"), ("BLKLEVEL", "12")]);
+        assert_eq!(metadata.detector.offset, Some(12));
+    }
+
+    #[test]
+    fn test_maxim_profile() {
+        let metadata = parse(MAXIM_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::MaximDl);
+        assert_eq!(software.version.as_deref(), Some("6.24"));
+        assert_eq!(metadata.detector.gain_setting, None);
+        assert_eq!(metadata.detector.electrons_per_adu, Some(1.42));
+        assert_eq!(metadata.detector.offset, Some(1024));
+        assert_eq!(metadata.equipment.focuser_position, Some(12000));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(2.25));
+        assert_eq!(metadata.exposure.date_loc, None);
+    }
+
+    #[test]
+    fn test_sharpcap_profile() {
+        let metadata = parse(SHARPCAP_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::SharpCap);
+        assert_eq!(software.version.as_deref(), Some("4.1.11234.0"));
+        assert_eq!(metadata.detector.gain_setting, Some(200.0));
+        assert_eq!(metadata.detector.electrons_per_adu, None);
+        assert_eq!(metadata.detector.offset, Some(40));
+        assert_eq!(metadata.equipment.focuser_position, None);
+        assert_eq!(metadata.exposure.date_loc, None);
+    }
+
+    #[test]
+    fn test_apt_profile() {
+        let metadata = parse(APT_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::Apt);
+        assert_eq!(software.version.as_deref(), Some("4.10"));
+        assert_eq!(metadata.detector.gain_setting, Some(76.0));
+        assert_eq!(metadata.detector.electrons_per_adu, Some(0.8));
+        assert_eq!(metadata.detector.offset, Some(25));
+        assert_eq!(metadata.equipment.focuser_position, None);
+        assert_eq!(metadata.exposure.date_loc, None);
+    }
+
+    #[test]
+    fn test_voyager_profile() {
+        let metadata = parse(VOYAGER_HEADERS);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(software.vendor, CaptureSoftware::Voyager);
+        assert_eq!(software.version.as_deref(), Some("2.3.12"));
+        assert_eq!(metadata.detector.gain_setting, Some(100.0));
+        assert_eq!(metadata.detector.electrons_per_adu, Some(0.21));
+        assert_eq!(metadata.detector.offset, None);
+        assert_eq!(metadata.equipment.focuser_position, Some(22000));
+        assert_eq!(metadata.exposure.date_loc, None);
+    }
+
+    #[test]
+    fn test_profile_keywords_win_over_caller_aliases() {
+        let mut registry = KeywordRegistry::default();
+        registry.register_alias(
+            MetadataField::GainSetting,
+            KeywordAlias::new("CAMGAIN"),
+            AliasPrecedence::Highest,
+        );
+
+        let mut pairs = NINA_HEADERS.to_vec();
+        pairs.push(("CAMGAIN", "0"));
+        let metadata = parse_with(&registry, &pairs);
+        assert_eq!(metadata.detector.gain_setting, Some(100.0));
+
+        pairs[0] = ("SWCREATE", "HomeBrew Capture 1.2");
+        let metadata = parse_with(&registry, &pairs);
+        assert_eq!(metadata.detector.gain_setting, Some(0.0));
+    }
+
+    #[test]
+    fn test_unknown_software_uses_builtin_registry() {
+        let metadata = parse(&[("SWCREATE", "HomeBrew Capture 1.2"), ("GAIN", "1.3")]);
+        let software = metadata.source_software.expect("software detected");
+
+        assert_eq!(
+            software.vendor,
+            CaptureSoftware::Other("HomeBrew Capture 1.2".to_string())
+        );
+        assert_eq!(software.version.as_deref(), Some("1.2"));
+        assert_eq!(metadata.detector.gain_setting, Some(1.3));
+    }
+
+    #[test]
+    fn test_indi_creator_is_not_ekos() {
+        assert_eq!(
+            CaptureSoftware::from_creator("KStars 3.6.7 (Ekos)"),
+            CaptureSoftware::Ekos
+        );
+        assert_eq!(
+            CaptureSoftware::from_creator("INDI CCD Simulator"),
+            CaptureSoftware::Other("INDI CCD Simulator".to_string())
+        );
+    }
+
+    #[test]
+    fn test_xisf_creator_is_lowest_priority() {
+        let fits_headers = headers(&[("SWCREATE", "N.I.N.A. 3.1.2.9001")]);
+        let software = detect_source_software(&fits_headers, Some("PixInsight 1.8.9-3"))
+            .expect("software detected");
+        assert_eq!(software.vendor, CaptureSoftware::Nina);
+
+        let software = detect_source_software(&HashMap::new(), Some("PixInsight 1.8.9-3"))
+            .expect("software detected");
+        assert_eq!(
+            software.vendor,
+            CaptureSoftware::Other("PixInsight 1.8.9-3".to_string())
+        );
+        assert_eq!(software.version.as_deref(), Some("1.8.9-3"));
+    }
+}
@@ -0,0 +1,529 @@
+//! Low-precision ephemeris for observing conditions
+//!
+//! Computes sidereal time, target altitude/azimuth and airmass, the Sun's
+//! altitude and twilight phase, and the Moon's position and phase for the
+//! middle of an exposure. The Sun and Moon use the short series from the
+//! Astronomical Almanac (about 0.01° and 0.3° respectively), which is plenty
+//! for correlating frame quality with observing conditions but not for
+//! astrometry.
+
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::types::AstroMetadata;
+
+/// Julian date of the J2000.0 epoch
+pub const J2000: f64 = 2_451_545.0;
+
+/// Julian date of the Unix epoch
+pub(crate) const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+
+/// Equatorial radius of the Earth in km
+const EARTH_RADIUS_KM: f64 = 6378.14;
+
+/// Altitude of the Sun's upper limb at sunrise/sunset, including refraction
+const SUNRISE_ALTITUDE: f64 = -0.833;
+
+/// Formula used to turn altitude into airmass
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AirmassFormula {
+    /// sec(z), the plane-parallel atmosphere
+    PlaneParallel,
+    /// Hardie (1962) polynomial in sec(z) - 1
+    Hardie,
+    /// Young & Irvine (1967)
+    YoungIrvine,
+    /// Rozenberg (1966), finite at the horizon
+    Rozenberg,
+    /// Kasten & Young (1989)
+    KastenYoung,
+    /// Pickering (2002), accurate down to the horizon
+    #[default]
+    Pickering,
+}
+
+/// Twilight phase from the Sun's altitude
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TwilightPhase {
+    /// Sun above the horizon
+    Day,
+    /// Sun between the horizon and -6°
+    Civil,
+    /// Sun between -6° and -12°
+    Nautical,
+    /// Sun between -12° and -18°
+    Astronomical,
+    /// Sun below -18°
+    Night,
+}
+
+/// Airmass of the target from each supported formula
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AirmassEstimates {
+    /// sec(z)
+    pub plane_parallel: f64,
+    /// Hardie (1962)
+    pub hardie: f64,
+    /// Young & Irvine (1967)
+    pub young_irvine: f64,
+    /// Rozenberg (1966)
+    pub rozenberg: f64,
+    /// Kasten & Young (1989)
+    pub kasten_young: f64,
+    /// Pickering (2002)
+    pub pickering: f64,
+}
+
+/// Observing conditions derived from time, site and target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DerivedMetadata {
+    /// Mid-exposure time the values refer to
+    pub mid_exposure: DateTime<Utc>,
+    /// Julian date of mid-exposure (UTC)
+    pub julian_date: f64,
+    /// Local mean sidereal time in hours
+    pub local_sidereal_time: f64,
+    /// Target hour angle in hours (-12 to +12, positive west of the meridian)
+    pub hour_angle: Option<f64>,
+    /// Target altitude in degrees
+    pub altitude: Option<f64>,
+    /// Target azimuth in degrees (north = 0, east = 90)
+    pub azimuth: Option<f64>,
+    /// Target airmass, absent when the target is below the horizon
+    pub airmass: Option<AirmassEstimates>,
+    /// Sun altitude in degrees
+    pub sun_altitude: f64,
+    /// Twilight phase at the site
+    pub twilight: TwilightPhase,
+    /// Fraction of the synodic month (0 = new, 0.5 = full)
+    pub moon_phase: f64,
+    /// Illuminated fraction of the Moon's disk (0-1)
+    pub moon_illumination: f64,
+    /// Topocentric Moon altitude in degrees
+    pub moon_altitude: f64,
+    /// Angular distance between the Moon and the target in degrees
+    pub moon_separation: Option<f64>,
+}
+
+/// Geocentric position of the Moon
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoonPosition {
+    /// Right ascension in degrees
+    pub ra: f64,
+    /// Declination in degrees
+    pub dec: f64,
+    /// Ecliptic longitude in degrees
+    pub longitude: f64,
+    /// Horizontal parallax in degrees
+    pub parallax: f64,
+}
+
+/// Geocentric position of the Sun
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    /// Right ascension in degrees
+    pub ra: f64,
+    /// Declination in degrees
+    pub dec: f64,
+    /// Ecliptic longitude in degrees
+    pub longitude: f64,
+    /// Distance in AU
+    pub distance: f64,
+}
+
+impl AirmassFormula {
+    /// Airmass at an altitude in degrees
+    ///
+    /// Returns `None` for targets at or below the horizon.
+    pub fn airmass(self, altitude: f64) -> Option<f64> {
+        if altitude <= 0.0 {
+            return None;
+        }
+
+        let z = 90.0 - altitude;
+        let cos_z = z.to_radians().cos();
+        let sec_z = 1.0 / cos_z;
+        let airmass = match self {
+            AirmassFormula::PlaneParallel => sec_z,
+            AirmassFormula::Hardie => {
+                let s = sec_z - 1.0;
+                sec_z - 0.0018167 * s - 0.002875 * s * s - 0.0008083 * s * s * s
+            }
+            AirmassFormula::YoungIrvine => sec_z * (1.0 - 0.0012 * (sec_z * sec_z - 1.0)),
+            AirmassFormula::Rozenberg => 1.0 / (cos_z + 0.025 * (-11.0 * cos_z).exp()),
+            AirmassFormula::KastenYoung => 1.0 / (cos_z + 0.50572 * (96.07995 - z).powf(-1.6364)),
+            AirmassFormula::Pickering => {
+                1.0 / (altitude + 244.0 / (165.0 + 47.0 * altitude.powf(1.1)))
+                    .to_radians()
+                    .sin()
+            }
+        };
+        Some(airmass)
+    }
+}
+
+impl AirmassEstimates {
+    /// Evaluate every formula at an altitude in degrees
+    pub fn at_altitude(altitude: f64) -> Option<Self> {
+        Some(AirmassEstimates {
+            plane_parallel: AirmassFormula::PlaneParallel.airmass(altitude)?,
+            hardie: AirmassFormula::Hardie.airmass(altitude)?,
+            young_irvine: AirmassFormula::YoungIrvine.airmass(altitude)?,
+            rozenberg: AirmassFormula::Rozenberg.airmass(altitude)?,
+            kasten_young: AirmassFormula::KastenYoung.airmass(altitude)?,
+            pickering: AirmassFormula::Pickering.airmass(altitude)?,
+        })
+    }
+
+    /// The estimate from one formula
+    pub fn get(&self, formula: AirmassFormula) -> f64 {
+        match formula {
+            AirmassFormula::PlaneParallel => self.plane_parallel,
+            AirmassFormula::Hardie => self.hardie,
+            AirmassFormula::YoungIrvine => self.young_irvine,
+            AirmassFormula::Rozenberg => self.rozenberg,
+            AirmassFormula::KastenYoung => self.kasten_young,
+            AirmassFormula::Pickering => self.pickering,
+        }
+    }
+}
+
+impl TwilightPhase {
+    /// Classify a Sun altitude in degrees
+    pub fn from_sun_altitude(altitude: f64) -> Self {
+        if altitude >= SUNRISE_ALTITUDE {
+            TwilightPhase::Day
+        } else if altitude >= -6.0 {
+            TwilightPhase::Civil
+        } else if altitude >= -12.0 {
+            TwilightPhase::Nautical
+        } else if altitude >= -18.0 {
+            TwilightPhase::Astronomical
+        } else {
+            TwilightPhase::Night
+        }
+    }
+}
+
+/// Compute observing conditions for a frame
+///
+/// Needs `date_obs` and the site latitude/longitude. Target values are
+/// filled when the frame has RA/DEC. Times refer to mid-exposure.
+pub fn derive(metadata: &AstroMetadata) -> Option<DerivedMetadata> {
+    let date_obs = metadata.exposure.date_obs?;
+    let mount = metadata.mount.as_ref()?;
+    let (latitude, longitude) = (mount.latitude?, mount.longitude?);
+    let height = mount.height.unwrap_or(0.0);
+
+    let mid_exposure = match &metadata.exposure.timing {
+        Some(timing) => timing.mid,
+        None => {
+            let half_exposure = metadata.exposure.exposure_time.unwrap_or(0.0) as f64 / 2.0;
+            date_obs + Duration::microseconds((half_exposure * 1e6).round() as i64)
+        }
+    };
+    let jd = julian_date(mid_exposure);
+    let lst = local_sidereal_time(jd, longitude);
+
+    let target = metadata.exposure.ra.zip(metadata.exposure.dec);
+    let horizontal_target = target.map(|(ra, dec)| horizontal(ra, dec, lst, latitude));
+
+    let sun = sun_position(jd);
+    let sun_altitude = horizontal(sun.ra, sun.dec, lst, latitude).0;
+
+    let moon = moon_position(jd);
+    let moon_altitude = topocentric_altitude(
+        horizontal(moon.ra, moon.dec, lst, latitude).0,
+        moon.parallax,
+        height,
+    );
+    let elongation = angular_separation(sun.ra, sun.dec, moon.ra, moon.dec);
+
+    Some(DerivedMetadata {
+        mid_exposure,
+        julian_date: jd,
+        local_sidereal_time: lst,
+        hour_angle: target.map(|(ra, _)| hour_angle(lst, ra)),
+        altitude: horizontal_target.map(|(altitude, _)| altitude),
+        azimuth: horizontal_target.map(|(_, azimuth)| azimuth),
+        airmass: horizontal_target
+            .and_then(|(altitude, _)| AirmassEstimates::at_altitude(altitude)),
+        sun_altitude,
+        twilight: TwilightPhase::from_sun_altitude(sun_altitude),
+        moon_phase: normalize_degrees(moon.longitude - sun.longitude) / 360.0,
+        moon_illumination: (1.0 - elongation.to_radians().cos()) / 2.0,
+        moon_altitude,
+        moon_separation: target.map(|(ra, dec)| angular_separation(ra, dec, moon.ra, moon.dec)),
+    })
+}
+
+/// Julian date of a UTC instant
+pub fn julian_date(time: DateTime<Utc>) -> f64 {
+    let seconds = time.timestamp() as f64 + time.timestamp_subsec_nanos() as f64 * 1e-9;
+    UNIX_EPOCH_JD + seconds / 86_400.0
+}
+
+/// Greenwich mean sidereal time in hours
+pub fn greenwich_mean_sidereal_time(jd: f64) -> f64 {
+    let d = jd - J2000;
+    let t = d / 36_525.0;
+    let degrees =
+        280.46061837 + 360.98564736629 * d + 0.000387933 * t * t - t * t * t / 38_710_000.0;
+    normalize_degrees(degrees) / 15.0
+}
+
+/// Local mean sidereal time in hours for an east-positive longitude
+pub fn local_sidereal_time(jd: f64, longitude: f64) -> f64 {
+    normalize_degrees(greenwich_mean_sidereal_time(jd) * 15.0 + longitude) / 15.0
+}
+
+/// Hour angle in hours (-12 to +12) from LST in hours and RA in degrees
+pub fn hour_angle(lst: f64, ra: f64) -> f64 {
+    let degrees = normalize_degrees(lst * 15.0 - ra);
+    if degrees > 180.0 {
+        (degrees - 360.0) / 15.0
+    } else {
+        degrees / 15.0
+    }
+}
+
+/// Altitude and azimuth in degrees of an RA/DEC position
+///
+/// Azimuth is measured from north through east.
+pub fn horizontal(ra: f64, dec: f64, lst: f64, latitude: f64) -> (f64, f64) {
+    let h = (lst * 15.0 - ra).to_radians();
+    let (dec, lat) = (dec.to_radians(), latitude.to_radians());
+
+    let altitude = (lat.sin() * dec.sin() + lat.cos() * dec.cos() * h.cos())
+        .clamp(-1.0, 1.0)
+        .asin();
+    let y = -dec.cos() * h.sin();
+    let x = dec.sin() * lat.cos() - dec.cos() * lat.sin() * h.cos();
+    (
+        altitude.to_degrees(),
+        normalize_degrees(y.atan2(x).to_degrees()),
+    )
+}
+
+/// Angular distance between two RA/DEC positions in degrees
+pub fn angular_separation(ra1: f64, dec1: f64, ra2: f64, dec2: f64) -> f64 {
+    let (dec1, dec2) = (dec1.to_radians(), dec2.to_radians());
+    let dra = (ra2 - ra1).to_radians();
+    // Haversine form stays accurate for small separations
+    let a =
+        ((dec2 - dec1) / 2.0).sin().powi(2) + dec1.cos() * dec2.cos() * (dra / 2.0).sin().powi(2);
+    (2.0 * a.sqrt().min(1.0).asin()).to_degrees()
+}
+
+/// Geocentric position of the Sun
+pub fn sun_position(jd: f64) -> SunPosition {
+    let n = jd - J2000;
+    let mean_longitude = 280.460 + 0.9856474 * n;
+    let g = (357.528 + 0.9856003 * n).to_radians();
+    let longitude = normalize_degrees(mean_longitude + 1.915 * g.sin() + 0.020 * (2.0 * g).sin());
+    let obliquity = 23.439 - 0.000_000_4 * n;
+    let (ra, dec) = ecliptic_to_equatorial(longitude, 0.0, obliquity);
+
+    SunPosition {
+        ra,
+        dec,
+        longitude,
+        distance: 1.00014 - 0.01671 * g.cos() - 0.00014 * (2.0 * g).cos(),
+    }
+}
+
+/// Geocentric position of the Moon
+pub fn moon_position(jd: f64) -> MoonPosition {
+    let t = (jd - J2000) / 36_525.0;
+    let sin = |degrees: f64| degrees.to_radians().sin();
+    let cos = |degrees: f64| degrees.to_radians().cos();
+
+    let longitude = normalize_degrees(
+        218.32 + 481_267.881 * t + 6.29 * sin(135.0 + 477_198.87 * t)
+            - 1.27 * sin(259.3 - 413_335.36 * t)
+            + 0.66 * sin(235.7 + 890_534.22 * t)
+            + 0.21 * sin(269.9 + 954_397.74 * t)
+            - 0.19 * sin(357.5 + 35_999.05 * t)
+            - 0.11 * sin(186.5 + 966_404.03 * t),
+    );
+    let latitude = 5.13 * sin(93.3 + 483_202.02 * t) + 0.28 * sin(228.2 + 960_400.89 * t)
+        - 0.28 * sin(318.3 + 6_003.15 * t)
+        - 0.17 * sin(217.6 - 407_332.21 * t);
+    let parallax = 0.9508
+        + 0.0518 * cos(135.0 + 477_198.87 * t)
+        + 0.0095 * cos(259.3 - 413_335.36 * t)
+        + 0.0078 * cos(235.7 + 890_534.22 * t)
+        + 0.0028 * cos(269.9 + 954_397.74 * t);
+    let (ra, dec) = ecliptic_to_equatorial(longitude, latitude, 23.439 - 0.013 * t);
+
+    MoonPosition {
+        ra,
+        dec,
+        longitude,
+        parallax,
+    }
+}
+
+/// Correct a geocentric altitude for horizontal parallax
+fn topocentric_altitude(altitude: f64, parallax: f64, height: f64) -> f64 {
+    let rho = 1.0 + height / 1000.0 / EARTH_RADIUS_KM;
+    altitude - (rho * parallax.to_radians().sin()).asin().to_degrees() * altitude.to_radians().cos()
+}
+
+/// Convert ecliptic longitude/latitude to RA/DEC, all in degrees
+fn ecliptic_to_equatorial(longitude: f64, latitude: f64, obliquity: f64) -> (f64, f64) {
+    let (lon, lat, eps) = (
+        longitude.to_radians(),
+        latitude.to_radians(),
+        obliquity.to_radians(),
+    );
+    let x = lat.cos() * lon.cos();
+    let y = eps.cos() * lat.cos() * lon.sin() - eps.sin() * lat.sin();
+    let z = eps.sin() * lat.cos() * lon.sin() + eps.cos() * lat.sin();
+    (
+        normalize_degrees(y.atan2(x).to_degrees()),
+        z.clamp(-1.0, 1.0).asin().to_degrees(),
+    )
+}
+
+/// Wrap an angle into [0, 360)
+fn normalize_degrees(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Mount;
+    use chrono::TimeZone;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected} ± {tolerance}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_sidereal_time() {
+        assert_close(greenwich_mean_sidereal_time(J2000), 18.697374558, 1e-6);
+
+        // Meeus, Astronomical Algorithms, example 12.a: 13h10m46.3668s
+        let jd = julian_date(Utc.with_ymd_and_hms(1987, 4, 10, 0, 0, 0).unwrap());
+        assert_close(jd, 2_446_895.5, 1e-9);
+        assert_close(greenwich_mean_sidereal_time(jd), 13.179546, 1e-5);
+    }
+
+    #[test]
+    fn test_horizontal_coordinates() {
+        // Meeus example 13.b: Venus from the US Naval Observatory
+        let jd = julian_date(Utc.with_ymd_and_hms(1987, 4, 10, 19, 21, 0).unwrap());
+        let longitude = -(77.0 + 3.0 / 60.0 + 56.0 / 3600.0);
+        let latitude = 38.0 + 55.0 / 60.0 + 17.0 / 3600.0;
+        let lst = local_sidereal_time(jd, longitude);
+        let (ra, dec) = (347.3193375, -6.7198917);
+
+        assert_close(hour_angle(lst, ra) * 15.0, 64.352133, 0.01);
+        let (altitude, azimuth) = horizontal(ra, dec, lst, latitude);
+        assert_close(altitude, 15.1249, 0.01);
+        assert_close(azimuth, 68.0337 + 180.0, 0.01);
+    }
+
+    #[test]
+    fn test_sun_and_moon_positions() {
+        // Meeus example 25.a (Sun) and 47.a/48.a (Moon)
+        let sun = sun_position(2_448_908.5);
+        assert_close(sun.ra, 198.38083, 0.02);
+        assert_close(sun.dec, -7.78507, 0.02);
+        assert_close(sun.distance, 0.99766, 0.0005);
+
+        let jd = 2_448_724.5;
+        let moon = moon_position(jd);
+        assert_close(moon.ra, 134.688470, 0.3);
+        assert_close(moon.dec, 13.768368, 0.3);
+        assert_close(moon.parallax, 0.991990, 0.005);
+
+        let sun = sun_position(jd);
+        let elongation = angular_separation(sun.ra, sun.dec, moon.ra, moon.dec);
+        assert_close((1.0 - elongation.to_radians().cos()) / 2.0, 0.6786, 0.01);
+    }
+
+    #[test]
+    fn test_airmass_formulas() {
+        for formula in [
+            AirmassFormula::PlaneParallel,
+            AirmassFormula::Hardie,
+            AirmassFormula::YoungIrvine,
+            AirmassFormula::Rozenberg,
+            AirmassFormula::KastenYoung,
+            AirmassFormula::Pickering,
+        ] {
+            assert_close(formula.airmass(90.0).expect("zenith"), 1.0, 0.003);
+            assert_close(formula.airmass(30.0).expect("30°"), 2.0, 0.01);
+            assert_eq!(formula.airmass(-1.0), None);
+        }
+
+        // Near the horizon the refraction-aware formulas stay finite
+        assert_close(
+            AirmassFormula::Pickering.airmass(1.0).expect("1°"),
+            26.3,
+            0.5,
+        );
+        assert!(AirmassFormula::PlaneParallel.airmass(1.0).expect("1°") > 57.0);
+    }
+
+    #[test]
+    fn test_twilight_phase() {
+        assert_eq!(TwilightPhase::from_sun_altitude(10.0), TwilightPhase::Day);
+        assert_eq!(TwilightPhase::from_sun_altitude(-3.0), TwilightPhase::Civil);
+        assert_eq!(
+            TwilightPhase::from_sun_altitude(-9.0),
+            TwilightPhase::Nautical
+        );
+        assert_eq!(
+            TwilightPhase::from_sun_altitude(-15.0),
+            TwilightPhase::Astronomical
+        );
+        assert_eq!(
+            TwilightPhase::from_sun_altitude(-25.0),
+            TwilightPhase::Night
+        );
+    }
+
+    #[test]
+    fn test_calculate_ephemeris_fills_wcs() {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(2024, 1, 15, 4, 0, 0).unwrap());
+        metadata.exposure.exposure_time = Some(300.0);
+        metadata.exposure.ra = Some(83.82);
+        metadata.exposure.dec = Some(-5.39);
+        metadata.mount = Some(Mount {
+            latitude: Some(32.90),
+            longitude: Some(-105.53),
+            height: Some(2225.0),
+            ..Default::default()
+        });
+
+        metadata.calculate_ephemeris();
+        let derived = metadata.derived.as_ref().expect("derived metadata");
+        assert_eq!(
+            derived.mid_exposure,
+            Utc.with_ymd_and_hms(2024, 1, 15, 4, 2, 30).unwrap()
+        );
+        // M42 from New Mexico around 21:00 local in January: well up, still rising
+        let altitude = derived.altitude.expect("altitude");
+        assert!(altitude > 45.0 && altitude < 55.0, "altitude {altitude}");
+        assert!(derived.hour_angle.expect("hour angle") < 0.0);
+        assert_eq!(derived.twilight, TwilightPhase::Night);
+        assert!(derived.moon_illumination < 0.3);
+
+        let wcs = metadata.wcs.as_ref().expect("wcs");
+        assert_eq!(wcs.altitude, Some(altitude as f32));
+        let airmass = derived.airmass.expect("airmass");
+        assert_eq!(
+            wcs.airmass,
+            Some(airmass.get(AirmassFormula::default()) as f32)
+        );
+    }
+}
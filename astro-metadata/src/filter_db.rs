@@ -0,0 +1,249 @@
+//! Filter name normalization and passband catalog
+//!
+//! `FILTER` values are free text ("Ha", "H-alpha", "HA 7nm", "Astrodon Ha
+//! 3nm", "Lum", "O3", ...). This module maps them onto canonical bands with a
+//! centre wavelength and bandwidth so frames can be grouped per band across
+//! equipment. The bundled table (`data/filters.json`) can be extended with a
+//! user JSON file.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::types::Filter;
+
+/// Bundled passband table
+const BUILTIN_FILTERS: &str = include_str!("../data/filters.json");
+
+/// Family of a passband
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PassbandKind {
+    /// Luminance or a color channel
+    Broadband,
+    /// A single emission line
+    Narrowband,
+    /// Clear filter blocking UV and IR (typical for OSC cameras)
+    UvIrCut,
+    /// Two emission lines for OSC cameras (Ha + OIII)
+    DualBand,
+    /// Three emission lines for OSC cameras (Hb + OIII + Ha)
+    TriBand,
+}
+
+/// A canonical filter band
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Passband {
+    /// Canonical name, e.g. "Ha"
+    pub name: String,
+    /// Family of the band
+    pub kind: PassbandKind,
+    /// Centre wavelength in nm
+    pub center: f32,
+    /// Bandwidth (FWHM) in nm, per line for multi-band filters
+    pub bandwidth: f32,
+    /// Emission lines passed by multi-band filters in nm
+    #[serde(default)]
+    pub lines: Vec<f32>,
+    /// Strings matched against the filter name (whole words, case insensitive)
+    pub aliases: Vec<String>,
+}
+
+/// A table of filter bands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterDatabase {
+    /// Known passbands
+    pub passbands: Vec<Passband>,
+}
+
+impl FilterDatabase {
+    /// The bundled passband table
+    pub fn builtin() -> &'static FilterDatabase {
+        static BUILTIN: OnceLock<FilterDatabase> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            FilterDatabase::from_json_str(BUILTIN_FILTERS).expect("bundled filter table is valid")
+        })
+    }
+
+    /// Parse a passband table from JSON
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Failed to parse filter database")
+    }
+
+    /// Load a passband table from a JSON file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read filter database {}", path.display()))?;
+        Self::from_json_str(&json)
+    }
+
+    /// Add passbands from another table, replacing entries with the same name
+    pub fn extend(&mut self, other: FilterDatabase) {
+        for passband in other.passbands {
+            self.passbands
+                .retain(|existing| !existing.name.eq_ignore_ascii_case(&passband.name));
+            self.passbands.push(passband);
+        }
+    }
+
+    /// Find the passband for a filter name
+    ///
+    /// Aliases match whole words, and the longest matching alias wins, so
+    /// "L-eXtreme" is a dual-band filter rather than luminance and
+    /// "Astrodon Ha 3nm" is Ha.
+    pub fn lookup(&self, filter_name: &str) -> Option<&Passband> {
+        let name_words = words(filter_name);
+        if name_words.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(usize, usize, &Passband)> = None;
+        for passband in &self.passbands {
+            for alias in &passband.aliases {
+                let alias = words(alias);
+                if alias.is_empty()
+                    || !name_words
+                        .windows(alias.len())
+                        .any(|window| window == alias)
+                {
+                    continue;
+                }
+                let score = (alias.len(), alias.concat().len());
+                if best.is_none_or(|(count, length, _)| score > (count, length)) {
+                    best = Some((score.0, score.1, passband));
+                }
+            }
+        }
+        best.map(|(_, _, passband)| passband)
+    }
+
+    /// Fill the band, centre wavelength and bandwidth of a filter
+    ///
+    /// A bandwidth in the name ("Ha 3nm") takes precedence over the table.
+    /// Values already present (e.g. from `WAVELEN`) are kept. Returns true if
+    /// a matching passband was found.
+    pub fn fill_filter(&self, filter: &mut Filter) -> bool {
+        let Some(name) = filter.name.as_deref() else {
+            return false;
+        };
+        let Some(passband) = self.lookup(name) else {
+            return false;
+        };
+
+        if filter.bandwidth.is_none() {
+            filter.bandwidth = Some(bandwidth_from_name(name).unwrap_or(passband.bandwidth));
+        }
+        if filter.wavelength.is_none() {
+            filter.wavelength = Some(passband.center);
+        }
+        if filter.band.is_none() {
+            filter.band = Some(passband.name.clone());
+        }
+
+        true
+    }
+}
+
+/// Bandwidth written into a filter name, e.g. 3.5 for "Ha 3.5nm"
+pub fn bandwidth_from_name(name: &str) -> Option<f32> {
+    let upper = name.to_uppercase();
+    upper.match_indices("NM").find_map(|(index, _)| {
+        let number = upper[..index].trim_end();
+        let start = number
+            .rfind(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .map_or(0, |position| position + 1);
+        number[start..]
+            .parse::<f32>()
+            .ok()
+            .filter(|width| *width > 0.0)
+    })
+}
+
+/// Upper-case words, also split between letters and digits ("Ha3nm" -> HA 3 NM)
+fn words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for c in text.chars().flat_map(char::to_uppercase) {
+        let boundary = current
+            .chars()
+            .last()
+            .is_some_and(|last| last.is_numeric() != c.is_numeric());
+        if (!c.is_alphanumeric() || boundary) && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        if c.is_alphanumeric() {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn band(name: &str) -> Option<&'static str> {
+        FilterDatabase::builtin()
+            .lookup(name)
+            .map(|passband| passband.name.as_str())
+    }
+
+    #[test]
+    fn test_lookup_aliases() {
+        for name in ["Ha", "H-alpha", "HA 7nm", "Astrodon Ha 3nm", "Ha3nm", "Hα"] {
+            assert_eq!(band(name), Some("Ha"), "{name}");
+        }
+        for name in ["L", "Lum", "Luminance", "Baader L"] {
+            assert_eq!(band(name), Some("L"), "{name}");
+        }
+        assert_eq!(band("OIII"), Some("OIII"));
+        assert_eq!(band("O3"), Some("OIII"));
+        assert_eq!(band("S2 6.5nm"), Some("SII"));
+        assert_eq!(band("Optolong L-eXtreme"), Some("Dual-band"));
+        assert_eq!(band("Ha/OIII"), Some("Dual-band"));
+        assert_eq!(band("L-eNhance"), Some("Tri-band"));
+        assert_eq!(band("UV/IR Cut"), Some("UV/IR-cut"));
+        assert_eq!(band("H-beta"), Some("Hb"));
+        assert_eq!(band("Filter 5"), None);
+        assert_eq!(band(""), None);
+    }
+
+    #[test]
+    fn test_bandwidth_from_name() {
+        assert_eq!(bandwidth_from_name("Astrodon Ha 3nm"), Some(3.0));
+        assert_eq!(bandwidth_from_name("S2 6.5 nm"), Some(6.5));
+        assert_eq!(bandwidth_from_name("OIII"), None);
+    }
+
+    #[test]
+    fn test_fill_filter_and_extend() -> anyhow::Result<()> {
+        let mut filter = Filter {
+            name: Some("HA 3nm".to_string()),
+            ..Default::default()
+        };
+        assert!(FilterDatabase::builtin().fill_filter(&mut filter));
+        assert_eq!(filter.band.as_deref(), Some("Ha"));
+        assert_eq!(filter.wavelength, Some(656.3));
+        assert_eq!(filter.bandwidth, Some(3.0));
+
+        let mut database = FilterDatabase::builtin().clone();
+        database.extend(FilterDatabase::from_json_str(
+            r#"{"passbands": [
+                {"name": "Ha", "kind": "narrowband", "center": 656.3, "bandwidth": 5.0,
+                 "aliases": ["Ha", "Slot 3"]}
+            ]}"#,
+        )?);
+        let mut filter = Filter {
+            name: Some("Slot 3".to_string()),
+            ..Default::default()
+        };
+        assert!(database.fill_filter(&mut filter));
+        assert_eq!(filter.band.as_deref(), Some("Ha"));
+        assert_eq!(filter.bandwidth, Some(5.0));
+        Ok(())
+    }
+}
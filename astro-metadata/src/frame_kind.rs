@@ -0,0 +1,344 @@
+//! Frame type normalization and classification
+//!
+//! `IMAGETYP` values differ between capture programs ("Light Frame", "LIGHT",
+//! "Flat Field", "DARKFLAT", "Master Dark", ...). `FrameKind::from_imagetyp`
+//! maps them onto one enum. When the header has no usable frame type,
+//! `AstroMetadata::classify_frame` falls back to heuristics on the exposure
+//! time, target headers and pixel statistics and reports a confidence.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::types::{AstroMetadata, Exposure};
+
+/// Exposures at or below this many seconds are treated as bias frames
+const BIAS_MAX_EXPOSURE: f32 = 0.001;
+
+/// Darks at least this long are told apart from flats by exposure time
+const DARK_MIN_EXPOSURE: f32 = 1.0;
+
+/// Normalized frame type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, JsonSchema)]
+pub enum FrameKind {
+    /// Science frame
+    Light,
+    /// Dark frame
+    Dark,
+    /// Flat field
+    Flat,
+    /// Bias (zero-length) frame
+    Bias,
+    /// Dark matching the flat exposure
+    DarkFlat,
+    /// Integrated light frames
+    MasterLight,
+    /// Integrated darks
+    MasterDark,
+    /// Integrated flats
+    MasterFlat,
+    /// Integrated bias frames
+    MasterBias,
+    /// Integrated dark flats
+    MasterDarkFlat,
+    /// Missing or unrecognized frame type
+    #[default]
+    Unknown,
+}
+
+/// What a classification was based on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ClassificationBasis {
+    /// The `IMAGETYP` header
+    Header,
+    /// A zero exposure time
+    ExposureTime,
+    /// Median and spread of the pixel values
+    PixelStatistics,
+    /// Target headers (`OBJECT`, `RA`/`DEC`) on a timed exposure
+    TargetHeaders,
+    /// Nothing to go on
+    None,
+}
+
+/// Frame kind with a confidence between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Classification {
+    /// Classified frame kind
+    pub kind: FrameKind,
+    /// Confidence between 0 (guess) and 1 (from the header)
+    pub confidence: f32,
+    /// What the classification was based on
+    pub basis: ClassificationBasis,
+}
+
+/// Pixel statistics used to classify frames without a frame type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PixelSummary {
+    /// Median pixel value
+    pub median: f32,
+    /// Median absolute deviation from the median
+    pub mad: f32,
+    /// 99.9th percentile pixel value
+    pub high_percentile: f32,
+    /// Largest representable value (e.g. 65535 for 16-bit data)
+    pub full_scale: f32,
+}
+
+impl FrameKind {
+    /// Normalize a raw `IMAGETYP` value
+    pub fn from_imagetyp(value: &str) -> FrameKind {
+        let upper = value.to_ascii_uppercase();
+        let words: Vec<&str> = upper
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .filter(|word| !matches!(*word, "FRAME" | "FRAMES" | "FIELD" | "IMAGE" | "INTEGRATED"))
+            .collect();
+        let joined = words.concat();
+
+        let (master, name) = match joined.strip_prefix("MASTER") {
+            Some(rest) => (true, rest),
+            None => (false, joined.as_str()),
+        };
+
+        let kind = match name {
+            "LIGHT" | "OBJECT" | "SCIENCE" => FrameKind::Light,
+            "DARKFLAT" | "FLATDARK" | "DARKFORFLAT" => FrameKind::DarkFlat,
+            "DARK" | "THERMAL" => FrameKind::Dark,
+            "BIAS" | "OFFSET" | "ZERO" => FrameKind::Bias,
+            name if name.ends_with("FLAT") => FrameKind::Flat,
+            _ => FrameKind::Unknown,
+        };
+
+        if master {
+            kind.master()
+        } else {
+            kind
+        }
+    }
+
+    /// The master frame integrated from frames of this kind
+    pub fn master(self) -> FrameKind {
+        match self {
+            FrameKind::Light | FrameKind::MasterLight => FrameKind::MasterLight,
+            FrameKind::Dark | FrameKind::MasterDark => FrameKind::MasterDark,
+            FrameKind::Flat | FrameKind::MasterFlat => FrameKind::MasterFlat,
+            FrameKind::Bias | FrameKind::MasterBias => FrameKind::MasterBias,
+            FrameKind::DarkFlat | FrameKind::MasterDarkFlat => FrameKind::MasterDarkFlat,
+            FrameKind::Unknown => FrameKind::Unknown,
+        }
+    }
+
+    /// The kind of the individual frames a master is integrated from
+    pub fn base(self) -> FrameKind {
+        match self {
+            FrameKind::MasterLight => FrameKind::Light,
+            FrameKind::MasterDark => FrameKind::Dark,
+            FrameKind::MasterFlat => FrameKind::Flat,
+            FrameKind::MasterBias => FrameKind::Bias,
+            FrameKind::MasterDarkFlat => FrameKind::DarkFlat,
+            kind => kind,
+        }
+    }
+
+    /// True for integrated master frames
+    pub fn is_master(self) -> bool {
+        self.base() != self
+    }
+
+    /// True for dark, flat, bias and dark-flat frames and their masters
+    pub fn is_calibration(self) -> bool {
+        matches!(
+            self.base(),
+            FrameKind::Dark | FrameKind::Flat | FrameKind::Bias | FrameKind::DarkFlat
+        )
+    }
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FrameKind::Light => "Light",
+            FrameKind::Dark => "Dark",
+            FrameKind::Flat => "Flat",
+            FrameKind::Bias => "Bias",
+            FrameKind::DarkFlat => "DarkFlat",
+            FrameKind::MasterLight => "Master Light",
+            FrameKind::MasterDark => "Master Dark",
+            FrameKind::MasterFlat => "Master Flat",
+            FrameKind::MasterBias => "Master Bias",
+            FrameKind::MasterDarkFlat => "Master DarkFlat",
+            FrameKind::Unknown => "Unknown",
+        })
+    }
+}
+
+impl PixelSummary {
+    /// Summarize raw pixel values; `None` for an empty image
+    pub fn from_pixels(pixels: &[f32], full_scale: f32) -> Option<Self> {
+        let mut values: Vec<f32> = pixels.iter().copied().filter(|v| v.is_finite()).collect();
+        if values.is_empty() {
+            return None;
+        }
+
+        let median = select(&mut values, 0.5);
+        let high_percentile = select(&mut values, 0.999);
+        for value in &mut values {
+            *value = (*value - median).abs();
+        }
+        let mad = select(&mut values, 0.5);
+
+        Some(PixelSummary {
+            median,
+            mad,
+            high_percentile,
+            full_scale,
+        })
+    }
+}
+
+impl Exposure {
+    /// Normalized frame type from `IMAGETYP`
+    pub fn frame_kind(&self) -> FrameKind {
+        self.frame_type
+            .as_deref()
+            .map(FrameKind::from_imagetyp)
+            .unwrap_or_default()
+    }
+}
+
+impl AstroMetadata {
+    /// Classify the frame from its header, falling back to heuristics
+    ///
+    /// A recognized `IMAGETYP` wins with full confidence. Otherwise a zero
+    /// exposure means bias, a median near mid-range with little spread means
+    /// flat, a low median without a bright tail on a long exposure means dark
+    /// and a timed exposure with target headers means light.
+    pub fn classify_frame(&self, pixels: Option<&PixelSummary>) -> Classification {
+        let classification = |kind, confidence, basis| Classification {
+            kind,
+            confidence,
+            basis,
+        };
+
+        let kind = self.exposure.frame_kind();
+        if kind != FrameKind::Unknown {
+            return classification(kind, 1.0, ClassificationBasis::Header);
+        }
+
+        let exposure_time = self.exposure.exposure_time;
+        if exposure_time.is_some_and(|time| time <= BIAS_MAX_EXPOSURE) {
+            return classification(FrameKind::Bias, 0.9, ClassificationBasis::ExposureTime);
+        }
+
+        if let Some(pixels) = pixels.filter(|pixels| pixels.full_scale > 0.0) {
+            let level = pixels.median / pixels.full_scale;
+            let spread = pixels.mad / pixels.median.max(f32::EPSILON);
+            let tail = (pixels.high_percentile - pixels.median) / pixels.mad.max(f32::EPSILON);
+
+            if (0.2..=0.8).contains(&level) && spread < 0.1 {
+                return classification(FrameKind::Flat, 0.8, ClassificationBasis::PixelStatistics);
+            }
+            if level < 0.05 && exposure_time.is_some_and(|time| time >= DARK_MIN_EXPOSURE) {
+                // Stars and sky put a bright tail on lights, darks only have hot pixels
+                if tail < 20.0 {
+                    return classification(
+                        FrameKind::Dark,
+                        0.7,
+                        ClassificationBasis::PixelStatistics,
+                    );
+                }
+                return classification(FrameKind::Light, 0.6, ClassificationBasis::PixelStatistics);
+            }
+        }
+
+        let has_target = self.exposure.object_name.is_some()
+            || (self.exposure.ra.is_some() && self.exposure.dec.is_some());
+        if has_target && exposure_time.is_some() {
+            return classification(FrameKind::Light, 0.5, ClassificationBasis::TargetHeaders);
+        }
+
+        classification(FrameKind::Unknown, 0.0, ClassificationBasis::None)
+    }
+}
+
+/// Value at a quantile, reordering the slice
+fn select(values: &mut [f32], quantile: f64) -> f32 {
+    let index = ((values.len() - 1) as f64 * quantile).round() as usize;
+    *values.select_nth_unstable_by(index, f32::total_cmp).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_imagetyp_vendor_strings() {
+        let cases = [
+            ("Light Frame", FrameKind::Light),
+            ("LIGHT", FrameKind::Light),
+            ("object", FrameKind::Light),
+            ("Dark Frame", FrameKind::Dark),
+            ("Flat Field", FrameKind::Flat),
+            ("SKYFLAT", FrameKind::Flat),
+            ("Bias Frame", FrameKind::Bias),
+            ("Offset", FrameKind::Bias),
+            ("DARKFLAT", FrameKind::DarkFlat),
+            ("Flat Dark", FrameKind::DarkFlat),
+            ("Master Dark", FrameKind::MasterDark),
+            ("MasterFlat", FrameKind::MasterFlat),
+            ("master_bias", FrameKind::MasterBias),
+            ("Master Dark Flat", FrameKind::MasterDarkFlat),
+            ("Tricolor", FrameKind::Unknown),
+            ("", FrameKind::Unknown),
+        ];
+        for (value, kind) in cases {
+            assert_eq!(FrameKind::from_imagetyp(value), kind, "{value}");
+        }
+        assert!(FrameKind::MasterDark.is_master());
+        assert!(FrameKind::MasterDark.is_calibration());
+        assert!(!FrameKind::Light.is_calibration());
+    }
+
+    #[test]
+    fn test_classify_without_frame_type() {
+        let mut metadata = AstroMetadata::default();
+        assert_eq!(metadata.classify_frame(None).kind, FrameKind::Unknown);
+
+        metadata.exposure.exposure_time = Some(0.0);
+        let bias = metadata.classify_frame(None);
+        assert_eq!(bias.kind, FrameKind::Bias);
+        assert_eq!(bias.basis, ClassificationBasis::ExposureTime);
+
+        // Uniform illumination near half of full scale
+        metadata.exposure.exposure_time = Some(2.5);
+        let flat: Vec<f32> = (0..1000).map(|i| 30000.0 + (i % 50) as f32).collect();
+        let summary = PixelSummary::from_pixels(&flat, 65535.0).unwrap();
+        assert_eq!(
+            metadata.classify_frame(Some(&summary)).kind,
+            FrameKind::Flat
+        );
+
+        // Offset level with read noise and no bright tail
+        metadata.exposure.exposure_time = Some(300.0);
+        let dark: Vec<f32> = (0..1000).map(|i| 500.0 + (i % 20) as f32).collect();
+        let summary = PixelSummary::from_pixels(&dark, 65535.0).unwrap();
+        let classified = metadata.classify_frame(Some(&summary));
+        assert_eq!(classified.kind, FrameKind::Dark);
+        assert!(classified.confidence < 1.0);
+
+        // The same background with stars is a light
+        let mut light = dark.clone();
+        light[..5].fill(40000.0);
+        let summary = PixelSummary::from_pixels(&light, 65535.0).unwrap();
+        assert_eq!(
+            metadata.classify_frame(Some(&summary)).kind,
+            FrameKind::Light
+        );
+
+        metadata.exposure.frame_type = Some("Dark Frame".to_string());
+        let classified = metadata.classify_frame(Some(&summary));
+        assert_eq!(classified.kind, FrameKind::Dark);
+        assert_eq!(classified.confidence, 1.0);
+    }
+}
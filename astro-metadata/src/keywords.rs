@@ -0,0 +1,970 @@
+//! Declarative FITS keyword registry
+//!
+//! This module maps FITS-style header keywords onto `AstroMetadata` fields.
+//! Each field has an ordered list of keyword aliases; the first alias that is
+//! present with a parseable value wins. Both the FITS and XISF parsers use the
+//! same registry, and callers can extend it at runtime with aliases used by
+//! their own capture software.
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::types::{AstroMetadata, Environment, Mount, WcsData};
+
+/// A metadata field that can be populated from a header keyword
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataField {
+    /// `Equipment::telescope_name`
+    TelescopeName,
+    /// `Equipment::focal_length`
+    FocalLength,
+    /// `Equipment::aperture`
+    Aperture,
+    /// `Equipment::focal_ratio`
+    FocalRatio,
+    /// `Equipment::mount_model`
+    MountModel,
+    /// `Equipment::focuser_position`
+    FocuserPosition,
+    /// `Equipment::focuser_temperature`
+    FocuserTemperature,
+    /// `Detector::camera_name`
+    CameraName,
+    /// `Detector::pixel_size`
+    PixelSize,
+    /// `Detector::width`
+    ImageWidth,
+    /// `Detector::height`
+    ImageHeight,
+    /// `Detector::binning_x`
+    BinningX,
+    /// `Detector::binning_y`
+    BinningY,
+    /// `Detector::gain_setting`
+    GainSetting,
+    /// `Detector::electrons_per_adu`
+    ElectronsPerAdu,
+    /// `Detector::offset`
+    Offset,
+    /// `Detector::readout_mode`
+    ReadoutMode,
+    /// `Detector::usb_limit`
+    UsbLimit,
+    /// `Detector::read_noise`
+    ReadNoise,
+    /// `Detector::full_well`
+    FullWell,
+    /// `Detector::temperature`
+    SensorTemperature,
+    /// `Detector::temp_setpoint`
+    TemperatureSetpoint,
+    /// `Detector::cooler_power`
+    CoolerPower,
+    /// `Detector::cooler_status`
+    CoolerStatus,
+    /// `Detector::rotator_angle`
+    RotatorAngle,
+    /// `Filter::name`
+    FilterName,
+    /// `Filter::position`
+    FilterPosition,
+    /// `Filter::wavelength`
+    FilterWavelength,
+    /// `Exposure::object_name`
+    ObjectName,
+    /// `Exposure::ra`
+    RightAscension,
+    /// `Exposure::dec`
+    Declination,
+    /// `Exposure::date_obs`
+    DateObs,
+    /// `Exposure::date_loc`
+    DateLoc,
+    /// `Exposure::exposure_time`
+    ExposureTime,
+    /// `Exposure::frame_type`
+    FrameType,
+    /// `Exposure::sequence_id`
+    SequenceId,
+    /// `Exposure::frame_number`
+    FrameNumber,
+    /// `Exposure::dither_offset_x`
+    DitherOffsetX,
+    /// `Exposure::dither_offset_y`
+    DitherOffsetY,
+    /// `Exposure::project_name`
+    ProjectName,
+    /// `Exposure::session_id`
+    SessionId,
+    /// `Mount::pier_side`
+    PierSide,
+    /// `Mount::meridian_flip`
+    MeridianFlip,
+    /// `Mount::latitude`
+    SiteLatitude,
+    /// `Mount::longitude`
+    SiteLongitude,
+    /// `Mount::height`
+    SiteElevation,
+    /// `Mount::guide_camera`
+    GuideCamera,
+    /// `Mount::guide_rms`
+    GuideRms,
+    /// `Mount::guide_scale`
+    GuideScale,
+    /// `Mount::dither_enabled`
+    DitherEnabled,
+    /// `Mount::peak_ra_error`
+    PeakRaError,
+    /// `Mount::peak_dec_error`
+    PeakDecError,
+    /// `Environment::ambient_temp`
+    AmbientTemperature,
+    /// `Environment::humidity`
+    Humidity,
+    /// `Environment::dew_heater_power`
+    DewHeaterPower,
+    /// `Environment::voltage`
+    Voltage,
+    /// `Environment::current`
+    Current,
+    /// `Environment::software_version`
+    SoftwareVersion,
+    /// `Environment::sqm`
+    Sqm,
+    /// `WcsData::ctype1`
+    Ctype1,
+    /// `WcsData::ctype2`
+    Ctype2,
+    /// `WcsData::crpix1`
+    Crpix1,
+    /// `WcsData::crpix2`
+    Crpix2,
+    /// `WcsData::crval1`
+    Crval1,
+    /// `WcsData::crval2`
+    Crval2,
+    /// `WcsData::cd1_1`
+    Cd1_1,
+    /// `WcsData::cd1_2`
+    Cd1_2,
+    /// `WcsData::cd2_1`
+    Cd2_1,
+    /// `WcsData::cd2_2`
+    Cd2_2,
+    /// `WcsData::crota2`
+    Crota2,
+    /// `WcsData::airmass`
+    Airmass,
+    /// `WcsData::altitude`
+    Altitude,
+    /// `WcsData::azimuth`
+    Azimuth,
+}
+
+/// The value type stored in a metadata field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// Free-form text
+    Text,
+    /// Floating point number
+    Float,
+    /// Integer number
+    Integer,
+    /// Boolean flag (`T`, `true`, `1`)
+    Flag,
+    /// Angle in degrees, either decimal or sexagesimal
+    Angle,
+    /// ISO 8601 style timestamp
+    Timestamp,
+    /// ISO 8601 style local time without a time zone
+    LocalTimestamp,
+}
+
+/// Conversion applied to a keyword value before it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueConversion {
+    /// Store the value unchanged
+    None,
+    /// Decimal values are degrees; sexagesimal `HH MM SS` values are hours
+    HoursIfSexagesimal,
+    /// Multiply numeric values by a constant factor
+    Scale(f64),
+    /// Prepend a fixed label to text values
+    Prefix(String),
+}
+
+/// Where a runtime alias is inserted relative to the built-in aliases
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasPrecedence {
+    /// Checked before all existing aliases for the field
+    Highest,
+    /// Checked after all existing aliases for the field
+    Lowest,
+}
+
+/// A single header keyword that maps onto a metadata field
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordAlias {
+    /// Header keyword (matched case-insensitively)
+    pub keyword: String,
+    /// Conversion applied to the raw value
+    pub conversion: ValueConversion,
+}
+
+impl KeywordAlias {
+    /// Create an alias that stores the value unchanged
+    pub fn new(keyword: &str) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            conversion: ValueConversion::None,
+        }
+    }
+
+    /// Create an alias with a value conversion
+    pub fn with_conversion(keyword: &str, conversion: ValueConversion) -> Self {
+        Self {
+            keyword: keyword.to_string(),
+            conversion,
+        }
+    }
+}
+
+/// Mapping from one metadata field to its ordered keyword aliases
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordMapping {
+    /// Target metadata field
+    pub field: MetadataField,
+    /// Value type of the field
+    pub kind: ValueKind,
+    /// Aliases in precedence order (first match wins)
+    pub aliases: Vec<KeywordAlias>,
+}
+
+/// A parsed keyword value ready to be assigned to a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    /// Text value
+    Text(String),
+    /// Floating point value
+    Float(f64),
+    /// Integer value
+    Integer(i64),
+    /// Boolean value
+    Flag(bool),
+    /// Timestamp value
+    Timestamp(DateTime<Utc>),
+    /// Local time value
+    LocalTimestamp(NaiveDateTime),
+}
+
+/// Registry of keyword mappings shared by the FITS and XISF parsers
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeywordRegistry {
+    mappings: Vec<KeywordMapping>,
+}
+
+impl Default for KeywordRegistry {
+    fn default() -> Self {
+        use MetadataField::*;
+        use ValueKind::*;
+
+        let mut registry = Self {
+            mappings: Vec::new(),
+        };
+
+        // Equipment
+        registry.define(TelescopeName, Text, &["TELESCOP"]);
+        registry.define(FocalLength, Float, &["FOCALLEN"]);
+        registry.define(Aperture, Float, &["APERTURE"]);
+        registry.define(FocalRatio, Float, &["FOCRATIO"]);
+        registry.define(MountModel, Text, &["MOUNT"]);
+        registry.define(FocuserPosition, Integer, &["FOCPOS", "FOCUSPOS"]);
+        registry.define(FocuserTemperature, Float, &["FOCTEMP", "FOCUSTEMP"]);
+
+        // Detector
+        registry.define(CameraName, Text, &["INSTRUME", "CAMERA"]);
+        registry.define(PixelSize, Float, &["PIXSIZE", "XPIXSZ"]);
+        registry.define(ImageWidth, Integer, &["NAXIS1"]);
+        registry.define(ImageHeight, Integer, &["NAXIS2"]);
+        registry.define(BinningX, Integer, &["XBINNING"]);
+        registry.define(BinningY, Integer, &["YBINNING"]);
+        registry.define(GainSetting, Float, &["GAIN"]);
+        registry.define(ElectronsPerAdu, Float, &["EGAIN"]);
+        registry.define(Offset, Integer, &["OFFSET", "CCDOFFST"]);
+        registry.define(ReadoutMode, Text, &["READOUT", "READOUTM"]);
+        registry.define(UsbLimit, Text, &["USBLIMIT", "USBTRFC"]);
+        registry.define(ReadNoise, Float, &["RDNOISE"]);
+        registry.define(FullWell, Float, &["FULLWELL"]);
+        registry.define(SensorTemperature, Float, &["CCD-TEMP", "CCDTEMP"]);
+        registry.define(
+            TemperatureSetpoint,
+            Float,
+            &["CCD-TEMP-SETPOINT", "SET-TEMP"],
+        );
+        registry.define(CoolerPower, Float, &["COOL-PWR", "COOLPWR"]);
+        registry.define(CoolerStatus, Text, &["COOL-STAT", "COOLSTAT"]);
+        registry.define(RotatorAngle, Float, &["ROTANG", "ROTPA", "ROTATANG"]);
+
+        // Filter
+        registry.define(FilterName, Text, &["FILTER"]);
+        registry.define(FilterPosition, Integer, &["FILTERID", "FLTPOS"]);
+        registry.define(FilterWavelength, Float, &["WAVELENG", "WAVELEN"]);
+
+        // Exposure
+        registry.define(ObjectName, Text, &["OBJECT"]);
+        registry.mappings.push(KeywordMapping {
+            field: RightAscension,
+            kind: Angle,
+            aliases: vec![
+                KeywordAlias::with_conversion("RA", ValueConversion::HoursIfSexagesimal),
+                KeywordAlias::with_conversion("OBJCTRA", ValueConversion::HoursIfSexagesimal),
+            ],
+        });
+        registry.define(Declination, Angle, &["DEC", "OBJCTDEC"]);
+        registry.define(DateObs, Timestamp, &["DATE-OBS"]);
+        registry.define(DateLoc, LocalTimestamp, &["DATE-LOC"]);
+        registry.define(ExposureTime, Float, &["EXPTIME", "EXPOSURE"]);
+        registry.define(FrameType, Text, &["IMAGETYP", "FRAME"]);
+        registry.define(SequenceId, Text, &["SEQID", "SEQFILE"]);
+        registry.define(FrameNumber, Integer, &["FRAMENUM", "SEQNUM"]);
+        registry.define(DitherOffsetX, Float, &["DX", "DITHX"]);
+        registry.define(DitherOffsetY, Float, &["DY", "DITHY"]);
+        registry.define(ProjectName, Text, &["PROJECT", "PROJNAME"]);
+        registry.define(SessionId, Text, &["SESSIONID", "SESSID"]);
+
+        // Mount and site
+        registry.define(PierSide, Text, &["PIERSIDE"]);
+        registry.define(MeridianFlip, Flag, &["MFLIP", "MFOC"]);
+        registry.define(SiteLatitude, Angle, &["SITELAT", "OBSLAT"]);
+        registry.define(SiteLongitude, Angle, &["SITELONG", "OBSLONG"]);
+        registry.define(SiteElevation, Float, &["SITEELEV", "OBSELEV"]);
+        registry.define(GuideCamera, Text, &["GUIDECAM"]);
+        registry.define(GuideRms, Float, &["GUIDERMS"]);
+        registry.define(GuideScale, Float, &["GUIDESCALE"]);
+        registry.define(DitherEnabled, Flag, &["DITHER"]);
+        registry.define(PeakRaError, Float, &["PEAKRA", "PEAKRAER"]);
+        registry.define(PeakDecError, Float, &["PEAKDEC", "PEAKDCER"]);
+
+        // Environment
+        registry.define(AmbientTemperature, Float, &["AMB_TEMP", "AMBTEMP"]);
+        registry.define(Humidity, Float, &["HUMIDITY"]);
+        registry.define(DewHeaterPower, Float, &["DEWPOWER", "DEWPWR"]);
+        registry.define(Voltage, Float, &["VOLTAGE", "SYSVOLT"]);
+        registry.define(Current, Float, &["CURRENT", "SYSCURR"]);
+        registry.mappings.push(KeywordMapping {
+            field: SoftwareVersion,
+            kind: Text,
+            aliases: vec![
+                KeywordAlias::with_conversion(
+                    "NINA-VERSION",
+                    ValueConversion::Prefix("NINA ".to_string()),
+                ),
+                KeywordAlias::with_conversion(
+                    "EKOS-VERSION",
+                    ValueConversion::Prefix("EKOS ".to_string()),
+                ),
+                KeywordAlias::new("SWCREATE"),
+                KeywordAlias::new("SOFTWARE"),
+            ],
+        });
+        registry.define(Sqm, Float, &["SQM", "SQMMAG", "SKYQUAL"]);
+
+        // World Coordinate System
+        registry.define(Ctype1, Text, &["CTYPE1"]);
+        registry.define(Ctype2, Text, &["CTYPE2"]);
+        registry.define(Crpix1, Float, &["CRPIX1"]);
+        registry.define(Crpix2, Float, &["CRPIX2"]);
+        registry.define(Crval1, Float, &["CRVAL1"]);
+        registry.define(Crval2, Float, &["CRVAL2"]);
+        registry.define(Cd1_1, Float, &["CD1_1"]);
+        registry.define(Cd1_2, Float, &["CD1_2"]);
+        registry.define(Cd2_1, Float, &["CD2_1"]);
+        registry.define(Cd2_2, Float, &["CD2_2"]);
+        registry.define(Crota2, Float, &["CROTA2"]);
+        registry.define(Airmass, Float, &["AIRMASS"]);
+        registry.define(Altitude, Float, &["OBJCTALT", "CENTALT"]);
+        registry.define(Azimuth, Float, &["OBJCTAZ", "CENTAZ"]);
+
+        registry
+    }
+}
+
+impl KeywordRegistry {
+    /// Shared instance of the built-in registry
+    pub fn builtin() -> &'static KeywordRegistry {
+        static BUILTIN: OnceLock<KeywordRegistry> = OnceLock::new();
+        BUILTIN.get_or_init(KeywordRegistry::default)
+    }
+
+    /// All mappings in the registry
+    pub fn mappings(&self) -> &[KeywordMapping] {
+        &self.mappings
+    }
+
+    /// Look up the mapping for a field
+    pub fn mapping(&self, field: MetadataField) -> Option<&KeywordMapping> {
+        self.mappings.iter().find(|mapping| mapping.field == field)
+    }
+
+    /// Register an additional keyword alias for a field
+    pub fn register_alias(
+        &mut self,
+        field: MetadataField,
+        alias: KeywordAlias,
+        precedence: AliasPrecedence,
+    ) {
+        let Some(mapping) = self
+            .mappings
+            .iter_mut()
+            .find(|mapping| mapping.field == field)
+        else {
+            return;
+        };
+
+        mapping
+            .aliases
+            .retain(|existing| !existing.keyword.eq_ignore_ascii_case(&alias.keyword));
+
+        match precedence {
+            AliasPrecedence::Highest => mapping.aliases.insert(0, alias),
+            AliasPrecedence::Lowest => mapping.aliases.push(alias),
+        }
+    }
+
+    /// Remove a keyword alias from a field, returning whether it was present
+    pub fn remove_alias(&mut self, field: MetadataField, keyword: &str) -> bool {
+        let Some(mapping) = self
+            .mappings
+            .iter_mut()
+            .find(|mapping| mapping.field == field)
+        else {
+            return false;
+        };
+
+        let before = mapping.aliases.len();
+        mapping
+            .aliases
+            .retain(|alias| !alias.keyword.eq_ignore_ascii_case(keyword));
+        mapping.aliases.len() != before
+    }
+
+    /// Resolve the value for a field from a header map
+    pub fn resolve(
+        &self,
+        field: MetadataField,
+        headers: &HashMap<String, String>,
+    ) -> Option<FieldValue> {
+        self.mapping(field)
+            .and_then(|mapping| resolve_mapping(mapping, headers))
+    }
+
+    /// Populate metadata fields from a header map
+    ///
+    /// Fields without a matching keyword are left untouched, so parser
+    /// defaults (e.g. binning of 1) survive.
+    pub fn apply(&self, headers: &HashMap<String, String>, metadata: &mut AstroMetadata) {
+        for mapping in &self.mappings {
+            if let Some(value) = resolve_mapping(mapping, headers) {
+                assign_field(metadata, mapping.field, value);
+            }
+        }
+
+        // Calculate focal ratio if not directly available
+        if metadata.equipment.focal_ratio.is_none() {
+            if let (Some(focal_length), Some(aperture)) =
+                (metadata.equipment.focal_length, metadata.equipment.aperture)
+            {
+                if aperture > 0.0 {
+                    metadata.equipment.focal_ratio = Some(focal_length / aperture);
+                }
+            }
+        }
+
+        // Try to extract reducer/flattener info from INSTRUME
+        if let Some(instrume) = get_header_value(headers, "INSTRUME") {
+            if instrume.contains("reducer") || instrume.contains("flattener") {
+                metadata.equipment.reducer_flattener = Some(instrume.to_string());
+            }
+        }
+    }
+
+    fn define(&mut self, field: MetadataField, kind: ValueKind, keywords: &[&str]) {
+        self.mappings.push(KeywordMapping {
+            field,
+            kind,
+            aliases: keywords
+                .iter()
+                .map(|keyword| KeywordAlias::new(keyword))
+                .collect(),
+        });
+    }
+}
+
+/// Find the first alias of a mapping with a usable value
+fn resolve_mapping(
+    mapping: &KeywordMapping,
+    headers: &HashMap<String, String>,
+) -> Option<FieldValue> {
+    mapping.aliases.iter().find_map(|alias| {
+        get_header_value(headers, &alias.keyword)
+            .filter(|value| !value.is_empty())
+            .and_then(|value| convert_value(value, mapping.kind, &alias.conversion))
+    })
+}
+
+/// Convert a raw header value according to the field type and alias conversion
+fn convert_value(value: &str, kind: ValueKind, conversion: &ValueConversion) -> Option<FieldValue> {
+    match kind {
+        ValueKind::Text => {
+            let text = match conversion {
+                ValueConversion::Prefix(prefix) => format!("{}{}", prefix, value),
+                _ => value.to_string(),
+            };
+            Some(FieldValue::Text(text))
+        }
+        ValueKind::Float => {
+            parse_float(value).map(|number| FieldValue::Float(scale(number, conversion)))
+        }
+        ValueKind::Integer => parse_integer(value).map(FieldValue::Integer),
+        ValueKind::Flag => Some(FieldValue::Flag(parse_flag(value))),
+        ValueKind::Angle => {
+            if let Some(number) = parse_float(value) {
+                Some(FieldValue::Float(scale(number, conversion)))
+            } else {
+                parse_sexagesimal(value).map(|angle| match conversion {
+                    ValueConversion::HoursIfSexagesimal => FieldValue::Float(angle * 15.0),
+                    _ => FieldValue::Float(scale(angle, conversion)),
+                })
+            }
+        }
+        ValueKind::Timestamp => parse_date_time(value).map(FieldValue::Timestamp),
+        ValueKind::LocalTimestamp => parse_local_date_time(value).map(FieldValue::LocalTimestamp),
+    }
+}
+
+fn scale(number: f64, conversion: &ValueConversion) -> f64 {
+    match conversion {
+        ValueConversion::Scale(factor) => number * factor,
+        _ => number,
+    }
+}
+
+fn parse_float(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+fn parse_integer(value: &str) -> Option<i64> {
+    let trimmed = value.trim();
+    trimmed.parse::<i64>().ok().or_else(|| {
+        // Some writers emit integral values as `1234.` or `1234.0`
+        trimmed
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite() && v.fract() == 0.0)
+            .map(|v| v as i64)
+    })
+}
+
+fn parse_flag(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "t" | "true" | "1" | "yes"
+    )
+}
+
+/// Assign a parsed value to the matching field of `AstroMetadata`
+fn assign_field(metadata: &mut AstroMetadata, field: MetadataField, value: FieldValue) {
+    use MetadataField::*;
+
+    let text = match &value {
+        FieldValue::Text(text) => Some(text.clone()),
+        _ => None,
+    };
+    let float = match value {
+        FieldValue::Float(number) => Some(number),
+        FieldValue::Integer(number) => Some(number as f64),
+        _ => None,
+    };
+    let integer = match value {
+        FieldValue::Integer(number) => Some(number),
+        _ => None,
+    };
+    let flag = match value {
+        FieldValue::Flag(flag) => Some(flag),
+        _ => None,
+    };
+    let count = integer.and_then(|number| usize::try_from(number).ok());
+    let float32 = float.map(|number| number as f32);
+    let int32 = integer.and_then(|number| i32::try_from(number).ok());
+
+    match field {
+        TelescopeName => metadata.equipment.telescope_name = text,
+        FocalLength => metadata.equipment.focal_length = float32,
+        Aperture => metadata.equipment.aperture = float32,
+        FocalRatio => metadata.equipment.focal_ratio = float32,
+        MountModel => metadata.equipment.mount_model = text,
+        FocuserPosition => metadata.equipment.focuser_position = int32,
+        FocuserTemperature => metadata.equipment.focuser_temperature = float32,
+
+        CameraName => metadata.detector.camera_name = text,
+        PixelSize => metadata.detector.pixel_size = float32,
+        ImageWidth => metadata.detector.width = count.unwrap_or(metadata.detector.width),
+        ImageHeight => metadata.detector.height = count.unwrap_or(metadata.detector.height),
+        BinningX => metadata.detector.binning_x = count.filter(|&b| b > 0).unwrap_or(1),
+        BinningY => metadata.detector.binning_y = count.filter(|&b| b > 0).unwrap_or(1),
+        GainSetting => metadata.detector.gain_setting = float32,
+        ElectronsPerAdu => metadata.detector.electrons_per_adu = float32,
+        Offset => metadata.detector.offset = int32,
+        ReadoutMode => metadata.detector.readout_mode = text,
+        UsbLimit => metadata.detector.usb_limit = text,
+        ReadNoise => metadata.detector.read_noise = float32,
+        FullWell => metadata.detector.full_well = float32,
+        SensorTemperature => metadata.detector.temperature = float32,
+        TemperatureSetpoint => metadata.detector.temp_setpoint = float32,
+        CoolerPower => metadata.detector.cooler_power = float32,
+        CoolerStatus => metadata.detector.cooler_status = text,
+        RotatorAngle => metadata.detector.rotator_angle = float32,
+
+        FilterName => metadata.filter.name = text,
+        FilterPosition => metadata.filter.position = count,
+        FilterWavelength => metadata.filter.wavelength = float32,
+
+        ObjectName => metadata.exposure.object_name = text,
+        RightAscension => metadata.exposure.ra = float,
+        Declination => metadata.exposure.dec = float,
+        DateObs => {
+            if let FieldValue::Timestamp(timestamp) = value {
+                metadata.exposure.date_obs = Some(timestamp);
+            }
+        }
+        DateLoc => {
+            if let FieldValue::LocalTimestamp(local) = value {
+                metadata.exposure.date_loc = Some(local);
+            }
+        }
+        ExposureTime => metadata.exposure.exposure_time = float32,
+        FrameType => metadata.exposure.frame_type = text,
+        SequenceId => metadata.exposure.sequence_id = text,
+        FrameNumber => metadata.exposure.frame_number = count,
+        DitherOffsetX => metadata.exposure.dither_offset_x = float32,
+        DitherOffsetY => metadata.exposure.dither_offset_y = float32,
+        ProjectName => metadata.exposure.project_name = text,
+        SessionId => metadata.exposure.session_id = text,
+
+        PierSide => mount(metadata).pier_side = text,
+        MeridianFlip => mount(metadata).meridian_flip = flag,
+        SiteLatitude => mount(metadata).latitude = float,
+        SiteLongitude => mount(metadata).longitude = float,
+        SiteElevation => mount(metadata).height = float,
+        GuideCamera => mount(metadata).guide_camera = text,
+        GuideRms => mount(metadata).guide_rms = float32,
+        GuideScale => mount(metadata).guide_scale = float32,
+        DitherEnabled => mount(metadata).dither_enabled = flag,
+        PeakRaError => mount(metadata).peak_ra_error = float32,
+        PeakDecError => mount(metadata).peak_dec_error = float32,
+
+        AmbientTemperature => environment(metadata).ambient_temp = float32,
+        Humidity => environment(metadata).humidity = float32,
+        DewHeaterPower => environment(metadata).dew_heater_power = float32,
+        Voltage => environment(metadata).voltage = float32,
+        Current => environment(metadata).current = float32,
+        SoftwareVersion => environment(metadata).software_version = text,
+        Sqm => environment(metadata).sqm = float32,
+
+        Ctype1 => wcs(metadata).ctype1 = text,
+        Ctype2 => wcs(metadata).ctype2 = text,
+        Crpix1 => wcs(metadata).crpix1 = float,
+        Crpix2 => wcs(metadata).crpix2 = float,
+        Crval1 => wcs(metadata).crval1 = float,
+        Crval2 => wcs(metadata).crval2 = float,
+        Cd1_1 => wcs(metadata).cd1_1 = float,
+        Cd1_2 => wcs(metadata).cd1_2 = float,
+        Cd2_1 => wcs(metadata).cd2_1 = float,
+        Cd2_2 => wcs(metadata).cd2_2 = float,
+        Crota2 => wcs(metadata).crota2 = float,
+        Airmass => wcs(metadata).airmass = float32,
+        Altitude => wcs(metadata).altitude = float32,
+        Azimuth => wcs(metadata).azimuth = float32,
+    }
+}
+
+fn mount(metadata: &mut AstroMetadata) -> &mut Mount {
+    metadata.mount.get_or_insert_with(Mount::default)
+}
+
+fn environment(metadata: &mut AstroMetadata) -> &mut Environment {
+    metadata
+        .environment
+        .get_or_insert_with(Environment::default)
+}
+
+fn wcs(metadata: &mut AstroMetadata) -> &mut WcsData {
+    metadata.wcs.get_or_insert_with(WcsData::default)
+}
+
+/// Look up a header value, falling back to a case-insensitive match
+pub fn get_header_value<'a>(headers: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    headers.get(key).map(String::as_str).or_else(|| {
+        headers
+            .iter()
+            .find(|(header_key, _)| header_key.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    })
+}
+
+/// Parse sexagesimal format (HH MM SS or DD MM SS) to decimal degrees
+///
+/// This function converts a string in sexagesimal format (hours/degrees, minutes, seconds)
+/// to decimal degrees. It handles both positive and negative values, and accepts
+/// space or colon separators.
+///
+/// # Examples
+///
+/// ```
+/// use astro_metadata::keywords::parse_sexagesimal;
+///
+/// // Parse right ascension: "12 34 56" (12h 34m 56s)
+/// let ra_deg = parse_sexagesimal("12 34 56").map(|ra| ra * 15.0); // Convert hours to degrees
+///
+/// // Parse declination: "-45:12:34" (-45° 12' 34")
+/// let dec_deg = parse_sexagesimal("-45:12:34");
+/// ```
+pub fn parse_sexagesimal(value: &str) -> Option<f64> {
+    let parts: Vec<&str> = value
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .filter(|part| !part.is_empty())
+        .collect();
+    if parts.len() >= 3 {
+        if let (Ok(h), Ok(m), Ok(s)) = (
+            parts[0].parse::<f64>(),
+            parts[1].parse::<f64>(),
+            parts[2].parse::<f64>(),
+        ) {
+            let sign = if h < 0.0 || value.trim_start().starts_with('-') {
+                -1.0
+            } else {
+                1.0
+            };
+            return Some(sign * (h.abs() + m / 60.0 + s / 3600.0));
+        }
+    }
+    None
+}
+
+/// Helper function to parse date/time strings
+///
+/// Strings with a `Z` or numeric UTC offset suffix are converted to UTC;
+/// strings without one are taken as UTC.
+pub(crate) fn parse_date_time(date_str: &str) -> Option<DateTime<Utc>> {
+    let date_str = date_str.trim();
+
+    // RFC 3339 covers `Z` and `+hh:mm` suffixes with either separator
+    if let Ok(dt) = DateTime::parse_from_rfc3339(date_str) {
+        return Some(dt.with_timezone(&Utc));
+    }
+
+    // Compact `+hhmm` offsets, which RFC 3339 rejects
+    let offset_formats = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"];
+    for format in &offset_formats {
+        if let Ok(dt) = DateTime::parse_from_str(date_str, format) {
+            return Some(dt.with_timezone(&Utc));
+        }
+    }
+
+    // Try different date formats
+    let formats = [
+        "%Y-%m-%dT%H:%M:%S%.f", // ISO 8601 with fractional seconds
+        "%Y-%m-%dT%H:%M:%S",    // ISO 8601 without fractional seconds
+        "%Y-%m-%d %H:%M:%S%.f", // Space-separated with fractional seconds
+        "%Y-%m-%d %H:%M:%S",    // Space-separated without fractional seconds
+    ];
+
+    for format in &formats {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(date_str, format) {
+            return Some(DateTime::from_naive_utc_and_offset(dt, Utc));
+        }
+    }
+
+    warn!("Failed to parse date string: {}", date_str);
+    None
+}
+
+/// Parse a local date/time, keeping the wall-clock time of any offset suffix
+fn parse_local_date_time(date_str: &str) -> Option<NaiveDateTime> {
+    let date_str = date_str.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(date_str)
+        .or_else(|_| DateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S%.f%z"))
+    {
+        return Some(dt.naive_local());
+    }
+    parse_date_time(date_str).map(|dt| dt.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_first_alias_takes_precedence() {
+        let registry = KeywordRegistry::default();
+        let headers = headers(&[("FOCUSPOS", "1200"), ("FOCPOS", "1100")]);
+
+        assert_eq!(
+            registry.resolve(MetadataField::FocuserPosition, &headers),
+            Some(FieldValue::Integer(1100))
+        );
+    }
+
+    #[test]
+    fn test_apply_maps_extended_keywords() {
+        let registry = KeywordRegistry::default();
+        let headers = headers(&[
+            ("CCD-TEMP-SETPOINT", "-10"),
+            ("COOL-PWR", "35.5"),
+            ("FOCUSTEMP", "4.25"),
+            ("GUIDERMS", "0.61"),
+            ("MFLIP", "T"),
+            ("DX", "1.5"),
+            ("DY", "-2.0"),
+            ("FRAMENUM", "17"),
+            ("SEQID", "seq-1"),
+            ("FOCRATIO", "5.4"),
+        ]);
+
+        let mut metadata = AstroMetadata::default();
+        registry.apply(&headers, &mut metadata);
+
+        assert_eq!(metadata.detector.temp_setpoint, Some(-10.0));
+        assert_eq!(metadata.detector.cooler_power, Some(35.5));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(4.25));
+        assert_eq!(metadata.equipment.focal_ratio, Some(5.4));
+        assert_eq!(metadata.exposure.dither_offset_x, Some(1.5));
+        assert_eq!(metadata.exposure.dither_offset_y, Some(-2.0));
+        assert_eq!(metadata.exposure.frame_number, Some(17));
+        assert_eq!(metadata.exposure.sequence_id.as_deref(), Some("seq-1"));
+
+        let mount = metadata.mount.expect("mount section should be created");
+        assert_eq!(mount.guide_rms, Some(0.61));
+        assert_eq!(mount.meridian_flip, Some(true));
+    }
+
+    #[test]
+    fn test_date_loc_keeps_local_wall_clock() {
+        let registry = KeywordRegistry::default();
+        let expected = NaiveDateTime::parse_from_str("2024-09-04T01:39:13", "%Y-%m-%dT%H:%M:%S")
+            .expect("valid time");
+
+        for value in ["2024-09-04T01:39:13", "2024-09-04T01:39:13-07:00"] {
+            let headers = headers(&[("DATE-LOC", value)]);
+            let mut metadata = AstroMetadata::default();
+            registry.apply(&headers, &mut metadata);
+            assert_eq!(metadata.exposure.date_loc, Some(expected), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_right_ascension_conversion() {
+        let registry = KeywordRegistry::default();
+
+        // Decimal RA is already in degrees
+        let decimal = headers(&[("RA", "83.82")]);
+        assert_eq!(
+            registry.resolve(MetadataField::RightAscension, &decimal),
+            Some(FieldValue::Float(83.82))
+        );
+
+        // Sexagesimal RA is in hours
+        let sexagesimal = headers(&[("OBJCTRA", "05 35 17.3")]);
+        let Some(FieldValue::Float(ra)) =
+            registry.resolve(MetadataField::RightAscension, &sexagesimal)
+        else {
+            panic!("RA should resolve");
+        };
+        assert!((ra - 83.822083).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_software_version_prefix() {
+        let registry = KeywordRegistry::default();
+        let headers = headers(&[("NINA-VERSION", "3.1"), ("SWCREATE", "N.I.N.A.")]);
+
+        assert_eq!(
+            registry.resolve(MetadataField::SoftwareVersion, &headers),
+            Some(FieldValue::Text("NINA 3.1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_register_custom_alias() {
+        let mut registry = KeywordRegistry::default();
+        let headers = headers(&[("GAIN", "100"), ("CAMGAIN", "120")]);
+
+        registry.register_alias(
+            MetadataField::GainSetting,
+            KeywordAlias::new("CAMGAIN"),
+            AliasPrecedence::Highest,
+        );
+        assert_eq!(
+            registry.resolve(MetadataField::GainSetting, &headers),
+            Some(FieldValue::Float(120.0))
+        );
+
+        registry.register_alias(
+            MetadataField::GainSetting,
+            KeywordAlias::new("CAMGAIN"),
+            AliasPrecedence::Lowest,
+        );
+        assert_eq!(
+            registry.resolve(MetadataField::GainSetting, &headers),
+            Some(FieldValue::Float(100.0))
+        );
+
+        assert!(registry.remove_alias(MetadataField::GainSetting, "gain"));
+        assert_eq!(
+            registry.resolve(MetadataField::GainSetting, &headers),
+            Some(FieldValue::Float(120.0))
+        );
+    }
+
+    #[test]
+    fn test_unparseable_alias_falls_through() {
+        let registry = KeywordRegistry::default();
+        let headers = headers(&[("EXPTIME", "n/a"), ("EXPOSURE", "300")]);
+
+        assert_eq!(
+            registry.resolve(MetadataField::ExposureTime, &headers),
+            Some(FieldValue::Float(300.0))
+        );
+    }
+
+    #[test]
+    fn test_parse_date_time_applies_offsets() {
+        use chrono::TimeZone;
+
+        let expected = Utc.with_ymd_and_hms(2024, 1, 15, 2, 0, 0).unwrap();
+        for value in [
+            "2024-01-15T02:00:00",
+            "2024-01-15T02:00:00Z",
+            "2024-01-15T04:00:00+02:00",
+            "2024-01-15 04:00:00+02:00",
+            "2024-01-14T21:00:00-0500",
+        ] {
+            assert_eq!(parse_date_time(value), Some(expected), "{value}");
+        }
+
+        assert_eq!(
+            parse_date_time("2024-01-15T02:00:00.250Z"),
+            Some(expected + chrono::Duration::milliseconds(250))
+        );
+    }
+}
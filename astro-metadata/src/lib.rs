@@ -1,8 +1,32 @@
 //! Metadata handling for astronomical images
 
+pub mod calibration;
+pub mod capture_software;
+pub mod ephemeris;
+pub mod filter_db;
 pub mod fits_parser;
+pub mod frame_kind;
+pub mod keywords;
+pub mod organizer;
+pub mod path_template;
+pub mod sensor_db;
+pub mod session;
+pub mod sidecar;
+pub mod tables;
+pub mod timing;
 pub mod types;
+pub mod validation;
 pub mod xisf_parser;
 
 pub use astro_io::fits::FitsHeaderCard;
+pub use calibration::CalibrationMatcher;
+pub use filter_db::FilterDatabase;
+pub use frame_kind::FrameKind;
+pub use keywords::KeywordRegistry;
+pub use organizer::Organizer;
+pub use path_template::PathTemplate;
+pub use sensor_db::SensorDatabase;
+pub use session::{SessionMode, SessionSettings, SiteDatabase};
+pub use tables::MetadataTables;
 pub use types::AstroMetadata;
+pub use validation::Validator;
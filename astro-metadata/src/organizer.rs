@@ -0,0 +1,704 @@
+//! Organize frames into folders rendered from a path template
+//!
+//! Organizing is split into planning and execution. `Organizer::plan` reads
+//! the metadata of every FITS/XISF file under a folder and works out where
+//! each one goes without touching anything, so printing the plan is a dry
+//! run. `OrganizePlan::execute` then moves or copies the files and appends
+//! one JSON line per transfer to an undo log, which `undo` replays in
+//! reverse. Overwritten files are kept in a backup folder next to the log.
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::path_template::PathTemplate;
+use super::types::AstroMetadata;
+use super::{fits_parser, xisf_parser};
+
+/// A frame path with its metadata
+pub(crate) type Frame = (PathBuf, AstroMetadata);
+
+/// File extensions picked up when scanning a folder
+const FRAME_EXTENSIONS: &[&str] = &["fits", "fit", "fts", "xisf"];
+
+/// Whether frames are moved or copied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferMode {
+    /// Copy frames, leaving the originals in place
+    #[default]
+    Copy,
+    /// Move frames
+    Move,
+}
+
+/// What to do when a destination file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Leave the frame where it is and report it as skipped
+    #[default]
+    Skip,
+    /// Append `_2`, `_3`, ... to the file name
+    Rename,
+    /// Replace the existing file, keeping a backup that `undo` restores
+    Overwrite,
+    /// Abort planning
+    Fail,
+}
+
+/// Plans and executes file organization
+#[derive(Debug, Clone)]
+pub struct Organizer {
+    template: PathTemplate,
+    mode: TransferMode,
+    collisions: CollisionPolicy,
+}
+
+/// A transfer in an organization plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedTransfer {
+    /// Current location of the frame
+    pub source: PathBuf,
+    /// Where the frame will be placed
+    pub destination: PathBuf,
+    /// The destination was renamed to avoid a collision
+    pub renamed: bool,
+    /// An existing file at the destination will be replaced
+    pub replaces_existing: bool,
+}
+
+/// A frame left out of a plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedFile {
+    /// Location of the frame
+    pub path: PathBuf,
+    /// Why it was skipped
+    pub reason: String,
+}
+
+/// The transfers needed to organize a set of frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrganizePlan {
+    /// Move or copy
+    pub mode: TransferMode,
+    /// Transfers in execution order
+    pub transfers: Vec<PlannedTransfer>,
+    /// Frames that will not be touched
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// One transfer, as recorded in the undo log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UndoEntry {
+    /// Move or copy
+    pub mode: TransferMode,
+    /// Original location
+    pub source: PathBuf,
+    /// New location
+    pub destination: PathBuf,
+    /// Directories created for this transfer, outermost first
+    pub created_dirs: Vec<PathBuf>,
+    /// Where the replaced file was moved before the transfer
+    #[serde(default)]
+    pub backup: Option<PathBuf>,
+    /// The transfer did not complete; only its directories and backup are undone
+    #[serde(default)]
+    pub failed: bool,
+}
+
+/// Result of undoing an organization run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UndoReport {
+    /// Transfers reverted
+    pub reverted: usize,
+    /// Destinations that no longer existed
+    pub missing: Vec<PathBuf>,
+}
+
+impl Organizer {
+    /// Create an organizer that copies frames and skips collisions
+    pub fn new(template: PathTemplate) -> Self {
+        Organizer {
+            template,
+            mode: TransferMode::default(),
+            collisions: CollisionPolicy::default(),
+        }
+    }
+
+    /// Set whether frames are moved or copied
+    pub fn mode(mut self, mode: TransferMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the collision policy
+    pub fn collisions(mut self, policy: CollisionPolicy) -> Self {
+        self.collisions = policy;
+        self
+    }
+
+    /// Plan the organization of every frame under a folder
+    ///
+    /// Frames whose metadata cannot be read are reported as skipped.
+    pub fn plan(&self, source_dir: &Path, destination_root: &Path) -> Result<OrganizePlan> {
+        let (frames, unreadable) = scan_frames(source_dir)?;
+        let mut plan = self.plan_frames(frames, destination_root)?;
+        plan.skipped.extend(unreadable);
+        Ok(plan)
+    }
+
+    /// Plan the organization of frames whose metadata is already known
+    pub fn plan_frames(
+        &self,
+        frames: impl IntoIterator<Item = (PathBuf, AstroMetadata)>,
+        destination_root: &Path,
+    ) -> Result<OrganizePlan> {
+        let mut plan = OrganizePlan {
+            mode: self.mode,
+            transfers: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut claimed = HashSet::new();
+
+        for (source, metadata) in frames {
+            let relative = match self.template.render(&metadata) {
+                Ok(relative) => relative,
+                Err(err) => {
+                    plan.skipped.push(SkippedFile {
+                        path: source,
+                        reason: format!("{err:#}"),
+                    });
+                    continue;
+                }
+            };
+
+            let mut file_name = relative.into_os_string();
+            if let Some(extension) = source.extension() {
+                file_name.push(".");
+                file_name.push(extension);
+            }
+            let mut destination = destination_root.join(file_name);
+
+            if destination == source {
+                plan.skipped.push(SkippedFile {
+                    path: source,
+                    reason: "already in place".to_string(),
+                });
+                continue;
+            }
+
+            let taken = |path: &Path| claimed.contains(path) || path.exists();
+            let mut renamed = false;
+            let mut replaces_existing = false;
+            if taken(&destination) {
+                match self.collisions {
+                    CollisionPolicy::Skip => {
+                        plan.skipped.push(SkippedFile {
+                            reason: format!("{} already exists", destination.display()),
+                            path: source,
+                        });
+                        continue;
+                    }
+                    CollisionPolicy::Rename => {
+                        destination = (2..)
+                            .map(|index| numbered_path(&destination, index))
+                            .find(|candidate| !taken(candidate))
+                            .expect("an unused name exists");
+                        renamed = true;
+                    }
+                    CollisionPolicy::Overwrite if !claimed.contains(&destination) => {
+                        replaces_existing = true;
+                    }
+                    CollisionPolicy::Overwrite => {
+                        bail!(
+                            "{} and another frame both map to {}",
+                            source.display(),
+                            destination.display()
+                        );
+                    }
+                    CollisionPolicy::Fail => {
+                        bail!(
+                            "Destination {} for {} is already taken",
+                            destination.display(),
+                            source.display()
+                        );
+                    }
+                }
+            }
+
+            claimed.insert(destination.clone());
+            plan.transfers.push(PlannedTransfer {
+                source,
+                destination,
+                renamed,
+                replaces_existing,
+            });
+        }
+
+        Ok(plan)
+    }
+}
+
+impl OrganizePlan {
+    /// Carry out the plan, recording each transfer in an undo log
+    ///
+    /// Entries are appended and flushed as transfers complete, so a run
+    /// that fails part-way can still be undone. Files replaced under
+    /// `CollisionPolicy::Overwrite` are moved into `<undo log>.backup`.
+    pub fn execute(&self, undo_log: &Path) -> Result<usize> {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(undo_log)
+            .with_context(|| format!("Failed to open undo log {}", undo_log.display()))?;
+
+        for transfer in &self.transfers {
+            let destination = &transfer.destination;
+            if destination.exists() && !transfer.replaces_existing {
+                bail!("{} appeared after planning", destination.display());
+            }
+
+            let created_dirs = create_parent_dirs(destination)?;
+            let mut backup = None;
+            let result = self.transfer(transfer, undo_log, &mut backup);
+
+            // Failed transfers are logged too, so undo removes their directories
+            let entry = UndoEntry {
+                mode: self.mode,
+                source: transfer.source.clone(),
+                destination: destination.clone(),
+                created_dirs,
+                backup,
+                failed: result.is_err(),
+            };
+            let mut line =
+                serde_json::to_string(&entry).context("Failed to serialize undo entry")?;
+            line.push('\n');
+            log.write_all(line.as_bytes())
+                .and_then(|_| log.flush())
+                .with_context(|| format!("Failed to write undo log {}", undo_log.display()))?;
+            result?;
+        }
+
+        Ok(self.transfers.len())
+    }
+
+    /// Back up a replaced file, then move or copy one frame
+    ///
+    /// A partial copy left by a failed transfer is removed.
+    fn transfer(
+        &self,
+        transfer: &PlannedTransfer,
+        undo_log: &Path,
+        backup: &mut Option<PathBuf>,
+    ) -> Result<()> {
+        let destination = &transfer.destination;
+        if transfer.replaces_existing && destination.exists() {
+            let backup_path = backup_path(undo_log, destination)?;
+            move_file(destination, &backup_path)?;
+            *backup = Some(backup_path);
+        }
+
+        let result = match self.mode {
+            TransferMode::Copy => fs::copy(&transfer.source, destination)
+                .map(|_| ())
+                .with_context(|| {
+                    format!(
+                        "Failed to copy {} to {}",
+                        transfer.source.display(),
+                        destination.display()
+                    )
+                }),
+            TransferMode::Move => move_file(&transfer.source, destination),
+        };
+        if result.is_err() && transfer.source.exists() {
+            let _ = fs::remove_file(destination);
+        }
+        result
+    }
+}
+
+impl fmt::Display for OrganizePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verb = match self.mode {
+            TransferMode::Copy => "copy",
+            TransferMode::Move => "move",
+        };
+        for transfer in &self.transfers {
+            write!(
+                f,
+                "{verb} {} -> {}",
+                transfer.source.display(),
+                transfer.destination.display()
+            )?;
+            if transfer.renamed {
+                write!(f, " (renamed)")?;
+            }
+            if transfer.replaces_existing {
+                write!(f, " (overwrite)")?;
+            }
+            writeln!(f)?;
+        }
+        for skipped in &self.skipped {
+            writeln!(f, "skip {}: {}", skipped.path.display(), skipped.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Revert an organization run from its undo log
+///
+/// Moved frames go back to their original location, copies are deleted,
+/// overwritten files are restored from their backup and directories created
+/// by the run are removed if empty. The log is deleted once every entry has
+/// been reverted.
+pub fn undo(undo_log: &Path) -> Result<UndoReport> {
+    let file = File::open(undo_log)
+        .with_context(|| format!("Failed to open undo log {}", undo_log.display()))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line =
+            line.with_context(|| format!("Failed to read undo log {}", undo_log.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: UndoEntry = serde_json::from_str(&line)
+            .with_context(|| format!("Malformed entry in undo log {}", undo_log.display()))?;
+        entries.push(entry);
+    }
+
+    let mut report = UndoReport::default();
+    for entry in entries.iter().rev() {
+        if entry.failed {
+            // The transfer never completed, so only its backup needs restoring
+        } else if !entry.destination.exists() {
+            report.missing.push(entry.destination.clone());
+        } else {
+            match entry.mode {
+                TransferMode::Copy => fs::remove_file(&entry.destination)
+                    .with_context(|| format!("Failed to remove {}", entry.destination.display()))?,
+                TransferMode::Move => {
+                    if entry.source.exists() {
+                        bail!(
+                            "Cannot move {} back: {} exists",
+                            entry.destination.display(),
+                            entry.source.display()
+                        );
+                    }
+                    create_parent_dirs(&entry.source)?;
+                    move_file(&entry.destination, &entry.source)?;
+                }
+            }
+            report.reverted += 1;
+        }
+
+        if let Some(backup) = &entry.backup {
+            if !backup.exists() {
+                report.missing.push(backup.clone());
+            } else if !entry.destination.exists() {
+                move_file(backup, &entry.destination)?;
+            }
+        }
+
+        // Innermost first; non-empty directories are left alone
+        for dir in entry.created_dirs.iter().rev() {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+
+    let _ = fs::remove_dir(backup_dir(undo_log));
+    fs::remove_file(undo_log)
+        .with_context(|| format!("Failed to remove undo log {}", undo_log.display()))?;
+    Ok(report)
+}
+
+/// Folder holding the files an undo log's run replaced
+fn backup_dir(undo_log: &Path) -> PathBuf {
+    let mut name = undo_log.file_name().unwrap_or_default().to_os_string();
+    name.push(".backup");
+    undo_log.with_file_name(name)
+}
+
+/// An unused backup location for a file about to be replaced
+fn backup_path(undo_log: &Path, replaced: &Path) -> Result<PathBuf> {
+    let dir = backup_dir(undo_log);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(replaced.file_name().unwrap_or_default());
+    if !path.exists() {
+        return Ok(path);
+    }
+    Ok((2..)
+        .map(|index| numbered_path(&path, index))
+        .find(|candidate| !candidate.exists())
+        .expect("an unused name exists"))
+}
+
+/// Read the metadata of every frame under a folder, sorted by path
+///
+/// Frames whose metadata cannot be read are returned separately.
+pub(crate) fn scan_frames(dir: &Path) -> Result<(Vec<Frame>, Vec<SkippedFile>)> {
+    let mut frames = Vec::new();
+    let mut unreadable = Vec::new();
+
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("Failed to scan {}", dir.display()))?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || frame_extension(path).is_none() {
+            continue;
+        }
+
+        match extract_metadata(path) {
+            Ok(metadata) => frames.push((path.to_path_buf(), metadata)),
+            Err(err) => unreadable.push(SkippedFile {
+                path: path.to_path_buf(),
+                reason: format!("{err:#}"),
+            }),
+        }
+    }
+
+    Ok((frames, unreadable))
+}
+
+/// Read metadata with the parser matching the file extension
+fn extract_metadata(path: &Path) -> Result<AstroMetadata> {
+    match frame_extension(path).as_deref() {
+        Some("xisf") => xisf_parser::extract_metadata_from_path(path),
+        _ => fits_parser::extract_metadata_from_path(path),
+    }
+}
+
+/// Lowercase extension of a supported frame file
+fn frame_extension(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    FRAME_EXTENSIONS
+        .contains(&extension.as_str())
+        .then_some(extension)
+}
+
+/// `name.ext` -> `name_<index>.ext`
+fn numbered_path(path: &Path, index: usize) -> PathBuf {
+    let mut file_name = OsString::from(path.file_stem().unwrap_or_default());
+    file_name.push(format!("_{index}"));
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+/// Create the missing parent directories of a path, returning them outermost first
+fn create_parent_dirs(path: &Path) -> Result<Vec<PathBuf>> {
+    let Some(parent) = path.parent() else {
+        return Ok(Vec::new());
+    };
+
+    let mut missing: Vec<PathBuf> = parent
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing.reverse();
+
+    fs::create_dir_all(parent).with_context(|| format!("Failed to create {}", parent.display()))?;
+    Ok(missing)
+}
+
+/// Rename a file, falling back to copy and delete across file systems
+fn move_file(source: &Path, destination: &Path) -> Result<()> {
+    if fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(source, destination).with_context(|| {
+        format!(
+            "Failed to move {} to {}",
+            source.display(),
+            destination.display()
+        )
+    })?;
+    fs::remove_file(source).with_context(|| format!("Failed to remove {}", source.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn unique_temp_dir(prefix: &str) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+
+        let dir = std::env::temp_dir().join(format!(
+            "astro-metadata-{prefix}-{}-{timestamp}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).expect("create temp dir");
+        dir
+    }
+
+    fn write_xisf(path: &Path, object: &str, filter: &str) {
+        let xml = format!(
+            concat!(
+                "<?xml version=\"1.0\"?>",
+                "<xisf version=\"1.0\">",
+                "<Image geometry=\"2:2:1\" sampleFormat=\"UInt16\">",
+                "<FITSKeyword name=\"OBJECT\" value=\"'{}'\"/>",
+                "<FITSKeyword name=\"FILTER\" value=\"'{}'\"/>",
+                "<FITSKeyword name=\"IMAGETYP\" value=\"'LIGHT'\"/>",
+                "</Image>",
+                "</xisf>"
+            ),
+            object, filter
+        );
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"XISF0100");
+        bytes.extend_from_slice(&(xml.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(xml.as_bytes());
+        fs::write(path, bytes).expect("write xisf");
+    }
+
+    fn organizer(mode: TransferMode, collisions: CollisionPolicy) -> Organizer {
+        let template = PathTemplate::parse("$$TARGETNAME$$\\$$FILTER$$\\$$IMAGETYPE$$")
+            .expect("valid pattern");
+        Organizer::new(template).mode(mode).collisions(collisions)
+    }
+
+    #[test]
+    fn test_plan_is_a_dry_run() -> Result<()> {
+        let root = unique_temp_dir("organize-plan");
+        let source = root.join("incoming");
+        fs::create_dir_all(&source)?;
+        write_xisf(&source.join("a.xisf"), "M31", "Ha");
+        write_xisf(&source.join("b.xisf"), "M31", "Ha");
+        fs::write(source.join("broken.fits"), b"not a fits file")?;
+        fs::write(source.join("notes.txt"), b"ignored")?;
+
+        let destination = root.join("library");
+        let plan =
+            organizer(TransferMode::Move, CollisionPolicy::Skip).plan(&source, &destination)?;
+
+        assert_eq!(plan.transfers.len(), 1);
+        assert_eq!(
+            plan.transfers[0].destination,
+            destination.join("M31").join("Ha").join("LIGHT.xisf")
+        );
+        let skipped: Vec<_> = plan
+            .skipped
+            .iter()
+            .filter_map(|skipped| skipped.path.file_name())
+            .collect();
+        assert_eq!(skipped, ["b.xisf", "broken.fits"]);
+        assert!(plan.to_string().starts_with("move "));
+        assert!(!destination.exists());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_with_rename_and_undo() -> Result<()> {
+        let root = unique_temp_dir("organize-move");
+        let source = root.join("incoming");
+        fs::create_dir_all(&source)?;
+        write_xisf(&source.join("a.xisf"), "M31", "Ha");
+        write_xisf(&source.join("b.xisf"), "M31", "Ha");
+        write_xisf(&source.join("c.xisf"), "M 33", "OIII");
+
+        let destination = root.join("library");
+        let undo_log = root.join("undo.jsonl");
+        let plan =
+            organizer(TransferMode::Move, CollisionPolicy::Rename).plan(&source, &destination)?;
+        assert_eq!(plan.execute(&undo_log)?, 3);
+
+        let ha = destination.join("M31").join("Ha");
+        assert!(ha.join("LIGHT.xisf").is_file());
+        assert!(ha.join("LIGHT_2.xisf").is_file());
+        assert!(destination
+            .join("M 33")
+            .join("OIII")
+            .join("LIGHT.xisf")
+            .is_file());
+        assert!(!source.join("a.xisf").exists());
+
+        let report = undo(&undo_log)?;
+        assert_eq!(report.reverted, 3);
+        assert!(report.missing.is_empty());
+        for name in ["a.xisf", "b.xisf", "c.xisf"] {
+            assert!(source.join(name).is_file(), "{name} restored");
+        }
+        assert!(!destination.exists());
+        assert!(!undo_log.exists());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_collisions() -> Result<()> {
+        let root = unique_temp_dir("organize-copy");
+        let source = root.join("incoming");
+        fs::create_dir_all(&source)?;
+        write_xisf(&source.join("a.xisf"), "M31", "Ha");
+        let existing = root.join("library").join("M31").join("Ha");
+        fs::create_dir_all(&existing)?;
+        fs::write(existing.join("LIGHT.xisf"), b"older frame")?;
+
+        let destination = root.join("library");
+        let plan = organizer(TransferMode::Copy, CollisionPolicy::Overwrite)
+            .plan(&source, &destination)?;
+        assert!(plan.transfers[0].replaces_existing);
+
+        let undo_log = root.join("undo.jsonl");
+        plan.execute(&undo_log)?;
+        assert!(source.join("a.xisf").is_file());
+        assert_eq!(
+            fs::read(existing.join("LIGHT.xisf"))?,
+            fs::read(source.join("a.xisf"))?
+        );
+
+        // Undo restores the replaced file and removes the backup folder
+        undo(&undo_log)?;
+        assert_eq!(fs::read(existing.join("LIGHT.xisf"))?, b"older frame");
+        assert!(!root.join("undo.jsonl.backup").exists());
+
+        write_xisf(&source.join("b.xisf"), "M31", "Ha");
+        assert!(organizer(TransferMode::Copy, CollisionPolicy::Fail)
+            .plan(&source, &destination)
+            .is_err());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_after_failed_copy() -> Result<()> {
+        let root = unique_temp_dir("organize-failed");
+        let source = root.join("incoming");
+        fs::create_dir_all(&source)?;
+        write_xisf(&source.join("a.xisf"), "M31", "Ha");
+
+        let destination = root.join("library");
+        let plan =
+            organizer(TransferMode::Copy, CollisionPolicy::Skip).plan(&source, &destination)?;
+        fs::remove_file(source.join("a.xisf"))?;
+
+        let undo_log = root.join("undo.jsonl");
+        assert!(plan.execute(&undo_log).is_err());
+        assert!(destination.join("M31").join("Ha").is_dir());
+
+        let report = undo(&undo_log)?;
+        assert_eq!(report.reverted, 0);
+        assert!(report.missing.is_empty());
+        assert!(!destination.exists());
+
+        fs::remove_dir_all(root)?;
+        Ok(())
+    }
+}
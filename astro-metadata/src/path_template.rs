@@ -0,0 +1,551 @@
+//! NINA-style `$$TOKEN$$` path templates
+//!
+//! Renders file organization patterns such as
+//! `$$TARGETNAME$$\SESSION_$$DATEMINUS12$$\$$FILTER$$\$$IMAGETYPE$$_$$FRAMENR$$`
+//! from `AstroMetadata`, formatting each token the way N.I.N.A. does
+//! (`300.00` exposure times, `0007` frame numbers, `2x2` binning, local
+//! dates as `yyyy-MM-dd`). Both `\` and `/` separate directories. Token
+//! values are made path-safe so they can never introduce a separator.
+//! See `docs/Supported_NINA_Tokens.md` for the token list.
+
+use anyhow::{bail, Result};
+use chrono::NaiveDateTime;
+use std::fmt;
+use std::path::PathBuf;
+
+use super::session::{self, SessionSettings, SiteDatabase};
+use super::types::AstroMetadata;
+
+/// Replacement for characters that are not allowed in file names
+const REPLACEMENT_CHAR: char = '-';
+
+/// Windows device names that cannot be used as file names
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// A supported NINA path token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Token {
+    /// Local date, `yyyy-MM-dd`
+    Date,
+    /// Session date (local time minus 12 hours), `yyyy-MM-dd`
+    DateMinus12,
+    /// Local date and time, `yyyy-MM-dd_HH-mm-ss`
+    DateTime,
+    /// UTC date, `yyyy-MM-dd`
+    DateUtc,
+    /// Local time, `HH-mm-ss`
+    Time,
+    /// UTC time, `HH-mm-ss`
+    TimeUtc,
+    /// Binning, e.g. `2x2`
+    Binning,
+    /// Camera name
+    Camera,
+    /// Gain setting
+    Gain,
+    /// Camera offset
+    Offset,
+    /// Readout mode
+    ReadoutModes,
+    /// Sensor temperature, two decimals
+    SensorTemp,
+    /// Sensor temperature setpoint, two decimals
+    TemperatureSetpoint,
+    /// USB limit
+    UsbLimit,
+    /// Exposure time in seconds, two decimals
+    ExposureTime,
+    /// Frame number, four digits
+    FrameNr,
+    /// Frame type, e.g. `LIGHT`
+    ImageType,
+    /// Sequence title
+    SequenceTitle,
+    /// Target name
+    TargetName,
+    /// Filter name
+    Filter,
+    /// Focuser position
+    FocuserPosition,
+    /// Focuser temperature, two decimals
+    FocuserTemp,
+    /// Peak DEC guiding error, two decimals
+    PeakDec,
+    /// Peak RA guiding error, two decimals
+    PeakRa,
+    /// Guiding RMS, two decimals
+    Rms,
+    /// Rotator angle, two decimals
+    RotatorAngle,
+    /// Sky quality, two decimals
+    Sqm,
+    /// Telescope name
+    Telescope,
+    /// Target Scheduler project name
+    TsProjectName,
+    /// Target Scheduler session id
+    TsSessionId,
+}
+
+/// What to render when a token has no value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MissingValuePolicy {
+    /// Substitute a fixed string
+    Placeholder(String),
+    /// Render nothing; directories that end up empty are dropped
+    Empty,
+    /// Fail rendering
+    Fail,
+}
+
+/// A parsed path template
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTemplate {
+    pattern: String,
+    components: Vec<Vec<Piece>>,
+    missing: MissingValuePolicy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Literal(String),
+    Token(Token),
+}
+
+impl Default for MissingValuePolicy {
+    fn default() -> Self {
+        MissingValuePolicy::Placeholder("UNKNOWN".to_string())
+    }
+}
+
+impl Token {
+    /// Every supported token
+    pub const ALL: [Token; 30] = [
+        Token::Date,
+        Token::DateMinus12,
+        Token::DateTime,
+        Token::DateUtc,
+        Token::Time,
+        Token::TimeUtc,
+        Token::Binning,
+        Token::Camera,
+        Token::Gain,
+        Token::Offset,
+        Token::ReadoutModes,
+        Token::SensorTemp,
+        Token::TemperatureSetpoint,
+        Token::UsbLimit,
+        Token::ExposureTime,
+        Token::FrameNr,
+        Token::ImageType,
+        Token::SequenceTitle,
+        Token::TargetName,
+        Token::Filter,
+        Token::FocuserPosition,
+        Token::FocuserTemp,
+        Token::PeakDec,
+        Token::PeakRa,
+        Token::Rms,
+        Token::RotatorAngle,
+        Token::Sqm,
+        Token::Telescope,
+        Token::TsProjectName,
+        Token::TsSessionId,
+    ];
+
+    /// Token name without the `$$` delimiters
+    pub fn name(self) -> &'static str {
+        match self {
+            Token::Date => "DATE",
+            Token::DateMinus12 => "DATEMINUS12",
+            Token::DateTime => "DATETIME",
+            Token::DateUtc => "DATEUTC",
+            Token::Time => "TIME",
+            Token::TimeUtc => "TIMEUTC",
+            Token::Binning => "BINNING",
+            Token::Camera => "CAMERA",
+            Token::Gain => "GAIN",
+            Token::Offset => "OFFSET",
+            Token::ReadoutModes => "READOUTMODES",
+            Token::SensorTemp => "SENSORTEMP",
+            Token::TemperatureSetpoint => "TEMPERATURESETPOINT",
+            Token::UsbLimit => "USBLIMIT",
+            Token::ExposureTime => "EXPOSURETIME",
+            Token::FrameNr => "FRAMENR",
+            Token::ImageType => "IMAGETYPE",
+            Token::SequenceTitle => "SEQUENCETITLE",
+            Token::TargetName => "TARGETNAME",
+            Token::Filter => "FILTER",
+            Token::FocuserPosition => "FOCUSERPOSITION",
+            Token::FocuserTemp => "FOCUSERTEMP",
+            Token::PeakDec => "PEAKDEC",
+            Token::PeakRa => "PEAKRA",
+            Token::Rms => "RMS",
+            Token::RotatorAngle => "ROTATORANGLE",
+            Token::Sqm => "SQM",
+            Token::Telescope => "TELESCOPE",
+            Token::TsProjectName => "TSPROJECTNAME",
+            Token::TsSessionId => "TSSESSIONID",
+        }
+    }
+
+    /// Look up a token by name (case-insensitive, without `$$`)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|token| token.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Render the token for a frame, or `None` if the value is missing
+    ///
+    /// `local_time` is the civil local observation time.
+    pub fn value(
+        self,
+        metadata: &AstroMetadata,
+        local_time: Option<NaiveDateTime>,
+    ) -> Option<String> {
+        let detector = &metadata.detector;
+        let exposure = &metadata.exposure;
+        let utc_time = exposure.date_obs.map(|date| date.naive_utc());
+        let fixed = |value: Option<f32>| value.map(|value| format!("{value:.2}"));
+
+        match self {
+            Token::Date => local_time.map(|time| time.format("%Y-%m-%d").to_string()),
+            Token::DateMinus12 => exposure
+                .session_local_date
+                .map(|date| date.format("%Y-%m-%d").to_string()),
+            Token::DateTime => local_time.map(|time| time.format("%Y-%m-%d_%H-%M-%S").to_string()),
+            Token::DateUtc => utc_time.map(|time| time.format("%Y-%m-%d").to_string()),
+            Token::Time => local_time.map(|time| time.format("%H-%M-%S").to_string()),
+            Token::TimeUtc => utc_time.map(|time| time.format("%H-%M-%S").to_string()),
+            Token::Binning => (detector.binning_x > 0 && detector.binning_y > 0)
+                .then(|| format!("{}x{}", detector.binning_x, detector.binning_y)),
+            Token::Camera => detector.camera_name.clone(),
+            Token::Gain => detector
+                .gain_setting
+                .map(|gain| format!("{}", gain.round() as i32)),
+            Token::Offset => detector.offset.map(|offset| offset.to_string()),
+            Token::ReadoutModes => detector.readout_mode.clone(),
+            Token::SensorTemp => fixed(detector.temperature),
+            Token::TemperatureSetpoint => fixed(detector.temp_setpoint),
+            Token::UsbLimit => detector.usb_limit.clone(),
+            Token::ExposureTime => fixed(exposure.exposure_time),
+            Token::FrameNr => exposure.frame_number.map(|number| format!("{number:04}")),
+            Token::ImageType => exposure.frame_type.clone(),
+            Token::SequenceTitle => exposure.sequence_id.clone(),
+            Token::TargetName => exposure.object_name.clone(),
+            Token::Filter => metadata.filter.name.clone(),
+            Token::FocuserPosition => metadata
+                .equipment
+                .focuser_position
+                .map(|position| position.to_string()),
+            Token::FocuserTemp => fixed(metadata.equipment.focuser_temperature),
+            Token::PeakDec => fixed(
+                metadata
+                    .mount
+                    .as_ref()
+                    .and_then(|mount| mount.peak_dec_error),
+            ),
+            Token::PeakRa => fixed(
+                metadata
+                    .mount
+                    .as_ref()
+                    .and_then(|mount| mount.peak_ra_error),
+            ),
+            Token::Rms => fixed(metadata.mount.as_ref().and_then(|mount| mount.guide_rms)),
+            Token::RotatorAngle => fixed(detector.rotator_angle),
+            Token::Sqm => fixed(metadata.environment.as_ref().and_then(|env| env.sqm)),
+            Token::Telescope => metadata.equipment.telescope_name.clone(),
+            Token::TsProjectName => exposure.project_name.clone(),
+            Token::TsSessionId => exposure.session_id.clone(),
+        }
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "$${}$$", self.name())
+    }
+}
+
+impl PathTemplate {
+    /// Parse a pattern
+    ///
+    /// Fails on unknown or unterminated tokens.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let mut components = Vec::new();
+        for component in pattern.split(['\\', '/']) {
+            let mut pieces = Vec::new();
+            let mut rest = component;
+            while let Some(start) = rest.find("$$") {
+                if start > 0 {
+                    pieces.push(Piece::Literal(rest[..start].to_string()));
+                }
+                let after = &rest[start + 2..];
+                let Some(end) = after.find("$$") else {
+                    bail!("Unterminated token in path pattern {pattern:?}");
+                };
+                let name = &after[..end];
+                match Token::from_name(name) {
+                    Some(token) => pieces.push(Piece::Token(token)),
+                    None => bail!("Unsupported path token $${name}$$"),
+                }
+                rest = &after[end + 2..];
+            }
+            if !rest.is_empty() {
+                pieces.push(Piece::Literal(rest.to_string()));
+            }
+            components.push(pieces);
+        }
+
+        Ok(PathTemplate {
+            pattern: pattern.to_string(),
+            components,
+            missing: MissingValuePolicy::default(),
+        })
+    }
+
+    /// Set the missing-value policy
+    pub fn missing_values(mut self, policy: MissingValuePolicy) -> Self {
+        self.missing = policy;
+        self
+    }
+
+    /// The original pattern
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Tokens used by the pattern, in order of appearance
+    pub fn tokens(&self) -> Vec<Token> {
+        self.components
+            .iter()
+            .flatten()
+            .filter_map(|piece| match piece {
+                Piece::Token(token) => Some(*token),
+                Piece::Literal(_) => None,
+            })
+            .collect()
+    }
+
+    /// Render the template to a relative path using the bundled site table
+    pub fn render(&self, metadata: &AstroMetadata) -> Result<PathBuf> {
+        self.render_with_sites(metadata, SiteDatabase::builtin())
+    }
+
+    /// Render the template to a relative path
+    ///
+    /// Local-time tokens use `DATE-LOC` or the site's time zone, as for
+    /// session dates.
+    pub fn render_with_sites(
+        &self,
+        metadata: &AstroMetadata,
+        sites: &SiteDatabase,
+    ) -> Result<PathBuf> {
+        self.render_with_session(
+            metadata,
+            &SessionSettings {
+                sites,
+                ..SessionSettings::builtin()
+            },
+        )
+    }
+
+    /// Render the template to a relative path, resolving local time with
+    /// `settings` as for session dates
+    pub fn render_with_session(
+        &self,
+        metadata: &AstroMetadata,
+        settings: &SessionSettings,
+    ) -> Result<PathBuf> {
+        let local_time = session::local_observation_time(metadata, settings);
+        let mut path = PathBuf::new();
+
+        for pieces in &self.components {
+            let mut component = String::new();
+            for piece in pieces {
+                match piece {
+                    Piece::Literal(text) => component.push_str(text),
+                    Piece::Token(token) => match token.value(metadata, local_time) {
+                        Some(value) => component.push_str(&sanitize_path_component(&value)),
+                        None => match &self.missing {
+                            MissingValuePolicy::Placeholder(text) => component.push_str(text),
+                            MissingValuePolicy::Empty => {}
+                            MissingValuePolicy::Fail => {
+                                bail!("No value for {token} in path pattern {:?}", self.pattern)
+                            }
+                        },
+                    },
+                }
+            }
+
+            let component = finish_component(&component);
+            if !component.is_empty() {
+                path.push(component);
+            }
+        }
+
+        if path.as_os_str().is_empty() {
+            bail!("Path pattern {:?} rendered an empty path", self.pattern);
+        }
+        Ok(path)
+    }
+}
+
+/// Make a token value safe to use inside a single path component
+///
+/// Path separators, characters Windows rejects and control characters are
+/// replaced with `-`.
+pub fn sanitize_path_component(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => REPLACEMENT_CHAR,
+            c if c.is_control() => REPLACEMENT_CHAR,
+            c => c,
+        })
+        .collect()
+}
+
+/// Trim trailing dots/spaces and avoid reserved names and `.`/`..`
+fn finish_component(component: &str) -> String {
+    let trimmed = component.trim().trim_end_matches(['.', ' ']);
+    if trimmed.is_empty() {
+        return String::new();
+    }
+
+    let stem = trimmed.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format!("_{trimmed}")
+    } else {
+        trimmed.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Mount;
+    use chrono::{NaiveDate, TimeZone, Utc};
+
+    /// The standard pattern from docs/NINA_Path_String.txt
+    const STANDARD_PATTERN: &str = "$$TARGETNAME$$\\$$CAMERA$$\\SESSION_$$DATEMINUS12$$\\TELESCOPE_$$TELESCOPE$$\\BIN_$$BINNING$$\\GAIN_$$GAIN$$\\$$FILTER$$\\$$IMAGETYPE$$\\DATETIME_$$DATETIME$$__IMAGETYPE_$$IMAGETYPE$$__FILTER_$$FILTER$$__EXPOSURE_$$EXPOSURETIME$$s__BIN_$$BINNING$$__GAIN_$$GAIN$$__FRAMENR_$$FRAMENR$$";
+
+    fn sample_metadata() -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.object_name = Some("M 42".to_string());
+        metadata.exposure.frame_type = Some("LIGHT".to_string());
+        metadata.exposure.exposure_time = Some(300.0);
+        metadata.exposure.frame_number = Some(7);
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(2024, 1, 15, 4, 2, 3).unwrap());
+        metadata.exposure.session_local_date = NaiveDate::from_ymd_opt(2024, 1, 14);
+        metadata.detector.camera_name = Some("ZWO ASI2600MM Pro".to_string());
+        metadata.detector.binning_x = 2;
+        metadata.detector.binning_y = 2;
+        metadata.detector.gain_setting = Some(100.0);
+        metadata.detector.temperature = Some(-10.0);
+        metadata.equipment.telescope_name = Some("RedCat 51".to_string());
+        metadata.filter.name = Some("Ha/OIII".to_string());
+        metadata.mount = Some(Mount {
+            latitude: Some(32.90),
+            longitude: Some(-105.53),
+            ..Default::default()
+        });
+        metadata
+    }
+
+    #[test]
+    fn test_render_standard_pattern() -> Result<()> {
+        let template = PathTemplate::parse(STANDARD_PATTERN)?;
+        let path = template.render(&sample_metadata())?;
+
+        let expected: PathBuf = [
+            "M 42",
+            "ZWO ASI2600MM Pro",
+            "SESSION_2024-01-14",
+            "TELESCOPE_RedCat 51",
+            "BIN_2x2",
+            "GAIN_100",
+            "Ha-OIII",
+            "LIGHT",
+            "DATETIME_2024-01-14_21-02-03__IMAGETYPE_LIGHT__FILTER_Ha-OIII__EXPOSURE_300.00s__BIN_2x2__GAIN_100__FRAMENR_0007",
+        ]
+        .iter()
+        .collect();
+        assert_eq!(path, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_formatting() {
+        let metadata = sample_metadata();
+        let local =
+            NaiveDate::from_ymd_opt(2024, 1, 14).and_then(|date| date.and_hms_opt(21, 2, 3));
+
+        assert_eq!(
+            Token::SensorTemp.value(&metadata, local).as_deref(),
+            Some("-10.00")
+        );
+        assert_eq!(
+            Token::Time.value(&metadata, local).as_deref(),
+            Some("21-02-03")
+        );
+        assert_eq!(
+            Token::TimeUtc.value(&metadata, local).as_deref(),
+            Some("04-02-03")
+        );
+        assert_eq!(
+            Token::DateUtc.value(&metadata, local).as_deref(),
+            Some("2024-01-15")
+        );
+        assert_eq!(Token::Offset.value(&metadata, local), None);
+        assert_eq!(Token::from_name("targetname"), Some(Token::TargetName));
+        assert_eq!(Token::FrameNr.to_string(), "$$FRAMENR$$");
+    }
+
+    #[test]
+    fn test_missing_value_policies() -> Result<()> {
+        let metadata = sample_metadata();
+        let pattern = "$$TARGETNAME$$/$$READOUTMODES$$/$$FILTER$$_$$OFFSET$$";
+
+        let path = PathTemplate::parse(pattern)?.render(&metadata)?;
+        assert_eq!(path, PathBuf::from("M 42/UNKNOWN/Ha-OIII_UNKNOWN"));
+
+        let path = PathTemplate::parse(pattern)?
+            .missing_values(MissingValuePolicy::Empty)
+            .render(&metadata)?;
+        assert_eq!(path, PathBuf::from("M 42/Ha-OIII_"));
+
+        let error = PathTemplate::parse(pattern)?
+            .missing_values(MissingValuePolicy::Fail)
+            .render(&metadata)
+            .expect_err("missing readout mode");
+        assert!(error.to_string().contains("$$READOUTMODES$$"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors_and_sanitizing() {
+        assert!(PathTemplate::parse("$$HFR$$").is_err());
+        assert!(PathTemplate::parse("$$TARGETNAME").is_err());
+
+        assert_eq!(sanitize_path_component("a/b\\c:d*e?\u{7}"), "a-b-c-d-e--");
+        assert_eq!(finish_component("con.txt"), "_con.txt");
+        assert_eq!(finish_component(" M31. "), "M31");
+
+        let mut metadata = sample_metadata();
+        metadata.exposure.object_name = Some("..".to_string());
+        let path = PathTemplate::parse("$$TARGETNAME$$/x")
+            .and_then(|template| template.render(&metadata))
+            .expect("renders");
+        assert_eq!(path, PathBuf::from("x"));
+    }
+}
@@ -0,0 +1,312 @@
+//! Bundled sensor characteristics keyed by camera name
+//!
+//! Headers rarely carry read noise or full well, and many capture programs
+//! only write the unitless gain setting. This module ships a small JSON table
+//! of published gain curves so the conversion gain (e-/ADU), read noise and
+//! full well can be filled in from the camera name and gain setting.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::types::Detector;
+
+/// Bundled sensor table
+const BUILTIN_SENSORS: &str = include_str!("../data/sensors.json");
+
+/// Sensor characteristics at a single gain setting
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GainPoint {
+    /// Camera gain setting
+    pub gain: f32,
+    /// Conversion gain in e-/ADU
+    pub electrons_per_adu: f32,
+    /// Read noise in e-
+    pub read_noise: Option<f32>,
+    /// Full well capacity in e-
+    pub full_well: Option<f32>,
+}
+
+/// A camera model and its gain curve
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorModel {
+    /// Display name, e.g. "ZWO ASI2600"
+    pub name: String,
+    /// Sensor part number, e.g. "Sony IMX571"
+    #[serde(default)]
+    pub sensor: Option<String>,
+    /// Strings matched against the camera name (case and punctuation insensitive)
+    pub aliases: Vec<String>,
+    /// Pixel size in μm
+    #[serde(default)]
+    pub pixel_size: Option<f32>,
+    /// ADC bit depth
+    #[serde(default)]
+    pub adc_bits: Option<u8>,
+    /// Characteristics at known gain settings, sorted by gain
+    pub gain_curve: Vec<GainPoint>,
+}
+
+/// Sensor characteristics resolved for a specific gain setting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SensorCharacteristics {
+    /// Conversion gain in e-/ADU
+    pub electrons_per_adu: f32,
+    /// Read noise in e-
+    pub read_noise: Option<f32>,
+    /// Full well capacity in e-
+    pub full_well: Option<f32>,
+}
+
+/// A table of sensor models
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SensorDatabase {
+    /// Known sensor models
+    pub sensors: Vec<SensorModel>,
+}
+
+impl SensorDatabase {
+    /// The bundled sensor table
+    pub fn builtin() -> &'static SensorDatabase {
+        static BUILTIN: OnceLock<SensorDatabase> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            SensorDatabase::from_json_str(BUILTIN_SENSORS).expect("bundled sensor table is valid")
+        })
+    }
+
+    /// Parse a sensor table from JSON
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let mut database: SensorDatabase =
+            serde_json::from_str(json).context("Failed to parse sensor database")?;
+        for sensor in &mut database.sensors {
+            sensor.gain_curve.sort_by(|a, b| a.gain.total_cmp(&b.gain));
+        }
+        Ok(database)
+    }
+
+    /// Load a sensor table from a JSON file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read sensor database {}", path.display()))?;
+        Self::from_json_str(&json)
+    }
+
+    /// Add sensors from another table, replacing entries with the same name
+    pub fn extend(&mut self, other: SensorDatabase) {
+        for sensor in other.sensors {
+            self.sensors
+                .retain(|existing| !existing.name.eq_ignore_ascii_case(&sensor.name));
+            self.sensors.push(sensor);
+        }
+    }
+
+    /// Find the sensor model for a camera name
+    ///
+    /// The longest alias contained in the camera name wins, so
+    /// "ZWO ASI294MM Pro" matches `ASI294MM` rather than `ASI294`.
+    pub fn lookup(&self, camera_name: &str) -> Option<&SensorModel> {
+        let camera = normalize(camera_name);
+        if camera.is_empty() {
+            return None;
+        }
+
+        self.sensors
+            .iter()
+            .flat_map(|sensor| {
+                sensor
+                    .aliases
+                    .iter()
+                    .map(move |alias| (normalize(alias), sensor))
+            })
+            .filter(|(alias, _)| !alias.is_empty() && camera.contains(alias.as_str()))
+            .max_by_key(|(alias, _)| alias.len())
+            .map(|(_, sensor)| sensor)
+    }
+
+    /// Fill missing conversion gain, read noise, full well and pixel size
+    ///
+    /// Values already present on the detector (e.g. from `EGAIN`) are kept.
+    /// Returns true if a matching sensor was found.
+    pub fn fill_detector(&self, detector: &mut Detector) -> bool {
+        let Some(sensor) = detector
+            .camera_name
+            .as_deref()
+            .and_then(|name| self.lookup(name))
+        else {
+            return false;
+        };
+
+        if detector.pixel_size.is_none() {
+            // Headers report the binned pixel size, so match that convention
+            let binning = detector.binning_x.max(1) as f32;
+            detector.pixel_size = sensor.pixel_size.map(|size| size * binning);
+        }
+
+        if let Some(characteristics) = detector
+            .gain_setting
+            .and_then(|gain| sensor.characteristics_at(gain))
+        {
+            if detector.electrons_per_adu.is_none() {
+                detector.electrons_per_adu = Some(characteristics.electrons_per_adu);
+            }
+            if detector.read_noise.is_none() {
+                detector.read_noise = characteristics.read_noise;
+            }
+            if detector.full_well.is_none() {
+                detector.full_well = characteristics.full_well;
+            }
+        }
+
+        true
+    }
+}
+
+impl SensorModel {
+    /// Interpolate the gain curve at a gain setting
+    ///
+    /// Gain settings are in 0.1 dB steps, so e-/ADU and full well fall off
+    /// exponentially and are interpolated in log space; read noise is
+    /// interpolated linearly. Settings outside the tabulated range are
+    /// clamped to the nearest end.
+    pub fn characteristics_at(&self, gain: f32) -> Option<SensorCharacteristics> {
+        let first = self.gain_curve.first()?;
+        let last = self.gain_curve.last()?;
+
+        if gain <= first.gain {
+            return Some(first.into());
+        }
+        if gain >= last.gain {
+            return Some(last.into());
+        }
+
+        let upper = self
+            .gain_curve
+            .iter()
+            .position(|point| point.gain >= gain)?;
+        let lo = &self.gain_curve[upper - 1];
+        let hi = &self.gain_curve[upper];
+        if hi.gain == gain {
+            return Some(hi.into());
+        }
+        let t = (gain - lo.gain) / (hi.gain - lo.gain);
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        let log_lerp = |a: f32, b: f32| {
+            if a > 0.0 && b > 0.0 {
+                lerp(a.ln(), b.ln()).exp()
+            } else {
+                lerp(a, b)
+            }
+        };
+
+        Some(SensorCharacteristics {
+            electrons_per_adu: log_lerp(lo.electrons_per_adu, hi.electrons_per_adu),
+            read_noise: lo.read_noise.zip(hi.read_noise).map(|(a, b)| lerp(a, b)),
+            full_well: lo.full_well.zip(hi.full_well).map(|(a, b)| log_lerp(a, b)),
+        })
+    }
+}
+
+impl From<&GainPoint> for SensorCharacteristics {
+    fn from(point: &GainPoint) -> Self {
+        SensorCharacteristics {
+            electrons_per_adu: point.electrons_per_adu,
+            read_noise: point.read_noise,
+            full_well: point.full_well,
+        }
+    }
+}
+
+/// Lowercase and strip everything but letters and digits
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(camera: &str, gain: f32) -> Detector {
+        Detector {
+            camera_name: Some(camera.to_string()),
+            gain_setting: Some(gain),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_builtin_lookup_prefers_longest_alias() {
+        let database = SensorDatabase::builtin();
+
+        let sensor = database.lookup("ZWO ASI294MM Pro").expect("sensor found");
+        assert_eq!(sensor.name, "ZWO ASI294MM");
+
+        let sensor = database.lookup("zwo asi294mc-pro").expect("sensor found");
+        assert_eq!(sensor.name, "ZWO ASI294");
+
+        assert!(database.lookup("Canon EOS 6D").is_none());
+    }
+
+    #[test]
+    fn test_characteristics_interpolate_and_clamp() {
+        let sensor = SensorDatabase::builtin()
+            .lookup("ZWO ASI2600MM Pro")
+            .expect("sensor found");
+
+        let at_100 = sensor.characteristics_at(100.0).expect("curve");
+        assert_eq!(at_100.electrons_per_adu, 0.25);
+        assert_eq!(at_100.read_noise, Some(1.5));
+
+        let at_150 = sensor.characteristics_at(150.0).expect("curve");
+        // Geometric mean of 0.25 at gain 100 and 0.079 at gain 200
+        assert!((at_150.electrons_per_adu - 0.1405).abs() < 1e-4);
+
+        let clamped = sensor.characteristics_at(460.0).expect("curve");
+        assert_eq!(clamped.electrons_per_adu, 0.025);
+    }
+
+    #[test]
+    fn test_fill_detector_keeps_header_values() {
+        let database = SensorDatabase::builtin();
+
+        let mut filled = detector("ZWO ASI2600MM Pro", 100.0);
+        assert!(database.fill_detector(&mut filled));
+        assert_eq!(filled.electrons_per_adu, Some(0.25));
+        assert_eq!(filled.read_noise, Some(1.5));
+        assert_eq!(filled.full_well, Some(16400.0));
+        assert_eq!(filled.pixel_size, Some(3.76));
+
+        let mut from_header = detector("ZWO ASI2600MM Pro", 100.0);
+        from_header.electrons_per_adu = Some(0.24);
+        from_header.pixel_size = Some(7.52);
+        database.fill_detector(&mut from_header);
+        assert_eq!(from_header.electrons_per_adu, Some(0.24));
+        assert_eq!(from_header.pixel_size, Some(7.52));
+    }
+
+    #[test]
+    fn test_extend_with_user_table() -> Result<()> {
+        let mut database = SensorDatabase::builtin().clone();
+        database.extend(SensorDatabase::from_json_str(
+            r#"{"sensors": [{
+                "name": "QHY268M",
+                "aliases": ["QHY268"],
+                "gain_curve": [
+                    {"gain": 56, "electrons_per_adu": 0.35},
+                    {"gain": 0, "electrons_per_adu": 0.6}
+                ]
+            }]}"#,
+        )?);
+
+        let mut detector = detector("QHY268M", 28.0);
+        assert!(database.fill_detector(&mut detector));
+        // Geometric mean of the curve ends
+        let expected = (0.35f32 * 0.6).sqrt();
+        assert!((detector.electrons_per_adu.expect("filled") - expected).abs() < 1e-6);
+        assert_eq!(detector.read_noise, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,525 @@
+//! Session dates in the observer's local time
+//!
+//! A session ("night") runs from noon to noon so that every frame of one
+//! night shares a date. Civil local time is taken from, in order of
+//! preference, the `DATE-LOC` header, a `TIMEZONE`/`TZ` header, a table of
+//! observatory sites mapped to IANA time zones (bundled in `data/sites.json`
+//! or supplied by the caller), the caller's observatory time zone, or an
+//! approximate offset from the site longitude. `SessionMode::Solar` uses
+//! apparent solar time at the site instead, so the session boundary is the
+//! Sun's meridian transit.
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use super::keywords::get_header_value;
+use super::types::AstroMetadata;
+
+/// Bundled site table
+const BUILTIN_SITES: &str = include_str!("../data/sites.json");
+
+/// Mean Earth radius in km
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Largest UTC offset accepted from `DATE-LOC`
+const MAX_UTC_OFFSET_SECONDS: i64 = 14 * 3600;
+
+/// Headers naming the capture time zone, as an IANA name or a UTC offset
+const TIME_ZONE_KEYWORDS: [&str; 2] = ["TIMEZONE", "TZ"];
+
+/// Time zone label used in `SessionMode::Solar`
+pub const SOLAR_TIMEZONE: &str = "solar";
+
+/// Which local clock defines the noon-to-noon session boundary
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SessionMode {
+    /// Civil time at the site, including daylight saving time
+    #[default]
+    Civil,
+    /// Apparent solar time at the site longitude
+    Solar,
+}
+
+/// How session dates and local times are resolved
+#[derive(Debug, Clone, Copy)]
+pub struct SessionSettings<'a> {
+    /// Which local clock defines the session boundary
+    pub mode: SessionMode,
+    /// Observing sites matched by the frame's coordinates
+    pub sites: &'a SiteDatabase,
+    /// Time zone for frames matching no site, e.g. the home observatory's;
+    /// without it the longitude gives an approximate offset
+    pub time_zone: Option<Tz>,
+}
+
+impl SessionSettings<'static> {
+    /// Civil time with the bundled site table and no observatory time zone
+    pub fn builtin() -> Self {
+        Self {
+            mode: SessionMode::default(),
+            sites: SiteDatabase::builtin(),
+            time_zone: None,
+        }
+    }
+}
+
+impl Default for SessionSettings<'static> {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+/// An observing site and its time zone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Site {
+    /// Display name
+    pub name: String,
+    /// Latitude in degrees (+ north, - south)
+    pub latitude: f64,
+    /// Longitude in degrees (+ east, - west)
+    pub longitude: f64,
+    /// Coordinates within this distance in km match the site
+    #[serde(default = "default_radius_km")]
+    pub radius_km: f64,
+    /// IANA time zone name, e.g. "America/Denver"
+    pub timezone: String,
+}
+
+/// A table of observing sites
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SiteDatabase {
+    /// Known sites
+    pub sites: Vec<Site>,
+}
+
+/// A resolved session date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionDate {
+    /// Local calendar date of the evening the session started
+    pub local_date: NaiveDate,
+    /// Time zone the date was computed in (IANA name, `UTC±hh:mm` or `solar`)
+    pub timezone: String,
+}
+
+/// The clock used to convert UTC to local time
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LocalClock {
+    Named(Tz),
+    Fixed(FixedOffset),
+    Solar { longitude: f64 },
+    Utc,
+}
+
+fn default_radius_km() -> f64 {
+    50.0
+}
+
+impl SiteDatabase {
+    /// The bundled site table
+    pub fn builtin() -> &'static SiteDatabase {
+        static BUILTIN: OnceLock<SiteDatabase> = OnceLock::new();
+        BUILTIN.get_or_init(|| {
+            SiteDatabase::from_json_str(BUILTIN_SITES).expect("bundled site table is valid")
+        })
+    }
+
+    /// Parse a site table from JSON
+    ///
+    /// Fails if a site names an unknown time zone.
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let database: SiteDatabase =
+            serde_json::from_str(json).context("Failed to parse site database")?;
+        for site in &database.sites {
+            site.time_zone()?;
+        }
+        Ok(database)
+    }
+
+    /// Load a site table from a JSON file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read site database {}", path.display()))?;
+        Self::from_json_str(&json)
+    }
+
+    /// Add sites from another table, replacing entries with the same name
+    pub fn extend(&mut self, other: SiteDatabase) {
+        for site in other.sites {
+            self.sites
+                .retain(|existing| !existing.name.eq_ignore_ascii_case(&site.name));
+            self.sites.push(site);
+        }
+    }
+
+    /// Find the nearest site whose radius contains the coordinates
+    pub fn lookup(&self, latitude: f64, longitude: f64) -> Option<&Site> {
+        self.sites
+            .iter()
+            .map(|site| (site.distance_km(latitude, longitude), site))
+            .filter(|(distance, site)| *distance <= site.radius_km)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, site)| site)
+    }
+}
+
+impl Site {
+    /// Parsed IANA time zone
+    pub fn time_zone(&self) -> Result<Tz> {
+        self.timezone.parse().map_err(|_| {
+            anyhow!(
+                "Unknown time zone {:?} for site {}",
+                self.timezone,
+                self.name
+            )
+        })
+    }
+
+    /// Great-circle distance to a point in km
+    pub fn distance_km(&self, latitude: f64, longitude: f64) -> f64 {
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+}
+
+impl SessionDate {
+    /// The session date at noon, in the representation used by `Exposure::session_date`
+    pub fn noon(&self) -> DateTime<Utc> {
+        let noon = self
+            .local_date
+            .and_hms_opt(12, 0, 0)
+            .expect("noon is a valid time");
+        DateTime::from_naive_utc_and_offset(noon, Utc)
+    }
+}
+
+impl LocalClock {
+    fn local_time(&self, utc: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            LocalClock::Named(tz) => utc.with_timezone(tz).naive_local(),
+            LocalClock::Fixed(offset) => utc.with_timezone(offset).naive_local(),
+            LocalClock::Solar { longitude } => {
+                let offset_seconds =
+                    longitude / 15.0 * 3600.0 + equation_of_time_minutes(utc) * 60.0;
+                utc.naive_utc() + Duration::seconds(offset_seconds.round() as i64)
+            }
+            LocalClock::Utc => utc.naive_utc(),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            LocalClock::Named(tz) => tz.name().to_string(),
+            LocalClock::Fixed(offset) => format!("UTC{offset}"),
+            LocalClock::Solar { .. } => SOLAR_TIMEZONE.to_string(),
+            LocalClock::Utc => "UTC".to_string(),
+        }
+    }
+}
+
+/// Resolve the session date of a frame
+///
+/// Returns `None` without an observation time, or in solar mode without a
+/// site longitude.
+pub fn resolve_session_date(
+    metadata: &AstroMetadata,
+    settings: &SessionSettings,
+) -> Option<SessionDate> {
+    let date_obs = metadata.exposure.date_obs?;
+    let clock = match settings.mode {
+        SessionMode::Civil => civil_clock(metadata, date_obs, settings),
+        SessionMode::Solar => LocalClock::Solar {
+            longitude: metadata.mount.as_ref()?.longitude?,
+        },
+    };
+
+    // Anything before local noon belongs to the previous evening's session
+    let local_date = (clock.local_time(date_obs) - Duration::hours(12)).date();
+    Some(SessionDate {
+        local_date,
+        timezone: clock.label(),
+    })
+}
+
+/// Civil local time of the observation start
+///
+/// Uses the same sources as `SessionMode::Civil`, falling back to UTC.
+pub fn local_observation_time(
+    metadata: &AstroMetadata,
+    settings: &SessionSettings,
+) -> Option<NaiveDateTime> {
+    let date_obs = metadata.exposure.date_obs?;
+    Some(civil_clock(metadata, date_obs, settings).local_time(date_obs))
+}
+
+/// Pick the best available civil time source for a frame
+fn civil_clock(
+    metadata: &AstroMetadata,
+    date_obs: DateTime<Utc>,
+    settings: &SessionSettings,
+) -> LocalClock {
+    if let Some(offset) = date_loc_offset(metadata, date_obs) {
+        return LocalClock::Fixed(offset);
+    }
+
+    if let Some(clock) = TIME_ZONE_KEYWORDS
+        .iter()
+        .filter_map(|keyword| get_header_value(&metadata.raw_headers, keyword))
+        .find_map(parse_time_zone)
+    {
+        return clock;
+    }
+
+    let location = metadata
+        .mount
+        .as_ref()
+        .and_then(|mount| mount.latitude.zip(mount.longitude));
+    if let Some(tz) = location
+        .and_then(|(latitude, longitude)| settings.sites.lookup(latitude, longitude))
+        .and_then(|site| site.time_zone().ok())
+        .or(settings.time_zone)
+    {
+        return LocalClock::Named(tz);
+    }
+
+    metadata
+        .approximate_timezone_from_longitude()
+        .and_then(|hours| FixedOffset::east_opt(hours * 3600))
+        .map_or(LocalClock::Utc, LocalClock::Fixed)
+}
+
+/// UTC offset implied by `DATE-LOC` and `DATE-OBS`, rounded to 15 minutes
+fn date_loc_offset(metadata: &AstroMetadata, date_obs: DateTime<Utc>) -> Option<FixedOffset> {
+    let date_loc = metadata.exposure.date_loc?;
+    let seconds = (date_loc - date_obs.naive_utc()).num_seconds();
+    let rounded = (seconds as f64 / 900.0).round() as i64 * 900;
+    if rounded.abs() > MAX_UTC_OFFSET_SECONDS {
+        return None;
+    }
+    FixedOffset::east_opt(rounded as i32)
+}
+
+/// Parse a time zone header value
+///
+/// Accepts IANA names (`Europe/Berlin`) and offsets in hours, optionally
+/// prefixed with `UTC` or `GMT` (`-7`, `+5.5`, `UTC+05:30`).
+fn parse_time_zone(value: &str) -> Option<LocalClock> {
+    let value = value.trim();
+    if let Ok(tz) = value.parse::<Tz>() {
+        return Some(LocalClock::Named(tz));
+    }
+
+    let offset = value
+        .strip_prefix("UTC")
+        .or_else(|| value.strip_prefix("GMT"))
+        .unwrap_or(value)
+        .trim();
+    let hours = match offset.split_once(':') {
+        Some((hours, minutes)) => {
+            let hours: f64 = hours.parse().ok()?;
+            let minutes: f64 = minutes.parse().ok()?;
+            hours + minutes.copysign(if offset.starts_with('-') { -1.0 } else { 1.0 }) / 60.0
+        }
+        None => offset.parse().ok()?,
+    };
+    let seconds = (hours * 3600.0).round() as i64;
+    if !hours.is_finite() || seconds.abs() > MAX_UTC_OFFSET_SECONDS {
+        return None;
+    }
+    FixedOffset::east_opt(seconds as i32).map(LocalClock::Fixed)
+}
+
+/// Equation of time (apparent minus mean solar time) in minutes
+///
+/// Uses the usual low-precision approximation, good to about 30 seconds.
+fn equation_of_time_minutes(utc: DateTime<Utc>) -> f64 {
+    use chrono::Datelike;
+
+    let b = 2.0 * std::f64::consts::PI * (utc.ordinal() as f64 - 81.0) / 364.0;
+    9.87 * (2.0 * b).sin() - 7.53 * b.cos() - 1.5 * b.sin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Mount;
+    use chrono::TimeZone;
+
+    fn metadata_at(date_obs: DateTime<Utc>, latitude: f64, longitude: f64) -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.date_obs = Some(date_obs);
+        metadata.mount = Some(Mount {
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            ..Default::default()
+        });
+        metadata
+    }
+
+    fn civil(sites: &SiteDatabase) -> SessionSettings<'_> {
+        SessionSettings {
+            sites,
+            ..SessionSettings::builtin()
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).expect("valid date")
+    }
+
+    #[test]
+    fn test_site_lookup_picks_nearest_within_radius() {
+        let sites = SiteDatabase::builtin();
+
+        let site = sites.lookup(31.95, -111.59).expect("Kitt Peak");
+        assert_eq!(site.timezone, "America/Phoenix");
+        assert!(sites.lookup(0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_site_time_zone_follows_dst() {
+        // 18:30 UTC is 12:30 MDT in July, already the next session, while the
+        // longitude approximation (UTC-7) would put it at 11:30
+        let sites = SiteDatabase::builtin();
+        let obs = Utc.with_ymd_and_hms(2024, 7, 4, 18, 30, 0).unwrap();
+        let metadata = metadata_at(obs, 32.90, -105.53);
+
+        let session = resolve_session_date(&metadata, &civil(sites)).expect("session");
+        assert_eq!(session.timezone, "America/Denver");
+        assert_eq!(session.local_date, date(2024, 7, 4));
+
+        let winter = metadata_at(
+            Utc.with_ymd_and_hms(2024, 1, 4, 18, 30, 0).unwrap(),
+            32.90,
+            -105.53,
+        );
+        let session = resolve_session_date(&winter, &civil(sites)).expect("session");
+        assert_eq!(session.local_date, date(2024, 1, 3));
+    }
+
+    #[test]
+    fn test_date_loc_takes_precedence() {
+        let mut metadata = metadata_at(
+            Utc.with_ymd_and_hms(2024, 9, 3, 23, 39, 13).unwrap(),
+            32.90,
+            -105.53,
+        );
+        metadata.exposure.date_loc = NaiveDate::from_ymd_opt(2024, 9, 4)
+            .and_then(|day| day.and_hms_milli_opt(1, 39, 13, 204));
+
+        let session =
+            resolve_session_date(&metadata, &SessionSettings::builtin()).expect("session");
+        assert_eq!(session.timezone, "UTC+02:00");
+        assert_eq!(session.local_date, date(2024, 9, 3));
+    }
+
+    #[test]
+    fn test_time_zone_header_before_longitude() {
+        // A backyard near Canberra is not in the site table; longitude / 15
+        // gives UTC+10 where the headers say AEDT (UTC+11) in January
+        let obs = Utc.with_ymd_and_hms(2024, 1, 10, 1, 30, 0).unwrap();
+        let mut metadata = metadata_at(obs, -35.28, 149.13);
+        let sites = SiteDatabase::builtin();
+        let session = resolve_session_date(&metadata, &civil(sites)).expect("session");
+        assert_eq!(session.timezone, "UTC+10:00");
+        assert_eq!(session.local_date, date(2024, 1, 9));
+
+        // The observatory time zone replaces the longitude estimate
+        let home = SessionSettings {
+            time_zone: Some(chrono_tz::Australia::Sydney),
+            ..civil(sites)
+        };
+        let session = resolve_session_date(&metadata, &home).expect("session");
+        assert_eq!(session.timezone, "Australia/Sydney");
+        assert_eq!(session.local_date, date(2024, 1, 10));
+
+        // So does the backyard added to the caller's site table
+        let mut backyard = sites.clone();
+        backyard.extend(
+            SiteDatabase::from_json_str(
+                r#"{"sites": [{
+                    "name": "Backyard",
+                    "latitude": -35.28,
+                    "longitude": 149.13,
+                    "radius_km": 5,
+                    "timezone": "Australia/Sydney"
+                }]}"#,
+            )
+            .expect("site table"),
+        );
+        let session = resolve_session_date(&metadata, &civil(&backyard)).expect("session");
+        assert_eq!(session.timezone, "Australia/Sydney");
+        assert_eq!(session.local_date, date(2024, 1, 10));
+
+        metadata
+            .raw_headers
+            .insert("TIMEZONE".to_string(), "Australia/Sydney".to_string());
+        let session = resolve_session_date(&metadata, &civil(sites)).expect("session");
+        assert_eq!(session.timezone, "Australia/Sydney");
+        assert_eq!(session.local_date, date(2024, 1, 10));
+
+        metadata
+            .raw_headers
+            .insert("TIMEZONE".to_string(), "UTC+11:00".to_string());
+        let session = resolve_session_date(&metadata, &civil(sites)).expect("session");
+        assert_eq!(session.timezone, "UTC+11:00");
+        assert_eq!(session.local_date, date(2024, 1, 10));
+
+        assert!(parse_time_zone("-5.5").is_some());
+        assert!(parse_time_zone("Mars/Olympus").is_none());
+        assert!(parse_time_zone("+20").is_none());
+    }
+
+    #[test]
+    fn test_solar_mode_uses_site_longitude() {
+        // Spain keeps CET/CEST, about two hours ahead of the Sun at Oria, so
+        // 11:00 UTC is after civil noon but before solar noon
+        let obs = Utc.with_ymd_and_hms(2024, 6, 21, 11, 0, 0).unwrap();
+        let metadata = metadata_at(obs, 37.52, -2.30);
+        let sites = SiteDatabase::builtin();
+
+        let session = resolve_session_date(&metadata, &civil(sites)).expect("session");
+        assert_eq!(session.timezone, "Europe/Madrid");
+        assert_eq!(session.local_date, date(2024, 6, 21));
+
+        let solar = SessionSettings {
+            mode: SessionMode::Solar,
+            ..civil(sites)
+        };
+        let session = resolve_session_date(&metadata, &solar).expect("session");
+        assert_eq!(session.timezone, SOLAR_TIMEZONE);
+        assert_eq!(session.local_date, date(2024, 6, 20));
+
+        assert!(resolve_session_date(&AstroMetadata::default(), &solar).is_none());
+    }
+
+    #[test]
+    fn test_extend_with_user_site() -> Result<()> {
+        let mut sites = SiteDatabase::builtin().clone();
+        sites.extend(SiteDatabase::from_json_str(
+            r#"{"sites": [{
+                "name": "Backyard",
+                "latitude": 52.52,
+                "longitude": 13.40,
+                "radius_km": 5,
+                "timezone": "Europe/Berlin"
+            }]}"#,
+        )?);
+        assert_eq!(
+            sites.lookup(52.5, 13.41).map(|site| site.name.as_str()),
+            Some("Backyard")
+        );
+
+        assert!(SiteDatabase::from_json_str(
+            r#"{"sites": [{"name": "Bad", "latitude": 0, "longitude": 0, "timezone": "Mars/Olympus"}]}"#
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! JSON sidecar files for cached metadata and metrics
+//!
+//! A sidecar is written next to its image as `<file name>.ravensky.json`
+//! (e.g. `M42_0001.fits.ravensky.json`). It wraps the cached payload in a
+//! versioned envelope together with a fingerprint of the source image, so a
+//! sidecar is ignored once the image changes or the schema moves on.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Current sidecar schema version
+///
+/// Bump this whenever a serialized type changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// File name suffix appended to the image file name
+pub const SIDECAR_SUFFIX: &str = ".ravensky.json";
+
+/// How thoroughly a sidecar is checked against its source image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Invalidation {
+    /// Compare file size and modification time
+    #[default]
+    SizeAndMtime,
+    /// Also compare a SHA-256 hash of the file contents
+    ContentHash,
+}
+
+/// Identifies the exact source file a sidecar was generated from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceFingerprint {
+    /// File size in bytes
+    pub size: u64,
+    /// Last modification time, if the platform reports one
+    pub modified: Option<DateTime<Utc>>,
+    /// Hex-encoded SHA-256 of the file contents (ContentHash mode only)
+    pub sha256: Option<String>,
+}
+
+/// Versioned envelope stored in a sidecar file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Sidecar<T> {
+    /// Sidecar schema version
+    pub schema_version: u32,
+    /// Crate version that wrote the sidecar
+    pub generator: String,
+    /// Fingerprint of the source image
+    pub source: SourceFingerprint,
+    /// Cached data
+    pub payload: T,
+}
+
+impl SourceFingerprint {
+    /// Fingerprint a file
+    pub fn from_path(path: &Path, invalidation: Invalidation) -> Result<Self> {
+        let file_metadata =
+            fs::metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?;
+        let sha256 = match invalidation {
+            Invalidation::SizeAndMtime => None,
+            Invalidation::ContentHash => Some(hash_file(path)?),
+        };
+
+        Ok(Self {
+            size: file_metadata.len(),
+            modified: file_metadata.modified().ok().map(DateTime::<Utc>::from),
+            sha256,
+        })
+    }
+
+    /// Check whether a file still matches this fingerprint
+    ///
+    /// The content hash is only compared when the fingerprint has one.
+    pub fn matches(&self, path: &Path) -> Result<bool> {
+        // Cheap checks first so a changed file is never hashed
+        let file_metadata =
+            fs::metadata(path).with_context(|| format!("Failed to stat {}", path.display()))?;
+        let modified = file_metadata.modified().ok().map(DateTime::<Utc>::from);
+        if file_metadata.len() != self.size || modified != self.modified {
+            return Ok(false);
+        }
+
+        Ok(self.sha256.is_none() || *self == Self::from_path(path, Invalidation::ContentHash)?)
+    }
+}
+
+/// Path of the sidecar file for an image
+pub fn sidecar_path(image_path: &Path) -> PathBuf {
+    let mut file_name = image_path
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    file_name.push(SIDECAR_SUFFIX);
+    image_path.with_file_name(file_name)
+}
+
+/// Write a sidecar for an image and return its path
+pub fn write_sidecar<T: Serialize>(
+    image_path: &Path,
+    payload: &T,
+    invalidation: Invalidation,
+) -> Result<PathBuf> {
+    let sidecar = Sidecar {
+        schema_version: SCHEMA_VERSION,
+        generator: format!("astro-metadata {}", env!("CARGO_PKG_VERSION")),
+        source: SourceFingerprint::from_path(image_path, invalidation)?,
+        payload,
+    };
+
+    let path = sidecar_path(image_path);
+    let json = serde_json::to_vec_pretty(&sidecar).context("Failed to serialize sidecar")?;
+    fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Read the sidecar for an image
+///
+/// Returns `Ok(None)` when there is no sidecar, when it was written with a
+/// different schema version, or when the image no longer matches its
+/// fingerprint. Unreadable or malformed sidecars are errors.
+pub fn read_sidecar<T: DeserializeOwned>(image_path: &Path) -> Result<Option<T>> {
+    let path = sidecar_path(image_path);
+    let json = match fs::read(&path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+
+    // Check the version before decoding the payload, which may have changed shape
+    let envelope: Sidecar<serde_json::Value> = serde_json::from_slice(&json)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    if envelope.schema_version != SCHEMA_VERSION || !envelope.source.matches(image_path)? {
+        return Ok(None);
+    }
+
+    let payload = serde_json::from_value(envelope.payload)
+        .with_context(|| format!("Failed to decode payload of {}", path.display()))?;
+    Ok(Some(payload))
+}
+
+/// Generate the JSON Schema of a sidecar holding `T`
+pub fn json_schema<T: JsonSchema>() -> serde_json::Value {
+    serde_json::to_value(schemars::schema_for!(Sidecar<T>)).expect("JSON Schema serializes to JSON")
+}
+
+/// Hex-encoded SHA-256 of a file
+fn hash_file(path: &Path) -> Result<String> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = reader
+            .read(&mut buffer)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AstroMetadata;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn unique_temp_image_path(prefix: &str) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+
+        std::env::temp_dir().join(format!(
+            "astro-metadata-{prefix}-{}-{timestamp}.fits",
+            std::process::id()
+        ))
+    }
+
+    fn sample_metadata() -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.object_name = Some("M42".to_string());
+        metadata.exposure.exposure_time = Some(300.0);
+        metadata.detector.gain_setting = Some(100.0);
+        metadata.exposure.date_obs = Some(Utc::now());
+        metadata
+            .raw_headers
+            .insert("OBJECT".to_string(), "M42".to_string());
+        metadata
+    }
+
+    #[test]
+    fn test_sidecar_path_keeps_extension() {
+        assert_eq!(
+            sidecar_path(Path::new("/data/M42_0001.fits")),
+            PathBuf::from("/data/M42_0001.fits.ravensky.json")
+        );
+    }
+
+    #[test]
+    fn test_round_trip_and_size_invalidation() -> Result<()> {
+        let image = unique_temp_image_path("sidecar");
+        fs::write(&image, b"SIMPLE  =                    T")?;
+
+        let metadata = sample_metadata();
+        let path = write_sidecar(&image, &metadata, Invalidation::SizeAndMtime)?;
+        let cached: AstroMetadata = read_sidecar(&image)?.expect("fresh sidecar");
+        assert_eq!(
+            serde_json::to_value(&cached)?,
+            serde_json::to_value(&metadata)?
+        );
+
+        fs::write(&image, b"SIMPLE  =                    T / changed")?;
+        assert!(read_sidecar::<AstroMetadata>(&image)?.is_none());
+
+        fs::remove_file(path)?;
+        fs::remove_file(image)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hash_and_schema_version_invalidation() -> Result<()> {
+        let image = unique_temp_image_path("sidecar-hash");
+        fs::write(&image, b"AAAA")?;
+        let path = write_sidecar(&image, &sample_metadata(), Invalidation::ContentHash)?;
+
+        // Same size and mtime but different contents must be detected
+        let mut sidecar: Sidecar<serde_json::Value> = serde_json::from_slice(&fs::read(&path)?)?;
+        assert_eq!(sidecar.source.sha256.as_ref().map(String::len), Some(64));
+        sidecar.source.sha256 = Some("0".repeat(64));
+        fs::write(&path, serde_json::to_vec(&sidecar)?)?;
+        assert!(read_sidecar::<AstroMetadata>(&image)?.is_none());
+
+        write_sidecar(&image, &sample_metadata(), Invalidation::ContentHash)?;
+        let mut sidecar: Sidecar<serde_json::Value> = serde_json::from_slice(&fs::read(&path)?)?;
+        sidecar.schema_version = SCHEMA_VERSION + 1;
+        fs::write(&path, serde_json::to_vec(&sidecar)?)?;
+        assert!(read_sidecar::<AstroMetadata>(&image)?.is_none());
+
+        fs::remove_file(path)?;
+        fs::remove_file(image)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_schema_describes_envelope() {
+        let schema = json_schema::<AstroMetadata>();
+        let properties = &schema["properties"];
+
+        assert!(properties.get("schema_version").is_some());
+        assert!(properties.get("source").is_some());
+        assert!(schema["definitions"].get("Detector").is_some());
+    }
+}
@@ -0,0 +1,65 @@
+//! Lookup tables the parsers resolve headers against
+//!
+//! The parsers map keywords through a `KeywordRegistry`, fill the detector and
+//! filter from the sensor and passband tables and resolve the session date
+//! with the site table. `MetadataTables` bundles them so a user-extended table
+//! is applied while parsing, where it can still decide the values, rather than
+//! after the builtin tables have filled them.
+
+use super::filter_db::FilterDatabase;
+use super::keywords::KeywordRegistry;
+use super::sensor_db::SensorDatabase;
+use super::session::SessionSettings;
+use super::types::AstroMetadata;
+
+/// Tables used while extracting metadata
+#[derive(Debug, Clone, Copy)]
+pub struct MetadataTables<'a> {
+    /// Header keyword aliases
+    pub keywords: &'a KeywordRegistry,
+    /// Sensor gain curves
+    pub sensors: &'a SensorDatabase,
+    /// Filter passbands
+    pub filters: &'a FilterDatabase,
+    /// Session mode, observing sites and observatory time zone
+    pub session: SessionSettings<'a>,
+}
+
+impl MetadataTables<'static> {
+    /// The bundled tables
+    pub fn builtin() -> Self {
+        Self {
+            keywords: KeywordRegistry::builtin(),
+            sensors: SensorDatabase::builtin(),
+            filters: FilterDatabase::builtin(),
+            session: SessionSettings::builtin(),
+        }
+    }
+}
+
+impl Default for MetadataTables<'static> {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl MetadataTables<'_> {
+    /// Fill the values derived after the headers are mapped: sensor and
+    /// filter characteristics, exposure timing, session date and ephemeris
+    pub(crate) fn complete(&self, metadata: &mut AstroMetadata) {
+        // Fill conversion gain, read noise and full well from the sensor table
+        self.sensors.fill_detector(&mut metadata.detector);
+
+        // Resolve the canonical band, centre wavelength and bandwidth of the filter
+        self.filters.fill_filter(&mut metadata.filter);
+
+        // Resolve exposure start/mid/end, correcting DATE-OBS for TIMESYS
+        metadata.calculate_timing();
+
+        // Calculate session date
+        metadata.calculate_session_date_with(&self.session);
+
+        // Derive altitude, airmass, twilight and Moon conditions
+        metadata.calculate_ephemeris();
+    }
+}
@@ -0,0 +1,698 @@
+//! Exposure timing and time scales
+//!
+//! Builds the start, middle and end of an exposure from `DATE-OBS`,
+//! `DATE-AVG`, `DATE-END`, `MJD-OBS`, `MJD-AVG`, `MJD-END` and `JD`,
+//! honoring `TIMESYS` (UTC, UT1, TAI, TT, TDB, GPS). All instants are
+//! converted to UTC; Julian dates are available in UTC and TDB.
+//!
+//! For a known target the mid-exposure time is also reduced to the Sun's
+//! centre (HJD, UTC) and to the solar-system barycentre (BJD_TDB). Earth and
+//! the giant planets use the JPL Keplerian element approximations, which
+//! keeps the barycentric correction within about 0.1 s. The observer is
+//! placed at the geocentre, which adds up to 21 ms.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use log::warn;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::ephemeris::{julian_date, J2000, UNIX_EPOCH_JD};
+use super::keywords::{get_header_value, parse_date_time};
+use super::types::AstroMetadata;
+
+/// Offset between Julian and modified Julian dates
+pub const MJD_OFFSET: f64 = 2_400_000.5;
+
+/// TT - TAI in seconds
+const TT_MINUS_TAI: f64 = 32.184;
+
+/// TAI - GPS in seconds
+const TAI_MINUS_GPS: f64 = 19.0;
+
+/// Light travel time for 1 AU in seconds
+const AU_LIGHT_SECONDS: f64 = 499.004_783_836;
+
+/// Obliquity of the ecliptic at J2000.0 in degrees
+const J2000_OBLIQUITY: f64 = 23.439_279_44;
+
+/// TAI - UTC in seconds from each leap second onwards (IERS Bulletin C)
+const LEAP_SECONDS: &[(i32, u32, f64)] = &[
+    (1972, 1, 10.0),
+    (1972, 7, 11.0),
+    (1973, 1, 12.0),
+    (1974, 1, 13.0),
+    (1975, 1, 14.0),
+    (1976, 1, 15.0),
+    (1977, 1, 16.0),
+    (1978, 1, 17.0),
+    (1979, 1, 18.0),
+    (1980, 1, 19.0),
+    (1981, 7, 20.0),
+    (1982, 7, 21.0),
+    (1983, 7, 22.0),
+    (1985, 7, 23.0),
+    (1988, 1, 24.0),
+    (1990, 1, 25.0),
+    (1991, 1, 26.0),
+    (1992, 7, 27.0),
+    (1993, 7, 28.0),
+    (1994, 7, 29.0),
+    (1996, 1, 30.0),
+    (1997, 7, 31.0),
+    (1999, 1, 32.0),
+    (2006, 1, 33.0),
+    (2009, 1, 34.0),
+    (2012, 7, 35.0),
+    (2015, 7, 36.0),
+    (2017, 1, 37.0),
+];
+
+/// Keplerian elements at J2000 and their rates per Julian century
+///
+/// Columns: a (AU), e, I, L, longitude of perihelion, longitude of the
+/// ascending node (degrees), followed by the same six rates.
+type OrbitalElements = [f64; 12];
+
+/// Earth-Moon barycentre (Standish, valid 1800-2050)
+const EARTH_MOON: OrbitalElements = [
+    1.000_002_61,
+    0.016_711_23,
+    -0.000_015_31,
+    100.464_571_66,
+    102.937_681_93,
+    0.0,
+    0.000_005_62,
+    -0.000_043_92,
+    -0.012_946_68,
+    35_999.372_449_81,
+    0.323_273_64,
+    0.0,
+];
+
+/// Giant planets with their mass relative to the Sun
+const GIANT_PLANETS: [(f64, OrbitalElements); 4] = [
+    (
+        1.0 / 1_047.348_6,
+        [
+            5.202_887_00,
+            0.048_386_24,
+            1.304_396_95,
+            34.396_440_51,
+            14.728_479_83,
+            100.473_909_09,
+            -0.000_116_07,
+            -0.000_132_53,
+            -0.001_837_14,
+            3_034.746_127_75,
+            0.212_526_68,
+            0.204_691_06,
+        ],
+    ),
+    (
+        1.0 / 3_497.898,
+        [
+            9.536_675_94,
+            0.053_861_79,
+            2.485_991_87,
+            49.954_244_23,
+            92.598_878_31,
+            113.662_424_48,
+            -0.001_250_60,
+            -0.000_509_91,
+            0.001_936_09,
+            1_222.493_622_01,
+            -0.418_972_16,
+            -0.288_677_94,
+        ],
+    ),
+    (
+        1.0 / 22_902.98,
+        [
+            19.189_164_64,
+            0.047_257_44,
+            0.772_637_83,
+            313.238_104_51,
+            170.954_276_30,
+            74.016_925_03,
+            -0.001_961_76,
+            -0.000_043_97,
+            -0.002_429_39,
+            428.482_027_85,
+            0.408_052_81,
+            0.042_405_89,
+        ],
+    ),
+    (
+        1.0 / 19_412.24,
+        [
+            30.069_922_76,
+            0.008_590_48,
+            1.770_043_47,
+            -55.120_029_69,
+            44.964_762_27,
+            131.784_225_74,
+            0.000_262_91,
+            0.000_051_05,
+            0.000_353_72,
+            218.459_453_25,
+            -0.322_414_64,
+            -0.005_086_64,
+        ],
+    ),
+];
+
+/// Mass of the Earth-Moon system relative to the Sun
+const EARTH_MOON_MASS: f64 = 1.0 / 328_900.56;
+
+/// Time scale of header timestamps (`TIMESYS`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TimeScale {
+    /// Coordinated Universal Time, the FITS default
+    #[default]
+    Utc,
+    /// Universal Time (UT1), treated as UTC (they differ by under 0.9 s)
+    Ut1,
+    /// International Atomic Time
+    Tai,
+    /// Terrestrial Time (also `TDT` and `ET`)
+    Tt,
+    /// Barycentric Dynamical Time
+    Tdb,
+    /// GPS time
+    Gps,
+}
+
+/// Start, middle and end of an exposure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ExposureTiming {
+    /// Time scale of the header timestamps
+    pub time_scale: TimeScale,
+    /// Exposure start (UTC)
+    pub start: DateTime<Utc>,
+    /// Exposure midpoint (UTC)
+    pub mid: DateTime<Utc>,
+    /// Exposure end (UTC)
+    pub end: DateTime<Utc>,
+    /// Julian date of mid-exposure (UTC)
+    pub jd_utc_mid: f64,
+    /// Modified Julian date of mid-exposure (UTC)
+    pub mjd_utc_mid: f64,
+    /// Julian date of mid-exposure (TDB)
+    pub jd_tdb_mid: f64,
+    /// Heliocentric Julian date of mid-exposure (UTC), for a known target
+    pub hjd_utc_mid: Option<f64>,
+    /// Barycentric Julian date of mid-exposure (TDB), for a known target
+    pub bjd_tdb_mid: Option<f64>,
+}
+
+impl TimeScale {
+    /// Parse a `TIMESYS` value
+    pub fn from_timesys(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "UTC" | "GMT" | "Z" => Some(TimeScale::Utc),
+            "UT1" | "UT" => Some(TimeScale::Ut1),
+            "TAI" | "IAT" => Some(TimeScale::Tai),
+            "TT" | "TDT" | "ET" => Some(TimeScale::Tt),
+            "TDB" => Some(TimeScale::Tdb),
+            "GPS" => Some(TimeScale::Gps),
+            _ => None,
+        }
+    }
+
+    /// Convert a timestamp written in this scale to UTC
+    pub fn to_utc(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let to_tai = match self {
+            TimeScale::Utc | TimeScale::Ut1 => return time,
+            TimeScale::Tai => 0.0,
+            TimeScale::Tt => -TT_MINUS_TAI,
+            TimeScale::Tdb => -TT_MINUS_TAI - tdb_minus_tt(julian_date(time)),
+            TimeScale::Gps => TAI_MINUS_GPS,
+        };
+        let tai = time + seconds(to_tai);
+        // Leap seconds are tabulated in UTC, but one step from TAI is enough
+        tai - seconds(tai_minus_utc(tai - seconds(tai_minus_utc(tai))))
+    }
+}
+
+impl ExposureTiming {
+    /// Build the timing model from header values
+    ///
+    /// The start comes from `DATE-OBS`, `MJD-OBS` or `JD`, the end from
+    /// `DATE-END`, `MJD-END` or the exposure time, and the midpoint from
+    /// `DATE-AVG`/`MJD-AVG` or halfway between. Returns `None` when neither
+    /// end of the exposure can be placed.
+    pub fn from_headers(
+        headers: &HashMap<String, String>,
+        exposure_time: Option<f32>,
+    ) -> Option<Self> {
+        let time_scale = match get_header_value(headers, "TIMESYS") {
+            Some(value) => TimeScale::from_timesys(value).unwrap_or_else(|| {
+                warn!("Unsupported TIMESYS {value:?}, assuming UTC");
+                TimeScale::Utc
+            }),
+            None => TimeScale::Utc,
+        };
+        let instant = |date_key: &str, mjd_key: &str| {
+            get_header_value(headers, date_key)
+                .and_then(parse_date_time)
+                .or_else(|| {
+                    header_number(headers, mjd_key).map(|mjd| from_julian_date(mjd + MJD_OFFSET))
+                })
+                .map(|time| time_scale.to_utc(time))
+        };
+
+        let start = instant("DATE-OBS", "MJD-OBS").or_else(|| {
+            header_number(headers, "JD").map(|jd| time_scale.to_utc(from_julian_date(jd)))
+        });
+        let end = instant("DATE-END", "MJD-END");
+        let mid = instant("DATE-AVG", "MJD-AVG");
+        Self::from_instants(time_scale, start, mid, end, exposure_time)
+    }
+
+    /// Build the timing model from a UTC start time and exposure length
+    pub fn from_start(start: DateTime<Utc>, exposure_time: Option<f32>) -> Self {
+        Self::from_instants(TimeScale::Utc, Some(start), None, None, exposure_time)
+            .expect("start time is known")
+    }
+
+    fn from_instants(
+        time_scale: TimeScale,
+        start: Option<DateTime<Utc>>,
+        mid: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        exposure_time: Option<f32>,
+    ) -> Option<Self> {
+        let duration = exposure_time.map(|exposure| seconds(exposure as f64));
+        let (start, end) = match (start, end, duration) {
+            (Some(start), Some(end), _) => (start, end),
+            (Some(start), None, duration) => (start, start + duration.unwrap_or_default()),
+            (None, Some(end), Some(duration)) => (end - duration, end),
+            _ => return None,
+        };
+        let mid = mid.unwrap_or_else(|| start + (end - start) / 2);
+
+        let jd_utc_mid = julian_date(mid);
+        let jd_tt_mid = jd_utc_mid + (tai_minus_utc(mid) + TT_MINUS_TAI) / 86_400.0;
+        let jd_tdb_mid = jd_tt_mid + tdb_minus_tt(jd_tt_mid) / 86_400.0;
+
+        Some(ExposureTiming {
+            time_scale,
+            start,
+            mid,
+            end,
+            jd_utc_mid,
+            mjd_utc_mid: jd_utc_mid - MJD_OFFSET,
+            jd_tdb_mid,
+            hjd_utc_mid: None,
+            bjd_tdb_mid: None,
+        })
+    }
+
+    /// Exposure duration in seconds
+    pub fn duration(&self) -> f64 {
+        (self.end - self.start)
+            .num_microseconds()
+            .unwrap_or_default() as f64
+            * 1e-6
+    }
+
+    /// Fill HJD and BJD_TDB for a target at RA/DEC (ICRS, degrees)
+    pub fn with_target(mut self, ra: f64, dec: f64) -> Self {
+        self.hjd_utc_mid =
+            Some(self.jd_utc_mid + heliocentric_correction(self.jd_tdb_mid, ra, dec) / 86_400.0);
+        self.bjd_tdb_mid =
+            Some(self.jd_tdb_mid + barycentric_correction(self.jd_tdb_mid, ra, dec) / 86_400.0);
+        self
+    }
+}
+
+impl AstroMetadata {
+    /// Build the exposure timing model from the headers
+    ///
+    /// Falls back to `date_obs` when no header places the exposure, and
+    /// corrects `date_obs` to UTC when `TIMESYS` names another scale.
+    pub fn calculate_timing(&mut self) {
+        let exposure_time = self.exposure.exposure_time;
+        let timing = ExposureTiming::from_headers(&self.raw_headers, exposure_time).or_else(|| {
+            self.exposure
+                .date_obs
+                .map(|start| ExposureTiming::from_start(start, exposure_time))
+        });
+
+        self.exposure.timing = timing.map(|timing| {
+            self.exposure.date_obs = Some(timing.start);
+            match self.exposure.ra.zip(self.exposure.dec) {
+                Some((ra, dec)) => timing.with_target(ra, dec),
+                None => timing,
+            }
+        });
+    }
+}
+
+/// Heliocentric light-time correction in seconds
+///
+/// Add to a geocentric time to get the time the light would have reached
+/// the Sun's centre.
+pub fn heliocentric_correction(jd_tdb: f64, ra: f64, dec: f64) -> f64 {
+    dot(earth_heliocentric_position(jd_tdb), unit_vector(ra, dec)) * AU_LIGHT_SECONDS
+}
+
+/// Barycentric light-time (Rømer) correction in seconds
+pub fn barycentric_correction(jd_tdb: f64, ra: f64, dec: f64) -> f64 {
+    dot(earth_barycentric_position(jd_tdb), unit_vector(ra, dec)) * AU_LIGHT_SECONDS
+}
+
+/// Heliocentric position of the Earth-Moon barycentre in AU (ICRS axes)
+pub fn earth_heliocentric_position(jd_tdb: f64) -> [f64; 3] {
+    heliocentric_position(&EARTH_MOON, jd_tdb)
+}
+
+/// Barycentric position of the Earth-Moon barycentre in AU (ICRS axes)
+pub fn earth_barycentric_position(jd_tdb: f64) -> [f64; 3] {
+    let earth = earth_heliocentric_position(jd_tdb);
+
+    // The Sun orbits the barycentre opposite the mass-weighted planets
+    let mut weighted = earth.map(|component| component * EARTH_MOON_MASS);
+    let mut total_mass = 1.0 + EARTH_MOON_MASS;
+    for (mass, elements) in &GIANT_PLANETS {
+        let planet = heliocentric_position(elements, jd_tdb);
+        for (sum, component) in weighted.iter_mut().zip(planet) {
+            *sum += mass * component;
+        }
+        total_mass += mass;
+    }
+
+    std::array::from_fn(|axis| earth[axis] - weighted[axis] / total_mass)
+}
+
+/// TAI - UTC in seconds at a UTC instant
+pub fn tai_minus_utc(time: DateTime<Utc>) -> f64 {
+    let date = time.date_naive();
+    LEAP_SECONDS
+        .iter()
+        .rev()
+        .find(|(year, month, _)| {
+            NaiveDate::from_ymd_opt(*year, *month, 1).is_some_and(|start| date >= start)
+        })
+        .map_or(LEAP_SECONDS[0].2, |(_, _, offset)| *offset)
+}
+
+/// UTC instant of a Julian date
+pub fn from_julian_date(jd: f64) -> DateTime<Utc> {
+    let micros = ((jd - UNIX_EPOCH_JD) * 86_400.0 * 1e6).round() as i64;
+    DateTime::<Utc>::UNIX_EPOCH + Duration::microseconds(micros)
+}
+
+/// TDB - TT in seconds (periodic terms, about 10 μs precision)
+fn tdb_minus_tt(jd: f64) -> f64 {
+    let g = (357.53 + 0.985_600_28 * (jd - J2000)).to_radians();
+    0.001_657 * g.sin() + 0.000_014 * (2.0 * g).sin()
+}
+
+/// Heliocentric position from Keplerian elements, in AU on ICRS axes
+fn heliocentric_position(elements: &OrbitalElements, jd_tdb: f64) -> [f64; 3] {
+    let t = (jd_tdb - J2000) / 36_525.0;
+    let element = |index: usize| elements[index] + elements[index + 6] * t;
+    let (a, e) = (element(0), element(1));
+    let inclination = element(2).to_radians();
+    let mean_longitude = element(3);
+    let perihelion = element(4);
+    let node = element(5);
+
+    let argument = (perihelion - node).to_radians();
+    let mean_anomaly = (mean_longitude - perihelion).rem_euclid(360.0).to_radians();
+    let eccentric_anomaly = solve_kepler(mean_anomaly, e);
+
+    let x_orbit = a * (eccentric_anomaly.cos() - e);
+    let y_orbit = a * (1.0 - e * e).sqrt() * eccentric_anomaly.sin();
+
+    let (sin_w, cos_w) = argument.sin_cos();
+    let (sin_n, cos_n) = node.to_radians().sin_cos();
+    let (sin_i, cos_i) = inclination.sin_cos();
+    let x = (cos_w * cos_n - sin_w * sin_n * cos_i) * x_orbit
+        + (-sin_w * cos_n - cos_w * sin_n * cos_i) * y_orbit;
+    let y = (cos_w * sin_n + sin_w * cos_n * cos_i) * x_orbit
+        + (-sin_w * sin_n + cos_w * cos_n * cos_i) * y_orbit;
+    let z = sin_w * sin_i * x_orbit + cos_w * sin_i * y_orbit;
+
+    // Ecliptic to equatorial J2000
+    let (sin_e, cos_e) = J2000_OBLIQUITY.to_radians().sin_cos();
+    [x, y * cos_e - z * sin_e, y * sin_e + z * cos_e]
+}
+
+/// Solve Kepler's equation M = E - e sin E for E (radians)
+fn solve_kepler(mean_anomaly: f64, e: f64) -> f64 {
+    let mut eccentric_anomaly = mean_anomaly + e * mean_anomaly.sin();
+    for _ in 0..10 {
+        let delta = (eccentric_anomaly - e * eccentric_anomaly.sin() - mean_anomaly)
+            / (1.0 - e * eccentric_anomaly.cos());
+        eccentric_anomaly -= delta;
+        if delta.abs() < 1e-12 {
+            break;
+        }
+    }
+    eccentric_anomaly
+}
+
+fn unit_vector(ra: f64, dec: f64) -> [f64; 3] {
+    let (ra, dec) = (ra.to_radians(), dec.to_radians());
+    [dec.cos() * ra.cos(), dec.cos() * ra.sin(), dec.sin()]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn seconds(value: f64) -> Duration {
+    Duration::microseconds((value * 1e6).round() as i64)
+}
+
+fn header_number(headers: &HashMap<String, String>, key: &str) -> Option<f64> {
+    get_header_value(headers, key)?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected} ± {tolerance}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_start_mid_end_from_date_obs() {
+        let timing = ExposureTiming::from_headers(
+            &headers(&[("DATE-OBS", "2024-01-15T04:00:00.000")]),
+            Some(300.0),
+        )
+        .expect("timing");
+
+        assert_eq!(
+            timing.start,
+            Utc.with_ymd_and_hms(2024, 1, 15, 4, 0, 0).unwrap()
+        );
+        assert_eq!(
+            timing.mid,
+            Utc.with_ymd_and_hms(2024, 1, 15, 4, 2, 30).unwrap()
+        );
+        assert_eq!(
+            timing.end,
+            Utc.with_ymd_and_hms(2024, 1, 15, 4, 5, 0).unwrap()
+        );
+        assert_eq!(timing.duration(), 300.0);
+        assert_close(timing.mjd_utc_mid, 60_324.168_402_78, 1e-8);
+        // TT - UTC = 69.184 s since 2017
+        assert_close(
+            (timing.jd_tdb_mid - timing.jd_utc_mid) * 86_400.0,
+            69.184,
+            0.002,
+        );
+        assert!(timing.bjd_tdb_mid.is_none());
+    }
+
+    #[test]
+    fn test_timesys_and_alternate_keywords() {
+        // TT timestamps are 69.184 s ahead of UTC
+        let timing = ExposureTiming::from_headers(
+            &headers(&[
+                ("TIMESYS", "TT"),
+                ("DATE-OBS", "2024-01-15T04:01:09.184"),
+                ("DATE-END", "2024-01-15T04:03:09.184"),
+            ]),
+            Some(300.0),
+        )
+        .expect("timing");
+        assert_eq!(timing.time_scale, TimeScale::Tt);
+        assert_eq!(
+            timing.start,
+            Utc.with_ymd_and_hms(2024, 1, 15, 4, 0, 0).unwrap()
+        );
+        assert_eq!(timing.duration(), 120.0);
+
+        let timing = ExposureTiming::from_headers(&headers(&[("MJD-OBS", "60324.0")]), Some(60.0))
+            .expect("timing");
+        assert_eq!(
+            timing.start,
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+
+        let timing =
+            ExposureTiming::from_headers(&headers(&[("JD", "2460324.5")]), None).expect("timing");
+        assert_eq!(
+            timing.mid,
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+
+        let timing = ExposureTiming::from_headers(
+            &headers(&[("DATE-END", "2024-01-15T00:01:00")]),
+            Some(60.0),
+        )
+        .expect("timing");
+        assert_eq!(
+            timing.start,
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+
+        assert!(ExposureTiming::from_headers(&headers(&[]), Some(60.0)).is_none());
+
+        // Keys stored in lower case, as from some XISF writers
+        let timing = ExposureTiming::from_headers(
+            &headers(&[("timesys", "TT"), ("mjd-obs", "60324.0")]),
+            Some(60.0),
+        )
+        .expect("timing");
+        assert_eq!(timing.time_scale, TimeScale::Tt);
+        assert_eq!(
+            timing.start,
+            Utc.with_ymd_and_hms(2024, 1, 14, 23, 58, 50).unwrap() + Duration::milliseconds(816)
+        );
+    }
+
+    #[test]
+    fn test_leap_seconds() {
+        assert_eq!(
+            tai_minus_utc(Utc.with_ymd_and_hms(2016, 12, 31, 23, 59, 59).unwrap()),
+            36.0
+        );
+        assert_eq!(
+            tai_minus_utc(Utc.with_ymd_and_hms(2017, 1, 1, 0, 0, 0).unwrap()),
+            37.0
+        );
+        assert_eq!(
+            tai_minus_utc(Utc.with_ymd_and_hms(1999, 6, 1, 0, 0, 0).unwrap()),
+            32.0
+        );
+
+        let gps = Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 18).unwrap();
+        assert_eq!(
+            TimeScale::Gps.to_utc(gps),
+            Utc.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_earth_position_at_j2000() {
+        let earth = earth_heliocentric_position(J2000);
+        assert_close(earth[0], -0.177, 0.005);
+        assert_close(earth[1], 0.887, 0.005);
+        assert_close(earth[2], 0.385, 0.005);
+
+        // The barycentre sits within about 0.01 AU of the Sun's centre
+        let offset = earth_barycentric_position(J2000)
+            .iter()
+            .zip(earth)
+            .map(|(bary, helio)| (bary - helio).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        assert!(offset > 0.001 && offset < 0.011, "offset {offset}");
+    }
+
+    #[test]
+    fn test_light_time_corrections() {
+        // Near the March equinox the Earth lies towards RA 12h
+        let jd = julian_date(Utc.with_ymd_and_hms(2024, 3, 20, 3, 0, 0).unwrap());
+        let helio = heliocentric_correction(jd, 180.0, 0.0);
+        assert_close(helio, 497.0, 3.0);
+        assert_close(barycentric_correction(jd, 180.0, 0.0), helio, 6.0);
+
+        // Targets near the ecliptic pole barely move
+        assert!(heliocentric_correction(jd, 270.0, 66.56).abs() < 5.0);
+
+        let timing = ExposureTiming::from_start(
+            Utc.with_ymd_and_hms(2024, 3, 20, 3, 0, 0).unwrap(),
+            Some(120.0),
+        )
+        .with_target(180.0, 0.0);
+        let bjd = timing.bjd_tdb_mid.expect("bjd");
+        let hjd = timing.hjd_utc_mid.expect("hjd");
+        assert_close((hjd - timing.jd_utc_mid) * 86_400.0, helio, 0.01);
+        assert!(bjd > timing.jd_tdb_mid);
+    }
+
+    #[test]
+    fn test_bjd_tdb_matches_reference() {
+        // IP Peg seen from Greenwich, from the astropy light travel time
+        // documentation: BJD_TDB - JD_TDB is -0.0037715 d and -0.00377286 d,
+        // HJD - JD is -0.00376576 d and -0.00376712 d. The Keplerian
+        // ephemeris and the geocentric observer allow about 0.1 s.
+        let (ra, dec) = (350.785_625, 18.416_472);
+        let cases = [
+            (56_325.958_333_33, -0.003_771_5, -0.003_765_76),
+            (56_325.978_254, -0.003_772_86, -0.003_767_12),
+        ];
+        for (mjd_utc, barycentric, heliocentric) in cases {
+            let start = from_julian_date(mjd_utc + MJD_OFFSET);
+            let timing = ExposureTiming::from_start(start, Some(0.0)).with_target(ra, dec);
+
+            // TDB - UTC is 35 leap seconds plus 32.184 s, give or take 1.7 ms
+            assert_close(
+                (timing.jd_tdb_mid - timing.jd_utc_mid) * 86_400.0,
+                67.184,
+                0.002,
+            );
+            let bjd = timing.bjd_tdb_mid.expect("bjd");
+            let hjd = timing.hjd_utc_mid.expect("hjd");
+            assert_close(
+                (bjd - timing.jd_tdb_mid) * 86_400.0,
+                barycentric * 86_400.0,
+                0.1,
+            );
+            assert_close(
+                (hjd - timing.jd_utc_mid) * 86_400.0,
+                heliocentric * 86_400.0,
+                0.1,
+            );
+        }
+    }
+
+    #[test]
+    fn test_calculate_timing_corrects_date_obs() {
+        let mut metadata = AstroMetadata {
+            raw_headers: headers(&[("TIMESYS", "TAI"), ("DATE-OBS", "2024-01-15T04:00:37")]),
+            ..Default::default()
+        };
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(2024, 1, 15, 4, 0, 37).unwrap());
+        metadata.exposure.exposure_time = Some(10.0);
+        metadata.exposure.ra = Some(83.82);
+        metadata.exposure.dec = Some(-5.39);
+
+        metadata.calculate_timing();
+        let start = Utc.with_ymd_and_hms(2024, 1, 15, 4, 0, 0).unwrap();
+        assert_eq!(metadata.exposure.date_obs, Some(start));
+        let timing = metadata.exposure.timing.expect("timing");
+        assert_eq!(timing.mid, start + Duration::seconds(5));
+        assert!(timing.bjd_tdb_mid.is_some());
+    }
+}
@@ -4,14 +4,20 @@
 //! astronomical image files, including equipment information, detector
 //! settings, filters, exposure details, and more.
 
-use chrono::{DateTime, Utc};
-use serde::Serialize;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use astro_io::fits::FitsHeaderCard;
 
+use crate::capture_software::SourceSoftware;
+use crate::ephemeris::{self, AirmassFormula, DerivedMetadata};
+use crate::session::{self, SessionSettings};
+use crate::timing::ExposureTiming;
+
 /// Core metadata structure with nested components for astronomical images
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct AstroMetadata {
     /// Equipment information
     pub equipment: Equipment,
@@ -25,8 +31,12 @@ pub struct AstroMetadata {
     pub mount: Option<Mount>,
     /// Environmental data
     pub environment: Option<Environment>,
+    /// Software that created the image (vendor and version)
+    pub source_software: Option<SourceSoftware>,
     /// World Coordinate System data
     pub wcs: Option<WcsData>,
+    /// Observing conditions computed from time, site and target
+    pub derived: Option<DerivedMetadata>,
     /// XISF-specific metadata
     pub xisf: Option<XisfMetadata>,
     /// Color management information
@@ -42,7 +52,7 @@ pub struct AstroMetadata {
 }
 
 /// Equipment information
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Equipment {
     /// Telescope make/model
     pub telescope_name: Option<String>,
@@ -63,7 +73,7 @@ pub struct Equipment {
 }
 
 /// Detector and camera settings
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Detector {
     /// Camera make/model
     pub camera_name: Option<String>,
@@ -77,8 +87,10 @@ pub struct Detector {
     pub binning_x: usize,
     /// Binning in Y direction
     pub binning_y: usize,
-    /// Gain in e-/ADU
-    pub gain: Option<f32>,
+    /// Camera gain setting as reported by the driver (unitless, e.g. 100)
+    pub gain_setting: Option<f32>,
+    /// Conversion gain in e-/ADU
+    pub electrons_per_adu: Option<f32>,
     /// Camera offset value
     pub offset: Option<i32>,
     /// Camera readout mode
@@ -102,7 +114,7 @@ pub struct Detector {
 }
 
 /// Filter information
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Filter {
     /// Filter name
     pub name: Option<String>,
@@ -110,10 +122,14 @@ pub struct Filter {
     pub position: Option<usize>,
     /// Filter wavelength in nm
     pub wavelength: Option<f32>,
+    /// Canonical band from the filter database, e.g. "Ha"
+    pub band: Option<String>,
+    /// Filter bandwidth in nm
+    pub bandwidth: Option<f32>,
 }
 
 /// Exposure and timing information
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Exposure {
     /// Object/target name
     pub object_name: Option<String>,
@@ -123,11 +139,19 @@ pub struct Exposure {
     pub dec: Option<f64>,
     /// Observation date/time (UTC)
     pub date_obs: Option<DateTime<Utc>>,
-    /// Session date (calculated from date_obs by subtracting 12 hours)
+    /// Local civil observation time from `DATE-LOC` (no time zone)
+    pub date_loc: Option<NaiveDateTime>,
+    /// Exposure start, midpoint and end with Julian dates
+    pub timing: Option<ExposureTiming>,
+    /// Session date at noon (local time of date_obs minus 12 hours)
     pub session_date: Option<DateTime<Utc>>,
+    /// Local calendar date of the evening the session started
+    pub session_local_date: Option<NaiveDate>,
+    /// Time zone of the session date (IANA name, `UTC±hh:mm` or `solar`)
+    pub session_timezone: Option<String>,
     /// Exposure time in seconds
     pub exposure_time: Option<f32>,
-    /// Frame type (LIGHT, DARK, BIAS, FLAT)
+    /// Raw frame type (LIGHT, DARK, BIAS, FLAT); see `Exposure::frame_kind`
     pub frame_type: Option<String>,
     /// Sequence identifier
     pub sequence_id: Option<String>,
@@ -144,7 +168,7 @@ pub struct Exposure {
 }
 
 /// Mount and guiding information
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Mount {
     /// Mount side of pier (EAST, WEST)
     pub pier_side: Option<String>,
@@ -171,7 +195,7 @@ pub struct Mount {
 }
 
 /// Environmental data
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Environment {
     /// Ambient temperature in °C
     pub ambient_temp: Option<f32>,
@@ -192,7 +216,7 @@ pub struct Environment {
 }
 
 /// World Coordinate System data
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct WcsData {
     /// Coordinate type for axis 1
     pub ctype1: Option<String>,
@@ -225,7 +249,7 @@ pub struct WcsData {
 }
 
 /// XISF-specific metadata
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct XisfMetadata {
     /// XISF format version
     pub version: String,
@@ -238,7 +262,7 @@ pub struct XisfMetadata {
 }
 
 /// Color management information
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ColorManagement {
     /// Color space (RGB, Gray, etc.)
     pub color_space: Option<String>,
@@ -249,7 +273,7 @@ pub struct ColorManagement {
 }
 
 /// Display function parameters
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct DisplayFunction {
     /// Display function type
     pub function_type: Option<String>,
@@ -258,7 +282,7 @@ pub struct DisplayFunction {
 }
 
 /// Information about an image attachment
-#[derive(Debug, Clone, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 pub struct AttachmentInfo {
     /// Attachment identifier
     pub id: String,
@@ -314,34 +338,54 @@ impl AstroMetadata {
     }
 
     /// Calculate approximate time zone offset in hours from longitude
-    fn approximate_timezone_from_longitude(&self) -> Option<i32> {
+    pub(crate) fn approximate_timezone_from_longitude(&self) -> Option<i32> {
         self.mount
             .as_ref()
             .and_then(|mount| mount.longitude)
             .map(|longitude| (longitude / 15.0).round() as i32)
     }
 
-    /// Calculate the session date using location information if available
+    /// Calculate the session date in civil local time
+    ///
+    /// Local time comes from `DATE-LOC`, a `TIMEZONE`/`TZ` header, the
+    /// bundled site table, or the longitude, in that order. See [`session`] for details.
     pub fn calculate_session_date(&mut self) {
-        if let Some(date_obs) = self.exposure.date_obs {
-            // Default to UTC time
-            let mut local_time = date_obs;
-
-            // If we have longitude information, adjust for approximate local time
-            if let Some(tz_offset) = self.approximate_timezone_from_longitude() {
-                local_time = date_obs + chrono::Duration::hours(tz_offset as i64);
-            }
-
-            // Get the date at noon (12:00) on the same day in the adjusted time
-            let naive_noon = local_time.date_naive().and_hms_opt(12, 0, 0).unwrap();
-            let noon = DateTime::from_naive_utc_and_offset(naive_noon, Utc);
-
-            // If the adjusted observation time is before noon, use the previous day's noon
-            self.exposure.session_date = if local_time < noon {
-                Some(noon - chrono::Duration::days(1))
-            } else {
-                Some(noon)
-            };
+        self.calculate_session_date_with(&SessionSettings::builtin());
+    }
+
+    /// Calculate the session date with a specific mode, site table and
+    /// observatory time zone
+    pub fn calculate_session_date_with(&mut self, settings: &SessionSettings) {
+        let session = session::resolve_session_date(self, settings);
+        self.exposure.session_date = session.as_ref().map(|session| session.noon());
+        self.exposure.session_local_date = session.as_ref().map(|session| session.local_date);
+        self.exposure.session_timezone = session.map(|session| session.timezone);
+    }
+
+    /// Compute observing conditions at mid-exposure
+    ///
+    /// Stores the result in `derived` and fills `WcsData` altitude, azimuth
+    /// and airmass when the headers lack them.
+    pub fn calculate_ephemeris(&mut self) {
+        self.derived = ephemeris::derive(self);
+        let Some(derived) = &self.derived else {
+            return;
+        };
+        if derived.altitude.is_none() {
+            return;
+        }
+
+        let wcs = self.wcs.get_or_insert_with(WcsData::default);
+        if wcs.altitude.is_none() {
+            wcs.altitude = derived.altitude.map(|altitude| altitude as f32);
+        }
+        if wcs.azimuth.is_none() {
+            wcs.azimuth = derived.azimuth.map(|azimuth| azimuth as f32);
+        }
+        if wcs.airmass.is_none() {
+            wcs.airmass = derived
+                .airmass
+                .map(|airmass| airmass.get(AirmassFormula::default()) as f32);
         }
     }
 }
@@ -471,5 +515,13 @@ mod tests {
         metadata.calculate_session_date();
         let expected_session = Utc.with_ymd_and_hms(2023, 5, 14, 12, 0, 0).unwrap();
         assert_eq!(metadata.exposure.session_date, Some(expected_session));
+        assert_eq!(
+            metadata.exposure.session_local_date,
+            NaiveDate::from_ymd_opt(2023, 5, 14)
+        );
+        assert_eq!(
+            metadata.exposure.session_timezone.as_deref(),
+            Some("UTC-05:00")
+        );
     }
 }
@@ -0,0 +1,811 @@
+//! Metadata validation rules
+//!
+//! Frames with bad metadata are often bad frames: a zero exposure time, a
+//! missing frame type, a sensor far from its setpoint or a clock reset to
+//! 1970. `Validator` runs a set of built-in rules over `AstroMetadata`, per
+//! file and across the files of a folder (e.g. the gain changing within a
+//! sequence). Every rule has an id, a default severity and an explanation;
+//! `ValidationConfig` disables rules, overrides severities and sets
+//! thresholds.
+
+use anyhow::Result;
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::frame_kind::FrameKind;
+use super::keywords::get_header_value;
+use super::organizer::scan_frames;
+use super::types::AstroMetadata;
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth knowing, the frame is usable
+    Info,
+    /// Probably a problem
+    Warning,
+    /// The frame or its metadata is unusable
+    Error,
+}
+
+/// Whether a rule looks at one file or a group of files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleScope {
+    /// Checked on each file
+    File,
+    /// Checked across the files of a folder
+    Folder,
+}
+
+/// A built-in validation rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    /// Stable identifier used in configuration and reports
+    pub id: &'static str,
+    /// Severity unless overridden
+    pub severity: Severity,
+    /// Per file or across files
+    pub scope: RuleScope,
+    /// Why the rule matters
+    pub explanation: &'static str,
+}
+
+/// Every built-in rule
+pub const RULES: &[Rule] = &[
+    Rule {
+        id: "unreadable",
+        severity: Severity::Error,
+        scope: RuleScope::File,
+        explanation: "The header could not be read, so the file is damaged or not a frame.",
+    },
+    Rule {
+        id: "exposure-time",
+        severity: Severity::Error,
+        scope: RuleScope::File,
+        explanation: "EXPTIME is missing or not positive on a non-bias frame, so the frame \
+                      cannot be scaled or matched to darks.",
+    },
+    Rule {
+        id: "frame-type",
+        severity: Severity::Warning,
+        scope: RuleScope::File,
+        explanation: "IMAGETYP is missing or not a known frame type, so the frame cannot be \
+                      sorted into lights and calibration frames.",
+    },
+    Rule {
+        id: "object-name",
+        severity: Severity::Warning,
+        scope: RuleScope::File,
+        explanation: "A light frame has no OBJECT, so it cannot be grouped by target.",
+    },
+    Rule {
+        id: "sensor-temperature",
+        severity: Severity::Warning,
+        scope: RuleScope::File,
+        explanation: "CCD-TEMP is far from SET-TEMP; the cooler had not settled or could not \
+                      reach the setpoint, so dark current differs from the matching darks.",
+    },
+    Rule {
+        id: "mono-filter",
+        severity: Severity::Warning,
+        scope: RuleScope::File,
+        explanation: "A light frame from a monochrome camera has no FILTER, so it cannot be \
+                      assigned to a channel or matched to flats.",
+    },
+    Rule {
+        id: "date-obs",
+        severity: Severity::Error,
+        scope: RuleScope::File,
+        explanation: "DATE-OBS is missing, implausibly early (e.g. 1970 from a reset clock) or \
+                      in the future, so session dates and ephemerides are wrong.",
+    },
+    Rule {
+        id: "target-coordinates",
+        severity: Severity::Info,
+        scope: RuleScope::File,
+        explanation: "A light frame has no RA/DEC, so altitude, airmass and Moon separation \
+                      cannot be computed.",
+    },
+    Rule {
+        id: "site-location",
+        severity: Severity::Info,
+        scope: RuleScope::File,
+        explanation: "SITELAT/SITELONG are missing, so session dates fall back to UTC and no \
+                      ephemeris is computed.",
+    },
+    Rule {
+        id: "setting-changed",
+        severity: Severity::Warning,
+        scope: RuleScope::Folder,
+        explanation: "Gain, offset or binning changed within a sequence of light frames, so \
+                      the frames need different calibration frames and do not stack cleanly.",
+    },
+    Rule {
+        id: "camera-changed",
+        severity: Severity::Warning,
+        scope: RuleScope::Folder,
+        explanation: "More than one camera appears within one session in the same folder, \
+                      which usually means frames were mixed up.",
+    },
+];
+
+/// Rule selection and thresholds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidationConfig {
+    /// Ids of rules that are not run
+    pub disabled_rules: Vec<String>,
+    /// Severity overrides by rule id
+    pub severity_overrides: HashMap<String, Severity>,
+    /// Largest accepted |CCD-TEMP - SET-TEMP| in °C
+    pub max_temperature_deviation: f32,
+    /// Earliest plausible observation date
+    pub earliest_date: NaiveDate,
+    /// How far in the future DATE-OBS may be, in hours
+    pub max_clock_skew_hours: i64,
+}
+
+/// A rule violation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Id of the rule
+    pub rule: &'static str,
+    /// Effective severity
+    pub severity: Severity,
+    /// What was found
+    pub message: String,
+    /// Files involved (empty for single-file findings)
+    pub files: Vec<PathBuf>,
+}
+
+/// Findings for one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    /// File path
+    pub path: PathBuf,
+    /// Single-file findings
+    pub findings: Vec<Finding>,
+}
+
+/// Findings for the files of one folder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FolderReport {
+    /// Folder path
+    pub folder: PathBuf,
+    /// Per-file findings
+    pub files: Vec<FileReport>,
+    /// Findings across files of the folder
+    pub findings: Vec<Finding>,
+}
+
+/// Findings for a set of folders
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// One report per folder, sorted by path
+    pub folders: Vec<FolderReport>,
+}
+
+/// Runs validation rules
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    config: ValidationConfig,
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        ValidationConfig {
+            disabled_rules: Vec::new(),
+            severity_overrides: HashMap::new(),
+            max_temperature_deviation: 2.0,
+            earliest_date: NaiveDate::from_ymd_opt(2000, 1, 1).expect("valid date"),
+            max_clock_skew_hours: 24,
+        }
+    }
+}
+
+impl Rule {
+    /// Look up a built-in rule by id
+    pub fn find(id: &str) -> Option<&'static Rule> {
+        RULES.iter().find(|rule| rule.id == id)
+    }
+}
+
+impl Validator {
+    /// Create a validator with a configuration
+    pub fn new(config: ValidationConfig) -> Self {
+        Validator { config }
+    }
+
+    /// The active configuration
+    pub fn config(&self) -> &ValidationConfig {
+        &self.config
+    }
+
+    /// Run the single-file rules on one frame
+    pub fn validate_file(&self, metadata: &AstroMetadata) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut report = |rule: &'static str, message: String| {
+            if let Some(finding) = self.finding(rule, message, Vec::new()) {
+                findings.push(finding);
+            }
+        };
+
+        let exposure = &metadata.exposure;
+        let kind = exposure.frame_kind();
+        let is_light = kind == FrameKind::Light;
+        let is_bias = kind.base() == FrameKind::Bias;
+
+        match exposure.exposure_time {
+            None if !is_bias => report("exposure-time", "EXPTIME is missing".to_string()),
+            Some(time) if time <= 0.0 && !is_bias => {
+                report("exposure-time", format!("EXPTIME is {time}"))
+            }
+            _ => {}
+        }
+
+        match exposure.frame_type.as_deref() {
+            None => report("frame-type", "IMAGETYP is missing".to_string()),
+            Some(value) if kind == FrameKind::Unknown => report(
+                "frame-type",
+                format!("IMAGETYP '{value}' is not a known frame type"),
+            ),
+            _ => {}
+        }
+
+        if is_light && exposure.object_name.is_none() {
+            report(
+                "object-name",
+                "OBJECT is missing on a light frame".to_string(),
+            );
+        }
+
+        if let (Some(temperature), Some(setpoint)) = (
+            metadata.detector.temperature,
+            metadata.detector.temp_setpoint,
+        ) {
+            let deviation = (temperature - setpoint).abs();
+            if deviation > self.config.max_temperature_deviation {
+                report(
+                    "sensor-temperature",
+                    format!(
+                        "CCD-TEMP {temperature:.1} °C is {deviation:.1} °C from SET-TEMP {setpoint:.1} °C"
+                    ),
+                );
+            }
+        }
+
+        if is_light && metadata.filter.name.is_none() && is_mono_camera(metadata) {
+            report(
+                "mono-filter",
+                "FILTER is missing on a monochrome light frame".to_string(),
+            );
+        }
+
+        match exposure.date_obs {
+            None => report("date-obs", "DATE-OBS is missing".to_string()),
+            Some(date) if date.date_naive() < self.config.earliest_date => report(
+                "date-obs",
+                format!(
+                    "DATE-OBS {} is before {}",
+                    date.to_rfc3339(),
+                    self.config.earliest_date
+                ),
+            ),
+            Some(date) if date > Utc::now() + Duration::hours(self.config.max_clock_skew_hours) => {
+                report(
+                    "date-obs",
+                    format!("DATE-OBS {} is in the future", date.to_rfc3339()),
+                )
+            }
+            _ => {}
+        }
+
+        if is_light && (exposure.ra.is_none() || exposure.dec.is_none()) {
+            report("target-coordinates", "RA/DEC are missing".to_string());
+        }
+
+        let has_site = metadata
+            .mount
+            .as_ref()
+            .is_some_and(|mount| mount.latitude.is_some() && mount.longitude.is_some());
+        if !has_site {
+            report("site-location", "SITELAT/SITELONG are missing".to_string());
+        }
+
+        findings
+    }
+
+    /// Run the cross-file rules on a group of frames
+    pub fn validate_group(&self, frames: &[(PathBuf, AstroMetadata)]) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        // Settings must be constant within a sequence of lights
+        let mut sequences: BTreeMap<String, Vec<&(PathBuf, AstroMetadata)>> = BTreeMap::new();
+        for frame in frames {
+            if frame.1.exposure.frame_kind() == FrameKind::Light {
+                sequences
+                    .entry(sequence_key(&frame.1))
+                    .or_default()
+                    .push(frame);
+            }
+        }
+        for (sequence, members) in &sequences {
+            let settings: [(&str, SettingFn); 3] = [
+                ("gain", |metadata| {
+                    metadata.detector.gain_setting.map(|gain| gain.to_string())
+                }),
+                ("offset", |metadata| {
+                    metadata.detector.offset.map(|offset| offset.to_string())
+                }),
+                ("binning", |metadata| {
+                    Some(format!(
+                        "{}x{}",
+                        metadata.detector.binning_x, metadata.detector.binning_y
+                    ))
+                }),
+            ];
+            for (setting, value) in settings {
+                if let Some((values, outliers)) = minority_values(members, value) {
+                    let message = format!("{setting} changes within {sequence}: {values}");
+                    findings.extend(self.finding("setting-changed", message, outliers));
+                }
+            }
+        }
+
+        // One camera per session
+        let mut sessions: BTreeMap<String, Vec<&(PathBuf, AstroMetadata)>> = BTreeMap::new();
+        for frame in frames {
+            if let Some(date) = frame.1.exposure.session_local_date {
+                sessions.entry(date.to_string()).or_default().push(frame);
+            }
+        }
+        for (session, members) in &sessions {
+            if let Some((values, outliers)) =
+                minority_values(members, |metadata| metadata.detector.camera_name.clone())
+            {
+                let message = format!("several cameras in session {session}: {values}");
+                findings.extend(self.finding("camera-changed", message, outliers));
+            }
+        }
+
+        findings
+    }
+
+    /// Validate frames, grouping them by parent folder
+    pub fn validate_frames(&self, frames: &[(PathBuf, AstroMetadata)]) -> ValidationReport {
+        let mut folders: BTreeMap<PathBuf, Vec<(PathBuf, AstroMetadata)>> = BTreeMap::new();
+        for (path, metadata) in frames {
+            let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            folders
+                .entry(folder)
+                .or_default()
+                .push((path.clone(), metadata.clone()));
+        }
+
+        ValidationReport {
+            folders: folders
+                .into_iter()
+                .map(|(folder, frames)| FolderReport {
+                    files: frames
+                        .iter()
+                        .map(|(path, metadata)| FileReport {
+                            path: path.clone(),
+                            findings: self.validate_file(metadata),
+                        })
+                        .collect(),
+                    findings: self.validate_group(&frames),
+                    folder,
+                })
+                .collect(),
+        }
+    }
+
+    /// Validate every FITS/XISF frame under a folder
+    ///
+    /// Files whose header cannot be read are reported by the `unreadable` rule.
+    pub fn validate_dir(&self, dir: &Path) -> Result<ValidationReport> {
+        let (frames, unreadable) = scan_frames(dir)?;
+        let mut report = self.validate_frames(&frames);
+
+        for skipped in unreadable {
+            let Some(finding) = self.finding("unreadable", skipped.reason, Vec::new()) else {
+                continue;
+            };
+            let folder = skipped
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            let index = match report.folders.binary_search_by(|f| f.folder.cmp(&folder)) {
+                Ok(index) => index,
+                Err(index) => {
+                    report.folders.insert(
+                        index,
+                        FolderReport {
+                            folder,
+                            files: Vec::new(),
+                            findings: Vec::new(),
+                        },
+                    );
+                    index
+                }
+            };
+            report.folders[index].files.push(FileReport {
+                path: skipped.path,
+                findings: vec![finding],
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Build a finding unless the rule is disabled
+    fn finding(&self, rule: &'static str, message: String, files: Vec<PathBuf>) -> Option<Finding> {
+        if self.config.disabled_rules.iter().any(|id| id == rule) {
+            return None;
+        }
+        let severity = self
+            .config
+            .severity_overrides
+            .get(rule)
+            .copied()
+            .or_else(|| Rule::find(rule).map(|rule| rule.severity))?;
+
+        Some(Finding {
+            rule,
+            severity,
+            message,
+            files,
+        })
+    }
+}
+
+impl FolderReport {
+    /// Every finding in the folder, per-file and cross-file
+    pub fn all_findings(&self) -> impl Iterator<Item = &Finding> {
+        self.files
+            .iter()
+            .flat_map(|file| &file.findings)
+            .chain(&self.findings)
+    }
+}
+
+impl ValidationReport {
+    /// Number of findings at each severity
+    pub fn counts(&self) -> BTreeMap<Severity, usize> {
+        let mut counts = BTreeMap::new();
+        for finding in self.folders.iter().flat_map(FolderReport::all_findings) {
+            *counts.entry(finding.severity).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Highest severity found, if any
+    pub fn worst(&self) -> Option<Severity> {
+        self.counts().keys().next_back().copied()
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for folder in &self.folders {
+            writeln!(f, "{}", folder.folder.display())?;
+            for file in &folder.files {
+                for finding in &file.findings {
+                    let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+                    writeln!(
+                        f,
+                        "  {name}: {} [{}] {}",
+                        finding.severity, finding.rule, finding.message
+                    )?;
+                }
+            }
+            for finding in &folder.findings {
+                writeln!(
+                    f,
+                    "  {} file(s): {} [{}] {}",
+                    finding.files.len(),
+                    finding.severity,
+                    finding.rule,
+                    finding.message
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads one acquisition setting as text
+type SettingFn = fn(&AstroMetadata) -> Option<String>;
+
+/// Headers only written for sensors with a color filter array
+const BAYER_KEYWORDS: [&str; 4] = ["BAYERPAT", "COLORTYP", "XBAYROFF", "YBAYROFF"];
+
+/// Camera name words marking a DSLR, mirrorless or one-shot-color camera
+const COLOR_CAMERA_WORDS: &[&str] = &[
+    "CANON",
+    "EOS",
+    "NIKON",
+    "SONY",
+    "ILCE",
+    "FUJIFILM",
+    "PENTAX",
+    "OLYMPUS",
+    "PANASONIC",
+    "LUMIX",
+    "DSLR",
+    "OSC",
+    "COLOR",
+    "COLOUR",
+];
+
+/// True when the camera is known and has no Bayer matrix
+fn is_mono_camera(metadata: &AstroMetadata) -> bool {
+    if BAYER_KEYWORDS
+        .iter()
+        .any(|key| get_header_value(&metadata.raw_headers, key).is_some())
+    {
+        return false;
+    }
+
+    let Some(camera) = metadata.detector.camera_name.as_deref() else {
+        return false;
+    };
+    let camera = camera.to_ascii_uppercase();
+    !camera
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| {
+            // Color astro cameras are named like "ASI2600MC" or "QHY268C"
+            let model = word.trim_end_matches(char::is_alphabetic);
+            let suffix = &word[model.len()..];
+            COLOR_CAMERA_WORDS.contains(&word)
+                || (model.ends_with(|c: char| c.is_ascii_digit()) && matches!(suffix, "MC" | "C"))
+        })
+}
+
+/// Sequence a light frame belongs to
+fn sequence_key(metadata: &AstroMetadata) -> String {
+    let exposure = &metadata.exposure;
+    match &exposure.sequence_id {
+        Some(sequence) => format!("sequence {sequence}"),
+        None => format!(
+            "{} {} {}",
+            exposure
+                .session_local_date
+                .map(|date| date.to_string())
+                .unwrap_or_else(|| "unknown session".to_string()),
+            exposure.object_name.as_deref().unwrap_or("unknown target"),
+            metadata
+                .filter
+                .band
+                .as_deref()
+                .or(metadata.filter.name.as_deref())
+                .unwrap_or("no filter"),
+        ),
+    }
+}
+
+/// Distinct values in a group and the files that deviate from the most common one
+fn minority_values(
+    members: &[&(PathBuf, AstroMetadata)],
+    value: impl Fn(&AstroMetadata) -> Option<String>,
+) -> Option<(String, Vec<PathBuf>)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for (_, metadata) in members {
+        if let Some(value) = value(metadata) {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+    }
+    if counts.len() < 2 {
+        return None;
+    }
+
+    let majority = counts
+        .iter()
+        .max_by_key(|(_, count)| **count)
+        .map(|(value, _)| value.clone())?;
+    let outliers = members
+        .iter()
+        .filter(|(_, metadata)| value(metadata).is_some_and(|value| value != majority))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let values = counts.keys().cloned().collect::<Vec<_>>().join(", ");
+    Some((values, outliers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Mount;
+    use chrono::TimeZone;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn light(camera: &str, gain: f32) -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        metadata.exposure.frame_type = Some("LIGHT".to_string());
+        metadata.exposure.object_name = Some("M31".to_string());
+        metadata.exposure.exposure_time = Some(300.0);
+        metadata.exposure.ra = Some(10.68);
+        metadata.exposure.dec = Some(41.27);
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(2024, 9, 4, 1, 0, 0).unwrap());
+        metadata.exposure.session_local_date = NaiveDate::from_ymd_opt(2024, 9, 3);
+        metadata.exposure.sequence_id = Some("M31 LRGB".to_string());
+        metadata.detector.camera_name = Some(camera.to_string());
+        metadata.detector.gain_setting = Some(gain);
+        metadata.detector.binning_x = 1;
+        metadata.detector.binning_y = 1;
+        metadata.filter.name = Some("L".to_string());
+        metadata.mount = Some(Mount {
+            latitude: Some(52.5),
+            longitude: Some(13.4),
+            ..Default::default()
+        });
+        metadata
+    }
+
+    fn unique_temp_dir(prefix: &str) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+
+        std::env::temp_dir().join(format!(
+            "astro-metadata-{prefix}-{}-{timestamp}",
+            std::process::id()
+        ))
+    }
+
+    fn rules(findings: &[Finding]) -> Vec<&'static str> {
+        findings.iter().map(|finding| finding.rule).collect()
+    }
+
+    #[test]
+    fn test_clean_light_has_no_findings() {
+        assert!(Validator::default()
+            .validate_file(&light("ZWO ASI2600MM Pro", 100.0))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_single_file_rules() {
+        let mut metadata = light("ZWO ASI2600MM Pro", 100.0);
+        metadata.exposure.exposure_time = Some(0.0);
+        metadata.exposure.date_obs = Some(Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap());
+        metadata.detector.temperature = Some(-4.0);
+        metadata.detector.temp_setpoint = Some(-10.0);
+        metadata.filter.name = None;
+        metadata.mount = None;
+
+        let findings = Validator::default().validate_file(&metadata);
+        assert_eq!(
+            rules(&findings),
+            [
+                "exposure-time",
+                "sensor-temperature",
+                "mono-filter",
+                "date-obs",
+                "site-location"
+            ]
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+
+        // Color cameras need no filter; bias frames may have no exposure time
+        for camera in [
+            "ZWO ASI2600MC Pro",
+            "Canon EOS 6D",
+            "NIKON D810A",
+            "Sony ILCE-7M3",
+        ] {
+            let mut color = light(camera, 100.0);
+            color.filter.name = None;
+            assert!(
+                Validator::default().validate_file(&color).is_empty(),
+                "{camera}"
+            );
+        }
+        let mut bayer = light("Atik Horizon", 100.0);
+        bayer.filter.name = None;
+        bayer
+            .raw_headers
+            .insert("XBAYROFF".to_string(), "0".to_string());
+        assert!(Validator::default().validate_file(&bayer).is_empty());
+
+        let mut bias = light("ZWO ASI2600MM Pro", 100.0);
+        bias.exposure.frame_type = Some("BIAS".to_string());
+        bias.exposure.exposure_time = Some(0.0);
+        assert!(Validator::default().validate_file(&bias).is_empty());
+    }
+
+    #[test]
+    fn test_config_disables_and_overrides() -> anyhow::Result<()> {
+        let config: ValidationConfig = serde_json::from_str(
+            r#"{
+                "disabled_rules": ["site-location"],
+                "severity_overrides": {"sensor-temperature": "error"},
+                "max_temperature_deviation": 0.5
+            }"#,
+        )?;
+        let mut metadata = light("ZWO ASI2600MM Pro", 100.0);
+        metadata.mount = None;
+        metadata.detector.temperature = Some(-9.0);
+        metadata.detector.temp_setpoint = Some(-10.0);
+
+        let findings = Validator::new(config).validate_file(&metadata);
+        assert_eq!(rules(&findings), ["sensor-temperature"]);
+        assert_eq!(findings[0].severity, Severity::Error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_file_rules_and_folder_report() {
+        let frames = vec![
+            (
+                PathBuf::from("/night/a.fits"),
+                light("ZWO ASI2600MM Pro", 100.0),
+            ),
+            (
+                PathBuf::from("/night/b.fits"),
+                light("ZWO ASI2600MM Pro", 100.0),
+            ),
+            (
+                PathBuf::from("/night/c.fits"),
+                light("ZWO ASI2600MM Pro", 0.0),
+            ),
+            (
+                PathBuf::from("/night/d.fits"),
+                light("ZWO ASI6200MM Pro", 100.0),
+            ),
+            (
+                PathBuf::from("/other/e.fits"),
+                light("ZWO ASI294MM Pro", 120.0),
+            ),
+        ];
+
+        let report = Validator::default().validate_frames(&frames);
+        assert_eq!(report.folders.len(), 2);
+
+        let night = &report.folders[0];
+        assert_eq!(night.folder, PathBuf::from("/night"));
+        assert_eq!(
+            rules(&night.findings),
+            ["setting-changed", "camera-changed"]
+        );
+        assert_eq!(night.findings[0].files, [PathBuf::from("/night/c.fits")]);
+        assert_eq!(night.findings[1].files, [PathBuf::from("/night/d.fits")]);
+        assert!(report.folders[1].findings.is_empty());
+
+        assert_eq!(report.worst(), Some(Severity::Warning));
+        assert_eq!(report.counts().get(&Severity::Warning), Some(&2));
+        assert!(report.to_string().contains("[camera-changed]"));
+    }
+
+    #[test]
+    fn test_validate_dir_reports_unreadable_files() -> anyhow::Result<()> {
+        let dir = unique_temp_dir("validation");
+        std::fs::create_dir_all(dir.join("night"))?;
+        std::fs::write(dir.join("night").join("broken.xisf"), b"not a frame")?;
+
+        let report = Validator::default().validate_dir(&dir);
+        std::fs::remove_dir_all(&dir)?;
+        let report = report?;
+
+        assert_eq!(report.folders.len(), 1);
+        assert_eq!(rules(&report.folders[0].files[0].findings), ["unreadable"]);
+        assert_eq!(report.worst(), Some(Severity::Error));
+        Ok(())
+    }
+}
@@ -5,17 +5,40 @@
 
 use anyhow::{Context, Result};
 use astro_io::fits::{header_cards_to_map, FitsHeaderCard};
-use chrono::{DateTime, NaiveDateTime, Utc};
-use log::warn;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Read, Seek};
 use std::path::Path;
 
+use super::capture_software;
+use super::keywords::{parse_date_time, KeywordRegistry};
+use super::tables::MetadataTables;
 use super::types::{AstroMetadata, AttachmentInfo, ColorManagement, DisplayFunction, XisfMetadata};
 
 /// Extract metadata from an XISF file
 pub fn extract_metadata<R: Read + Seek>(reader: &mut R) -> Result<AstroMetadata> {
+    extract_metadata_with_registry(reader, KeywordRegistry::builtin())
+}
+
+/// Extract metadata from an XISF file using a custom keyword registry
+pub fn extract_metadata_with_registry<R: Read + Seek>(
+    reader: &mut R,
+    registry: &KeywordRegistry,
+) -> Result<AstroMetadata> {
+    extract_metadata_with_tables(
+        reader,
+        &MetadataTables {
+            keywords: registry,
+            ..MetadataTables::builtin()
+        },
+    )
+}
+
+/// Extract metadata from an XISF file using custom lookup tables
+pub fn extract_metadata_with_tables<R: Read + Seek>(
+    reader: &mut R,
+    tables: &MetadataTables,
+) -> Result<AstroMetadata> {
     let mut metadata = AstroMetadata::default();
     metadata.detector.binning_x = 1;
     metadata.detector.binning_y = 1;
@@ -48,8 +71,16 @@ pub fn extract_metadata<R: Read + Seek>(reader: &mut R) -> Result<AstroMetadata>
 
     // Extract XML content from the header
     if let Ok(xml_content) = extract_xml_content(reader, header_size) {
-        // Extract FITS keywords from the XML
-        extract_fits_keywords(&xml_content, &mut metadata, &mut raw_header_cards);
+        // Extract FITS keywords from the XML and map them onto metadata fields
+        // using the capture software's profile
+        extract_fits_keywords(&xml_content, &mut raw_header_cards);
+        let creator = extract_property_value(&xml_content, "XISF:CreatorApplication");
+        capture_software::apply_headers(
+            tables.keywords,
+            &header_cards_to_map(&raw_header_cards),
+            creator.as_deref(),
+            &mut metadata,
+        );
 
         // Extract other metadata from XML attributes
         extract_xml_attributes(&xml_content, &mut metadata);
@@ -69,16 +100,37 @@ pub fn extract_metadata<R: Read + Seek>(reader: &mut R) -> Result<AstroMetadata>
     metadata.raw_header_cards = raw_header_cards;
     metadata.xisf = Some(xisf_metadata);
 
-    // Calculate session date
-    metadata.calculate_session_date();
+    tables.complete(&mut metadata);
 
     Ok(metadata)
 }
 
 /// Extract metadata from an XISF file path
 pub fn extract_metadata_from_path(path: &Path) -> Result<AstroMetadata> {
+    extract_metadata_from_path_with_registry(path, KeywordRegistry::builtin())
+}
+
+/// Extract metadata from an XISF file path using a custom keyword registry
+pub fn extract_metadata_from_path_with_registry(
+    path: &Path,
+    registry: &KeywordRegistry,
+) -> Result<AstroMetadata> {
+    extract_metadata_from_path_with_tables(
+        path,
+        &MetadataTables {
+            keywords: registry,
+            ..MetadataTables::builtin()
+        },
+    )
+}
+
+/// Extract metadata from an XISF file path using custom lookup tables
+pub fn extract_metadata_from_path_with_tables(
+    path: &Path,
+    tables: &MetadataTables,
+) -> Result<AstroMetadata> {
     let mut file = File::open(path).context("Failed to open XISF file")?;
-    extract_metadata(&mut file)
+    extract_metadata_with_tables(&mut file, tables)
 }
 
 /// Extract XML content from the XISF header
@@ -112,11 +164,7 @@ fn extract_xml_content<R: Read>(reader: &mut R, header_size: usize) -> Result<St
 }
 
 /// Extract FITS keywords from XML content
-fn extract_fits_keywords(
-    xml: &str,
-    metadata: &mut AstroMetadata,
-    raw_header_cards: &mut Vec<FitsHeaderCard>,
-) {
+fn extract_fits_keywords(xml: &str, raw_header_cards: &mut Vec<FitsHeaderCard>) {
     let mut pos = 0;
 
     while let Some(start_pos) = xml[pos..].find("<FITSKeyword ") {
@@ -136,14 +184,11 @@ fn extract_fits_keywords(
                     raw_header_cards.push(FitsHeaderCard {
                         hdu_index: 0,
                         card_index,
-                        keyword: name.clone(),
-                        value: Some(clean_value.clone()),
+                        keyword: name,
+                        value: Some(clean_value),
                         comment: None,
                         raw_card: None,
                     });
-
-                    // Process known FITS keywords
-                    process_fits_keyword(metadata, &name, &clean_value);
                 }
             }
 
@@ -372,197 +417,6 @@ fn extract_attachments(xml: &str, metadata: &mut AstroMetadata) {
     }
 }
 
-/// Process a FITS keyword and update metadata
-fn process_fits_keyword(metadata: &mut AstroMetadata, name: &str, value: &str) {
-    match name {
-        // Equipment information
-        "TELESCOP" => metadata.equipment.telescope_name = Some(value.to_string()),
-        "FOCALLEN" => metadata.equipment.focal_length = value.parse().ok(),
-        "APERTURE" => metadata.equipment.aperture = value.parse().ok(),
-        "FOCRATIO" => metadata.equipment.focal_ratio = value.parse().ok(),
-
-        // Detector information
-        "INSTRUME" | "CAMERA" => metadata.detector.camera_name = Some(value.to_string()),
-        "XPIXSZ" | "PIXSIZE" => metadata.detector.pixel_size = value.parse().ok(),
-        "XBINNING" => metadata.detector.binning_x = value.parse().unwrap_or(1),
-        "YBINNING" => metadata.detector.binning_y = value.parse().unwrap_or(1),
-        "GAIN" | "EGAIN" => metadata.detector.gain = value.parse().ok(),
-        "RDNOISE" => metadata.detector.read_noise = value.parse().ok(),
-        "CCD-TEMP" | "CCDTEMP" => metadata.detector.temperature = value.parse().ok(),
-        "SET-TEMP" => metadata.detector.temp_setpoint = value.parse().ok(),
-
-        // Filter information
-        "FILTER" => metadata.filter.name = Some(value.to_string()),
-
-        // Exposure information
-        "OBJECT" => metadata.exposure.object_name = Some(value.to_string()),
-        "RA" | "OBJCTRA" => {
-            // Handle both numeric and sexagesimal formats
-            if let Ok(ra) = value.parse::<f32>() {
-                metadata.exposure.ra = Some(ra as f64);
-            } else {
-                // Try to parse sexagesimal format (HH MM SS)
-                if let Some(ra_deg) = parse_sexagesimal(value) {
-                    metadata.exposure.ra = Some(ra_deg * 15.0); // Convert hours to degrees
-                }
-            }
-        }
-        "DEC" | "OBJCTDEC" => {
-            // Handle both numeric and sexagesimal formats
-            if let Ok(dec) = value.parse::<f32>() {
-                metadata.exposure.dec = Some(dec as f64);
-            } else {
-                // Try to parse sexagesimal format (DD MM SS)
-                if let Some(dec_deg) = parse_sexagesimal(value) {
-                    metadata.exposure.dec = Some(dec_deg);
-                }
-            }
-        }
-        "DATE-OBS" => metadata.exposure.date_obs = parse_date_time(value),
-        "EXPTIME" | "EXPOSURE" => metadata.exposure.exposure_time = value.parse().ok(),
-        "IMAGETYP" | "FRAME" => metadata.exposure.frame_type = Some(value.to_string()),
-
-        // Mount information
-        "PIERSIDE" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.pier_side = Some(value.to_string());
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    pier_side: Some(value.to_string()),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // Environment information
-        "AMB_TEMP" | "AMBTEMP" => {
-            if let Some(ref mut env) = metadata.environment {
-                env.ambient_temp = value.parse().ok();
-            } else {
-                metadata.environment = Some(super::types::Environment {
-                    ambient_temp: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-        "HUMIDITY" => {
-            if let Some(ref mut env) = metadata.environment {
-                env.humidity = value.parse().ok();
-            } else {
-                metadata.environment = Some(super::types::Environment {
-                    humidity: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // WCS information
-        "CRPIX1" => {
-            if let Some(ref mut wcs) = metadata.wcs {
-                wcs.crpix1 = value.parse().ok();
-            } else {
-                metadata.wcs = Some(super::types::WcsData {
-                    crpix1: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-        "CRPIX2" => {
-            if let Some(ref mut wcs) = metadata.wcs {
-                wcs.crpix2 = value.parse().ok();
-            } else {
-                metadata.wcs = Some(super::types::WcsData {
-                    crpix2: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // Observatory location
-        "SITELAT" | "OBSLAT" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.latitude = value.parse().ok();
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    latitude: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-        "SITELONG" | "OBSLONG" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.longitude = value.parse().ok();
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    longitude: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-        "SITEELEV" | "OBSELEV" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.height = value.parse().ok();
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    height: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // Detector information
-        "OFFSET" | "CCDOFFST" => metadata.detector.offset = value.parse().ok(),
-        "READOUT" | "READOUTM" => metadata.detector.readout_mode = Some(value.to_string()),
-        "USBLIMIT" | "USBTRFC" => metadata.detector.usb_limit = Some(value.to_string()),
-        "ROTANG" | "ROTPA" | "ROTATANG" => metadata.detector.rotator_angle = value.parse().ok(),
-
-        // Equipment information
-        "FOCPOS" | "FOCUSPOS" => metadata.equipment.focuser_position = value.parse().ok(),
-        "FOCTEMP" | "FOCUSTEMP" => metadata.equipment.focuser_temperature = value.parse().ok(),
-
-        // Mount information
-        "PEAKRA" | "PEAKRAER" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.peak_ra_error = value.parse().ok();
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    peak_ra_error: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-        "PEAKDEC" | "PEAKDCER" => {
-            if let Some(ref mut mount) = metadata.mount {
-                mount.peak_dec_error = value.parse().ok();
-            } else {
-                metadata.mount = Some(super::types::Mount {
-                    peak_dec_error: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // Environment information
-        "SQM" | "SQMMAG" | "SKYQUAL" => {
-            if let Some(ref mut env) = metadata.environment {
-                env.sqm = value.parse().ok();
-            } else {
-                metadata.environment = Some(super::types::Environment {
-                    sqm: value.parse().ok(),
-                    ..Default::default()
-                });
-            }
-        }
-
-        // Exposure information
-        "PROJECT" | "PROJNAME" => metadata.exposure.project_name = Some(value.to_string()),
-        "SESSIONID" | "SESSID" => metadata.exposure.session_id = Some(value.to_string()),
-
-        // Ignore other keywords
-        _ => {}
-    }
-}
-
 /// Extract an attribute value from XML content
 fn extract_attribute(xml: &str, attr_name: &str) -> Option<String> {
     let search_pattern = format!("{}=\"", attr_name);
@@ -597,54 +451,23 @@ fn extract_property_value(xml: &str, property_id: &str) -> Option<String> {
     None
 }
 
-/// Parse sexagesimal format (HH MM SS or DD MM SS) to decimal degrees
-fn parse_sexagesimal(value: &str) -> Option<f64> {
-    let parts: Vec<&str> = value.split_whitespace().collect();
-    if parts.len() >= 3 {
-        if let (Ok(h), Ok(m), Ok(s)) = (
-            parts[0].parse::<f64>(),
-            parts[1].parse::<f64>(),
-            parts[2].parse::<f64>(),
-        ) {
-            let sign = if h < 0.0 || value.starts_with('-') {
-                -1.0
-            } else {
-                1.0
-            };
-            return Some(sign * (h.abs() + m / 60.0 + s / 3600.0));
-        }
-    }
-    None
-}
-
-/// Helper function to parse date/time strings
-fn parse_date_time(date_str: &str) -> Option<DateTime<Utc>> {
-    // Try different date formats
-    let formats = [
-        "%Y-%m-%dT%H:%M:%S%.fZ", // ISO 8601 with Z suffix
-        "%Y-%m-%dT%H:%M:%SZ",    // ISO 8601 with Z suffix, no fractional seconds
-        "%Y-%m-%dT%H:%M:%S%.f",  // ISO 8601 with fractional seconds
-        "%Y-%m-%dT%H:%M:%S",     // ISO 8601 without fractional seconds
-        "%Y-%m-%d %H:%M:%S%.f",  // Space-separated with fractional seconds
-        "%Y-%m-%d %H:%M:%S",     // Space-separated without fractional seconds
-    ];
-
-    for format in &formats {
-        if let Ok(dt) = NaiveDateTime::parse_from_str(date_str, format) {
-            return Some(DateTime::from_naive_utc_and_offset(dt, Utc));
-        }
-    }
-
-    warn!("Failed to parse date string: {}", date_str);
-    None
-}
-
 #[cfg(test)]
 mod tests {
-    use super::extract_metadata;
+    use super::{extract_metadata, extract_metadata_with_registry, extract_metadata_with_tables};
+    use crate::keywords::{AliasPrecedence, KeywordAlias, KeywordRegistry, MetadataField};
+    use crate::{FilterDatabase, MetadataTables, SensorDatabase};
     use chrono::{TimeZone, Utc};
     use std::io::Cursor;
 
+    fn xisf_bytes(xml: &str) -> Vec<u8> {
+        let header_size = xml.len() as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"XISF0100");
+        bytes.extend_from_slice(&header_size.to_le_bytes());
+        bytes.extend_from_slice(xml.as_bytes());
+        bytes
+    }
+
     #[test]
     fn test_creation_time_does_not_override_observation_date() {
         let xml = concat!(
@@ -702,4 +525,119 @@ mod tests {
         assert_eq!(metadata.detector.binning_x, 1);
         assert_eq!(metadata.detector.binning_y, 1);
     }
+
+    #[test]
+    fn test_fits_keywords_use_shared_registry() {
+        let xml = concat!(
+            "<?xml version=\"1.0\"?>",
+            "<xisf version=\"1.0\">",
+            "<Image geometry=\"2:2:1\" sampleFormat=\"UInt16\">",
+            "<FITSKeyword name=\"CCD-TEMP-SETPOINT\" value=\"-10\"/>",
+            "<FITSKeyword name=\"COOL-PWR\" value=\"42.\"/>",
+            "<FITSKeyword name=\"FOCUSTEMP\" value=\"3.5\"/>",
+            "<FITSKeyword name=\"GUIDERMS\" value=\"0.55\"/>",
+            "<FITSKeyword name=\"MFLIP\" value=\"T\"/>",
+            "<FITSKeyword name=\"FRAMENUM\" value=\"12\"/>",
+            "<FITSKeyword name=\"SEQID\" value=\"'M31 LRGB'\"/>",
+            "<FITSKeyword name=\"DATE-LOC\" value=\"2024-09-04T01:39:13.204\"/>",
+            "</Image>",
+            "</xisf>"
+        );
+
+        let metadata =
+            extract_metadata(&mut Cursor::new(xisf_bytes(xml))).expect("metadata should parse");
+
+        assert_eq!(metadata.detector.temp_setpoint, Some(-10.0));
+        assert_eq!(metadata.detector.cooler_power, Some(42.0));
+        assert_eq!(metadata.equipment.focuser_temperature, Some(3.5));
+        assert_eq!(metadata.exposure.frame_number, Some(12));
+        assert_eq!(metadata.exposure.sequence_id.as_deref(), Some("M31 LRGB"));
+        let mount = metadata.mount.expect("mount should be populated");
+        assert_eq!(mount.guide_rms, Some(0.55));
+        assert_eq!(mount.meridian_flip, Some(true));
+        assert_eq!(
+            metadata.exposure.date_loc.map(|local| local.to_string()),
+            Some("2024-09-04 01:39:13.204".to_string())
+        );
+    }
+
+    #[test]
+    fn test_custom_registry_alias() {
+        let xml = concat!(
+            "<?xml version=\"1.0\"?>",
+            "<xisf version=\"1.0\">",
+            "<Image geometry=\"2:2:1\" sampleFormat=\"UInt16\">",
+            "<FITSKeyword name=\"TARGET\" value=\"'NGC 7000'\"/>",
+            "</Image>",
+            "</xisf>"
+        );
+
+        let mut registry = KeywordRegistry::default();
+        registry.register_alias(
+            MetadataField::ObjectName,
+            KeywordAlias::new("TARGET"),
+            AliasPrecedence::Lowest,
+        );
+
+        let metadata = extract_metadata_with_registry(&mut Cursor::new(xisf_bytes(xml)), &registry)
+            .expect("metadata should parse");
+
+        assert_eq!(metadata.exposure.object_name.as_deref(), Some("NGC 7000"));
+    }
+
+    #[test]
+    fn test_extended_tables_override_builtin() {
+        let xml = concat!(
+            "<?xml version=\"1.0\"?>",
+            "<xisf version=\"1.0\">",
+            "<Image geometry=\"2:2:1\" sampleFormat=\"UInt16\">",
+            "<FITSKeyword name=\"INSTRUME\" value=\"'ZWO ASI2600MM Pro'\"/>",
+            "<FITSKeyword name=\"GAIN\" value=\"100\"/>",
+            "<FITSKeyword name=\"FILTER\" value=\"'Ha'\"/>",
+            "</Image>",
+            "</xisf>"
+        );
+
+        let builtin =
+            extract_metadata(&mut Cursor::new(xisf_bytes(xml))).expect("metadata should parse");
+        assert_eq!(builtin.detector.electrons_per_adu, Some(0.25));
+        assert_eq!(builtin.filter.bandwidth, Some(7.0));
+
+        // A measured gain curve and a 3 nm Ha filter replace the bundled entries
+        let mut sensors = SensorDatabase::builtin().clone();
+        sensors.extend(
+            SensorDatabase::from_json_str(
+                r#"{"sensors": [{
+                    "name": "ZWO ASI2600",
+                    "aliases": ["ASI2600MM"],
+                    "gain_curve": [{"gain": 100, "electrons_per_adu": 0.24, "read_noise": 1.4}]
+                }]}"#,
+            )
+            .expect("sensor table"),
+        );
+        let mut filters = FilterDatabase::builtin().clone();
+        filters.extend(
+            FilterDatabase::from_json_str(
+                r#"{"passbands": [{
+                    "name": "Ha",
+                    "kind": "narrowband",
+                    "center": 656.3,
+                    "bandwidth": 3.0,
+                    "aliases": ["Ha"]
+                }]}"#,
+            )
+            .expect("filter table"),
+        );
+        let tables = MetadataTables {
+            sensors: &sensors,
+            filters: &filters,
+            ..MetadataTables::builtin()
+        };
+        let metadata = extract_metadata_with_tables(&mut Cursor::new(xisf_bytes(xml)), &tables)
+            .expect("metadata should parse");
+        assert_eq!(metadata.detector.electrons_per_adu, Some(0.24));
+        assert_eq!(metadata.detector.read_noise, Some(1.4));
+        assert_eq!(metadata.filter.band.as_deref(), Some("Ha"));
+        assert_eq!(metadata.filter.bandwidth, Some(3.0));
+    }
 }
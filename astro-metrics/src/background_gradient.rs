@@ -0,0 +1,307 @@
+//! Gradient and vignetting fit to the background mesh
+//!
+//! A single bright halo or a cloud can dominate min/max statistics over the
+//! mesh. The fit here rejects outlier boxes by sigma clipping, describes the
+//! smooth part of the background with a plane plus a radial term, and keeps
+//! the structure left over (clouds, branches) in a separate lumpiness value.
+
+use crate::background_model::BackgroundModel;
+use crate::types::BackgroundGradient;
+
+/// Number of polynomial terms: constant, x, y and r²
+const TERMS: usize = 4;
+
+/// Mesh boxes needed along each axis to fit the gradient
+const MIN_MESH_SIZE: usize = 3;
+
+/// Upper bound on clipping iterations
+const MAX_ITERATIONS: usize = 10;
+
+/// Result of fitting the background mesh
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MeshFit {
+    /// The fit, `None` when the mesh is too small or degenerate
+    pub gradient: Option<BackgroundGradient>,
+    /// Boxes rejected by sigma clipping, row-major like the mesh
+    pub outliers: Vec<bool>,
+}
+
+/// Fit `level + gx·x + gy·y + v·r²` to the mesh of `model`, iteratively
+/// rejecting boxes more than `clip_sigma` robust sigma from the fit
+pub(crate) fn fit_mesh(model: &BackgroundModel, clip_sigma: f32) -> MeshFit {
+    let cells = model.mesh_width * model.mesh_height;
+    let mut outliers = vec![false; cells];
+    if model.mesh_width < MIN_MESH_SIZE
+        || model.mesh_height < MIN_MESH_SIZE
+        || model.mesh.len() != cells
+    {
+        return MeshFit {
+            gradient: None,
+            outliers,
+        };
+    }
+
+    let basis = basis(model);
+    let values: Vec<f64> = model.mesh.iter().map(|&v| f64::from(v)).collect();
+    let usable: Vec<bool> = values.iter().map(|v| v.is_finite()).collect();
+    let mut keep = usable.clone();
+    let mut coefficients = None;
+    for _ in 0..MAX_ITERATIONS {
+        let Some(fit) = least_squares(&basis, &values, &keep) else {
+            break;
+        };
+        coefficients = Some(fit);
+        if clip_sigma <= 0.0 {
+            break;
+        }
+
+        let residuals = residuals(&basis, &values, &fit);
+        let kept: Vec<f64> = residuals
+            .iter()
+            .zip(&keep)
+            .filter(|(_, &k)| k)
+            .map(|(r, _)| r.abs())
+            .collect();
+        let sigma = 1.4826 * median(kept);
+        if sigma <= 0.0 {
+            break;
+        }
+        let limit = f64::from(clip_sigma) * sigma;
+        let next: Vec<bool> = residuals
+            .iter()
+            .zip(&usable)
+            .map(|(r, &u)| u && r.abs() <= limit)
+            .collect();
+        if next == keep || next.iter().filter(|&&k| k).count() < 2 * TERMS {
+            break;
+        }
+        keep = next;
+    }
+
+    let Some(fit) = coefficients else {
+        return MeshFit {
+            gradient: None,
+            outliers,
+        };
+    };
+    for ((outlier, &k), &u) in outliers.iter_mut().zip(&keep).zip(&usable) {
+        *outlier = u && !k;
+    }
+
+    // Extended outlier regions are real structure and count towards
+    // lumpiness; isolated boxes are halos or hot spots and don't
+    let isolated = isolated(&outliers, model.mesh_width, model.mesh_height);
+    let residuals = residuals(&basis, &values, &fit);
+    let (sum, n) = residuals
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| usable[i] && !isolated[i])
+        .fold((0.0, 0usize), |(sum, n), (_, r)| (sum + r * r, n + 1));
+    let lumpiness = if n > 0 { (sum / n as f64).sqrt() } else { 0.0 };
+
+    MeshFit {
+        gradient: Some(BackgroundGradient {
+            level: fit[0] as f32,
+            gradient: (2.0 * fit[1].hypot(fit[2])) as f32,
+            angle: fit[2].atan2(fit[1]).to_degrees() as f32,
+            vignetting: fit[3] as f32,
+            lumpiness: lumpiness as f32,
+            outlier_boxes: outliers.iter().filter(|&&o| o).count(),
+        }),
+        outliers,
+    }
+}
+
+impl BackgroundGradient {
+    /// Fitted background at pixel `(x, y)` of a `width` x `height` image
+    pub fn level_at(&self, x: f64, y: f64, width: usize, height: usize) -> f32 {
+        let (width, height) = (width as f64, height as f64);
+        let half_diagonal = width.hypot(height) / 2.0;
+        let x = (x - width / 2.0) / half_diagonal;
+        let y = (y - height / 2.0) / half_diagonal;
+        let (sin, cos) = f64::from(self.angle).to_radians().sin_cos();
+        let slope = f64::from(self.gradient) / 2.0;
+        (f64::from(self.level)
+            + slope * (cos * x + sin * y)
+            + f64::from(self.vignetting) * (x * x + y * y)) as f32
+    }
+}
+
+/// Polynomial terms at the centre of every mesh box
+fn basis(model: &BackgroundModel) -> Vec<[f64; TERMS]> {
+    let (width, height) = (model.width as f64, model.height as f64);
+    let half_diagonal = width.hypot(height) / 2.0;
+    // The last box along each axis may be cut short by the image edge
+    let centre = |i: usize, size: usize, extent: f64| {
+        let start = (i * size) as f64;
+        (start + (start + size as f64).min(extent)) / 2.0
+    };
+    let mut basis = Vec::with_capacity(model.mesh_width * model.mesh_height);
+    for j in 0..model.mesh_height {
+        let y = (centre(j, model.box_height, height) - height / 2.0) / half_diagonal;
+        for i in 0..model.mesh_width {
+            let x = (centre(i, model.box_width, width) - width / 2.0) / half_diagonal;
+            basis.push([1.0, x, y, x * x + y * y]);
+        }
+    }
+    basis
+}
+
+fn residuals(basis: &[[f64; TERMS]], values: &[f64], fit: &[f64; TERMS]) -> Vec<f64> {
+    basis
+        .iter()
+        .zip(values)
+        .map(|(terms, v)| v - terms.iter().zip(fit).map(|(t, c)| t * c).sum::<f64>())
+        .collect()
+}
+
+/// Solve the normal equations over the kept boxes
+fn least_squares(basis: &[[f64; TERMS]], values: &[f64], keep: &[bool]) -> Option<[f64; TERMS]> {
+    let mut a = [[0.0; TERMS + 1]; TERMS];
+    for ((terms, &v), _) in basis.iter().zip(values).zip(keep).filter(|(_, &k)| k) {
+        for (r, row) in a.iter_mut().enumerate() {
+            for c in 0..TERMS {
+                row[c] += terms[r] * terms[c];
+            }
+            row[TERMS] += terms[r] * v;
+        }
+    }
+
+    // Gaussian elimination with partial pivoting
+    for col in 0..TERMS {
+        let pivot = (col..TERMS).max_by(|&p, &q| a[p][col].abs().total_cmp(&a[q][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        let pivot_row = a[col];
+        for row in a.iter_mut().skip(col + 1) {
+            let factor = row[col] / pivot_row[col];
+            for (value, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                *value -= factor * p;
+            }
+        }
+    }
+    let mut fit = [0.0; TERMS];
+    for row in (0..TERMS).rev() {
+        let known: f64 = (row + 1..TERMS).map(|c| a[row][c] * fit[c]).sum();
+        fit[row] = (a[row][TERMS] - known) / a[row][row];
+    }
+    Some(fit)
+}
+
+/// Outlier boxes with no outlier among their 8 neighbours
+fn isolated(outliers: &[bool], width: usize, height: usize) -> Vec<bool> {
+    let mut isolated = vec![false; outliers.len()];
+    for y in 0..height {
+        for x in 0..width {
+            if !outliers[y * width + x] {
+                continue;
+            }
+            let neighbour = (y.saturating_sub(1)..(y + 2).min(height)).any(|ny| {
+                (x.saturating_sub(1)..(x + 2).min(width))
+                    .any(|nx| (nx, ny) != (x, y) && outliers[ny * width + nx])
+            });
+            isolated[y * width + x] = !neighbour;
+        }
+    }
+    isolated
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 512x384 frame with 64 px boxes, background from `f(x, y)` in
+    /// normalised coordinates
+    fn model(f: impl Fn(f64, f64) -> f64) -> BackgroundModel {
+        let (width, height, size) = (512, 384, 64);
+        let half_diagonal = (width as f64).hypot(height as f64) / 2.0;
+        let (mesh_width, mesh_height) = (width / size, height / size);
+        let mut mesh = Vec::new();
+        for j in 0..mesh_height {
+            for i in 0..mesh_width {
+                let x = ((i as f64 + 0.5) * size as f64 - width as f64 / 2.0) / half_diagonal;
+                let y = ((j as f64 + 0.5) * size as f64 - height as f64 / 2.0) / half_diagonal;
+                mesh.push(f(x, y) as f32);
+            }
+        }
+        BackgroundModel {
+            width,
+            height,
+            box_width: size,
+            box_height: size,
+            mesh_width,
+            mesh_height,
+            mesh_rms: vec![5.0; mesh.len()],
+            mesh,
+            global: 1000.0,
+            global_rms: 5.0,
+            background: Vec::new(),
+            rms: Vec::new(),
+            mesh_outliers: Vec::new(),
+            gradient: None,
+        }
+    }
+
+    #[test]
+    fn test_fit_gradient_and_vignetting() {
+        // Brighter towards +y, darker corners, and one star halo box
+        let mut model = model(|x, y| 1000.0 + 40.0 * y - 30.0 * (x * x + y * y));
+        model.mesh[3 * 8 + 5] += 500.0;
+        let fit = fit_mesh(&model, 3.0);
+        let gradient = fit.gradient.unwrap();
+        assert!((gradient.level - 1000.0).abs() < 0.1);
+        assert!((gradient.gradient - 80.0).abs() < 0.1);
+        assert!((gradient.angle - 90.0).abs() < 0.1);
+        assert!((gradient.vignetting + 30.0).abs() < 0.1);
+        assert!(gradient.lumpiness < 0.01);
+        assert_eq!(gradient.outlier_boxes, 1);
+        assert!(fit.outliers[3 * 8 + 5]);
+        assert!(
+            (gradient.level_at(256.0, 0.0, 512, 384) - (1000.0 - 40.0 * 0.6 - 30.0 * 0.36)).abs()
+                < 0.1
+        );
+
+        // Without clipping the halo pulls the fit and shows up as residual
+        let unclipped = fit_mesh(&model, 0.0).gradient.unwrap();
+        assert_eq!(unclipped.outlier_boxes, 0);
+        assert!(unclipped.lumpiness > 10.0);
+    }
+
+    #[test]
+    fn test_cloud_counts_as_lumpiness() {
+        let mut model = model(|x, _| 1000.0 + 20.0 * x);
+        for (i, j) in [(1, 1), (2, 1), (1, 2), (2, 2)] {
+            model.mesh[j * 8 + i] += 200.0;
+        }
+        let gradient = fit_mesh(&model, 3.0).gradient.unwrap();
+        assert_eq!(gradient.outlier_boxes, 4);
+        assert!((gradient.gradient - 40.0).abs() < 0.1);
+        assert!(gradient.angle.abs() < 0.1);
+        assert!(gradient.lumpiness > 50.0);
+    }
+
+    #[test]
+    fn test_small_mesh_has_no_fit() {
+        let mut model = model(|_, _| 1000.0);
+        model.mesh_height = 2;
+        model.mesh.truncate(16);
+        let fit = fit_mesh(&model, 3.0);
+        assert!(fit.gradient.is_none());
+        assert_eq!(fit.outliers, vec![false; 16]);
+    }
+}
@@ -11,6 +11,7 @@ impl BackgroundMetrics {
             min: 0.0, // Default values, can be updated later
             max: 0.0,
             uniformity: 1.0,
+            gradient: None,
         }
     }
 
@@ -22,6 +23,7 @@ impl BackgroundMetrics {
             min,
             max,
             uniformity,
+            gradient: None,
         }
     }
 }
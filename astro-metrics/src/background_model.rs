@@ -0,0 +1,396 @@
+//! Full SEP background model: mesh, interpolated maps and subtraction
+//!
+//! The global level and RMS hide gradients from light pollution and the Moon.
+//! `BackgroundModel` keeps SEP's mesh along with the background and RMS
+//! interpolated to every pixel, so the gradient can be inspected, exported and
+//! subtracted before measuring.
+
+use crate::background_gradient;
+use crate::mask::PixelMask;
+use crate::preview;
+use crate::sep::{Background, SepImage};
+use crate::types::{BackgroundConfig, BackgroundGradient, BackgroundMetrics};
+use anyhow::{bail, Context, Result};
+use fitsio::images::{ImageDescription, ImageType};
+use fitsio::FitsFile;
+use std::path::Path;
+
+/// Which full-resolution map to export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundMap {
+    /// Interpolated background level
+    Background,
+    /// Interpolated background RMS
+    Rms,
+}
+
+/// SEP background of a frame at mesh and full resolution
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundModel {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Mesh box width in pixels
+    pub box_width: usize,
+    /// Mesh box height in pixels
+    pub box_height: usize,
+    /// Number of mesh boxes along x
+    pub mesh_width: usize,
+    /// Number of mesh boxes along y
+    pub mesh_height: usize,
+    /// Filtered background level per mesh box, row-major, including the
+    /// outlier boxes
+    pub mesh: Vec<f32>,
+    /// Filtered background RMS per mesh box, row-major
+    pub mesh_rms: Vec<f32>,
+    /// Global background level
+    pub global: f32,
+    /// Global background RMS
+    pub global_rms: f32,
+    /// Background interpolated to every pixel, row-major, with the outlier
+    /// boxes filled from their neighbours
+    pub background: Vec<f32>,
+    /// Background RMS interpolated to every pixel, row-major, with the
+    /// outlier boxes filled from their neighbours
+    pub rms: Vec<f32>,
+    /// Mesh boxes rejected as outliers from the gradient fit, row-major
+    pub mesh_outliers: Vec<bool>,
+    /// Gradient, vignetting and lumpiness fitted to the mesh
+    pub gradient: Option<BackgroundGradient>,
+}
+
+impl BackgroundModel {
+    /// Estimate the background of an image, leaving masked pixels out
+    ///
+    /// Mesh boxes rejected by the gradient fit are masked and the background
+    /// estimated again, so SEP fills them from their neighbours and a halo or
+    /// hot spot doesn't leak into the interpolated maps.
+    pub fn estimate(
+        data: &[f32],
+        width: usize,
+        height: usize,
+        config: &BackgroundConfig,
+        mask: Option<&PixelMask>,
+    ) -> Result<Self> {
+        let mut image = SepImage::new(data, width, height)?;
+        if let Some(mask) = mask {
+            image = image.with_mask(mask)?;
+        }
+        let mut model =
+            Self::from_background(&Background::new(&image, config)?, config.clip_sigma)?;
+        if !model.mesh_outliers.contains(&true) {
+            return Ok(model);
+        }
+
+        let mut clipped = mask
+            .cloned()
+            .unwrap_or_else(|| PixelMask::new(width, height));
+        for (i, _) in model.mesh_outliers.iter().enumerate().filter(|(_, &o)| o) {
+            let (x, y) = (i % model.mesh_width, i / model.mesh_width);
+            clipped.mask_rect(
+                x * model.box_width,
+                y * model.box_height,
+                (x + 1) * model.box_width,
+                (y + 1) * model.box_height,
+            );
+        }
+        let image = SepImage::new(data, width, height)?.with_mask(&clipped)?;
+        let filled = Background::new(&image, config)?;
+        model.background = filled.array()?;
+        model.rms = filled.rms_array()?;
+        Ok(model)
+    }
+
+    /// Copy the mesh and interpolated maps out of a SEP background and fit
+    /// the gradient, clipping boxes more than `clip_sigma` from the fit
+    ///
+    /// Unlike `estimate`, the maps keep any rejected boxes.
+    pub fn from_background(bkg: &Background, clip_sigma: f32) -> Result<Self> {
+        let (width, height) = bkg.image_size();
+        let (box_width, box_height) = bkg.box_size();
+        let (mesh_width, mesh_height) = bkg.mesh_size();
+        let mut model = Self {
+            width,
+            height,
+            box_width,
+            box_height,
+            mesh_width,
+            mesh_height,
+            mesh: bkg.mesh().to_vec(),
+            mesh_rms: bkg.mesh_rms().to_vec(),
+            global: bkg.global(),
+            global_rms: bkg.global_rms(),
+            background: bkg.array()?,
+            rms: bkg.rms_array()?,
+            mesh_outliers: Vec::new(),
+            gradient: None,
+        };
+        model.fit_gradient(clip_sigma);
+        Ok(model)
+    }
+
+    /// Fit gradient and vignetting to the mesh, rejecting boxes more than
+    /// `clip_sigma` from the fit; 0 disables clipping
+    pub fn fit_gradient(&mut self, clip_sigma: f32) {
+        let fit = background_gradient::fit_mesh(self, clip_sigma);
+        self.gradient = fit.gradient;
+        self.mesh_outliers = fit.outliers;
+    }
+
+    /// Summary metrics; uniformity is `1 - (max - min) / max` over the mesh
+    /// boxes that were not rejected as outliers
+    pub fn metrics(&self) -> BackgroundMetrics {
+        let (min, max) = self
+            .mesh
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !self.mesh_outliers.get(i).copied().unwrap_or(false))
+            .fold((f32::MAX, f32::MIN), |(lo, hi), (_, &v)| {
+                (lo.min(v), hi.max(v))
+            });
+        // Higher values (closer to 1) mean more uniform background
+        let uniformity = if max > 0.0 {
+            1.0 - (max - min) / max
+        } else {
+            1.0
+        };
+        BackgroundMetrics {
+            gradient: self.gradient,
+            ..BackgroundMetrics::with_all_metrics(
+                self.global,
+                self.global_rms,
+                min,
+                max,
+                uniformity,
+            )
+        }
+    }
+
+    /// Subtract the interpolated background from `data` in place
+    pub fn subtract(&self, data: &mut [f32]) -> Result<()> {
+        self.check_size(data.len())?;
+        for (value, background) in data.iter_mut().zip(&self.background) {
+            *value -= background;
+        }
+        Ok(())
+    }
+
+    /// A background-subtracted copy of `data`
+    pub fn subtracted(&self, data: &[f32]) -> Result<Vec<f32>> {
+        let mut copy = data.to_vec();
+        self.subtract(&mut copy)?;
+        Ok(copy)
+    }
+
+    /// The requested full-resolution map
+    pub fn map(&self, map: BackgroundMap) -> &[f32] {
+        match map {
+            BackgroundMap::Background => &self.background,
+            BackgroundMap::Rms => &self.rms,
+        }
+    }
+
+    /// Write the background and RMS maps as FITS image extensions named
+    /// `BACKGROUND` and `RMS` with `DATASUM`/`CHECKSUM`; an existing file is
+    /// overwritten
+    pub fn write_fits(&self, path: &Path) -> Result<()> {
+        let mut fits = FitsFile::create(path)
+            .overwrite()
+            .open()
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let description = ImageDescription {
+            data_type: ImageType::Float,
+            dimensions: &[self.height, self.width],
+        };
+        for (name, map) in [("BACKGROUND", &self.background), ("RMS", &self.rms)] {
+            let hdu = fits.create_image(name, &description)?;
+            hdu.write_image(&mut fits, map)?;
+            hdu.write_key(&mut fits, "BKGBOXW", self.box_width as i64)?;
+            hdu.write_key(&mut fits, "BKGBOXH", self.box_height as i64)?;
+            hdu.write_key(&mut fits, "BACKGND", self.global)?;
+            hdu.write_key(&mut fits, "BACKRMS", self.global_rms)?;
+        }
+        // Checksums cover the finished file, so close it first
+        drop(fits);
+        astro_io::fits_checksum::write_checksums(path)
+            .with_context(|| format!("Failed to checksum {}", path.display()))
+    }
+
+    /// Write a map as an 8-bit grayscale PNG, stretched between its 0.5 and
+    /// 99.5 percentiles
+    pub fn write_png(&self, path: &Path, map: BackgroundMap) -> Result<()> {
+        let pixels = preview::stretch(self.map(map));
+        preview::write_gray_png(path, self.width, self.height, &pixels)
+    }
+
+    fn check_size(&self, len: usize) -> Result<()> {
+        if len != self.width * self.height {
+            bail!(
+                "Background model is {}x{} but the image has {} pixels",
+                self.width,
+                self.height,
+                len
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4x2 model with a left-to-right gradient
+    fn model() -> BackgroundModel {
+        BackgroundModel {
+            width: 4,
+            height: 2,
+            box_width: 2,
+            box_height: 2,
+            mesh_width: 2,
+            mesh_height: 1,
+            mesh: vec![100.0, 120.0],
+            mesh_rms: vec![5.0, 5.0],
+            global: 110.0,
+            global_rms: 5.0,
+            background: vec![100.0, 105.0, 115.0, 120.0, 100.0, 105.0, 115.0, 120.0],
+            rms: vec![5.0; 8],
+            mesh_outliers: vec![false, false],
+            gradient: None,
+        }
+    }
+
+    #[test]
+    fn test_subtract_and_metrics() {
+        let model = model();
+        let data = vec![110.0; 8];
+        let subtracted = model.subtracted(&data).unwrap();
+        assert_eq!(
+            subtracted,
+            vec![10.0, 5.0, -5.0, -10.0, 10.0, 5.0, -5.0, -10.0]
+        );
+        assert!(model.subtracted(&data[..4]).is_err());
+
+        let metrics = model.metrics();
+        assert_eq!((metrics.min, metrics.max), (100.0, 120.0));
+        assert!((metrics.uniformity - (1.0 - 20.0 / 120.0)).abs() < 1e-6);
+        assert_eq!(model.map(BackgroundMap::Rms), &[5.0; 8]);
+
+        // Outlier boxes don't count towards uniformity
+        let mut clipped = model.clone();
+        clipped.mesh_outliers[1] = true;
+        assert_eq!(clipped.metrics().uniformity, 1.0);
+    }
+
+    #[test]
+    fn test_estimate_follows_gradient() {
+        // Sky rising 0.1 ADU/px along x and 0.05 ADU/px along y, with noise
+        let (width, height) = (320, 256);
+        let sky = |x: f64, y: f64| 100.0 + 0.1 * x + 0.05 * y;
+        let mut seed = 99u32;
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                sky(x, y) as f32 + ((seed >> 16) % 17) as f32 - 8.0
+            })
+            .collect();
+
+        let model =
+            BackgroundModel::estimate(&data, width, height, &BackgroundConfig::default(), None)
+                .unwrap();
+        assert_eq!((model.mesh_width, model.mesh_height), (5, 4));
+        assert!(model.mesh_outliers.iter().all(|&o| !o));
+        for (x, y) in [(32, 32), (160, 128), (288, 224), (100, 200)] {
+            let fitted = model.background[y * width + x];
+            let expected = sky(x as f64, y as f64) as f32;
+            assert!((fitted - expected).abs() < 1.0, "({x}, {y}): {fitted}");
+        }
+
+        let gradient = model.gradient.unwrap();
+        let diagonal = (width as f32).hypot(height as f32);
+        assert!((gradient.gradient - diagonal * 0.1f32.hypot(0.05)).abs() < 1.0);
+        assert!((gradient.angle - 0.5f32.atan().to_degrees()).abs() < 1.0);
+        assert!(gradient.vignetting.abs() < 1.0);
+        assert_eq!(gradient.outlier_boxes, 0);
+    }
+
+    #[test]
+    fn test_rejected_halo_is_not_subtracted() {
+        // Flat sky with a broad halo filling one mesh box
+        let (width, height) = (320, 256);
+        let (cx, cy) = (160.0, 160.0);
+        let mut seed = 31u32;
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                let r2 = (x - cx).powi(2) + (y - cy).powi(2);
+                let halo = 400.0 * (-r2 / (2.0 * 15.0 * 15.0)).exp();
+                100.0 + ((seed >> 16) % 17) as f32 - 8.0 + halo as f32
+            })
+            .collect();
+        // Without median filtering the halo box reaches the maps unless clipped
+        let config = BackgroundConfig {
+            filter_width: 1,
+            filter_height: 1,
+            ..BackgroundConfig::default()
+        };
+        let centre = 160 * width + 160;
+
+        let model = BackgroundModel::estimate(&data, width, height, &config, None).unwrap();
+        assert!(model.mesh_outliers[2 * model.mesh_width + 2]);
+        assert!(model.mesh[2 * model.mesh_width + 2] > 110.0);
+        assert!((model.background[centre] - 100.0).abs() < 2.0);
+        let flattened = model.subtracted(&data).unwrap();
+        assert!(flattened[centre] > 380.0);
+
+        let unclipped = BackgroundConfig {
+            clip_sigma: 0.0,
+            ..config
+        };
+        let model = BackgroundModel::estimate(&data, width, height, &unclipped, None).unwrap();
+        assert!(model.background[centre] > 110.0);
+    }
+
+    #[test]
+    fn test_fits_export_reads_back() -> Result<()> {
+        let model = model();
+        let path = std::env::temp_dir().join(format!(
+            "astro-metrics-background-{}-{}.fits",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_nanos()
+        ));
+        model.write_fits(&path)?;
+
+        let mut fits = FitsFile::open(&path)?;
+        let hdu = fits.hdu("BACKGROUND")?;
+        let background: Vec<f32> = hdu.read_image(&mut fits)?;
+        let box_width: i64 = hdu.read_key(&mut fits, "BKGBOXW")?;
+        let global: f32 = hdu.read_key(&mut fits, "BACKGND")?;
+        let hdu = fits.hdu("RMS")?;
+        let rms: Vec<f32> = hdu.read_image(&mut fits)?;
+        let shape = match hdu.info {
+            fitsio::hdu::HduInfo::ImageInfo { ref shape, .. } => shape.clone(),
+            _ => Vec::new(),
+        };
+        drop(fits);
+        let checksums = astro_io::fits_checksum::verify_checksums(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(background, model.background);
+        assert_eq!(rms, model.rms);
+        assert_eq!(shape, [2, 4]);
+        assert_eq!(box_width, 2);
+        assert_eq!(global, 110.0);
+        assert_eq!(checksums.hdus.len(), 3);
+        assert_eq!(
+            checksums.status(),
+            astro_io::fits_checksum::ChecksumStatus::Pass
+        );
+        Ok(())
+    }
+}
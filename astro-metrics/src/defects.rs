@@ -0,0 +1,418 @@
+//! Hot pixel, cold pixel and bad column mapping
+//!
+//! Hot pixels pass SEP's minimum area once they bloom into their neighbours
+//! and end up as tiny "stars" that pull the FWHM statistics down. A
+//! `DefectMap` built from a master dark, or from a stack of dithered lights
+//! where stars move but defects stay put, gives a mask to leave them out of
+//! detection. Frames without darks can go through `reject_pixel_outliers`
+//! instead.
+
+use crate::mask::PixelMask;
+use crate::types::{DefectConfig, DefectMap};
+use anyhow::{bail, Result};
+
+/// Scale from the median absolute deviation to the standard deviation
+const MAD_TO_SIGMA: f32 = 1.4826;
+
+/// Frames needed before the per-pixel median of a stack rejects stars
+const MIN_STACK_FRAMES: usize = 3;
+
+/// Largest spread of the 8 neighbours, relative to the pixel's height above
+/// (or depth below) them, for a single-pixel outlier; even a star with a
+/// 1.5 pixel FWHM spreads more than this into its neighbours
+const SHARPNESS: f32 = 0.15;
+
+/// Neighbour spread in noise sigma that plain noise stays within
+const NOISE_SPREAD: f32 = 5.0;
+
+impl Default for DefectConfig {
+    fn default() -> Self {
+        Self {
+            hot_sigma: 5.0,
+            cold_sigma: 5.0,
+            column_sigma: 5.0,
+        }
+    }
+}
+
+impl DefectMap {
+    /// Defects of a master dark, from its median and robust sigma
+    pub fn from_dark(
+        data: &[f32],
+        width: usize,
+        height: usize,
+        config: &DefectConfig,
+    ) -> Result<Self> {
+        check_size(data.len(), width, height)?;
+        let (median, sigma) = robust_stats(data);
+        let column_floor = sigma / (height as f32).sqrt();
+        Ok(Self::classify(
+            data,
+            width,
+            height,
+            median,
+            sigma,
+            column_floor,
+            config,
+        ))
+    }
+
+    /// Defects common to a stack of dithered lights
+    ///
+    /// Each frame is compared with its 3x3 median and scaled by its noise;
+    /// the per-pixel median over the stack keeps what stays fixed on the
+    /// sensor and drops the stars, which move between frames.
+    pub fn from_lights(
+        frames: &[&[f32]],
+        width: usize,
+        height: usize,
+        config: &DefectConfig,
+    ) -> Result<Self> {
+        if frames.len() < MIN_STACK_FRAMES {
+            bail!(
+                "Defect mapping from lights needs at least {MIN_STACK_FRAMES} frames, got {}",
+                frames.len()
+            );
+        }
+        let mut residuals = Vec::with_capacity(frames.len());
+        for frame in frames {
+            check_size(frame.len(), width, height)?;
+            let mut residual: Vec<f32> = frame
+                .iter()
+                .zip(median_filter(frame, width, height, true))
+                .map(|(v, m)| v - m)
+                .collect();
+            let (_, sigma) = robust_stats(&residual);
+            if sigma > 0.0 {
+                residual.iter_mut().for_each(|v| *v /= sigma);
+            }
+            residuals.push(residual);
+        }
+
+        let mut values = vec![0.0; residuals.len()];
+        let stacked: Vec<f32> = (0..width * height)
+            .map(|i| {
+                for (value, residual) in values.iter_mut().zip(&residuals) {
+                    *value = residual[i];
+                }
+                median(&mut values)
+            })
+            .collect();
+        let column_floor = 1.0 / (height as f32).sqrt();
+        Ok(Self::classify(
+            &stacked,
+            width,
+            height,
+            0.0,
+            1.0,
+            column_floor,
+            config,
+        ))
+    }
+
+    fn classify(
+        data: &[f32],
+        width: usize,
+        height: usize,
+        level: f32,
+        sigma: f32,
+        column_floor: f32,
+        config: &DefectConfig,
+    ) -> Self {
+        let bad_columns = bad_columns(data, width, height, column_floor, config.column_sigma);
+        let mut hot_pixels = Vec::new();
+        let mut cold_pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if bad_columns.contains(&x) {
+                    continue;
+                }
+                let value = data[y * width + x];
+                if value > level + config.hot_sigma * sigma {
+                    hot_pixels.push((x, y));
+                } else if value < level - config.cold_sigma * sigma {
+                    cold_pixels.push((x, y));
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            hot_pixels,
+            cold_pixels,
+            bad_columns,
+        }
+    }
+
+    /// Number of hot pixels
+    pub fn hot_count(&self) -> usize {
+        self.hot_pixels.len()
+    }
+
+    /// Mask of every defect, for `DetectionInputs::with_mask`
+    pub fn mask(&self) -> PixelMask {
+        let mut mask = PixelMask::new(self.width, self.height);
+        for &(x, y) in self.hot_pixels.iter().chain(&self.cold_pixels) {
+            mask.set(x, y);
+        }
+        for &x in &self.bad_columns {
+            mask.mask_rect(x, 0, x + 1, self.height);
+        }
+        mask
+    }
+}
+
+/// Single-pixel outliers replaced by `reject_pixel_outliers`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelOutliers {
+    /// Hot pixels replaced
+    pub hot: usize,
+    /// Cold pixels replaced
+    pub cold: usize,
+    /// The replaced pixels
+    pub mask: PixelMask,
+}
+
+/// Replace single-pixel outliers with the median of their 8 neighbours, for
+/// frames without a dark or defect map
+///
+/// A pixel is an outlier when it is more than `hot_sigma` (or `cold_sigma`)
+/// noise sigma away from its neighbours' median while the neighbours
+/// themselves stay flat, which they don't around a star. `hot` is the
+/// frame's hot pixel count for `FrameQualityMetrics::with_hot_pixels`.
+pub fn reject_pixel_outliers(
+    data: &mut [f32],
+    width: usize,
+    height: usize,
+    config: &DefectConfig,
+) -> Result<PixelOutliers> {
+    check_size(data.len(), width, height)?;
+    let neighbours = median_filter(data, width, height, false);
+    let residual: Vec<f32> = data.iter().zip(&neighbours).map(|(v, m)| v - m).collect();
+    let (_, sigma) = robust_stats(&residual);
+
+    let mut outliers = PixelOutliers {
+        hot: 0,
+        cold: 0,
+        mask: PixelMask::new(width, height),
+    };
+    if sigma <= 0.0 {
+        return Ok(outliers);
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            let excess = residual[i];
+            let hot = excess > config.hot_sigma * sigma;
+            let cold = -excess > config.cold_sigma * sigma;
+            if !hot && !cold {
+                continue;
+            }
+            let (low, high) = neighbourhood(x, y, width, height)
+                .filter(|&j| j != i)
+                .fold((f32::MAX, f32::MIN), |(lo, hi), j| {
+                    (lo.min(data[j]), hi.max(data[j]))
+                });
+            let contrast = if hot { data[i] - low } else { high - data[i] };
+            // A star lifts its neighbours unevenly; a lone pixel doesn't
+            if high - low >= (SHARPNESS * contrast).max(NOISE_SPREAD * sigma) {
+                continue;
+            }
+            if hot {
+                outliers.hot += 1;
+            } else {
+                outliers.cold += 1;
+            }
+            outliers.mask.set(x, y);
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            if outliers.mask.is_masked(x, y) {
+                data[y * width + x] = neighbours[y * width + x];
+            }
+        }
+    }
+    Ok(outliers)
+}
+
+fn check_size(len: usize, width: usize, height: usize) -> Result<()> {
+    if len != width * height {
+        bail!(
+            "Image of {width}x{height} needs {} pixels, got {len}",
+            width * height
+        );
+    }
+    Ok(())
+}
+
+/// Indices of the 3x3 neighbourhood of `(x, y)`, cut at the edges
+fn neighbourhood(x: usize, y: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
+    (y.saturating_sub(1)..(y + 2).min(height)).flat_map(move |ny| {
+        (x.saturating_sub(1)..(x + 2).min(width)).map(move |nx| ny * width + nx)
+    })
+}
+
+/// 3x3 median of every pixel, with or without the pixel itself
+fn median_filter(data: &[f32], width: usize, height: usize, with_centre: bool) -> Vec<f32> {
+    let mut window = Vec::with_capacity(9);
+    let mut filtered = Vec::with_capacity(data.len());
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            window.clear();
+            window.extend(
+                neighbourhood(x, y, width, height)
+                    .filter(|&j| with_centre || j != i)
+                    .map(|j| data[j]),
+            );
+            filtered.push(if window.is_empty() {
+                data[i]
+            } else {
+                median(&mut window)
+            });
+        }
+    }
+    filtered
+}
+
+/// Columns whose median is off the median of all columns by more than
+/// `sigma` times their robust spread, or `floor` when the columns agree
+fn bad_columns(data: &[f32], width: usize, height: usize, floor: f32, sigma: f32) -> Vec<usize> {
+    let mut column = vec![0.0; height];
+    let medians: Vec<f32> = (0..width)
+        .map(|x| {
+            for (y, value) in column.iter_mut().enumerate() {
+                *value = data[y * width + x];
+            }
+            median(&mut column)
+        })
+        .collect();
+    let (level, spread) = robust_stats(&medians);
+    let limit = sigma * spread.max(floor);
+    medians
+        .iter()
+        .enumerate()
+        .filter(|(_, &m)| (m - level).abs() > limit)
+        .map(|(x, _)| x)
+        .collect()
+}
+
+/// Median and MAD-based sigma
+fn robust_stats(values: &[f32]) -> (f32, f32) {
+    let mut copy: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    let level = median(&mut copy);
+    copy.iter_mut().for_each(|v| *v = (*v - level).abs());
+    (level, MAD_TO_SIGMA * median(&mut copy))
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let (len, mid) = (values.len(), values.len() / 2);
+    let (below, &mut upper, _) = values.select_nth_unstable_by(mid, f32::total_cmp);
+    if len.is_multiple_of(2) {
+        let lower = below.iter().copied().fold(f32::MIN, f32::max);
+        (lower + upper) / 2.0
+    } else {
+        upper
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 60;
+    const HEIGHT: usize = 40;
+
+    /// Flat frame at 100 ADU with uniform noise of ±3 ADU
+    fn frame(seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..WIDTH * HEIGHT)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                100.0 + ((state >> 16) % 7) as f32 - 3.0
+            })
+            .collect()
+    }
+
+    fn add_star(data: &mut [f32], cx: f64, cy: f64, peak: f64) {
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let r2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                // FWHM of 3 pixels
+                data[y * WIDTH + x] += (peak * (-r2 * 4.0 * 2f64.ln() / 9.0).exp()) as f32;
+            }
+        }
+    }
+
+    /// Hot pixels at (5, 5) and (30, 20), a cold pixel at (10, 30) and a
+    /// bright column at x = 40
+    fn add_defects(data: &mut [f32]) {
+        data[5 * WIDTH + 5] += 5000.0;
+        data[20 * WIDTH + 30] += 800.0;
+        data[30 * WIDTH + 10] = 0.0;
+        for y in 0..HEIGHT {
+            data[y * WIDTH + 40] += 50.0;
+        }
+    }
+
+    #[test]
+    fn test_defects_from_dark() {
+        let mut dark = frame(1);
+        add_defects(&mut dark);
+        let map = DefectMap::from_dark(&dark, WIDTH, HEIGHT, &DefectConfig::default()).unwrap();
+        assert_eq!(map.hot_pixels, vec![(5, 5), (30, 20)]);
+        assert_eq!(map.cold_pixels, vec![(10, 30)]);
+        assert_eq!(map.bad_columns, vec![40]);
+        assert_eq!(map.hot_count(), 2);
+
+        let mask = map.mask();
+        assert_eq!(mask.count(), 3 + HEIGHT);
+        assert!(mask.is_masked(40, 7) && !mask.is_masked(41, 7));
+        assert!(DefectMap::from_dark(&dark[1..], WIDTH, HEIGHT, &DefectConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_defects_from_dithered_lights() {
+        let lights: Vec<Vec<f32>> = (0..5)
+            .map(|n| {
+                let mut light = frame(n + 10);
+                add_star(
+                    &mut light,
+                    15.0 + 4.0 * n as f64,
+                    12.0 + 3.0 * n as f64,
+                    2000.0,
+                );
+                add_defects(&mut light);
+                light
+            })
+            .collect();
+        let frames: Vec<&[f32]> = lights.iter().map(Vec::as_slice).collect();
+        let map = DefectMap::from_lights(&frames, WIDTH, HEIGHT, &DefectConfig::default()).unwrap();
+        assert_eq!(map.hot_pixels, vec![(5, 5), (30, 20)]);
+        assert_eq!(map.cold_pixels, vec![(10, 30)]);
+        assert_eq!(map.bad_columns, vec![40]);
+        assert!(
+            DefectMap::from_lights(&frames[..2], WIDTH, HEIGHT, &DefectConfig::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_reject_pixel_outliers() {
+        let mut light = frame(3);
+        add_star(&mut light, 45.0, 25.0, 3000.0);
+        light[5 * WIDTH + 5] += 5000.0;
+        light[30 * WIDTH + 10] = 0.0;
+        let star_peak = light[25 * WIDTH + 45];
+
+        let outliers =
+            reject_pixel_outliers(&mut light, WIDTH, HEIGHT, &DefectConfig::default()).unwrap();
+        assert_eq!((outliers.hot, outliers.cold), (1, 1));
+        assert!(outliers.mask.is_masked(5, 5) && outliers.mask.is_masked(10, 30));
+        assert!((light[5 * WIDTH + 5] - 100.0).abs() <= 3.0);
+        assert_eq!(light[25 * WIDTH + 45], star_peak);
+    }
+}
@@ -0,0 +1,171 @@
+//! SEP detection settings and presets
+//!
+//! The right settings depend on how many pixels a star covers. A 4"/px
+//! wide-field rig puts a star on a handful of pixels, while a 0.4"/px
+//! long-focal-length rig spreads it over hundreds, and Milky Way fields need
+//! aggressive deblending.
+
+use crate::types::{BackgroundConfig, DetectionConfig, FilterMode, KernelConfig};
+
+/// Plate scale in arcsec/px below which frames count as oversampled
+const OVERSAMPLED_SCALE: f32 = 1.0;
+
+/// Plate scale in arcsec/px above which frames count as undersampled
+const UNDERSAMPLED_SCALE: f32 = 2.5;
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            box_width: 64,
+            box_height: 64,
+            filter_width: 3,
+            filter_height: 3,
+            filter_threshold: 0.0,
+            clip_sigma: 3.0,
+        }
+    }
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            background: BackgroundConfig::default(),
+            threshold_sigma: 3.0,
+            kernel: KernelConfig::None,
+            filter_mode: FilterMode::Conv,
+            min_area: 5,
+            deblend_nthresh: 32,
+            deblend_contrast: 0.005,
+            clean: true,
+            clean_param: 1.0,
+            kron_factor: 6.0,
+            auto_aperture_scale: 2.5,
+            sub_object_limit: 100_000,
+            pixel_stack: None,
+        }
+    }
+}
+
+impl DetectionConfig {
+    /// Wide-field rigs (above ~2.5"/px) where stars cover only a few pixels
+    ///
+    /// A smaller minimum area keeps faint, compact stars, and a finer mesh
+    /// follows the gradients that span a wide field.
+    pub fn undersampled() -> Self {
+        Self {
+            background: BackgroundConfig {
+                box_width: 32,
+                box_height: 32,
+                ..BackgroundConfig::default()
+            },
+            min_area: 3,
+            ..Self::default()
+        }
+    }
+
+    /// Long-focal-length rigs (below ~1"/px) where stars spread over many pixels
+    ///
+    /// The per-pixel signal is low, so the threshold is lower and the minimum
+    /// area larger, and a Gaussian kernel sized to the stars lifts them out of
+    /// the noise. A coarser mesh keeps large stars and galaxies out of the
+    /// background, and a higher deblend contrast avoids splitting bloated stars.
+    pub fn oversampled() -> Self {
+        Self {
+            background: BackgroundConfig {
+                box_width: 128,
+                box_height: 128,
+                filter_width: 5,
+                filter_height: 5,
+                ..BackgroundConfig::default()
+            },
+            threshold_sigma: 2.0,
+            kernel: KernelConfig::Auto { fallback_fwhm: 4.0 },
+            min_area: 12,
+            deblend_contrast: 0.01,
+            ..Self::default()
+        }
+    }
+
+    /// Crowded Milky Way fields
+    ///
+    /// A higher threshold skips the unresolved stellar background, and more
+    /// deblending levels with a low contrast separate touching stars.
+    pub fn crowded() -> Self {
+        Self {
+            background: BackgroundConfig {
+                box_width: 96,
+                box_height: 96,
+                filter_width: 5,
+                filter_height: 5,
+                ..BackgroundConfig::default()
+            },
+            threshold_sigma: 4.0,
+            min_area: 4,
+            deblend_nthresh: 64,
+            deblend_contrast: 0.0005,
+            sub_object_limit: 1_000_000,
+            ..Self::default()
+        }
+    }
+
+    /// Preset for a plate scale in arcsec/px
+    ///
+    /// Below 1"/px this is `oversampled`, above 2.5"/px `undersampled`, and
+    /// the defaults in between.
+    pub fn for_plate_scale(arcsec_per_pixel: f32) -> Self {
+        if arcsec_per_pixel < OVERSAMPLED_SCALE {
+            Self::oversampled()
+        } else if arcsec_per_pixel > UNDERSAMPLED_SCALE {
+            Self::undersampled()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Pixel stack size for an image, at least SEP's default of 300 000
+    pub fn pixel_stack_for(&self, width: usize, height: usize) -> usize {
+        self.pixel_stack
+            .unwrap_or_else(|| width.saturating_mul(height).clamp(300_000, 20_000_000))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_by_plate_scale() {
+        assert_eq!(
+            DetectionConfig::for_plate_scale(0.4),
+            DetectionConfig::oversampled()
+        );
+        assert_eq!(
+            DetectionConfig::for_plate_scale(1.5),
+            DetectionConfig::default()
+        );
+        assert_eq!(
+            DetectionConfig::for_plate_scale(4.0),
+            DetectionConfig::undersampled()
+        );
+        assert!(DetectionConfig::oversampled().min_area > DetectionConfig::undersampled().min_area);
+        assert_eq!(
+            DetectionConfig::default().pixel_stack_for(100, 100),
+            300_000
+        );
+        assert_eq!(
+            DetectionConfig::default().pixel_stack_for(4000, 3000),
+            4000 * 3000
+        );
+    }
+
+    #[test]
+    fn test_partial_json_uses_defaults() {
+        let config: DetectionConfig =
+            serde_json::from_str(r#"{"threshold_sigma": 5.0, "background": {"box_width": 128}}"#)
+                .unwrap();
+        assert_eq!(config.threshold_sigma, 5.0);
+        assert_eq!(config.background.box_width, 128);
+        assert_eq!(config.background.box_height, 64);
+        assert_eq!(config.min_area, 5);
+    }
+}
@@ -0,0 +1,132 @@
+//! Per-frame masks, noise maps, gain and saturation for SEP detection
+
+use crate::mask::PixelMask;
+use astro_metadata::keywords::get_header_value;
+use astro_metadata::AstroMetadata;
+
+/// Per-pixel noise passed to SEP, same size as the image
+#[derive(Debug, Clone, Copy)]
+pub enum NoiseMap<'a> {
+    /// Standard deviation in ADU
+    StdDev(&'a [f32]),
+    /// Variance in ADU²
+    Variance(&'a [f32]),
+}
+
+impl NoiseMap<'_> {
+    /// The noise values
+    pub fn values(&self) -> &[f32] {
+        match self {
+            NoiseMap::StdDev(values) | NoiseMap::Variance(values) => values,
+        }
+    }
+}
+
+/// Everything detection needs about a frame besides its pixels
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DetectionInputs<'a> {
+    /// Pixels to leave out: bad pixels, borders, manual regions
+    pub mask: Option<&'a PixelMask>,
+    /// Per-pixel noise; without it the global background RMS is used
+    pub noise: Option<NoiseMap<'a>>,
+    /// Conversion gain in e-/ADU for Poisson flux errors; 1.0 when unknown
+    pub gain: Option<f64>,
+    /// Pixels at or above this level in ADU are masked and their stars flagged
+    pub saturation_level: Option<f32>,
+}
+
+impl<'a> DetectionInputs<'a> {
+    /// Gain and saturation level from the frame's metadata
+    pub fn from_metadata(metadata: &AstroMetadata) -> Self {
+        Self {
+            gain: metadata.detector.electrons_per_adu.map(f64::from),
+            saturation_level: saturation_level(metadata),
+            ..Self::default()
+        }
+    }
+
+    /// Use `mask` for detection
+    pub fn with_mask(mut self, mask: &'a PixelMask) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    /// Use `noise` for detection
+    pub fn with_noise(mut self, noise: NoiseMap<'a>) -> Self {
+        self.noise = Some(noise);
+        self
+    }
+}
+
+/// Saturation level in ADU from `SATURATE`, `DATAMAX` or the integer `BITPIX`
+///
+/// Floating-point images have no fixed saturation level and give `None`
+/// unless one of the keywords is present.
+pub fn saturation_level(metadata: &AstroMetadata) -> Option<f32> {
+    let header = |key: &str| {
+        get_header_value(&metadata.raw_headers, key)
+            .and_then(|value| value.trim().parse::<f64>().ok())
+    };
+    if let Some(level) = header("SATURATE").or_else(|| header("DATAMAX")) {
+        return Some(level as f32);
+    }
+
+    let bitpix = header("BITPIX")? as i32;
+    if bitpix <= 0 || bitpix > 32 {
+        return None;
+    }
+    // Unsigned data is stored signed with BZERO = 2^(BITPIX-1)
+    let bzero = header("BZERO").unwrap_or(0.0);
+    let signed_max = 2f64.powi(bitpix - 1) - 1.0;
+    let max = if bitpix == 8 {
+        255.0
+    } else {
+        signed_max + bzero
+    };
+    Some(max as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(headers: &[(&str, &str)]) -> AstroMetadata {
+        let mut metadata = AstroMetadata::default();
+        for (key, value) in headers {
+            metadata
+                .raw_headers
+                .insert(key.to_string(), value.to_string());
+        }
+        metadata
+    }
+
+    #[test]
+    fn test_saturation_level_from_headers() {
+        let unsigned = metadata(&[("BITPIX", "16"), ("BZERO", "32768")]);
+        assert_eq!(saturation_level(&unsigned), Some(65535.0));
+        assert_eq!(
+            saturation_level(&metadata(&[("BITPIX", "16")])),
+            Some(32767.0)
+        );
+        assert_eq!(saturation_level(&metadata(&[("BITPIX", "8")])), Some(255.0));
+        assert_eq!(saturation_level(&metadata(&[("BITPIX", "-32")])), None);
+        let datamax = metadata(&[("BITPIX", "16"), ("BZERO", "32768"), ("DATAMAX", "16383")]);
+        assert_eq!(saturation_level(&datamax), Some(16383.0));
+
+        // Keys stored in another case are still found
+        let lower = metadata(&[("bitpix", "16"), ("Bzero", "32768")]);
+        assert_eq!(saturation_level(&lower), Some(65535.0));
+        let saturate = metadata(&[("BITPIX", "16"), ("saturate", "60000")]);
+        assert_eq!(saturation_level(&saturate), Some(60000.0));
+    }
+
+    #[test]
+    fn test_inputs_from_metadata() {
+        let mut meta = metadata(&[("BITPIX", "16"), ("BZERO", "32768")]);
+        meta.detector.electrons_per_adu = Some(0.25);
+        let inputs = DetectionInputs::from_metadata(&meta);
+        assert_eq!(inputs.gain, Some(0.25));
+        assert_eq!(inputs.saturation_level, Some(65535.0));
+        assert!(inputs.mask.is_none() && inputs.noise.is_none());
+    }
+}
@@ -0,0 +1,306 @@
+//! Convolution kernels for SEP detection
+//!
+//! Convolving with a kernel close to the star profile (a matched filter)
+//! averages down pixel noise, so faint stars in short subs clear the threshold
+//! more consistently from frame to frame.
+
+use crate::types::{KernelConfig, StarMetrics};
+use anyhow::{bail, Result};
+
+/// Standard deviation of a Gaussian per unit FWHM
+const SIGMA_PER_FWHM: f64 = 1.0 / 2.354_820_045;
+
+/// Largest kernel half-width in pixels
+const MAX_HALF_WIDTH: usize = 15;
+
+/// Auto-sized Gaussians are kept within this FWHM range in pixels
+const AUTO_FWHM_RANGE: (f64, f64) = (1.0, 10.0);
+
+/// Number of bright stars used to estimate the FWHM
+const AUTO_STARS: usize = 50;
+
+/// Fewest stars needed for an FWHM estimate
+const AUTO_MIN_STARS: usize = 5;
+
+/// A convolution kernel in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvolutionKernel {
+    /// Kernel width in pixels (odd)
+    pub width: usize,
+    /// Kernel height in pixels (odd)
+    pub height: usize,
+    /// Kernel values, `width * height` of them
+    pub values: Vec<f32>,
+}
+
+impl ConvolutionKernel {
+    /// Gaussian with the given FWHM in pixels, normalized to unit sum
+    pub fn gaussian(fwhm: f64) -> Result<Self> {
+        check_size("Gaussian FWHM", fwhm)?;
+        let sigma = fwhm * SIGMA_PER_FWHM;
+        let half = half_width(1.5 * fwhm)?;
+        let mut kernel = Self::from_fn(half, |r2| (-r2 / (2.0 * sigma * sigma)).exp());
+        kernel.normalize();
+        Ok(kernel)
+    }
+
+    /// Flat disk with the given radius in pixels, normalized to unit sum
+    pub fn tophat(radius: f64) -> Result<Self> {
+        check_size("Tophat radius", radius)?;
+        let half = half_width(radius)?;
+        let mut kernel = Self::from_fn(half, |r2| if r2 <= radius * radius { 1.0 } else { 0.0 });
+        kernel.normalize();
+        Ok(kernel)
+    }
+
+    /// Mexican hat with the given FWHM of its Gaussian, zero sum
+    ///
+    /// The negative ring suppresses smooth structure such as nebulosity and
+    /// gradients, leaving compact sources.
+    pub fn mexican_hat(fwhm: f64) -> Result<Self> {
+        check_size("Mexican hat FWHM", fwhm)?;
+        let sigma = fwhm * SIGMA_PER_FWHM;
+        let half = half_width(2.0 * fwhm)?;
+        let mut kernel = Self::from_fn(half, |r2| {
+            let q = r2 / (2.0 * sigma * sigma);
+            (1.0 - q) * (-q).exp()
+        });
+        let mean = kernel.values.iter().sum::<f32>() / kernel.values.len() as f32;
+        kernel.values.iter_mut().for_each(|v| *v -= mean);
+        let norm: f32 = kernel.values.iter().map(|v| v.abs()).sum();
+        kernel.values.iter_mut().for_each(|v| *v /= norm);
+        Ok(kernel)
+    }
+
+    /// Kernel from row-major values; width and height must be odd
+    pub fn custom(width: usize, height: usize, values: Vec<f32>) -> Result<Self> {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) {
+            bail!("Kernel size must be odd, got {width}x{height}");
+        }
+        if values.len() != width * height {
+            bail!(
+                "Kernel of {width}x{height} needs {} values, got {}",
+                width * height,
+                values.len()
+            );
+        }
+        if values.iter().any(|v| !v.is_finite()) {
+            bail!("Kernel values must be finite");
+        }
+        if values.iter().all(|v| *v == 0.0) {
+            bail!("Kernel values must not all be zero");
+        }
+        Ok(Self {
+            width,
+            height,
+            values,
+        })
+    }
+
+    /// Build the kernel for a configuration; `Auto` uses its fallback FWHM
+    pub fn from_config(config: &KernelConfig) -> Result<Option<Self>> {
+        let kernel = match config.resolve(None) {
+            KernelConfig::None => return Ok(None),
+            KernelConfig::Gaussian { fwhm } => Self::gaussian(fwhm)?,
+            KernelConfig::Tophat { radius } => Self::tophat(radius)?,
+            KernelConfig::MexicanHat { fwhm } => Self::mexican_hat(fwhm)?,
+            KernelConfig::Custom {
+                width,
+                height,
+                values,
+            } => Self::custom(width, height, values)?,
+            KernelConfig::Auto { .. } => unreachable!("resolve replaces Auto"),
+        };
+        Ok(Some(kernel))
+    }
+
+    /// Square kernel of half-width `half` from a function of the squared radius
+    fn from_fn(half: usize, value: impl Fn(f64) -> f64) -> Self {
+        let size = 2 * half + 1;
+        let mut values = Vec::with_capacity(size * size);
+        for y in 0..size {
+            for x in 0..size {
+                let dx = x as f64 - half as f64;
+                let dy = y as f64 - half as f64;
+                values.push(value(dx * dx + dy * dy) as f32);
+            }
+        }
+        Self {
+            width: size,
+            height: size,
+            values,
+        }
+    }
+
+    fn normalize(&mut self) {
+        let sum: f32 = self.values.iter().sum();
+        self.values.iter_mut().for_each(|v| *v /= sum);
+    }
+}
+
+impl KernelConfig {
+    /// Replace `Auto` with a Gaussian at `estimated_fwhm`, or at the fallback
+    /// FWHM when there is no estimate; other kernels are returned unchanged
+    pub fn resolve(&self, estimated_fwhm: Option<f64>) -> KernelConfig {
+        match self {
+            KernelConfig::Auto { fallback_fwhm } => KernelConfig::Gaussian {
+                fwhm: estimated_fwhm
+                    .unwrap_or(*fallback_fwhm)
+                    .clamp(AUTO_FWHM_RANGE.0, AUTO_FWHM_RANGE.1),
+            },
+            other => other.clone(),
+        }
+    }
+}
+
+/// Median FWHM of the brightest unflagged stars, `None` when there are too few
+///
+/// SEP's `a` and `b` are the profile's standard deviations along its axes,
+/// so each star's FWHM is 2.3548·√(ab).
+pub fn estimate_fwhm(stars: &[StarMetrics]) -> Option<f64> {
+    let mut bright: Vec<&StarMetrics> = stars
+        .iter()
+        .filter(|s| s.flag == 0 && s.a.is_finite() && s.b.is_finite() && s.b > 0.0)
+        .collect();
+    if bright.len() < AUTO_MIN_STARS {
+        return None;
+    }
+    bright.sort_by(|a, b| b.flux.total_cmp(&a.flux));
+    bright.truncate(AUTO_STARS);
+    let mut fwhms: Vec<f64> = bright
+        .iter()
+        .map(|s| (f64::from(s.a) * f64::from(s.b)).sqrt() / SIGMA_PER_FWHM)
+        .collect();
+    fwhms.sort_by(f64::total_cmp);
+    Some(fwhms[fwhms.len() / 2])
+}
+
+fn check_size(name: &str, value: f64) -> Result<()> {
+    if !value.is_finite() || value <= 0.0 {
+        bail!("{name} must be positive, got {value}");
+    }
+    Ok(())
+}
+
+fn half_width(extent: f64) -> Result<usize> {
+    let half = extent.ceil().max(1.0) as usize;
+    if half > MAX_HALF_WIDTH {
+        bail!(
+            "Kernel would be {0}x{0} pixels, larger than the {1}x{1} limit",
+            2 * half + 1,
+            2 * MAX_HALF_WIDTH + 1
+        );
+    }
+    Ok(half)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DetectionConfig;
+
+    fn star(fwhm: f32, flux: f32, flag: u8) -> StarMetrics {
+        let sigma = (f64::from(fwhm) * SIGMA_PER_FWHM) as f32;
+        StarMetrics {
+            x: 0.0,
+            y: 0.0,
+            flux,
+            peak: 0.0,
+            a: sigma,
+            b: sigma,
+            theta: 0.0,
+            eccentricity: 0.0,
+            fwhm,
+            kron_radius: 0.0,
+            flux_auto: flux,
+            fluxerr_auto: 0.0,
+            npix: 10,
+            elongation: 1.0,
+            flag,
+        }
+    }
+
+    #[test]
+    fn test_builtin_kernels() {
+        let gaussian = ConvolutionKernel::gaussian(2.0).unwrap();
+        assert_eq!((gaussian.width, gaussian.height), (7, 7));
+        assert!((gaussian.values.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        let center = gaussian.values[3 * 7 + 3];
+        assert!(gaussian.values.iter().all(|v| *v <= center));
+
+        let tophat = ConvolutionKernel::tophat(1.0).unwrap();
+        assert_eq!(tophat.values.iter().filter(|v| **v > 0.0).count(), 5);
+
+        let hat = ConvolutionKernel::mexican_hat(2.0).unwrap();
+        assert!(hat.values.iter().sum::<f32>().abs() < 1e-5);
+        assert!(hat.values.iter().any(|v| *v < 0.0));
+        assert!(hat.values[hat.values.len() / 2] > 0.0);
+
+        assert!(ConvolutionKernel::gaussian(0.0).is_err());
+        assert!(ConvolutionKernel::gaussian(40.0).is_err());
+        assert!(ConvolutionKernel::custom(2, 3, vec![1.0; 6]).is_err());
+        assert!(ConvolutionKernel::custom(3, 3, vec![1.0; 8]).is_err());
+        assert!(ConvolutionKernel::custom(3, 1, vec![1.0, 2.0, 1.0]).is_ok());
+    }
+
+    #[test]
+    fn test_auto_kernel_from_bright_stars() {
+        let mut stars: Vec<StarMetrics> =
+            (0..10).map(|i| star(3.0, 1000.0 + i as f32, 0)).collect();
+        stars.push(star(20.0, 1e6, 1));
+        let fwhm = estimate_fwhm(&stars).expect("enough stars");
+        assert!((fwhm - 3.0).abs() < 1e-5, "{fwhm}");
+        assert_eq!(estimate_fwhm(&stars[..3]), None);
+
+        let auto = KernelConfig::Auto { fallback_fwhm: 2.5 };
+        assert_eq!(
+            auto.resolve(Some(3.0)),
+            KernelConfig::Gaussian { fwhm: 3.0 }
+        );
+        assert_eq!(
+            auto.resolve(Some(0.2)),
+            KernelConfig::Gaussian { fwhm: 1.0 }
+        );
+        let fallback = ConvolutionKernel::from_config(&auto).unwrap();
+        assert_eq!(fallback, Some(ConvolutionKernel::gaussian(2.5).unwrap()));
+        assert_eq!(
+            ConvolutionKernel::from_config(&KernelConfig::None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_estimate_fwhm_of_detected_stars() {
+        // Gaussian stars with a 4 px FWHM on a noisy sky
+        const SIZE: usize = 200;
+        const FWHM: f64 = 4.0;
+        let sigma = FWHM * SIGMA_PER_FWHM;
+        let mut seed = 4321u32;
+        let mut data: Vec<f32> = (0..SIZE * SIZE)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                100.0 + ((seed >> 16) % 17) as f32 - 8.0
+            })
+            .collect();
+        for cy in [40.0, 100.0, 160.0] {
+            for cx in [40.5, 100.25, 160.0] {
+                for y in 0..SIZE {
+                    for x in 0..SIZE {
+                        let r2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                        data[y * SIZE + x] += (3000.0 * (-r2 / (2.0 * sigma * sigma)).exp()) as f32;
+                    }
+                }
+            }
+        }
+
+        let config = DetectionConfig {
+            kernel: KernelConfig::None,
+            ..DetectionConfig::default()
+        };
+        let catalog =
+            crate::sep_detect::detect_catalog_with_config(&data, SIZE, SIZE, &config).unwrap();
+        assert_eq!(catalog.len(), 9);
+        let fwhm = estimate_fwhm(&catalog.stars).expect("enough stars");
+        assert!((fwhm - FWHM).abs() < 0.3, "estimated FWHM {fwhm}");
+    }
+}
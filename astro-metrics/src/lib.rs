@@ -1,16 +1,38 @@
 //! Statistical metrics for astronomical images
 
+mod background_gradient;
 pub mod background_metrics;
+pub mod background_model;
+pub mod defects;
+pub mod detection_config;
+pub mod detection_inputs;
+pub mod kernel;
+pub mod mask;
+pub mod obstruction;
+mod preview;
 pub mod quality_metrics;
+pub mod sep;
 pub mod sep_detect;
+pub mod star_catalog;
 pub mod star_metrics;
+pub mod trail;
 pub mod types;
 
 // Re-export common types
+pub use background_model::{BackgroundMap, BackgroundModel};
+pub use defects::{reject_pixel_outliers, PixelOutliers};
+pub use detection_inputs::{DetectionInputs, NoiseMap};
+pub use kernel::ConvolutionKernel;
+pub use mask::PixelMask;
+pub use obstruction::detect_obstruction;
 pub use quality_metrics::{
     calculate_overall_score, calculate_quality_scores, create_frame_metrics,
-    create_frame_metrics_with_weights,
+    create_frame_metrics_with_trails, create_frame_metrics_with_weights,
 };
+pub use trail::{detect_trails, trail_mask};
 pub use types::{
-    BackgroundMetrics, FrameQualityMetrics, QualityScores, QualityWeights, StarMetrics, StarStats,
+    BackgroundConfig, BackgroundGradient, BackgroundMetrics, DefectConfig, DefectMap,
+    DetectionConfig, FilterMode, FrameQualityMetrics, KernelConfig, ObstructionConfig,
+    ObstructionReport, QualityScores, QualityWeights, StarCatalog, StarMetrics, StarStats, Trail,
+    TrailConfig,
 };
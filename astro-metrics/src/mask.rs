@@ -0,0 +1,245 @@
+//! Pixel masks for detection
+//!
+//! Masked pixels are left out of background estimation and object detection.
+//! Masks combine with `union`, so bad pixels, image borders, manual regions
+//! and saturated pixels can be built separately and merged.
+
+use anyhow::{bail, Result};
+
+/// A per-pixel mask, `true` meaning the pixel is excluded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelMask {
+    width: usize,
+    height: usize,
+    /// One byte per pixel, 1 when masked, in the layout SEP reads
+    data: Vec<u8>,
+}
+
+impl PixelMask {
+    /// An empty mask of the given size
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width * height],
+        }
+    }
+
+    /// A mask from row-major flags
+    pub fn from_flags(width: usize, height: usize, flags: &[bool]) -> Result<Self> {
+        if flags.len() != width * height {
+            bail!(
+                "Mask of {width}x{height} needs {} values, got {}",
+                width * height,
+                flags.len()
+            );
+        }
+        Ok(Self {
+            width,
+            height,
+            data: flags.iter().map(|&f| u8::from(f)).collect(),
+        })
+    }
+
+    /// Mask pixels at or above `level`, the saturation level in ADU
+    pub fn saturated(data: &[f32], width: usize, height: usize, level: f32) -> Self {
+        Self {
+            width,
+            height,
+            data: data.iter().map(|&v| u8::from(v >= level)).collect(),
+        }
+    }
+
+    /// Mask width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Mask height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// True when the pixel is masked; pixels outside the image count as unmasked
+    pub fn is_masked(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.data[y * self.width + x] != 0
+    }
+
+    /// Mask a single pixel
+    pub fn set(&mut self, x: usize, y: usize) {
+        if x < self.width && y < self.height {
+            self.data[y * self.width + x] = 1;
+        }
+    }
+
+    /// Mask a band of `margin` pixels along every edge
+    pub fn mask_border(&mut self, margin: usize) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if x < margin || y < margin || x + margin >= self.width || y + margin >= self.height
+                {
+                    self.data[y * self.width + x] = 1;
+                }
+            }
+        }
+    }
+
+    /// Mask the rectangle from `(x0, y0)` up to but excluding `(x1, y1)`
+    pub fn mask_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize) {
+        for y in y0..y1.min(self.height) {
+            for x in x0..x1.min(self.width) {
+                self.data[y * self.width + x] = 1;
+            }
+        }
+    }
+
+    /// Mask a disk of `radius` pixels around `(cx, cy)`
+    pub fn mask_circle(&mut self, cx: f64, cy: f64, radius: f64) {
+        let y0 = (cy - radius).floor().max(0.0) as usize;
+        let x0 = (cx - radius).floor().max(0.0) as usize;
+        let y1 = ((cy + radius).ceil().max(0.0) as usize + 1).min(self.height);
+        let x1 = ((cx + radius).ceil().max(0.0) as usize + 1).min(self.width);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let dx = x as f64 - cx;
+                let dy = y as f64 - cy;
+                if dx * dx + dy * dy <= radius * radius {
+                    self.data[y * self.width + x] = 1;
+                }
+            }
+        }
+    }
+
+    /// Mask pixels within `half_width` of the segment from `(x0, y0)` to `(x1, y1)`
+    pub fn mask_segment(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, half_width: f64) {
+        let xmin = (x0.min(x1) - half_width).floor().max(0.0) as usize;
+        let ymin = (y0.min(y1) - half_width).floor().max(0.0) as usize;
+        let xmax = ((x0.max(x1) + half_width).ceil().max(0.0) as usize + 1).min(self.width);
+        let ymax = ((y0.max(y1) + half_width).ceil().max(0.0) as usize + 1).min(self.height);
+        for y in ymin..ymax {
+            for x in xmin..xmax {
+                if segment_distance(x as f64, y as f64, x0, y0, x1, y1) <= half_width {
+                    self.data[y * self.width + x] = 1;
+                }
+            }
+        }
+    }
+
+    /// Also mask every pixel masked in `other`
+    pub fn union(&mut self, other: &PixelMask) -> Result<()> {
+        if (self.width, self.height) != (other.width, other.height) {
+            bail!(
+                "Cannot combine a {}x{} mask with a {}x{} mask",
+                self.width,
+                self.height,
+                other.width,
+                other.height
+            );
+        }
+        for (a, b) in self.data.iter_mut().zip(&other.data) {
+            *a |= b;
+        }
+        Ok(())
+    }
+
+    /// Grow the masked regions by `radius` pixels (square neighbourhood)
+    pub fn dilate(&mut self, radius: usize) {
+        if radius == 0 {
+            return;
+        }
+        let source = self.data.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if source[y * self.width + x] == 0 {
+                    continue;
+                }
+                let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(self.width));
+                let (y0, y1) = (y.saturating_sub(radius), (y + radius + 1).min(self.height));
+                for yy in y0..y1 {
+                    self.data[yy * self.width + x0..yy * self.width + x1].fill(1);
+                }
+            }
+        }
+    }
+
+    /// True when any pixel in the inclusive box is masked
+    pub fn any_in_box(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> bool {
+        (y0..=y1.min(self.height.saturating_sub(1)))
+            .any(|y| (x0..=x1.min(self.width.saturating_sub(1))).any(|x| self.is_masked(x, y)))
+    }
+
+    /// Number of masked pixels
+    pub fn count(&self) -> usize {
+        self.data.iter().filter(|&&v| v != 0).count()
+    }
+
+    /// Fraction of pixels masked
+    pub fn fraction(&self) -> f32 {
+        if self.data.is_empty() {
+            0.0
+        } else {
+            self.count() as f32 / self.data.len() as f32
+        }
+    }
+
+    /// Row-major bytes, 1 for masked pixels
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// Distance from `(x, y)` to the segment from `(x0, y0)` to `(x1, y1)`
+pub(crate) fn segment_distance(x: f64, y: f64, x0: f64, y0: f64, x1: f64, y1: f64) -> f64 {
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let length2 = dx * dx + dy * dy;
+    let t = if length2 > 0.0 {
+        (((x - x0) * dx + (y - y0) * dy) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (x - x0 - t * dx).hypot(y - y0 - t * dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions_and_union() {
+        let mut mask = PixelMask::new(10, 8);
+        mask.mask_border(1);
+        assert_eq!(mask.count(), 10 * 8 - 8 * 6);
+        assert!(mask.is_masked(0, 3) && mask.is_masked(9, 7) && !mask.is_masked(1, 1));
+
+        let mut manual = PixelMask::new(10, 8);
+        manual.mask_rect(4, 3, 6, 5);
+        manual.mask_circle(2.0, 2.0, 1.0);
+        assert_eq!(manual.count(), 4 + 5);
+        mask.union(&manual).unwrap();
+        assert!(mask.is_masked(5, 4) && mask.is_masked(2, 1));
+        assert!(mask.union(&PixelMask::new(4, 4)).is_err());
+        assert!(PixelMask::from_flags(2, 2, &[true; 3]).is_err());
+    }
+
+    #[test]
+    fn test_saturation_mask_and_dilate() {
+        let mut data = vec![100.0; 25];
+        data[12] = 65535.0;
+        let mut mask = PixelMask::saturated(&data, 5, 5, 65000.0);
+        assert_eq!(mask.count(), 1);
+        assert!(!mask.any_in_box(0, 0, 1, 1) && mask.any_in_box(2, 2, 4, 4));
+        mask.dilate(1);
+        assert_eq!(mask.count(), 9);
+        assert!(mask.is_masked(1, 1) && !mask.is_masked(0, 0));
+    }
+
+    #[test]
+    fn test_mask_segment() {
+        let mut mask = PixelMask::new(10, 10);
+        mask.mask_segment(1.0, 1.0, 8.0, 1.0, 1.0);
+        // A 3-pixel band with rounded ends
+        assert_eq!(mask.count(), 8 * 3 + 2);
+        assert!(mask.is_masked(8, 2) && mask.is_masked(9, 1));
+        assert!(!mask.is_masked(9, 2) && !mask.is_masked(4, 3));
+    }
+}
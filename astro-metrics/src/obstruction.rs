@@ -0,0 +1,676 @@
+//! Cloud, branch and other obstruction detection per frame
+//!
+//! The frame is split into tiles and each tile is checked for three signs of
+//! an obstruction: fewer stars than expected, background above the smooth
+//! gradient (clouds lit by light pollution or the Moon), and stars that lost
+//! flux against a clear reference frame. A tile showing any of them counts
+//! as obstructed. The reference may be dithered or, after a meridian flip,
+//! rotated by 180°.
+//!
+//! The background check compares SEP's mesh, which keeps the boxes the
+//! gradient fit rejected, with the fitted gradient. A cloud covering a large
+//! part of the frame (a quadrant or more) pulls the fit towards itself and is
+//! taken for gradient: its tiles can pass and clear tiles across the frame
+//! can be flagged instead. Such a frame has a `BackgroundGradient::lumpiness`
+//! of several background RMS, and the star count and reference flux checks
+//! still see the cloud.
+
+use crate::background_model::BackgroundModel;
+use crate::mask::PixelMask;
+use crate::types::{ObstructionConfig, ObstructionReport, StarCatalog, StarMetrics};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+/// Brightest stars per frame used to align the frames
+const OFFSET_STARS: usize = 50;
+
+/// Pairs that must agree on an alignment before it is used
+const MIN_OFFSET_VOTES: usize = 3;
+
+impl Default for ObstructionConfig {
+    fn default() -> Self {
+        Self {
+            tile_size: 256,
+            min_tile_stars: 4,
+            density_drop: 0.5,
+            background_sigma: 3.0,
+            flux_drop: 0.7,
+            match_radius: 3.0,
+        }
+    }
+}
+
+impl ObstructionReport {
+    /// Mask covering the obstructed tiles
+    pub fn mask(&self) -> PixelMask {
+        let mut mask = PixelMask::new(self.width, self.height);
+        for (index, _) in self.obstructed_tiles.iter().enumerate().filter(|(_, &o)| o) {
+            let (x0, y0) = (
+                index % self.tiles_x * self.tile_size,
+                index / self.tiles_x * self.tile_size,
+            );
+            mask.mask_rect(x0, y0, x0 + self.tile_size, y0 + self.tile_size);
+        }
+        mask
+    }
+}
+
+/// Look for obstructions in the frame of `catalog`
+///
+/// `background` enables the background excess check and `reference`, the
+/// catalog of a clear frame of the same field, the flux check and the
+/// transparency estimate. Without a reference the expected star count is the
+/// median over the tiles.
+pub fn detect_obstruction(
+    catalog: &StarCatalog,
+    background: Option<&BackgroundModel>,
+    reference: Option<&StarCatalog>,
+    config: &ObstructionConfig,
+) -> Result<ObstructionReport> {
+    if config.tile_size == 0 {
+        bail!("Obstruction tile size must be positive");
+    }
+    let grid = TileGrid::new(catalog.width, catalog.height, config.tile_size);
+    if let Some(model) = background {
+        if (model.width, model.height) != (catalog.width, catalog.height) {
+            bail!(
+                "Background model is {}x{} but the catalog is {}x{}",
+                model.width,
+                model.height,
+                catalog.width,
+                catalog.height
+            );
+        }
+    }
+    if let Some(reference) = reference {
+        if (reference.width, reference.height) != (catalog.width, catalog.height) {
+            bail!(
+                "Reference catalog is {}x{} but the catalog is {}x{}",
+                reference.width,
+                reference.height,
+                catalog.width,
+                catalog.height
+            );
+        }
+    }
+
+    let reference = reference.map(|reference| {
+        let alignment = align(&catalog.stars, &reference.stars, config.match_radius);
+        (reference, alignment)
+    });
+    let aligned = reference.and_then(|(reference, alignment)| Some((reference, alignment?)));
+
+    let counts = grid.count(catalog.stars.iter().map(|s| (s.x, s.y)));
+    let expected: Vec<f32> = match aligned {
+        Some((reference, alignment)) => {
+            // Reference stars where they fall on this frame
+            let positions = reference
+                .stars
+                .iter()
+                .map(|s| alignment.on_frame(s.x, s.y))
+                .filter(|&(x, y)| grid.contains(x, y));
+            grid.count(positions).iter().map(|&c| c as f32).collect()
+        }
+        None => {
+            // Median star density over the tiles, scaled to each tile's area
+            let densities: Vec<f32> = counts
+                .iter()
+                .enumerate()
+                .map(|(tile, &c)| c as f32 / grid.area(tile))
+                .collect();
+            let density = median(densities);
+            (0..grid.len())
+                .map(|tile| density * grid.area(tile))
+                .collect()
+        }
+    };
+    let density_drop: Vec<bool> = counts
+        .iter()
+        .zip(&expected)
+        .map(|(&c, &e)| e >= config.min_tile_stars as f32 && (c as f32) < config.density_drop * e)
+        .collect();
+
+    let background_excess: Vec<bool> = match background {
+        Some(model) => mesh_excess(&grid, model)
+            .into_iter()
+            .map(|excess| excess.is_some_and(|e| e > config.background_sigma * model.global_rms))
+            .collect(),
+        None => vec![false; grid.len()],
+    };
+
+    let mut flux_drop = vec![false; grid.len()];
+    let mut transparency = None;
+    let mut matched_stars = 0;
+    if let Some((reference, alignment)) = aligned {
+        let pairs = match_stars(
+            &catalog.stars,
+            &reference.stars,
+            &alignment,
+            config.match_radius,
+        );
+        matched_stars = pairs.len();
+        let mut ratios = vec![Vec::new(); grid.len()];
+        for (star, reference_star) in &pairs {
+            let ratio = flux(star) / flux(reference_star);
+            ratios[grid.tile(star.x, star.y)].push(ratio);
+        }
+        transparency = (!pairs.is_empty()).then(|| median(ratios.concat()));
+        for (drop, tile_ratios) in flux_drop.iter_mut().zip(ratios) {
+            *drop = tile_ratios.len() >= config.min_tile_stars
+                && median(tile_ratios) < config.flux_drop;
+        }
+    }
+
+    let obstructed_tiles: Vec<bool> = (0..grid.len())
+        .map(|tile| density_drop[tile] || background_excess[tile] || flux_drop[tile])
+        .collect();
+    let obstructed_area: f32 = obstructed_tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, &o)| o)
+        .map(|(tile, _)| grid.area(tile))
+        .sum();
+    let total_area: f32 = (0..grid.len()).map(|tile| grid.area(tile)).sum();
+    let count = |flags: &[bool]| flags.iter().filter(|&&f| f).count();
+    Ok(ObstructionReport {
+        width: catalog.width,
+        height: catalog.height,
+        tile_size: config.tile_size,
+        tiles_x: grid.tiles_x,
+        tiles_y: grid.tiles_y,
+        obstructed_fraction: if total_area > 0.0 {
+            obstructed_area / total_area
+        } else {
+            0.0
+        },
+        obstructed_tiles,
+        transparency,
+        matched_stars,
+        reference_flipped: aligned.is_some_and(|(_, alignment)| alignment.flipped),
+        reference_unaligned: reference.is_some() && aligned.is_none(),
+        star_density_tiles: count(&density_drop),
+        background_tiles: count(&background_excess),
+        flux_drop_tiles: count(&flux_drop),
+    })
+}
+
+/// Square tiles over the image, the last row and column cut by the edges
+struct TileGrid {
+    width: usize,
+    height: usize,
+    size: usize,
+    tiles_x: usize,
+    tiles_y: usize,
+}
+
+impl TileGrid {
+    fn new(width: usize, height: usize, size: usize) -> Self {
+        Self {
+            width,
+            height,
+            size,
+            tiles_x: width.div_ceil(size),
+            tiles_y: height.div_ceil(size),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.tiles_x * self.tiles_y
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        x >= 0.0 && y >= 0.0 && x < self.width as f64 && y < self.height as f64
+    }
+
+    /// Tile containing pixel `(x, y)`, clamped to the grid
+    fn tile(&self, x: f64, y: f64) -> usize {
+        let tx = ((x.max(0.0) as usize) / self.size).min(self.tiles_x.saturating_sub(1));
+        let ty = ((y.max(0.0) as usize) / self.size).min(self.tiles_y.saturating_sub(1));
+        ty * self.tiles_x + tx
+    }
+
+    /// Pixel bounds of a tile, end-exclusive
+    fn bounds(&self, tile: usize) -> (usize, usize, usize, usize) {
+        let (x0, y0) = (
+            tile % self.tiles_x * self.size,
+            tile / self.tiles_x * self.size,
+        );
+        (
+            x0,
+            y0,
+            (x0 + self.size).min(self.width),
+            (y0 + self.size).min(self.height),
+        )
+    }
+
+    /// Tile area relative to a full tile
+    fn area(&self, tile: usize) -> f32 {
+        let (x0, y0, x1, y1) = self.bounds(tile);
+        ((x1 - x0) * (y1 - y0)) as f32 / (self.size * self.size) as f32
+    }
+
+    fn count(&self, positions: impl Iterator<Item = (f64, f64)>) -> Vec<usize> {
+        let mut counts = vec![0; self.len()];
+        for (x, y) in positions {
+            counts[self.tile(x, y)] += 1;
+        }
+        counts
+    }
+}
+
+/// Mean excess of the mesh boxes centred in each tile over the smooth
+/// background, the global level without a fit; `None` for tiles holding no
+/// box centre
+///
+/// The mesh is SEP's first pass, so boxes the fit rejected keep their level
+/// where `BackgroundModel::background` has them filled from their neighbours.
+fn mesh_excess(grid: &TileGrid, model: &BackgroundModel) -> Vec<Option<f32>> {
+    // The last box along each axis may be cut short by the image edge
+    let centre = |i: usize, size: usize, extent: usize| {
+        (i * size + ((i + 1) * size).min(extent)) as f64 / 2.0
+    };
+    let mut sums = vec![(0.0, 0); grid.len()];
+    for (index, &value) in model.mesh.iter().enumerate() {
+        let x = centre(index % model.mesh_width, model.box_width, model.width);
+        let y = centre(index / model.mesh_width, model.box_height, model.height);
+        let smooth = match &model.gradient {
+            Some(gradient) => gradient.level_at(x, y, model.width, model.height),
+            None => model.global,
+        };
+        let (sum, n) = &mut sums[grid.tile(x, y)];
+        *sum += f64::from(value - smooth);
+        *n += 1;
+    }
+    sums.into_iter()
+        .map(|(sum, n)| (n > 0).then(|| (sum / n as f64) as f32))
+        .collect()
+}
+
+/// Background-subtracted flux of a star, AUTO aperture when measured
+fn flux(star: &StarMetrics) -> f32 {
+    if star.flux_auto > 0.0 {
+        star.flux_auto
+    } else {
+        star.flux
+    }
+}
+
+/// How a frame lies on its reference: a shift, after a 180° rotation about
+/// the origin when `flipped`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Alignment {
+    flipped: bool,
+    dx: f64,
+    dy: f64,
+}
+
+impl Alignment {
+    /// Reference position of a frame position
+    fn on_reference(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.flipped {
+            (self.dx - x, self.dy - y)
+        } else {
+            (x - self.dx, y - self.dy)
+        }
+    }
+
+    /// Frame position of a reference position
+    fn on_frame(&self, x: f64, y: f64) -> (f64, f64) {
+        if self.flipped {
+            (self.dx - x, self.dy - y)
+        } else {
+            (x + self.dx, y + self.dy)
+        }
+    }
+}
+
+/// Usable stars for matching: not saturated and with positive flux
+fn usable(stars: &[StarMetrics]) -> Vec<&StarMetrics> {
+    stars
+        .iter()
+        .filter(|s| !s.is_saturated() && flux(s) > 0.0)
+        .collect()
+}
+
+/// Pair stars with their nearest reference star after aligning the frames
+fn match_stars<'a>(
+    stars: &'a [StarMetrics],
+    reference: &'a [StarMetrics],
+    alignment: &Alignment,
+    radius: f64,
+) -> Vec<(&'a StarMetrics, &'a StarMetrics)> {
+    if radius <= 0.0 {
+        return Vec::new();
+    }
+
+    // Reference stars hashed into cells of the match radius
+    let cell = |x: f64, y: f64| ((x / radius).floor() as i64, (y / radius).floor() as i64);
+    let mut cells: HashMap<(i64, i64), Vec<&StarMetrics>> = HashMap::new();
+    for star in usable(reference) {
+        cells.entry(cell(star.x, star.y)).or_default().push(star);
+    }
+
+    let mut pairs = Vec::new();
+    for star in usable(stars) {
+        let (x, y) = alignment.on_reference(star.x, star.y);
+        let (cx, cy) = cell(x, y);
+        let nearest = (cx - 1..=cx + 1)
+            .flat_map(|i| (cy - 1..=cy + 1).map(move |j| (i, j)))
+            .filter_map(|key| cells.get(&key))
+            .flatten()
+            .map(|r| (r, (r.x - x).hypot(r.y - y)))
+            .filter(|&(_, d)| d <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((reference_star, _)) = nearest {
+            pairs.push((star, *reference_star));
+        }
+    }
+    pairs
+}
+
+/// Shift of the frame from the reference, straight or rotated by 180°, voted
+/// on by the brightest stars; `None` when too few pairs agree
+fn align(stars: &[StarMetrics], reference: &[StarMetrics], radius: f64) -> Option<Alignment> {
+    fn brightest(stars: &[StarMetrics]) -> Vec<&StarMetrics> {
+        let mut sorted = usable(stars);
+        sorted.sort_by(|a, b| flux(b).total_cmp(&flux(a)));
+        sorted.truncate(OFFSET_STARS);
+        sorted
+    }
+    if radius <= 0.0 {
+        return None;
+    }
+    let (stars, reference) = (brightest(stars), brightest(reference));
+
+    // A shift votes on the difference of positions, a rotation by 180° on
+    // their sum
+    let mut votes: HashMap<(bool, i64, i64), Vec<(f64, f64)>> = HashMap::new();
+    for star in &stars {
+        for reference_star in &reference {
+            for flipped in [false, true] {
+                let (dx, dy) = if flipped {
+                    (star.x + reference_star.x, star.y + reference_star.y)
+                } else {
+                    (star.x - reference_star.x, star.y - reference_star.y)
+                };
+                let key = (
+                    flipped,
+                    (dx / radius).round() as i64,
+                    (dy / radius).round() as i64,
+                );
+                votes.entry(key).or_default().push((dx, dy));
+            }
+        }
+    }
+    let ((flipped, _, _), shifts) = votes.into_iter().max_by_key(|(_, v)| v.len())?;
+    (shifts.len() >= MIN_OFFSET_VOTES).then(|| Alignment {
+        flipped,
+        dx: median(shifts.iter().map(|s| s.0 as f32).collect()) as f64,
+        dy: median(shifts.iter().map(|s| s.1 as f32).collect()) as f64,
+    })
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BackgroundConfig, BackgroundMetrics, DetectionConfig};
+
+    fn star(x: f64, y: f64, flux: f32) -> StarMetrics {
+        StarMetrics {
+            x,
+            y,
+            flux,
+            peak: flux / 10.0,
+            a: 1.5,
+            b: 1.5,
+            theta: 0.0,
+            eccentricity: 0.0,
+            fwhm: 3.5,
+            kron_radius: 3.0,
+            flux_auto: flux,
+            fluxerr_auto: 1.0,
+            npix: 20,
+            elongation: 1.0,
+            flag: 0,
+        }
+    }
+
+    /// A 512x512 catalog with a star in every 32 px cell, jittered so the
+    /// field isn't a regular grid, each passed through `f`
+    fn catalog(f: impl Fn(f64, f64) -> Option<f32>) -> StarCatalog {
+        let jitter = |a: usize, b: usize| ((a * 7 + b * 13) % 11) as f64 - 5.0;
+        let stars = (0..16)
+            .flat_map(|j| {
+                (0..16).map(move |i| {
+                    (
+                        16.0 + 32.0 * i as f64 + jitter(i, j),
+                        16.0 + 32.0 * j as f64 + jitter(j, i + 3),
+                    )
+                })
+            })
+            .enumerate()
+            .filter_map(|(n, (x, y))| f(x, y).map(|flux| star(x, y, flux + n as f32)))
+            .collect();
+        StarCatalog {
+            width: 512,
+            height: 512,
+            threshold: 15.0,
+            config: DetectionConfig::default(),
+            background: BackgroundMetrics::new(100.0, 5.0),
+            stars,
+        }
+    }
+
+    #[test]
+    fn test_plain_gradient_is_not_flagged() {
+        // Light pollution brightening towards one side and the corners, with
+        // little noise and no clouds
+        let (width, height) = (768, 512);
+        let half_diagonal = (width as f64).hypot(height as f64) / 2.0;
+        let mut seed = 5u32;
+        let data: Vec<f32> = (0..width * height)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let (x, y) = ((i % width) as f64, (i / width) as f64);
+                let r2 = ((x - width as f64 / 2.0).powi(2) + (y - height as f64 / 2.0).powi(2))
+                    / half_diagonal.powi(2);
+                let sky = 500.0 + 0.3 * x + 0.1 * y + 150.0 * r2;
+                sky as f32 + ((seed >> 16) % 5) as f32 - 2.0
+            })
+            .collect();
+        let model =
+            BackgroundModel::estimate(&data, width, height, &BackgroundConfig::default(), None)
+                .unwrap();
+        let frame = StarCatalog {
+            width,
+            height,
+            ..catalog(|_, _| None)
+        };
+        let report =
+            detect_obstruction(&frame, Some(&model), None, &ObstructionConfig::default()).unwrap();
+        assert_eq!(report.background_tiles, 0);
+        assert_eq!(report.obstructed_fraction, 0.0);
+    }
+
+    #[test]
+    fn test_star_density_drop() {
+        // No stars in the top-left tile
+        let frame = catalog(|x, y| (x > 256.0 || y > 256.0).then_some(1000.0));
+        let report = detect_obstruction(&frame, None, None, &ObstructionConfig::default()).unwrap();
+        assert_eq!((report.tiles_x, report.tiles_y), (2, 2));
+        assert_eq!(report.obstructed_tiles, vec![true, false, false, false]);
+        assert_eq!(report.star_density_tiles, 1);
+        assert!((report.obstructed_fraction - 0.25).abs() < 1e-6);
+        assert_eq!(report.transparency, None);
+        let mask = report.mask();
+        assert!(mask.is_masked(10, 10) && !mask.is_masked(300, 10));
+    }
+
+    #[test]
+    fn test_flux_drop_against_shifted_reference() {
+        let reference = catalog(|_, _| Some(1000.0));
+        // Dithered by (7.5, -4) px, with the right half dimmed by a cloud
+        let mut frame = catalog(|x, _| Some(if x > 256.0 { 400.0 } else { 1000.0 }));
+        for star in &mut frame.stars {
+            star.x += 7.5;
+            star.y -= 4.0;
+        }
+        let report = detect_obstruction(
+            &frame,
+            None,
+            Some(&reference),
+            &ObstructionConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(report.matched_stars, 256);
+        assert!(!report.reference_flipped && !report.reference_unaligned);
+        assert_eq!(report.obstructed_tiles, vec![false, true, false, true]);
+        assert_eq!((report.flux_drop_tiles, report.star_density_tiles), (2, 0));
+        let transparency = report.transparency.unwrap();
+        assert!(transparency > 0.4 && transparency < 1.0);
+    }
+
+    #[test]
+    fn test_flux_drop_against_flipped_reference() {
+        let reference = catalog(|_, _| Some(1000.0));
+        // After a meridian flip the field is rotated by 180°; a cloud dims
+        // what is now the left half of the frame
+        let mut frame = catalog(|x, _| Some(if x < 256.0 { 1000.0 } else { 400.0 }));
+        for star in &mut frame.stars {
+            star.x = 511.0 - star.x + 3.0;
+            star.y = 511.0 - star.y - 2.0;
+        }
+        let report = detect_obstruction(
+            &frame,
+            None,
+            Some(&reference),
+            &ObstructionConfig::default(),
+        )
+        .unwrap();
+        assert!(report.reference_flipped && !report.reference_unaligned);
+        assert_eq!(report.matched_stars, 256);
+        assert_eq!(report.obstructed_tiles, vec![true, false, true, false]);
+        assert_eq!((report.flux_drop_tiles, report.star_density_tiles), (2, 0));
+    }
+
+    #[test]
+    fn test_unaligned_reference_is_reported() {
+        // A different field: no offset gets enough votes
+        let reference = catalog(|_, _| Some(1000.0));
+        let frame = StarCatalog {
+            stars: vec![star(100.0, 100.0, 900.0), star(400.0, 300.0, 800.0)],
+            ..catalog(|_, _| None)
+        };
+        let report = detect_obstruction(
+            &frame,
+            None,
+            Some(&reference),
+            &ObstructionConfig::default(),
+        )
+        .unwrap();
+        assert!(report.reference_unaligned && !report.reference_flipped);
+        assert_eq!((report.matched_stars, report.transparency), (0, None));
+        assert_eq!(report.flux_drop_tiles, 0);
+    }
+
+    /// A `size` x `size` sky sloping along x with ±8 ADU noise and
+    /// `cloud` ADU added over `(x0, y0, x1, y1)`
+    fn cloudy_sky(
+        size: usize,
+        cloud: f32,
+        (x0, y0, x1, y1): (usize, usize, usize, usize),
+    ) -> Vec<f32> {
+        let mut seed = 11u32;
+        (0..size * size)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let (x, y) = (i % size, i / size);
+                let lit = (x0..x1).contains(&x) && (y0..y1).contains(&y);
+                200.0 + 0.05 * x as f32 + if lit { cloud } else { 0.0 } + ((seed >> 16) % 17) as f32
+                    - 8.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_background_excess() {
+        // A cloud over one 256 px tile of a 1024 px frame, lit 60 ADU above
+        // the sky
+        let data = cloudy_sky(1024, 60.0, (256, 512, 512, 768));
+        let model =
+            BackgroundModel::estimate(&data, 1024, 1024, &BackgroundConfig::default(), None)
+                .unwrap();
+        // The fit rejects the cloud and the interpolated map fills it in, so
+        // only the mesh still shows it
+        assert!(model.mesh_outliers.iter().filter(|&&o| o).count() >= 9);
+        assert!((model.background[640 * 1024 + 384] - 219.2).abs() < 5.0);
+
+        let frame = StarCatalog {
+            width: 1024,
+            height: 1024,
+            ..catalog(|_, _| None)
+        };
+        let report =
+            detect_obstruction(&frame, Some(&model), None, &ObstructionConfig::default()).unwrap();
+        let mut expected = vec![false; 16];
+        expected[2 * 4 + 1] = true;
+        assert_eq!(report.obstructed_tiles, expected);
+        assert_eq!(report.background_tiles, 1);
+
+        // The excess passes a higher threshold
+        let config = ObstructionConfig {
+            background_sigma: 20.0,
+            ..ObstructionConfig::default()
+        };
+        let report = detect_obstruction(&frame, Some(&model), None, &config).unwrap();
+        assert_eq!(report.background_tiles, 0);
+
+        let mismatched = StarCatalog {
+            width: 256,
+            ..frame
+        };
+        assert!(detect_obstruction(
+            &mismatched,
+            Some(&model),
+            None,
+            &ObstructionConfig::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_large_cloud_is_taken_for_gradient() {
+        // A cloud over a quarter of the frame bends the fit into a gradient
+        // rather than standing out from it, and shows up as lumpiness
+        let data = cloudy_sky(1024, 60.0, (0, 0, 512, 512));
+        let model =
+            BackgroundModel::estimate(&data, 1024, 1024, &BackgroundConfig::default(), None)
+                .unwrap();
+        let frame = StarCatalog {
+            width: 1024,
+            height: 1024,
+            ..catalog(|_, _| None)
+        };
+        let report =
+            detect_obstruction(&frame, Some(&model), None, &ObstructionConfig::default()).unwrap();
+        let gradient = model.gradient.unwrap();
+        assert_eq!(gradient.outlier_boxes, 0);
+        assert!(gradient.gradient > 30.0);
+        assert!(gradient.lumpiness > 3.0 * model.global_rms);
+        // The corner tile, deepest in the cloud, sits on the bent fit
+        assert!(!report.obstructed_tiles[0]);
+    }
+}
@@ -0,0 +1,124 @@
+//! 8-bit grayscale PNG previews of maps and masks
+
+use anyhow::{bail, Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Percentiles mapped to black and white when stretching a map
+const STRETCH_PERCENTILES: (f32, f32) = (0.005, 0.995);
+
+/// Linear stretch of `values` to 0..=255 between the 0.5 and 99.5 percentiles
+pub(crate) fn stretch(values: &[f32]) -> Vec<u8> {
+    let mut sorted: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return vec![0; values.len()];
+    }
+    sorted.sort_by(f32::total_cmp);
+    let at = |q: f32| sorted[((sorted.len() - 1) as f32 * q).round() as usize];
+    let (lo, hi) = (at(STRETCH_PERCENTILES.0), at(STRETCH_PERCENTILES.1));
+    let span = if hi > lo { hi - lo } else { 1.0 };
+    values
+        .iter()
+        .map(|&v| {
+            if v.is_finite() {
+                ((v - lo) / span * 255.0).round().clamp(0.0, 255.0) as u8
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+/// Write row-major 8-bit grayscale pixels as a PNG
+pub(crate) fn write_gray_png(
+    path: &Path,
+    width: usize,
+    height: usize,
+    pixels: &[u8],
+) -> Result<()> {
+    if pixels.len() != width * height {
+        bail!(
+            "Preview of {width}x{height} needs {} pixels, got {}",
+            width * height,
+            pixels.len()
+        );
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8-bit grayscale, deflate, no filtering, no interlace
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    // Every scanline starts with filter type 0 (none)
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(width.max(1)) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    let compressed = encoder.finish()?;
+
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_chunk(&mut out, b"IHDR", &header)?;
+    write_chunk(&mut out, b"IDAT", &compressed)?;
+    write_chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc.sum().to_be_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_stretch_and_png() -> Result<()> {
+        let values: Vec<f32> = (0..6).map(|v| v as f32).collect();
+        let pixels = stretch(&values);
+        assert_eq!(pixels.first(), Some(&0));
+        assert_eq!(pixels.last(), Some(&255));
+        assert_eq!(stretch(&[f32::NAN, 1.0]), vec![0, 0]);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!(
+            "astro-metrics-preview-{}-{timestamp}.png",
+            std::process::id()
+        ));
+        write_gray_png(&path, 3, 2, &pixels)?;
+        let bytes = std::fs::read(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+        let idat_len = u32::from_be_bytes(bytes[33..37].try_into()?) as usize;
+        assert_eq!(&bytes[37..41], b"IDAT");
+        let mut rows = Vec::new();
+        ZlibDecoder::new(&bytes[41..41 + idat_len]).read_to_end(&mut rows)?;
+        assert_eq!(rows, [&[0][..], &pixels[..3], &[0], &pixels[3..]].concat());
+        assert!(bytes.ends_with(&[0xae, 0x42, 0x60, 0x82]));
+        Ok(())
+    }
+}
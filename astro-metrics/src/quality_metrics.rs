@@ -1,7 +1,8 @@
 //! Quality metrics calculation for astronomical images
 
 use crate::types::{
-    BackgroundMetrics, FrameQualityMetrics, QualityScores, QualityWeights, StarStats,
+    BackgroundMetrics, FrameQualityMetrics, QualityScores, QualityWeights, StarCatalog, StarStats,
+    Trail,
 };
 use std::path::Path;
 
@@ -120,6 +121,8 @@ pub fn create_frame_metrics(
         star_stats,
         background,
         scores,
+        trails: Vec::new(),
+        hot_pixels: None,
     }
 }
 
@@ -187,6 +190,38 @@ pub fn create_frame_metrics_with_weights(
         star_stats,
         background,
         scores,
+        trails: Vec::new(),
+        hot_pixels: None,
+    }
+}
+
+/// Create frame quality metrics for a catalog crossed by `trails`
+///
+/// Stars on a trail are trail fragments; they are left out of the statistics
+/// so that the frame is flagged with its trails rather than scored as
+/// elongated or out of focus.
+pub fn create_frame_metrics_with_trails(
+    path: &Path,
+    catalog: &StarCatalog,
+    trails: Vec<Trail>,
+) -> FrameQualityMetrics {
+    let star_stats = catalog.without_trails(&trails).stats(None);
+    FrameQualityMetrics {
+        trails,
+        ..create_frame_metrics(path, star_stats, catalog.background.clone())
+    }
+}
+
+impl FrameQualityMetrics {
+    /// True when a trail crosses the frame
+    pub fn has_trails(&self) -> bool {
+        !self.trails.is_empty()
+    }
+
+    /// Record the number of hot pixels found in the frame
+    pub fn with_hot_pixels(mut self, count: usize) -> Self {
+        self.hot_pixels = Some(count);
+        self
     }
 }
 
@@ -219,6 +254,7 @@ mod tests {
             min: 90.0,
             max: 110.0,
             uniformity: 0.9,
+            gradient: None,
         };
 
         // Calculate scores
@@ -0,0 +1,747 @@
+//! Safe wrapper around the SEP C library
+//!
+//! `SepImage` borrows the pixel, mask and noise buffers for as long as SEP may
+//! read them, and `Background` and `Catalog` free their SEP allocations on
+//! drop, so an early return can no longer leak them. All `unsafe` calls into
+//! `sep-sys` live in this module.
+
+use crate::detection_inputs::NoiseMap;
+use crate::kernel::ConvolutionKernel;
+use crate::mask::PixelMask;
+use crate::types::{BackgroundConfig, FilterMode};
+use anyhow::{anyhow, bail, Result};
+use sep_sys as ffi;
+use std::ffi::{c_int, c_short, c_void, CStr};
+use std::ptr::NonNull;
+
+/// Turn a non-zero SEP status into an error with SEP's message
+fn check(status: c_int, context: &str) -> Result<()> {
+    if status == 0 {
+        return Ok(());
+    }
+    let mut errbuf = [0 as std::ffi::c_char; 512];
+    // SAFETY: SEP writes a NUL-terminated message of at most 61 bytes
+    let message = unsafe {
+        ffi::sep_get_errmsg(status, errbuf.as_mut_ptr());
+        CStr::from_ptr(errbuf.as_ptr())
+            .to_string_lossy()
+            .into_owned()
+    };
+    Err(anyhow!("{context}: {message}"))
+}
+
+/// A single-precision image with optional mask and noise, borrowed for SEP
+#[derive(Debug, Clone, Copy)]
+pub struct SepImage<'a> {
+    data: &'a [f32],
+    width: usize,
+    height: usize,
+    mask: Option<&'a PixelMask>,
+    noise: Option<NoiseMap<'a>>,
+    noise_value: f64,
+    gain: f64,
+}
+
+impl<'a> SepImage<'a> {
+    /// Wrap row-major pixels of the given size
+    pub fn new(data: &'a [f32], width: usize, height: usize) -> Result<Self> {
+        if data.len() != width * height {
+            bail!(
+                "Image of {width}x{height} needs {} pixels, got {}",
+                width * height,
+                data.len()
+            );
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+            mask: None,
+            noise: None,
+            noise_value: 0.0,
+            gain: 0.0,
+        })
+    }
+
+    /// Leave the masked pixels out
+    pub fn with_mask(mut self, mask: &'a PixelMask) -> Result<Self> {
+        if (mask.width(), mask.height()) != (self.width, self.height) {
+            bail!(
+                "Mask is {}x{} but the image is {}x{}",
+                mask.width(),
+                mask.height(),
+                self.width,
+                self.height
+            );
+        }
+        self.mask = Some(mask);
+        Ok(self)
+    }
+
+    /// Per-pixel noise for thresholds and flux errors
+    pub fn with_noise(mut self, noise: NoiseMap<'a>) -> Result<Self> {
+        if noise.values().len() != self.data.len() {
+            bail!(
+                "Noise map has {} values but the image has {} pixels",
+                noise.values().len(),
+                self.data.len()
+            );
+        }
+        self.noise = Some(noise);
+        Ok(self)
+    }
+
+    /// Constant noise standard deviation, used when there is no noise map
+    pub fn with_noise_value(mut self, std_dev: f64) -> Self {
+        self.noise_value = std_dev;
+        self
+    }
+
+    /// Gain in e-/ADU for Poisson errors; 0 leaves them out
+    pub fn with_gain(mut self, gain: f64) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Image width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Image height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// True when a noise map is attached
+    pub fn has_noise_map(&self) -> bool {
+        self.noise.is_some()
+    }
+
+    /// The C struct pointing at the borrowed buffers; valid while `self` is
+    fn raw(&self) -> ffi::sep_image {
+        let (noise, ndtype, noise_type) = match self.noise {
+            Some(NoiseMap::StdDev(values)) => (
+                values.as_ptr() as *const c_void,
+                ffi::SEP_TFLOAT as c_int,
+                ffi::SEP_NOISE_STDDEV,
+            ),
+            Some(NoiseMap::Variance(values)) => (
+                values.as_ptr() as *const c_void,
+                ffi::SEP_TFLOAT as c_int,
+                ffi::SEP_NOISE_VAR,
+            ),
+            None if self.noise_value > 0.0 => (std::ptr::null(), 0, ffi::SEP_NOISE_STDDEV),
+            None => (std::ptr::null(), 0, ffi::SEP_NOISE_NONE),
+        };
+        let (mask, mdtype) = match self.mask {
+            Some(mask) => (
+                mask.as_bytes().as_ptr() as *const c_void,
+                ffi::SEP_TBYTE as c_int,
+            ),
+            None => (std::ptr::null(), 0),
+        };
+        ffi::sep_image {
+            data: self.data.as_ptr() as *const c_void,
+            noise,
+            mask,
+            segmap: std::ptr::null(),
+            dtype: ffi::SEP_TFLOAT as c_int,
+            ndtype,
+            mdtype,
+            sdtype: 0,
+            segids: std::ptr::null_mut(),
+            idcounts: std::ptr::null_mut(),
+            numids: 0,
+            w: self.width as i64,
+            h: self.height as i64,
+            noiseval: self.noise_value,
+            noise_type,
+            gain: self.gain,
+            maskthresh: 0.0,
+        }
+    }
+}
+
+/// A SEP background mesh, freed on drop
+#[derive(Debug)]
+pub struct Background {
+    raw: NonNull<ffi::sep_bkg>,
+}
+
+impl Background {
+    /// Estimate the background of `image` on a mesh
+    pub fn new(image: &SepImage, config: &BackgroundConfig) -> Result<Self> {
+        let raw_image = image.raw();
+        let mut bkg: *mut ffi::sep_bkg = std::ptr::null_mut();
+        // SAFETY: `raw_image` points into buffers borrowed by `image`
+        let status = unsafe {
+            ffi::sep_background(
+                &raw_image,
+                config.box_width as i64,
+                config.box_height as i64,
+                config.filter_width as i64,
+                config.filter_height as i64,
+                config.filter_threshold,
+                &mut bkg,
+            )
+        };
+        check(status, "SEP background estimation error")?;
+        let raw = NonNull::new(bkg).ok_or_else(|| anyhow!("SEP returned no background"))?;
+        Ok(Self { raw })
+    }
+
+    fn bkg(&self) -> &ffi::sep_bkg {
+        // SAFETY: `raw` is a live allocation owned by `self`
+        unsafe { self.raw.as_ref() }
+    }
+
+    /// Global background level
+    pub fn global(&self) -> f32 {
+        self.bkg().global
+    }
+
+    /// Global background RMS
+    pub fn global_rms(&self) -> f32 {
+        self.bkg().globalrms
+    }
+
+    /// Number of mesh boxes along x and y
+    pub fn mesh_size(&self) -> (usize, usize) {
+        (self.bkg().nx as usize, self.bkg().ny as usize)
+    }
+
+    /// Mesh box size in pixels along x and y
+    pub fn box_size(&self) -> (usize, usize) {
+        (self.bkg().bw as usize, self.bkg().bh as usize)
+    }
+
+    /// Filtered background level per mesh box, row-major
+    pub fn mesh(&self) -> &[f32] {
+        let bkg = self.bkg();
+        // SAFETY: SEP allocates `nx * ny` values that live as long as the mesh
+        unsafe { std::slice::from_raw_parts(bkg.back, (bkg.nx * bkg.ny) as usize) }
+    }
+
+    /// Filtered background RMS per mesh box, row-major
+    pub fn mesh_rms(&self) -> &[f32] {
+        let bkg = self.bkg();
+        // SAFETY: as for `mesh`
+        unsafe { std::slice::from_raw_parts(bkg.sigma, (bkg.nx * bkg.ny) as usize) }
+    }
+
+    /// Image size the mesh was estimated on
+    pub fn image_size(&self) -> (usize, usize) {
+        (self.bkg().w as usize, self.bkg().h as usize)
+    }
+
+    /// Background interpolated to every pixel, row-major
+    pub fn array(&self) -> Result<Vec<f32>> {
+        let (w, h) = self.image_size();
+        let mut values = vec![0.0f32; w * h];
+        // SAFETY: `values` holds the `w * h` floats SEP writes
+        let status = unsafe {
+            ffi::sep_bkg_array(
+                self.raw.as_ptr(),
+                values.as_mut_ptr() as *mut c_void,
+                ffi::SEP_TFLOAT as c_int,
+            )
+        };
+        check(status, "SEP background map error")?;
+        Ok(values)
+    }
+
+    /// Background RMS interpolated to every pixel, row-major
+    pub fn rms_array(&self) -> Result<Vec<f32>> {
+        let (w, h) = self.image_size();
+        let mut values = vec![0.0f32; w * h];
+        // SAFETY: `values` holds the `w * h` floats SEP writes
+        let status = unsafe {
+            ffi::sep_bkg_rmsarray(
+                self.raw.as_ptr(),
+                values.as_mut_ptr() as *mut c_void,
+                ffi::SEP_TFLOAT as c_int,
+            )
+        };
+        check(status, "SEP background RMS map error")?;
+        Ok(values)
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        // SAFETY: `raw` came from `sep_background` and is freed once
+        unsafe { ffi::sep_bkg_free(self.raw.as_ptr()) }
+    }
+}
+
+/// How the detection threshold is given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    /// Absolute pixel value
+    Absolute(f32),
+    /// Multiple of the image noise
+    Relative(f32),
+}
+
+/// Settings for one `sep_extract` call
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractOptions<'a> {
+    /// Detection threshold
+    pub threshold: Threshold,
+    /// Minimum number of pixels per object
+    pub min_area: usize,
+    /// Convolution kernel, `None` for no filtering
+    pub kernel: Option<&'a ConvolutionKernel>,
+    /// How the kernel is applied
+    pub filter_mode: FilterMode,
+    /// Number of deblending thresholds
+    pub deblend_nthresh: usize,
+    /// Minimum contrast ratio for deblending
+    pub deblend_contrast: f64,
+    /// Cleaning parameter, `None` to skip cleaning
+    pub clean: Option<f64>,
+}
+
+/// One object from a SEP catalog
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CatalogObject {
+    /// X centroid (0-based)
+    pub x: f64,
+    /// Y centroid (0-based)
+    pub y: f64,
+    /// Semi-major axis
+    pub a: f32,
+    /// Semi-minor axis
+    pub b: f32,
+    /// Position angle in radians
+    pub theta: f32,
+    /// Ellipse coefficient of x²
+    pub cxx: f32,
+    /// Ellipse coefficient of y²
+    pub cyy: f32,
+    /// Ellipse coefficient of xy
+    pub cxy: f32,
+    /// Isophotal flux
+    pub flux: f32,
+    /// Peak pixel value
+    pub peak: f32,
+    /// Number of pixels above the threshold
+    pub npix: usize,
+    /// Bounding box, inclusive
+    pub xmin: usize,
+    pub xmax: usize,
+    pub ymin: usize,
+    pub ymax: usize,
+    /// Extraction flags
+    pub flag: i16,
+}
+
+/// Objects extracted by SEP, freed on drop
+#[derive(Debug)]
+pub struct Catalog {
+    raw: NonNull<ffi::sep_catalog>,
+}
+
+impl Catalog {
+    /// Extract objects from `image`
+    pub fn extract(image: &SepImage, options: &ExtractOptions) -> Result<Self> {
+        let raw_image = image.raw();
+        let (threshold, thresh_type) = match options.threshold {
+            Threshold::Absolute(value) => (value, ffi::SEP_THRESH_ABS),
+            Threshold::Relative(value) => (value, ffi::SEP_THRESH_REL),
+        };
+        let (conv, convw, convh) = match options.kernel {
+            Some(kernel) => (
+                kernel.values.as_ptr(),
+                kernel.width as i64,
+                kernel.height as i64,
+            ),
+            None => (std::ptr::null(), 0, 0),
+        };
+        let filter_type = match options.filter_mode {
+            FilterMode::Conv => ffi::SEP_FILTER_CONV,
+            FilterMode::Matched => ffi::SEP_FILTER_MATCHED,
+        };
+        let mut catalog: *mut ffi::sep_catalog = std::ptr::null_mut();
+        // SAFETY: `raw_image` and `conv` point into buffers borrowed for this call
+        let status = unsafe {
+            ffi::sep_extract(
+                &raw_image,
+                threshold,
+                thresh_type as c_int,
+                options.min_area as c_int,
+                conv,
+                convw,
+                convh,
+                filter_type as c_int,
+                options.deblend_nthresh as c_int,
+                options.deblend_contrast,
+                c_int::from(options.clean.is_some()),
+                options.clean.unwrap_or(0.0),
+                &mut catalog,
+            )
+        };
+        check(status, "SEP error")?;
+        let raw = NonNull::new(catalog).ok_or_else(|| anyhow!("SEP returned no catalog"))?;
+        Ok(Self { raw })
+    }
+
+    fn catalog(&self) -> &ffi::sep_catalog {
+        // SAFETY: `raw` is a live allocation owned by `self`
+        unsafe { self.raw.as_ref() }
+    }
+
+    /// Number of objects
+    pub fn len(&self) -> usize {
+        self.catalog().nobj.max(0) as usize
+    }
+
+    /// True when nothing was extracted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The object at `index`
+    pub fn get(&self, index: usize) -> Option<CatalogObject> {
+        if index >= self.len() {
+            return None;
+        }
+        let c = self.catalog();
+        // SAFETY: every per-object array holds `nobj` values
+        unsafe {
+            Some(CatalogObject {
+                x: *c.x.add(index),
+                y: *c.y.add(index),
+                a: *c.a.add(index),
+                b: *c.b.add(index),
+                theta: *c.theta.add(index),
+                cxx: *c.cxx.add(index),
+                cyy: *c.cyy.add(index),
+                cxy: *c.cxy.add(index),
+                flux: *c.flux.add(index),
+                peak: *c.peak.add(index),
+                npix: (*c.npix.add(index)).max(0) as usize,
+                xmin: (*c.xmin.add(index)).max(0) as usize,
+                xmax: (*c.xmax.add(index)).max(0) as usize,
+                ymin: (*c.ymin.add(index)).max(0) as usize,
+                ymax: (*c.ymax.add(index)).max(0) as usize,
+                flag: *c.flag.add(index),
+            })
+        }
+    }
+
+    /// All objects in extraction order
+    pub fn iter(&self) -> impl Iterator<Item = CatalogObject> + '_ {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+}
+
+impl Drop for Catalog {
+    fn drop(&mut self) {
+        // SAFETY: `raw` came from `sep_extract` and is freed once
+        unsafe { ffi::sep_catalog_free(self.raw.as_ptr()) }
+    }
+}
+
+/// Raise SEP's extraction pixel stack to at least `size` pixels
+pub fn ensure_pixel_stack(size: usize) {
+    // SAFETY: plain setters of SEP's global settings
+    unsafe {
+        if size > ffi::sep_get_extract_pixstack() {
+            ffi::sep_set_extract_pixstack(size);
+        }
+    }
+}
+
+/// Set the largest number of sub-objects SEP keeps when deblending
+pub fn set_sub_object_limit(limit: usize) {
+    // SAFETY: plain setter of SEP's global settings
+    unsafe { ffi::sep_set_sub_object_limit(limit.min(c_int::MAX as usize) as c_int) }
+}
+
+/// Sum over an aperture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApertureSum {
+    /// Sum of pixel values
+    pub sum: f64,
+    /// Error on the sum
+    pub sum_err: f64,
+    /// Aperture area in pixels
+    pub area: f64,
+    /// Aperture flags
+    pub flag: i16,
+}
+
+/// Ellipse coefficients `cxx`, `cyy`, `cxy` from semi-axes and position angle
+pub fn ellipse_coeffs(a: f64, b: f64, theta: f64) -> (f64, f64, f64) {
+    let (mut cxx, mut cyy, mut cxy) = (0.0, 0.0, 0.0);
+    // SAFETY: pure computation into local outputs
+    unsafe { ffi::sep_ellipse_coeffs(a, b, theta, &mut cxx, &mut cyy, &mut cxy) };
+    (cxx, cyy, cxy)
+}
+
+/// Kron radius of the ellipse `cxx x² + cyy y² + cxy xy = r²`, in units of
+/// the ellipse, with SEP's flag
+pub fn kron_radius(
+    image: &SepImage,
+    x: f64,
+    y: f64,
+    coeffs: (f64, f64, f64),
+    r: f64,
+) -> Result<(f64, i16)> {
+    let raw_image = image.raw();
+    let (cxx, cyy, cxy) = coeffs;
+    let mut radius = 0.0f64;
+    let mut flag: c_short = 0;
+    // SAFETY: `raw_image` borrows `image`; outputs are locals of the right type
+    let status = unsafe {
+        ffi::sep_kron_radius(
+            &raw_image,
+            x,
+            y,
+            cxx,
+            cyy,
+            cxy,
+            r,
+            0,
+            &mut radius,
+            &mut flag,
+        )
+    };
+    check(status, "SEP Kron radius error")?;
+    Ok((radius, flag))
+}
+
+/// Sum inside the ellipse with semi-axes `a * r` and `b * r`
+#[allow(clippy::too_many_arguments)]
+pub fn sum_ellipse(
+    image: &SepImage,
+    x: f64,
+    y: f64,
+    a: f64,
+    b: f64,
+    theta: f64,
+    r: f64,
+    subpix: usize,
+) -> Result<ApertureSum> {
+    let raw_image = image.raw();
+    let (mut sum, mut sum_err, mut area) = (0.0, 0.0, 0.0);
+    let mut flag: c_short = 0;
+    // SAFETY: `raw_image` borrows `image`; outputs are locals of the right type
+    let status = unsafe {
+        ffi::sep_sum_ellipse(
+            &raw_image,
+            x,
+            y,
+            a,
+            b,
+            theta,
+            r,
+            0,
+            subpix as c_int,
+            0,
+            &mut sum,
+            &mut sum_err,
+            &mut area,
+            &mut flag,
+        )
+    };
+    check(status, "SEP aperture sum error")?;
+    Ok(ApertureSum {
+        sum,
+        sum_err,
+        area,
+        flag,
+    })
+}
+
+/// Radii within `rmax` enclosing each of `fractions` of `total_flux`, or of
+/// the flux inside `rmax` when `total_flux` is `None`
+pub fn flux_radius(
+    image: &SepImage,
+    x: f64,
+    y: f64,
+    rmax: f64,
+    total_flux: Option<f64>,
+    fractions: &[f64],
+) -> Result<(Vec<f64>, i16)> {
+    let raw_image = image.raw();
+    let mut radii = vec![0.0; fractions.len()];
+    let mut flag: c_short = 0;
+    let total = total_flux
+        .as_ref()
+        .map_or(std::ptr::null(), |t| t as *const f64);
+    // SAFETY: `radii` holds one output per fraction and `total` is null or a local
+    let status = unsafe {
+        ffi::sep_flux_radius(
+            &raw_image,
+            x,
+            y,
+            rmax,
+            0,
+            5,
+            0,
+            total,
+            fractions.as_ptr(),
+            fractions.len() as i64,
+            radii.as_mut_ptr(),
+            &mut flag,
+        )
+    };
+    check(status, "SEP flux radius error")?;
+    Ok((radii, flag))
+}
+
+/// Windowed centroid with a Gaussian window of `sigma` pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowedPosition {
+    /// Windowed X centroid
+    pub x: f64,
+    /// Windowed Y centroid
+    pub y: f64,
+    /// Iterations used
+    pub iterations: usize,
+    /// Aperture flags
+    pub flag: i16,
+}
+
+/// Refine a centroid with SExtractor's windowed position
+pub fn winpos(image: &SepImage, x: f64, y: f64, sigma: f64) -> Result<WindowedPosition> {
+    let raw_image = image.raw();
+    let (mut xout, mut yout) = (0.0, 0.0);
+    let mut niter: c_int = 0;
+    let mut flag: c_short = 0;
+    // SAFETY: `raw_image` borrows `image`; outputs are locals of the right type
+    let status = unsafe {
+        ffi::sep_windowed(
+            &raw_image, x, y, sigma, 11, 0, &mut xout, &mut yout, &mut niter, &mut flag,
+        )
+    };
+    check(status, "SEP windowed position error")?;
+    Ok(WindowedPosition {
+        x: xout,
+        y: yout,
+        iterations: niter.max(0) as usize,
+        flag,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_checks_buffer_sizes() {
+        let data = vec![0.0f32; 12];
+        assert!(SepImage::new(&data, 4, 4).is_err());
+        let image = SepImage::new(&data, 4, 3).unwrap();
+        assert!(image.with_mask(&PixelMask::new(3, 4)).is_err());
+        assert!(image.with_noise(NoiseMap::StdDev(&data[..6])).is_err());
+
+        let mask = PixelMask::new(4, 3);
+        let image = image
+            .with_mask(&mask)
+            .unwrap()
+            .with_noise(NoiseMap::Variance(&data))
+            .unwrap()
+            .with_gain(0.25);
+        let raw = image.raw();
+        assert_eq!((raw.w, raw.h), (4, 3));
+        assert_eq!(raw.mdtype, ffi::SEP_TBYTE as c_int);
+        assert_eq!(raw.noise_type, ffi::SEP_NOISE_VAR);
+        assert_eq!(raw.gain, 0.25);
+        assert!(image.has_noise_map());
+    }
+
+    #[test]
+    fn test_gaussian_star_measurements() {
+        // One Gaussian star, sigma 2 px, on a noisy sky of 100 ADU
+        const SIZE: usize = 64;
+        const SIGMA: f64 = 2.0;
+        const PEAK: f64 = 2000.0;
+        let (cx, cy) = (32.3, 31.6);
+        let mut seed = 777u32;
+        let data: Vec<f32> = (0..SIZE * SIZE)
+            .map(|i| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let (x, y) = ((i % SIZE) as f64, (i / SIZE) as f64);
+                let r2 = (x - cx).powi(2) + (y - cy).powi(2);
+                let star = PEAK * (-r2 / (2.0 * SIGMA * SIGMA)).exp();
+                100.0 + ((seed >> 16) % 17) as f32 - 8.0 + star as f32
+            })
+            .collect();
+
+        let image = SepImage::new(&data, SIZE, SIZE).unwrap();
+        let bkg = Background::new(&image, &BackgroundConfig::default()).unwrap();
+        assert!((bkg.global() - 100.0).abs() < 1.0);
+        let subtracted: Vec<f32> = data.iter().map(|v| v - bkg.global()).collect();
+        let image = SepImage::new(&subtracted, SIZE, SIZE)
+            .unwrap()
+            .with_noise_value(f64::from(bkg.global_rms()));
+        let thresh = 3.0 * bkg.global_rms();
+        let catalog = Catalog::extract(
+            &image,
+            &ExtractOptions {
+                threshold: Threshold::Absolute(thresh),
+                min_area: 5,
+                kernel: None,
+                filter_mode: FilterMode::Conv,
+                deblend_nthresh: 32,
+                deblend_contrast: 0.005,
+                clean: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(catalog.len(), 1);
+        let obj = catalog.get(0).unwrap();
+        assert!((obj.x - cx).abs() < 0.05 && (obj.y - cy).abs() < 0.05);
+
+        // npix counts the pixels of the star above the threshold
+        let above = (0..SIZE * SIZE)
+            .filter(|&i| {
+                let (x, y) = ((i % SIZE) as f64, (i / SIZE) as f64);
+                (x - cx).hypot(y - cy) < 12.0 && subtracted[i] > thresh
+            })
+            .count();
+        assert!(
+            obj.npix.abs_diff(above) <= 2,
+            "npix {} vs {above}",
+            obj.npix
+        );
+
+        // A Gaussian's Kron radius is sigma * sqrt(pi / 2)
+        let coeffs = (f64::from(obj.cxx), f64::from(obj.cyy), f64::from(obj.cxy));
+        let (kron, flag) = kron_radius(&image, obj.x, obj.y, coeffs, 6.0).unwrap();
+        assert_eq!(flag, 0);
+        let kron_pixels = kron * f64::from(obj.a);
+        let expected = SIGMA * (std::f64::consts::PI / 2.0).sqrt();
+        assert!(
+            (kron_pixels / expected - 1.0).abs() < 0.1,
+            "Kron radius {kron_pixels} px"
+        );
+
+        // FLUX_AUTO in 2.5 Kron radii holds nearly all of 2 pi sigma² peak
+        let (a, b, theta) = (f64::from(obj.a), f64::from(obj.b), f64::from(obj.theta));
+        let auto = sum_ellipse(&image, obj.x, obj.y, a, b, theta, 2.5 * kron, 0).unwrap();
+        let total = 2.0 * std::f64::consts::PI * SIGMA * SIGMA * PEAK;
+        assert!(
+            (auto.sum / total - 1.0).abs() < 0.02,
+            "FLUX_AUTO {}",
+            auto.sum
+        );
+
+        // Half the light falls within sigma * sqrt(2 ln 2)
+        let (radii, _) =
+            flux_radius(&image, obj.x, obj.y, 6.0 * a, Some(auto.sum), &[0.5]).unwrap();
+        let half_light = SIGMA * (2.0 * std::f64::consts::LN_2).sqrt();
+        assert!(
+            (radii[0] - half_light).abs() < 0.1,
+            "half-light radius {}",
+            radii[0]
+        );
+
+        let window = winpos(&image, obj.x, obj.y, SIGMA).unwrap();
+        assert!((window.x - cx).abs() < 0.05 && (window.y - cy).abs() < 0.05);
+    }
+}
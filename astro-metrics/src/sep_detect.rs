@@ -1,9 +1,14 @@
 //! Star detection using the SEP (Source Extractor as a Library) C library
 
-use crate::types::{BackgroundMetrics, StarMetrics, StarStats};
-use anyhow::{anyhow, Result};
-use sep_sys as sep;
-use std::ffi::{c_int, CStr};
+use crate::background_model::BackgroundModel;
+use crate::detection_inputs::{DetectionInputs, NoiseMap};
+use crate::kernel::{self, ConvolutionKernel};
+use crate::mask::PixelMask;
+use crate::sep::{self, Catalog, ExtractOptions, SepImage, Threshold};
+use crate::types::{
+    BackgroundMetrics, DetectionConfig, KernelConfig, StarCatalog, StarMetrics, StarStats,
+};
+use anyhow::Result;
 
 /// Detect stars using SEP's built-in background estimation and object detection
 pub fn detect_stars_with_sep_background(
@@ -12,99 +17,102 @@ pub fn detect_stars_with_sep_background(
     height: usize,
     max_stars: Option<usize>,
 ) -> Result<(StarStats, BackgroundMetrics)> {
-    unsafe {
-        // Create a sep_image struct for background estimation
-        let mut image_data = data.to_vec();
-        let sep_img = sep::sep_image {
-            data: image_data.as_mut_ptr() as *const std::ffi::c_void,
-            noise: std::ptr::null(),
-            mask: std::ptr::null(),
-            segmap: std::ptr::null(),
-            dtype: sep::SEP_TFLOAT as c_int,
-            ndtype: 0,
-            mdtype: 0,
-            sdtype: 0,
-            segids: std::ptr::null_mut(),
-            idcounts: std::ptr::null_mut(),
-            numids: 0,
-            w: width as i64,
-            h: height as i64,
-            noiseval: 0.0,
-            noise_type: 0,
-            gain: 1.0,
-            maskthresh: 0.0,
-        };
-
-        // Set background estimation parameters
-        let bw = 64; // box width
-        let bh = 64; // box height
-        let fw = 3; // filter width
-        let fh = 3; // filter height
-        let fthresh = 0.0; // filter threshold
-
-        // Create a mutable pointer for the background struct
-        let mut bkg: *mut sep::sep_bkg = std::ptr::null_mut();
-
-        // Estimate background
-        let status = sep::sep_background(
-            &sep_img as *const sep::sep_image,
-            bw,
-            bh,
-            fw,
-            fh,
-            fthresh,
-            &mut bkg,
-        );
-
-        if status != 0 {
-            let mut errbuf = [0i8; 512];
-            sep::sep_get_errmsg(status, errbuf.as_mut_ptr());
-            let error_msg = CStr::from_ptr(errbuf.as_ptr()).to_string_lossy();
-            return Err(anyhow!("SEP background estimation error: {}", error_msg));
-        }
-
-        // Get global background and RMS
-        let background = sep::sep_bkg_global(bkg);
-        let rms = sep::sep_bkg_globalrms(bkg);
-
-        // Get min and max background values
-        let mut min_bg = f32::MAX;
-        let mut max_bg = f32::MIN;
-
-        // Calculate background uniformity
-        let nx = (*bkg).nx;
-        let ny = (*bkg).ny;
-        let back = (*bkg).back;
+    detect_stars_with_config(data, width, height, &DetectionConfig::default(), max_stars)
+}
 
-        for i in 0..(nx * ny) {
-            let val = *back.offset(i as isize);
-            min_bg = min_bg.min(val);
-            max_bg = max_bg.max(val);
-        }
+/// Detect stars with SEP's background estimation and custom detection settings
+pub fn detect_stars_with_config(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    config: &DetectionConfig,
+    max_stars: Option<usize>,
+) -> Result<(StarStats, BackgroundMetrics)> {
+    let catalog = detect_catalog_with_config(data, width, height, config)?;
+    Ok((catalog.stats(max_stars), catalog.background))
+}
 
-        // Calculate uniformity as 1 - (max-min)/max
-        // Higher values (closer to 1) mean more uniform background
-        let uniformity = if max_bg > 0.0 {
-            1.0 - (max_bg - min_bg) / max_bg
-        } else {
-            1.0
-        };
+/// Detect stars using SEP's background estimation and return every star
+pub fn detect_catalog_with_sep_background(
+    data: &[f32],
+    width: usize,
+    height: usize,
+) -> Result<StarCatalog> {
+    detect_catalog_with_config(data, width, height, &DetectionConfig::default())
+}
 
-        // Create background metrics with all values
-        let bg_metrics =
-            BackgroundMetrics::with_all_metrics(background, rms, min_bg, max_bg, uniformity);
+/// Detect stars with custom detection settings and return every star
+pub fn detect_catalog_with_config(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    config: &DetectionConfig,
+) -> Result<StarCatalog> {
+    detect_catalog_with_inputs(data, width, height, config, &DetectionInputs::default())
+}
 
-        // Free the background memory
-        sep::sep_bkg_free(bkg);
+/// Detect stars with a mask, noise map, gain and saturation level
+pub fn detect_stars_with_inputs(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    config: &DetectionConfig,
+    inputs: &DetectionInputs,
+    max_stars: Option<usize>,
+) -> Result<(StarStats, BackgroundMetrics)> {
+    let catalog = detect_catalog_with_inputs(data, width, height, config, inputs)?;
+    Ok((catalog.stats(max_stars), catalog.background))
+}
 
-        // Detect stars using the estimated background and RMS
-        let star_stats = detect_stars_sep(data, width, height, background, rms, max_stars)?;
+/// Detect stars with a mask, noise map, gain and saturation level and return every star
+pub fn detect_catalog_with_inputs(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    config: &DetectionConfig,
+    inputs: &DetectionInputs,
+) -> Result<StarCatalog> {
+    let inputs = PreparedInputs::new(data, width, height, inputs)?;
+
+    // Keep masked and saturated pixels out of the background
+    let model = BackgroundModel::estimate(
+        data,
+        width,
+        height,
+        &config.background,
+        inputs.mask.as_ref(),
+    )?;
+    extract_catalog(
+        &model.subtracted(data)?,
+        width,
+        height,
+        model.metrics(),
+        config,
+        &inputs,
+    )
+}
 
-        Ok((star_stats, bg_metrics))
-    }
+/// Detect stars after subtracting an already estimated background model
+pub fn detect_catalog_with_model(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    config: &DetectionConfig,
+    inputs: &DetectionInputs,
+    model: &BackgroundModel,
+) -> Result<StarCatalog> {
+    let inputs = PreparedInputs::new(data, width, height, inputs)?;
+    extract_catalog(
+        &model.subtracted(data)?,
+        width,
+        height,
+        model.metrics(),
+        config,
+        &inputs,
+    )
 }
 
-/// Detect stars using the SEP library and return detailed measurements for each star.
+/// Detect stars using the SEP library and return aggregate statistics
 pub fn detect_stars_sep(
     data: &[f32],
     width: usize,
@@ -113,231 +121,250 @@ pub fn detect_stars_sep(
     std_dev: f32,
     max_stars: Option<usize>,
 ) -> Result<StarStats> {
-    // Skip processing if image is too small
-    if width < 3 || height < 3 {
-        return Ok(StarStats {
-            count: 0,
-            median_fwhm: 0.0,
-            median_eccentricity: 0.0,
-            fwhm_std_dev: 0.0,
-            eccentricity_std_dev: 0.0,
-            median_kron_radius: 0.0,
-            median_flux: 0.0,
-            median_snr: 0.0,
-            median_elongation: 0.0,
-            flagged_fraction: 0.0,
-            kron_radius_std_dev: 0.0,
-            flux_std_dev: 0.0,
-            snr_std_dev: 0.0,
-        });
-    }
+    let catalog = detect_catalog_sep(data, width, height, background, std_dev)?;
+    Ok(catalog.stats(max_stars))
+}
+
+/// Detect stars using the SEP library and return detailed measurements for each star
+pub fn detect_catalog_sep(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    background: f32,
+    std_dev: f32,
+) -> Result<StarCatalog> {
+    let subtracted: Vec<f32> = data.iter().map(|v| v - background).collect();
+    extract_catalog(
+        &subtracted,
+        width,
+        height,
+        BackgroundMetrics::new(background, std_dev),
+        &DetectionConfig::default(),
+        &PreparedInputs::default(),
+    )
+}
+
+/// Saturated regions are grown by this many pixels to cover bleeding
+const SATURATION_GROW: usize = 1;
+
+/// Caller inputs checked against the image size, with saturated pixels masked
+#[derive(Default)]
+struct PreparedInputs<'a> {
+    /// Caller mask combined with the saturation mask
+    mask: Option<PixelMask>,
+    /// Saturated pixels, grown by `SATURATION_GROW`
+    saturated: Option<PixelMask>,
+    noise: Option<NoiseMap<'a>>,
+    gain: Option<f64>,
+}
 
-    // Create a copy of the data as f32 (SEP requires contiguous memory)
-    let mut image_data = data.to_vec();
-
-    unsafe {
-        // Create a sep_image struct
-        let sep_img = sep::sep_image {
-            data: image_data.as_mut_ptr() as *const std::ffi::c_void,
-            noise: std::ptr::null(),
-            mask: std::ptr::null(),
-            segmap: std::ptr::null(),
-            dtype: sep::SEP_TFLOAT as c_int,
-            ndtype: 0,
-            mdtype: 0,
-            sdtype: 0,
-            segids: std::ptr::null_mut(),
-            idcounts: std::ptr::null_mut(),
-            numids: 0,
-            w: width as i64,
-            h: height as i64,
-            noiseval: std_dev as f64,
-            noise_type: sep::SEP_NOISE_STDDEV,
-            gain: 1.0,
-            maskthresh: 0.0,
+impl<'a> PreparedInputs<'a> {
+    fn new(
+        data: &[f32],
+        width: usize,
+        height: usize,
+        inputs: &DetectionInputs<'a>,
+    ) -> Result<Self> {
+        let saturated = inputs.saturation_level.map(|level| {
+            let mut saturated = PixelMask::saturated(data, width, height, level);
+            saturated.dilate(SATURATION_GROW);
+            saturated
+        });
+        let mask = match (inputs.mask, &saturated) {
+            (Some(mask), Some(saturated)) => {
+                let mut combined = mask.clone();
+                combined.union(saturated)?;
+                Some(combined)
+            }
+            (Some(mask), None) => Some(mask.clone()),
+            (None, saturated) => saturated.clone(),
         };
 
-        // Set threshold to 3 sigma above background
-        let thresh = background + 3.0 * std_dev;
+        Ok(Self {
+            mask,
+            saturated,
+            noise: inputs.noise,
+            gain: inputs.gain,
+        })
+    }
 
-        // Increase SEP's internal extraction pixel stack for large/crowded images.
-        // Default is 300_000, which can underflow for modern high-resolution frames.
-        let required_pixstack = width.saturating_mul(height).clamp(300_000, 20_000_000);
-        let current_pixstack = sep::sep_get_extract_pixstack();
-        if required_pixstack > current_pixstack {
-            sep::sep_set_extract_pixstack(required_pixstack);
+    /// Wrap `data` with the mask, noise map and gain
+    fn image<'b>(
+        &'b self,
+        data: &'b [f32],
+        width: usize,
+        height: usize,
+        std_dev: f32,
+    ) -> Result<SepImage<'b>> {
+        let mut image = SepImage::new(data, width, height)?
+            .with_noise_value(f64::from(std_dev))
+            .with_gain(self.gain.unwrap_or(1.0));
+        if let Some(mask) = &self.mask {
+            image = image.with_mask(mask)?;
         }
-        sep::sep_set_sub_object_limit(100_000);
-
-        // Create pointers for the catalog
-        let mut catalog: *mut sep::sep_catalog = std::ptr::null_mut();
-
-        // Call SEP to extract objects
-        let status = sep::sep_extract(
-            &sep_img as *const sep::sep_image,
-            thresh,
-            sep::SEP_THRESH_ABS as c_int,
-            5,                // Minimum area in pixels
-            std::ptr::null(), // No convolution filter
-            0,                // No convolution width
-            0,                // No convolution height
-            sep::SEP_FILTER_CONV as c_int,
-            32,    // Deblend thresholds
-            0.005, // Deblend contrast
-            1,     // Clean flag
-            1.0,   // Clean parameter
-            &mut catalog,
-        );
-
-        // Check for errors
-        if status != 0 {
-            let mut errbuf = [0i8; 512];
-            sep::sep_get_errmsg(status, errbuf.as_mut_ptr());
-            let error_msg = CStr::from_ptr(errbuf.as_ptr()).to_string_lossy();
-            return Err(anyhow!("SEP error: {}", error_msg));
+        if let Some(noise) = self.noise {
+            image = image.with_noise(noise)?;
         }
+        Ok(image)
+    }
 
-        // Convert SEP catalog to Vec<StarMetrics>
-        let nobj = (*catalog).nobj as usize;
-        let mut stars = Vec::with_capacity(nobj);
-
-        for i in 0..nobj {
-            // Get pointers to arrays
-            let x = *(*catalog).x.add(i);
-            let y = *(*catalog).y.add(i);
-            let a = *(*catalog).a.add(i);
-            let b = *(*catalog).b.add(i);
-            let theta = *(*catalog).theta.add(i);
-            let flux = *(*catalog).flux.add(i);
-            let peak = *(*catalog).peak.add(i);
-
-            // Extract additional metrics from SEP catalog
-            let npix = (*catalog).npix.add(i) as usize;
-            let flag = *(*catalog).flag.add(i) as u8;
-
-            // Calculate derived metrics
-            let elongation = if a > 0.0 && b > 0.0 { a / b } else { 1.0 };
-
-            // Calculate Kron radius (radius containing 50% of flux)
-            let mut kron_radius = 0.0;
-            let mut krflag: i16 = 0;
-
-            // Call sep_kron_radius to get the Kron radius
-            // Note: SEP expects f64 for coordinates and dimensions
-            let kr_status = sep::sep_kron_radius(
-                &sep_img as *const sep::sep_image,
-                x,
-                y, // Object position (already f64)
-                a as f64,
-                b as f64,     // Semi-major and semi-minor axes (convert f32 to f64)
-                theta as f64, // Position angle (convert f32 to f64)
-                6.0,          // Number of Kron radii for measurement (typically 2.5 or 6.0)
-                0,            // Flags (0 = default)
-                &mut kron_radius as *mut f32 as *mut f64, // Output Kron radius
-                &mut krflag,  // Output flag
-            );
-
-            if kr_status != 0 {
-                // If there's an error, use a default value
-                kron_radius = 0.0;
-            }
+    /// True when the inclusive pixel box touches a saturated region
+    fn touches_saturation(&self, xmin: usize, ymin: usize, xmax: usize, ymax: usize) -> bool {
+        self.saturated.as_ref().is_some_and(|saturated| {
+            saturated.any_in_box(
+                xmin.saturating_sub(1),
+                ymin.saturating_sub(1),
+                xmax + 1,
+                ymax + 1,
+            )
+        })
+    }
+}
 
-            // Calculate flux within elliptical aperture (AUTO flux)
-            let mut flux_auto = flux;
-            let mut fluxerr_auto = 0.0;
-            let _seflag = 0;
-
-            if kron_radius > 0.0 {
-                // Use Kron radius for aperture measurement (typically 2.5 * kron_radius)
-                let kr_scale = 2.5;
-                let auto_a = kr_scale * kron_radius * a;
-                let auto_b = kr_scale * kron_radius * b;
-
-                // Call sep_sum_ellipse to get the flux within the aperture
-                let mut flux_auto_f64 = flux_auto as f64;
-                let mut fluxerr_auto_f64 = 0.0;
-                let mut seflag: i16 = 0;
-
-                let se_status = sep::sep_sum_ellipse(
-                    &sep_img as *const sep::sep_image,
-                    x,
-                    y, // Object position (already f64)
-                    auto_a as f64,
-                    auto_b as f64,         // Scaled semi-major and semi-minor axes
-                    theta as f64,          // Position angle
-                    0.0,                   // No inner radius (full ellipse)
-                    0,                     // Subpixel sampling (0 = default)
-                    0,                     // Error type (0 = default)
-                    0,                     // Aperture correction (0 = none)
-                    &mut flux_auto_f64,    // Output flux
-                    &mut fluxerr_auto_f64, // Output flux error
-                    &mut 0.0,              // Output area (not used)
-                    &mut seflag,           // Output flag
-                );
-
-                // Convert back to f32 for our struct
-                flux_auto = flux_auto_f64 as f32;
-                fluxerr_auto = fluxerr_auto_f64 as f32;
-
-                if se_status != 0 {
-                    // If there's an error, use the isophotal flux
-                    flux_auto = flux;
-                    fluxerr_auto = 0.0;
-                }
-            }
+/// Threshold for the first pass that sizes an `Auto` kernel, in background RMS
+const AUTO_PROBE_SIGMA: f32 = 10.0;
 
-            let mut star = StarMetrics {
-                x,
-                y,
-                flux,
-                peak,
-                a,
-                b,
-                theta,
-                eccentricity: 0.0,
-                fwhm: 0.0,
-                kron_radius,
-                flux_auto,
-                fluxerr_auto,
-                npix,
-                elongation,
-                flag,
-            };
-
-            // Calculate derived metrics
-            star.calc_eccentricity();
-            star.calc_fwhm();
-            stars.push(star);
-        }
+/// Extract objects from a background-subtracted image and measure each one
+fn extract_catalog(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    background_metrics: BackgroundMetrics,
+    config: &DetectionConfig,
+    inputs: &PreparedInputs,
+) -> Result<StarCatalog> {
+    // Size an automatic kernel from the bright stars of an unfiltered pass
+    if let KernelConfig::Auto { .. } = config.kernel {
+        let probe = DetectionConfig {
+            kernel: KernelConfig::None,
+            threshold_sigma: config.threshold_sigma.max(AUTO_PROBE_SIGMA),
+            ..config.clone()
+        };
+        let probe_catalog = extract_catalog(
+            data,
+            width,
+            height,
+            background_metrics.clone(),
+            &probe,
+            inputs,
+        )?;
+        let fwhm = kernel::estimate_fwhm(&probe_catalog.stars);
+        let config = DetectionConfig {
+            kernel: config.kernel.resolve(fwhm),
+            ..config.clone()
+        };
+        return extract_catalog(data, width, height, background_metrics, &config, inputs);
+    }
+    let conv = ConvolutionKernel::from_config(&config.kernel)?;
+
+    let std_dev = background_metrics.rms;
+    let thresh = config.threshold_sigma * std_dev;
+    let mut catalog_out = StarCatalog {
+        width,
+        height,
+        threshold: thresh,
+        config: config.clone(),
+        background: background_metrics,
+        stars: Vec::new(),
+    };
 
-        // Free the memory allocated by SEP
-        if !catalog.is_null() {
-            sep::sep_catalog_free(catalog);
-        }
+    // Skip processing if image is too small
+    if width < 3 || height < 3 {
+        return Ok(catalog_out);
+    }
 
-        // Check if we found any stars
-        if stars.is_empty() {
-            return Ok(StarStats {
-                count: 0,
-                median_fwhm: 0.0,
-                median_eccentricity: 0.0,
-                fwhm_std_dev: 0.0,
-                eccentricity_std_dev: 0.0,
-                median_kron_radius: 0.0,
-                median_flux: 0.0,
-                median_snr: 0.0,
-                median_elongation: 0.0,
-                flagged_fraction: 0.0,
-                kron_radius_std_dev: 0.0,
-                flux_std_dev: 0.0,
-                snr_std_dev: 0.0,
-            });
+    let image = inputs.image(data, width, height, std_dev)?;
+
+    // Increase SEP's internal extraction pixel stack for large/crowded images.
+    // Default is 300_000, which can underflow for modern high-resolution frames.
+    sep::ensure_pixel_stack(config.pixel_stack_for(width, height));
+    sep::set_sub_object_limit(config.sub_object_limit);
+
+    // With a noise map the threshold follows the per-pixel noise
+    let threshold = if image.has_noise_map() {
+        Threshold::Relative(config.threshold_sigma)
+    } else {
+        Threshold::Absolute(thresh)
+    };
+    let catalog = Catalog::extract(
+        &image,
+        &ExtractOptions {
+            threshold,
+            min_area: config.min_area,
+            kernel: conv.as_ref(),
+            filter_mode: config.filter_mode,
+            deblend_nthresh: config.deblend_nthresh,
+            deblend_contrast: config.deblend_contrast,
+            clean: config.clean.then_some(config.clean_param),
+        },
+    )?;
+
+    // Convert SEP catalog to Vec<StarMetrics>
+    let mut stars = Vec::with_capacity(catalog.len());
+    for obj in catalog.iter() {
+        let mut flag = obj.flag as u8;
+        if inputs.touches_saturation(obj.xmin, obj.ymin, obj.xmax, obj.ymax) {
+            flag |= StarMetrics::FLAG_SATURATED;
         }
 
-        // Calculate aggregate statistics
-        let stats = StarStats::from_stars(&stars, max_stars);
-        Ok(stats)
+        // Calculate derived metrics
+        let elongation = if obj.a > 0.0 && obj.b > 0.0 {
+            obj.a / obj.b
+        } else {
+            1.0
+        };
+
+        // Kron radius in units of the isophotal ellipse; 0 when SEP fails
+        let coeffs = (f64::from(obj.cxx), f64::from(obj.cyy), f64::from(obj.cxy));
+        let kron_radius = sep::kron_radius(&image, obj.x, obj.y, coeffs, config.kron_factor)
+            .map(|(radius, _)| radius as f32)
+            .unwrap_or(0.0);
+
+        // AUTO flux inside `auto_aperture_scale` Kron radii, falling back to
+        // the isophotal flux
+        let (flux_auto, fluxerr_auto) = if kron_radius > 0.0 {
+            sep::sum_ellipse(
+                &image,
+                obj.x,
+                obj.y,
+                f64::from(obj.a),
+                f64::from(obj.b),
+                f64::from(obj.theta),
+                config.auto_aperture_scale * f64::from(kron_radius),
+                0,
+            )
+            .map(|aperture| (aperture.sum as f32, aperture.sum_err as f32))
+            .unwrap_or((obj.flux, 0.0))
+        } else {
+            (obj.flux, 0.0)
+        };
+
+        let mut star = StarMetrics {
+            x: obj.x,
+            y: obj.y,
+            flux: obj.flux,
+            peak: obj.peak,
+            a: obj.a,
+            b: obj.b,
+            theta: obj.theta,
+            eccentricity: 0.0,
+            fwhm: 0.0,
+            kron_radius,
+            flux_auto,
+            fluxerr_auto,
+            npix: obj.npix,
+            elongation,
+            flag,
+        };
+
+        // Calculate derived metrics
+        star.calc_eccentricity();
+        star.calc_fwhm();
+        stars.push(star);
     }
+
+    catalog_out.stars = stars;
+    Ok(catalog_out)
 }
 
 #[cfg(test)]
@@ -374,4 +401,53 @@ mod tests {
             "Uniformity should be between 0 and 1"
         );
     }
+
+    #[test]
+    fn test_detect_catalog_with_mask_and_saturation() {
+        // Three Gaussian stars: one under the mask, one clipped at full well
+        const SIZE: usize = 120;
+        const SATURATION: f32 = 4000.0;
+        let sigma = 4.0 / 2.354_820_045;
+        let mut seed = 2024u32;
+        let mut data: Vec<f32> = (0..SIZE * SIZE)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                100.0 + ((seed >> 16) % 17) as f32 - 8.0
+            })
+            .collect();
+        for (cx, cy, peak) in [
+            (30.0, 30.0, 3000.0),
+            (90.0, 30.0, 3000.0),
+            (60.0, 90.0, 4500.0),
+        ] {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    let r2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                    let value = &mut data[y * SIZE + x];
+                    *value += (peak * (-r2 / (2.0 * sigma * sigma)).exp()) as f32;
+                    *value = value.min(SATURATION);
+                }
+            }
+        }
+
+        let mut mask = PixelMask::new(SIZE, SIZE);
+        mask.mask_circle(90.0, 30.0, 10.0);
+        let inputs = DetectionInputs {
+            saturation_level: Some(SATURATION),
+            ..DetectionInputs::default()
+        }
+        .with_mask(&mask);
+        let config = DetectionConfig {
+            kernel: KernelConfig::None,
+            ..DetectionConfig::default()
+        };
+        let catalog = detect_catalog_with_inputs(&data, SIZE, SIZE, &config, &inputs).unwrap();
+
+        assert_eq!(catalog.len(), 2);
+        assert!(catalog.stars.iter().all(|s| (s.x - 90.0).abs() > 10.0));
+        let normal = catalog.stars.iter().find(|s| s.y < 60.0).unwrap();
+        assert_eq!(normal.flag & StarMetrics::FLAG_SATURATED, 0);
+        let saturated = catalog.stars.iter().find(|s| s.y > 60.0).unwrap();
+        assert_ne!(saturated.flag & StarMetrics::FLAG_SATURATED, 0);
+    }
 }
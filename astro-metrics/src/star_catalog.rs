@@ -0,0 +1,309 @@
+//! Per-star catalogs and their CSV, JSON and FITS exports
+
+use crate::types::{StarCatalog, StarMetrics, StarStats, Trail};
+use anyhow::{Context, Result};
+use fitsio::tables::{ColumnDataType, ColumnDescription};
+use fitsio::FitsFile;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+impl StarCatalog {
+    /// Number of detected stars
+    pub fn len(&self) -> usize {
+        self.stars.len()
+    }
+
+    /// True when no star was detected
+    pub fn is_empty(&self) -> bool {
+        self.stars.is_empty()
+    }
+
+    /// Aggregate statistics over the catalog, optionally limited to the brightest stars
+    pub fn stats(&self, max_stars: Option<usize>) -> StarStats {
+        StarStats::from_stars(&self.stars, max_stars)
+    }
+
+    /// The `n` brightest stars by flux, brightest first
+    pub fn brightest(&self, n: usize) -> Vec<&StarMetrics> {
+        let mut stars: Vec<&StarMetrics> = self.stars.iter().collect();
+        stars.sort_by(|a, b| b.flux.total_cmp(&a.flux));
+        stars.truncate(n);
+        stars
+    }
+
+    /// A copy without the stars lying on any of `trails`
+    pub fn without_trails(&self, trails: &[Trail]) -> StarCatalog {
+        StarCatalog {
+            width: self.width,
+            height: self.height,
+            threshold: self.threshold,
+            config: self.config.clone(),
+            background: self.background.clone(),
+            stars: self
+                .stars
+                .iter()
+                .filter(|star| !trails.iter().any(|trail| trail.covers(star.x, star.y)))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Write one CSV row per star
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        for star in &self.stars {
+            writer.serialize(star)?;
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Write the catalog, including detection settings and background, as JSON
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Read a catalog written by `write_json`
+    pub fn read_json(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to parse star catalog {}", path.display()))
+    }
+
+    /// Write the catalog as a FITS binary table extension named `STARS`
+    ///
+    /// Detection settings and background are stored as header keywords,
+    /// `PIXORIG = 0` records that `X`/`Y` are 0-based, and every HDU gets
+    /// `DATASUM`/`CHECKSUM` cards. An existing file is overwritten.
+    pub fn write_fits(&self, path: &Path) -> Result<()> {
+        let mut fits = FitsFile::create(path)
+            .overwrite()
+            .open()
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        let columns = [
+            ("X", ColumnDataType::Double),
+            ("Y", ColumnDataType::Double),
+            ("FLUX", ColumnDataType::Float),
+            ("PEAK", ColumnDataType::Float),
+            ("A", ColumnDataType::Float),
+            ("B", ColumnDataType::Float),
+            ("THETA", ColumnDataType::Float),
+            ("ECCENTRICITY", ColumnDataType::Float),
+            ("FWHM", ColumnDataType::Float),
+            ("KRON_RADIUS", ColumnDataType::Float),
+            ("FLUX_AUTO", ColumnDataType::Float),
+            ("FLUXERR_AUTO", ColumnDataType::Float),
+            ("NPIX", ColumnDataType::Long),
+            ("ELONGATION", ColumnDataType::Float),
+            ("FLAG", ColumnDataType::Int),
+        ];
+        let descriptions = columns
+            .into_iter()
+            .map(|(name, data_type)| ColumnDescription::new(name).with_type(data_type).create())
+            .collect::<Result<Vec<_>, _>>()?;
+        let hdu = fits.create_table("STARS", &descriptions)?;
+
+        let doubles =
+            |value: fn(&StarMetrics) -> f64| -> Vec<f64> { self.stars.iter().map(value).collect() };
+        let floats =
+            |value: fn(&StarMetrics) -> f32| -> Vec<f32> { self.stars.iter().map(value).collect() };
+        hdu.write_col(&mut fits, "X", &doubles(|s| s.x))?;
+        hdu.write_col(&mut fits, "Y", &doubles(|s| s.y))?;
+        hdu.write_col(&mut fits, "FLUX", &floats(|s| s.flux))?;
+        hdu.write_col(&mut fits, "PEAK", &floats(|s| s.peak))?;
+        hdu.write_col(&mut fits, "A", &floats(|s| s.a))?;
+        hdu.write_col(&mut fits, "B", &floats(|s| s.b))?;
+        hdu.write_col(&mut fits, "THETA", &floats(|s| s.theta))?;
+        hdu.write_col(&mut fits, "ECCENTRICITY", &floats(|s| s.eccentricity))?;
+        hdu.write_col(&mut fits, "FWHM", &floats(|s| s.fwhm))?;
+        hdu.write_col(&mut fits, "KRON_RADIUS", &floats(|s| s.kron_radius))?;
+        hdu.write_col(&mut fits, "FLUX_AUTO", &floats(|s| s.flux_auto))?;
+        hdu.write_col(&mut fits, "FLUXERR_AUTO", &floats(|s| s.fluxerr_auto))?;
+        let npix: Vec<i64> = self.stars.iter().map(|s| s.npix as i64).collect();
+        hdu.write_col(&mut fits, "NPIX", &npix)?;
+        hdu.write_col(&mut fits, "ELONGATION", &floats(|s| s.elongation))?;
+        let flags: Vec<i32> = self.stars.iter().map(|s| i32::from(s.flag)).collect();
+        hdu.write_col(&mut fits, "FLAG", &flags)?;
+
+        hdu.write_key(&mut fits, "IMAGEW", self.width as i64)?;
+        hdu.write_key(&mut fits, "IMAGEH", self.height as i64)?;
+        hdu.write_key(&mut fits, "NSTARS", self.stars.len() as i64)?;
+        hdu.write_key(&mut fits, "BACKGND", self.background.median)?;
+        hdu.write_key(&mut fits, "BACKRMS", self.background.rms)?;
+        hdu.write_key(&mut fits, "THRESH", self.threshold)?;
+        hdu.write_key(&mut fits, "THRSIGMA", self.config.threshold_sigma)?;
+        hdu.write_key(&mut fits, "MINAREA", self.config.min_area as i64)?;
+        hdu.write_key(&mut fits, "DEBLNTHR", self.config.deblend_nthresh as i64)?;
+        hdu.write_key(&mut fits, "DEBLCONT", self.config.deblend_contrast)?;
+        hdu.write_key(&mut fits, "CLEAN", i64::from(self.config.clean))?;
+        hdu.write_key(&mut fits, "CLEANPAR", self.config.clean_param)?;
+        hdu.write_key(&mut fits, "KRONFACT", self.config.kron_factor)?;
+        hdu.write_key(&mut fits, "AUTOSCAL", self.config.auto_aperture_scale)?;
+        hdu.write_key(
+            &mut fits,
+            "BKGBOXW",
+            self.config.background.box_width as i64,
+        )?;
+        hdu.write_key(
+            &mut fits,
+            "BKGBOXH",
+            self.config.background.box_height as i64,
+        )?;
+        hdu.write_key(
+            &mut fits,
+            "BKGFILTW",
+            self.config.background.filter_width as i64,
+        )?;
+        hdu.write_key(
+            &mut fits,
+            "BKGFILTH",
+            self.config.background.filter_height as i64,
+        )?;
+        hdu.write_key(
+            &mut fits,
+            "PIXORIG",
+            (
+                0i64,
+                "X and Y are 0-based; add 1 for FITS pixel coordinates",
+            ),
+        )?;
+        drop(fits);
+
+        astro_io::fits_checksum::write_checksums(path)
+            .with_context(|| format!("Failed to write checksums to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BackgroundMetrics, DetectionConfig};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn unique_temp_path(prefix: &str, extension: &str) -> std::path::PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time before UNIX_EPOCH")
+            .as_nanos();
+
+        std::env::temp_dir().join(format!(
+            "astro-metrics-{prefix}-{}-{timestamp}.{extension}",
+            std::process::id()
+        ))
+    }
+
+    fn star(x: f64, y: f64, flux: f32) -> StarMetrics {
+        let mut star = StarMetrics {
+            x,
+            y,
+            flux,
+            peak: flux / 10.0,
+            a: 2.0,
+            b: 1.5,
+            theta: 0.3,
+            eccentricity: 0.0,
+            fwhm: 0.0,
+            kron_radius: 3.0,
+            flux_auto: flux,
+            fluxerr_auto: flux.sqrt(),
+            npix: 25,
+            elongation: 2.0 / 1.5,
+            flag: 0,
+        };
+        star.calc_eccentricity();
+        star.calc_fwhm();
+        star
+    }
+
+    fn catalog() -> StarCatalog {
+        StarCatalog {
+            width: 100,
+            height: 80,
+            threshold: 130.0,
+            config: DetectionConfig::default(),
+            background: BackgroundMetrics::new(100.0, 10.0),
+            stars: vec![
+                star(10.5, 20.25, 500.0),
+                star(50.0, 40.0, 2000.0),
+                star(70.0, 5.0, 900.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_stats_and_brightest() {
+        let catalog = catalog();
+        assert_eq!(catalog.stats(None).count, 3);
+        // The count covers every star; the limit only selects stars for the medians
+        let limited = catalog.stats(Some(2));
+        assert_eq!(limited.count, 3);
+        assert_eq!(limited.median_flux, 2000.0);
+
+        let brightest: Vec<f32> = catalog.brightest(2).iter().map(|s| s.flux).collect();
+        assert_eq!(brightest, [2000.0, 900.0]);
+    }
+
+    #[test]
+    fn test_csv_and_json_export() -> Result<()> {
+        let catalog = catalog();
+
+        let csv_path = unique_temp_path("catalog", "csv");
+        catalog.write_csv(&csv_path)?;
+        let csv = std::fs::read_to_string(&csv_path)?;
+        std::fs::remove_file(&csv_path)?;
+        let mut lines = csv.lines();
+        assert!(lines.next().unwrap().starts_with("x,y,flux,peak,a,b,theta"));
+        assert!(lines.next().unwrap().starts_with("10.5,20.25,500.0"));
+        assert_eq!(lines.count(), 2);
+
+        let json_path = unique_temp_path("catalog", "json");
+        catalog.write_json(&json_path)?;
+        let read = StarCatalog::read_json(&json_path)?;
+        std::fs::remove_file(&json_path)?;
+        assert_eq!(read.len(), 3);
+        assert_eq!(read.stars[1].x, 50.0);
+        assert_eq!(read.config, DetectionConfig::default());
+        assert_eq!(read.background.rms, 10.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fits_export_reads_back() -> Result<()> {
+        let catalog = catalog();
+        let path = unique_temp_path("catalog", "fits");
+        catalog.write_fits(&path)?;
+
+        let mut fits = FitsFile::open(&path)?;
+        let hdu = fits.hdu("STARS")?;
+        let x: Vec<f64> = hdu.read_col(&mut fits, "X")?;
+        let flux: Vec<f32> = hdu.read_col(&mut fits, "FLUX")?;
+        let npix: Vec<i64> = hdu.read_col(&mut fits, "NPIX")?;
+        let width: i64 = hdu.read_key(&mut fits, "IMAGEW")?;
+        let origin: i64 = hdu.read_key(&mut fits, "PIXORIG")?;
+        let threshold: f32 = hdu.read_key(&mut fits, "THRESH")?;
+        drop(fits);
+        let checksums = astro_io::fits_checksum::verify_checksums(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(x, [10.5, 50.0, 70.0]);
+        assert_eq!(flux, [500.0, 2000.0, 900.0]);
+        assert_eq!(npix, [25, 25, 25]);
+        assert_eq!(width, 100);
+        assert_eq!(origin, 0);
+        assert_eq!(threshold, 130.0);
+        assert_eq!(
+            checksums.status(),
+            astro_io::fits_checksum::ChecksumStatus::Pass
+        );
+        Ok(())
+    }
+}
@@ -3,6 +3,15 @@
 use crate::types::{StarMetrics, StarStats};
 
 impl StarMetrics {
+    /// Flag bit set when the star touches saturated pixels; SEP's own
+    /// extraction flags only use the low four bits
+    pub const FLAG_SATURATED: u8 = 0x80;
+
+    /// True when the star touches saturated pixels
+    pub fn is_saturated(&self) -> bool {
+        self.flag & Self::FLAG_SATURATED != 0
+    }
+
     /// Calculate FWHM as average of semi-major and semi-minor axes
     pub fn calc_fwhm(&mut self) {
         self.fwhm = (self.a + self.b) / 2.0;
@@ -56,6 +65,11 @@ impl StarStats {
                     .unwrap_or(std::cmp::Ordering::Equal)
             }
         });
+        // Saturated cores inflate FWHM and clip SNR, so leave those stars out
+        // unless nothing else is left
+        if sorted_stars.iter().any(|s| !s.is_saturated()) {
+            sorted_stars.retain(|s| !s.is_saturated());
+        }
         let stars_to_use = if let Some(max) = max_stars {
             &sorted_stars[..max.min(sorted_stars.len())]
         } else {
@@ -364,4 +378,41 @@ mod tests {
         // Check flagged fraction (1 out of 3 stars is flagged)
         assert_eq!(stats.flagged_fraction, 1.0 / 3.0);
     }
+
+    #[test]
+    fn test_from_stars_skips_saturated() {
+        let star = |fwhm: f32, flag: u8| StarMetrics {
+            x: 0.0,
+            y: 0.0,
+            flux: 1000.0,
+            peak: 100.0,
+            a: fwhm,
+            b: fwhm,
+            theta: 0.0,
+            eccentricity: 0.0,
+            fwhm,
+            kron_radius: 10.0,
+            flux_auto: 1000.0,
+            fluxerr_auto: 20.0,
+            npix: 50,
+            elongation: 1.0,
+            flag,
+        };
+        let saturated = StarMetrics::FLAG_SATURATED;
+        let stars = vec![
+            star(3.0, 0),
+            star(3.2, 0),
+            star(9.0, saturated),
+            star(9.5, saturated),
+        ];
+
+        let stats = StarStats::from_stars(&stars, None);
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.median_fwhm, 3.2);
+        assert_eq!(stats.flagged_fraction, 0.0);
+
+        // Only saturated stars: use them rather than report nothing
+        let stats = StarStats::from_stars(&stars[2..], None);
+        assert_eq!(stats.median_fwhm, 9.5);
+    }
 }
@@ -0,0 +1,443 @@
+//! Satellite, airplane and meteor trail detection
+//!
+//! SEP breaks a trail into elongated fragments that look like stretched
+//! stars. Here the background-subtracted image is thresholded, round regions
+//! (stars) are dropped, and straight lines are found in the remaining pixels
+//! with a Hough transform. Each line is then walked along to find where the
+//! trail starts and ends.
+
+use crate::background_model::BackgroundModel;
+use crate::mask::{segment_distance, PixelMask};
+use crate::types::{Trail, TrailConfig};
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+
+/// Extra pixels around a trail treated as part of it when masking or
+/// dropping fragments
+const TRAIL_MARGIN: f64 = 2.0;
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        Self {
+            threshold_sigma: 2.0,
+            min_area: 5,
+            min_elongation: 3.0,
+            min_length: 100.0,
+            max_width: 20.0,
+            max_gap: 30.0,
+            angle_step: 0.5,
+            max_trails: 10,
+        }
+    }
+}
+
+impl Trail {
+    /// Length between the endpoints in pixels
+    pub fn length(&self) -> f64 {
+        (self.x1 - self.x0).hypot(self.y1 - self.y0)
+    }
+
+    /// True when `(x, y)` lies on the trail
+    pub fn covers(&self, x: f64, y: f64) -> bool {
+        segment_distance(x, y, self.x0, self.y0, self.x1, self.y1)
+            <= f64::from(self.width) / 2.0 + TRAIL_MARGIN
+    }
+}
+
+/// Mask covering `trails`, for rejection or inpainting
+pub fn trail_mask(trails: &[Trail], width: usize, height: usize) -> PixelMask {
+    let mut mask = PixelMask::new(width, height);
+    for trail in trails {
+        mask.mask_segment(
+            trail.x0,
+            trail.y0,
+            trail.x1,
+            trail.y1,
+            f64::from(trail.width) / 2.0 + TRAIL_MARGIN,
+        );
+    }
+    mask
+}
+
+/// Find trails in an image, brightest first
+pub fn detect_trails(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    model: &BackgroundModel,
+    config: &TrailConfig,
+) -> Result<Vec<Trail>> {
+    if data.len() != width * height || (model.width, model.height) != (width, height) {
+        bail!(
+            "Image of {width}x{height} with {} pixels doesn't match the {}x{} background model",
+            data.len(),
+            model.width,
+            model.height
+        );
+    }
+    if config.angle_step <= 0.0 {
+        bail!("Trail angle step must be positive");
+    }
+
+    let residual = model.subtracted(data)?;
+    let above: Vec<bool> = residual
+        .iter()
+        .zip(&model.rms)
+        .map(|(&v, &rms)| v > config.threshold_sigma * rms)
+        .collect();
+    let mut points = elongated_pixels(&above, width, height, config);
+
+    let hough = Hough::new(width, height, config.angle_step);
+    let mut trails = Vec::new();
+    // Lines too short to be trails still use up an attempt
+    for _ in 0..config.max_trails * 4 {
+        if trails.len() >= config.max_trails {
+            break;
+        }
+        let Some((theta, rho, votes)) = hough.peak(&points) else {
+            break;
+        };
+        if (votes as f32) < config.min_length {
+            break;
+        }
+        let Some(segment) = walk_line(&points, theta, rho, config) else {
+            break;
+        };
+        points.retain(|p| !segment.members.contains(p));
+        if segment.length >= config.min_length {
+            trails.push(segment.trail(&residual, width));
+        }
+    }
+    trails.sort_by(|a, b| b.brightness.total_cmp(&a.brightness));
+    Ok(trails)
+}
+
+/// Pixels of connected regions that are large and elongated enough to be
+/// part of a trail
+fn elongated_pixels(
+    above: &[bool],
+    width: usize,
+    height: usize,
+    config: &TrailConfig,
+) -> Vec<(usize, usize)> {
+    let mut seen = vec![false; above.len()];
+    let mut points = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..above.len() {
+        if !above[start] || seen[start] {
+            continue;
+        }
+        // 8-connected flood fill
+        let mut region = Vec::new();
+        seen[start] = true;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index % width, index / width);
+            region.push((x, y));
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let next = ny * width + nx;
+                    if above[next] && !seen[next] {
+                        seen[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        if region.len() >= config.min_area && elongation(&region) >= config.min_elongation {
+            points.extend(region);
+        }
+    }
+    points
+}
+
+/// Ratio of the major to minor axis from the second moments of a region
+fn elongation(region: &[(usize, usize)]) -> f32 {
+    let n = region.len() as f64;
+    let (mx, my) = region.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| {
+        (sx + x as f64 / n, sy + y as f64 / n)
+    });
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for &(x, y) in region {
+        let (dx, dy) = (x as f64 - mx, y as f64 - my);
+        xx += dx * dx / n;
+        yy += dy * dy / n;
+        xy += dx * dy / n;
+    }
+    // Pixels are unit squares, which keeps a one-pixel-wide line finite
+    let (xx, yy) = (xx + 1.0 / 12.0, yy + 1.0 / 12.0);
+    let half_trace = (xx + yy) / 2.0;
+    let spread = (((xx - yy) / 2.0).powi(2) + xy * xy).sqrt();
+    ((half_trace + spread) / (half_trace - spread)).sqrt() as f32
+}
+
+/// Line accumulator over angle and distance from the origin
+struct Hough {
+    angles: Vec<(f64, f64)>,
+    offset: f64,
+    bins: usize,
+}
+
+impl Hough {
+    fn new(width: usize, height: usize, angle_step: f32) -> Self {
+        let steps = (180.0 / angle_step).round().max(1.0) as usize;
+        let angles = (0..steps)
+            .map(|k| (k as f64 * 180.0 / steps as f64).to_radians().sin_cos())
+            .collect();
+        let diagonal = (width as f64).hypot(height as f64).ceil();
+        Self {
+            angles,
+            offset: diagonal,
+            bins: 2 * diagonal as usize + 1,
+        }
+    }
+
+    /// The most voted line as `(theta, rho, votes)`, with the line being
+    /// `x·cos(theta) + y·sin(theta) = rho`
+    fn peak(&self, points: &[(usize, usize)]) -> Option<(f64, f64, usize)> {
+        if points.is_empty() {
+            return None;
+        }
+        let mut votes = vec![0u32; self.angles.len() * self.bins];
+        for &(x, y) in points {
+            for (k, &(sin, cos)) in self.angles.iter().enumerate() {
+                let rho = x as f64 * cos + y as f64 * sin + self.offset;
+                votes[k * self.bins + rho.round() as usize] += 1;
+            }
+        }
+        let (best, &count) = votes.iter().enumerate().max_by_key(|&(_, &v)| v)?;
+        let (sin, cos) = self.angles[best / self.bins];
+        Some((
+            sin.atan2(cos),
+            (best % self.bins) as f64 - self.offset,
+            count as usize,
+        ))
+    }
+}
+
+/// The stretch of a line with the most pixels
+struct Segment {
+    members: HashSet<(usize, usize)>,
+    start: (f64, f64),
+    end: (f64, f64),
+    length: f32,
+}
+
+impl Segment {
+    fn trail(&self, residual: &[f32], width: usize) -> Trail {
+        let brightness = self
+            .members
+            .iter()
+            .map(|&(x, y)| f64::from(residual[y * width + x]))
+            .sum::<f64>()
+            / self.members.len() as f64;
+        let (dx, dy) = (self.end.0 - self.start.0, self.end.1 - self.start.1);
+        Trail {
+            x0: self.start.0,
+            y0: self.start.1,
+            x1: self.end.0,
+            y1: self.end.1,
+            angle: dy.atan2(dx).to_degrees().rem_euclid(180.0) as f32,
+            width: self.members.len() as f32 / (self.length + 1.0),
+            brightness: brightness as f32,
+            pixels: self.members.len(),
+        }
+    }
+}
+
+/// Collect the pixels near a Hough line, re-centre the line on them and
+/// keep the longest run with no gap above `max_gap`
+fn walk_line(
+    points: &[(usize, usize)],
+    theta: f64,
+    rho: f64,
+    config: &TrailConfig,
+) -> Option<Segment> {
+    let (sin, cos) = theta.sin_cos();
+    let half_width = f64::from(config.max_width) / 2.0;
+    let near = |rho: f64| -> Vec<((usize, usize), f64, f64)> {
+        points
+            .iter()
+            .map(|&(x, y)| {
+                let (fx, fy) = (x as f64, y as f64);
+                ((x, y), fx * cos + fy * sin - rho, fy * cos - fx * sin)
+            })
+            .filter(|&(_, d, _)| d.abs() <= half_width)
+            .collect()
+    };
+    let first = near(rho);
+    if first.is_empty() {
+        return None;
+    }
+    let mut offsets: Vec<f64> = first.iter().map(|&(_, d, _)| d).collect();
+    offsets.sort_by(f64::total_cmp);
+    let rho = rho + offsets[offsets.len() / 2];
+
+    let mut along = near(rho);
+    along.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let mut best = 0..0;
+    let mut run_start = 0;
+    for i in 1..=along.len() {
+        if i == along.len() || along[i].2 - along[i - 1].2 > f64::from(config.max_gap) {
+            if i - run_start > best.len() {
+                best = run_start..i;
+            }
+            run_start = i;
+        }
+    }
+    let run = &along[best];
+    let (t0, t1) = (run.first()?.2, run.last()?.2);
+    let point = |t: f64| (rho * cos - t * sin, rho * sin + t * cos);
+    Some(Segment {
+        members: run.iter().map(|&(p, _, _)| p).collect(),
+        start: point(t0),
+        end: point(t1),
+        length: (t1 - t0) as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{BackgroundMetrics, DetectionConfig, StarCatalog, StarMetrics};
+
+    const WIDTH: usize = 240;
+    const HEIGHT: usize = 160;
+
+    fn model() -> BackgroundModel {
+        BackgroundModel {
+            width: WIDTH,
+            height: HEIGHT,
+            box_width: 64,
+            box_height: 64,
+            mesh_width: 4,
+            mesh_height: 3,
+            mesh: vec![100.0; 12],
+            mesh_rms: vec![5.0; 12],
+            global: 100.0,
+            global_rms: 5.0,
+            background: vec![100.0; WIDTH * HEIGHT],
+            rms: vec![5.0; WIDTH * HEIGHT],
+            mesh_outliers: vec![false; 12],
+            gradient: None,
+        }
+    }
+
+    /// Flat sky with uniform noise of ±8 ADU, a few stars and a trail from
+    /// (20, 30) to (210, 140)
+    fn image() -> Vec<f32> {
+        let mut seed = 12345u32;
+        let mut data: Vec<f32> = (0..WIDTH * HEIGHT)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                100.0 + ((seed >> 16) % 17) as f32 - 8.0
+            })
+            .collect();
+        for (cx, cy) in [(60.0, 120.0), (180.0, 40.0), (120.0, 20.0)] {
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let r2 = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2);
+                    data[y * WIDTH + x] += (800.0 * (-r2 / 4.0).exp()) as f32;
+                }
+            }
+        }
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if segment_distance(x as f64, y as f64, 20.0, 30.0, 210.0, 140.0) <= 1.5 {
+                    data[y * WIDTH + x] += 60.0;
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_detect_trail() {
+        let trails =
+            detect_trails(&image(), WIDTH, HEIGHT, &model(), &TrailConfig::default()).unwrap();
+        assert_eq!(trails.len(), 1);
+        let trail = trails[0];
+        let ends = [(trail.x0, trail.y0), (trail.x1, trail.y1)];
+        assert!(ends.iter().any(|&(x, y)| (x - 20.0).hypot(y - 30.0) < 3.0));
+        assert!(ends
+            .iter()
+            .any(|&(x, y)| (x - 210.0).hypot(y - 140.0) < 3.0));
+        let expected_angle = (110.0f32).atan2(190.0).to_degrees();
+        assert!((trail.angle - expected_angle).abs() < 1.0);
+        assert!(trail.width > 2.0 && trail.width < 4.5);
+        assert!((trail.brightness - 60.0).abs() < 5.0);
+
+        let mask = trail_mask(&trails, WIDTH, HEIGHT);
+        assert!(mask.is_masked(115, 85) && !mask.is_masked(60, 120));
+    }
+
+    #[test]
+    fn test_no_trail_in_star_field() {
+        let mut data = image();
+        let model = model();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if segment_distance(x as f64, y as f64, 20.0, 30.0, 210.0, 140.0) <= 1.5 {
+                    data[y * WIDTH + x] = 100.0;
+                }
+            }
+        }
+        let trails = detect_trails(&data, WIDTH, HEIGHT, &model, &TrailConfig::default()).unwrap();
+        assert!(trails.is_empty());
+        assert!(detect_trails(&data[1..], WIDTH, HEIGHT, &model, &TrailConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_trail_fragments_left_out_of_stats() {
+        let trail = Trail {
+            x0: 0.0,
+            y0: 50.0,
+            x1: 200.0,
+            y1: 50.0,
+            angle: 0.0,
+            width: 3.0,
+            brightness: 60.0,
+            pixels: 600,
+        };
+        let star = |x: f64, y: f64, elongation: f32| StarMetrics {
+            x,
+            y,
+            flux: 1000.0,
+            peak: 100.0,
+            a: 2.0 * elongation,
+            b: 2.0,
+            theta: 0.0,
+            eccentricity: 0.0,
+            fwhm: 3.0,
+            kron_radius: 3.0,
+            flux_auto: 1000.0,
+            fluxerr_auto: 10.0,
+            npix: 20,
+            elongation,
+            flag: 0,
+        };
+        let catalog = StarCatalog {
+            width: WIDTH,
+            height: HEIGHT,
+            threshold: 10.0,
+            config: DetectionConfig::default(),
+            background: BackgroundMetrics::new(100.0, 5.0),
+            stars: vec![
+                star(30.0, 50.5, 8.0),
+                star(120.0, 49.0, 8.0),
+                star(30.0, 100.0, 1.1),
+                star(150.0, 20.0, 1.1),
+                star(90.0, 130.0, 1.1),
+            ],
+        };
+        assert_eq!(catalog.without_trails(&[trail]).len(), 3);
+        let metrics = crate::quality_metrics::create_frame_metrics_with_trails(
+            std::path::Path::new("light.fits"),
+            &catalog,
+            vec![trail],
+        );
+        assert!(metrics.has_trails());
+        assert_eq!(metrics.star_stats.count, 3);
+        assert!((metrics.star_stats.median_elongation - 1.1).abs() < 1e-6);
+    }
+}
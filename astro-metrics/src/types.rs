@@ -4,10 +4,11 @@
 //! for astronomical images, including star measurements, background analysis,
 //! and quality scores.
 
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 /// Measurements for a single detected star
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StarMetrics {
     /// X centroid position
     pub x: f64,
@@ -41,8 +42,111 @@ pub struct StarMetrics {
     pub flag: u8,
 }
 
+/// SEP background mesh settings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct BackgroundConfig {
+    /// Mesh box width in pixels
+    pub box_width: usize,
+    /// Mesh box height in pixels
+    pub box_height: usize,
+    /// Median filter width in mesh boxes
+    pub filter_width: usize,
+    /// Median filter height in mesh boxes
+    pub filter_height: usize,
+    /// Only filter boxes deviating from the median by more than this
+    pub filter_threshold: f64,
+    /// Mesh boxes whose residual from the gradient fit exceeds this many
+    /// sigma are rejected as outliers; 0 disables clipping
+    pub clip_sigma: f32,
+}
+
+/// Convolution kernel applied to the image before thresholding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KernelConfig {
+    /// No convolution
+    None,
+    /// Gaussian with the given FWHM in pixels
+    Gaussian { fwhm: f64 },
+    /// Flat disk with the given radius in pixels
+    Tophat { radius: f64 },
+    /// Mexican hat (negative Laplacian of Gaussian) with the given FWHM in pixels
+    MexicanHat { fwhm: f64 },
+    /// Row-major kernel with odd width and height
+    Custom {
+        width: usize,
+        height: usize,
+        values: Vec<f32>,
+    },
+    /// Gaussian sized from the FWHM of the brightest stars in a first pass,
+    /// or `fallback_fwhm` when too few stars are found
+    Auto { fallback_fwhm: f64 },
+}
+
+/// How SEP applies the convolution kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterMode {
+    /// Plain convolution
+    #[default]
+    Conv,
+    /// Matched filter weighted by the per-pixel noise; same as `Conv`
+    /// without a noise map
+    Matched,
+}
+
+/// SEP extraction and measurement settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DetectionConfig {
+    /// Background mesh settings
+    pub background: BackgroundConfig,
+    /// Detection threshold in units of the background RMS
+    pub threshold_sigma: f32,
+    /// Convolution kernel for detection
+    pub kernel: KernelConfig,
+    /// How the kernel is applied
+    pub filter_mode: FilterMode,
+    /// Minimum number of pixels per object
+    pub min_area: usize,
+    /// Number of deblending thresholds
+    pub deblend_nthresh: usize,
+    /// Minimum contrast ratio for deblending
+    pub deblend_contrast: f64,
+    /// Merge spurious detections near bright objects
+    pub clean: bool,
+    /// Cleaning parameter (moffat beta)
+    pub clean_param: f64,
+    /// Ellipse scale for the Kron radius measurement (SExtractor uses 6)
+    pub kron_factor: f64,
+    /// Kron radii per AUTO aperture (SExtractor uses 2.5)
+    pub auto_aperture_scale: f64,
+    /// Largest number of sub-objects when deblending
+    pub sub_object_limit: usize,
+    /// Pixel stack size; `None` sizes it from the image
+    pub pixel_stack: Option<usize>,
+}
+
+/// Every star detected in a frame, with the detection context
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StarCatalog {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Detection threshold above the background
+    pub threshold: f32,
+    /// Settings used for detection, with an `Auto` kernel resolved to its Gaussian
+    pub config: DetectionConfig,
+    /// Background the detection threshold was based on
+    pub background: BackgroundMetrics,
+    /// Detected stars in extraction order (0-based pixel coordinates)
+    pub stars: Vec<StarMetrics>,
+}
+
 /// Aggregate statistics for a collection of stars
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct StarStats {
     /// Total number of stars detected
     pub count: usize,
@@ -73,7 +177,7 @@ pub struct StarStats {
 }
 
 /// Holds background statistics for an image
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BackgroundMetrics {
     /// Median background level
     pub median: f32,
@@ -85,10 +189,163 @@ pub struct BackgroundMetrics {
     pub max: f32,
     /// Measure of background uniformity (0-1, higher is more uniform)
     pub uniformity: f32,
+    /// Smooth gradient, vignetting and residual structure of the mesh
+    #[serde(default)]
+    pub gradient: Option<BackgroundGradient>,
+}
+
+/// Low-order polynomial fit to the background mesh
+///
+/// The mesh is fitted with `level + gx·x + gy·y + vignetting·r²`, with `x`
+/// and `y` measured from the image centre in units of half the diagonal, so
+/// `r² = 1` at the corners. A light-pollution gradient shows up in
+/// `gradient`, a passing cloud or tree branch in `lumpiness`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BackgroundGradient {
+    /// Fitted background level at the image centre in ADU
+    pub level: f32,
+    /// Change of the planar term over the length of the diagonal in ADU
+    pub gradient: f32,
+    /// Direction of increasing background in degrees, 0 along +x and 90 along +y
+    pub angle: f32,
+    /// Change of the radial term from the centre to the corners in ADU,
+    /// negative when the corners are darker
+    pub vignetting: f32,
+    /// RMS residual from the fit in ADU, leaving out isolated outlier boxes
+    pub lumpiness: f32,
+    /// Mesh boxes rejected by sigma clipping
+    pub outlier_boxes: usize,
+}
+
+/// Settings for detecting clouds and other obstructions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ObstructionConfig {
+    /// Tile size in pixels
+    pub tile_size: usize,
+    /// Tiles expecting fewer stars are not judged on star count or flux
+    pub min_tile_stars: usize,
+    /// A tile is obstructed when its star count falls below this fraction of
+    /// the expected count
+    pub density_drop: f32,
+    /// A tile is obstructed when the mesh boxes centred in it exceed the
+    /// fitted gradient by this many global background RMS on average
+    pub background_sigma: f32,
+    /// A tile is obstructed when its stars keep less than this fraction of
+    /// their flux in the reference frame
+    pub flux_drop: f32,
+    /// Largest distance in pixels between a star and its reference star,
+    /// after removing the offset between the frames
+    pub match_radius: f64,
+}
+
+/// Clouds, branches and other obstructions found in a frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ObstructionReport {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Tile size in pixels
+    pub tile_size: usize,
+    /// Number of tiles along x
+    pub tiles_x: usize,
+    /// Number of tiles along y
+    pub tiles_y: usize,
+    /// Obstructed tiles, row-major
+    pub obstructed_tiles: Vec<bool>,
+    /// Fraction of the image area in obstructed tiles
+    pub obstructed_fraction: f32,
+    /// Median flux of matched stars relative to the reference frame
+    pub transparency: Option<f32>,
+    /// Stars matched to the reference frame
+    pub matched_stars: usize,
+    /// The frame is rotated by 180° against the reference, as after a
+    /// meridian flip
+    pub reference_flipped: bool,
+    /// A reference was given but no offset to it was found, so the flux check
+    /// was skipped and star counts were judged without it
+    pub reference_unaligned: bool,
+    /// Tiles with an unexpected drop in star count
+    pub star_density_tiles: usize,
+    /// Tiles with background above the smooth gradient
+    pub background_tiles: usize,
+    /// Tiles whose stars lost flux against the reference frame
+    pub flux_drop_tiles: usize,
+}
+
+/// Settings for satellite, airplane and meteor trail detection
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TrailConfig {
+    /// Pixels this many background RMS above the background take part
+    pub threshold_sigma: f32,
+    /// Connected regions with fewer pixels are ignored
+    pub min_area: usize,
+    /// Connected regions rounder than this axis ratio (stars) are ignored
+    pub min_elongation: f32,
+    /// Shortest trail in pixels
+    pub min_length: f32,
+    /// Widest trail in pixels
+    pub max_width: f32,
+    /// Longest gap in pixels bridged along a trail
+    pub max_gap: f32,
+    /// Hough transform angle step in degrees
+    pub angle_step: f32,
+    /// Most trails reported per frame
+    pub max_trails: usize,
+}
+
+/// A satellite, airplane or meteor trail
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Trail {
+    /// X of the first endpoint
+    pub x0: f64,
+    /// Y of the first endpoint
+    pub y0: f64,
+    /// X of the second endpoint
+    pub x1: f64,
+    /// Y of the second endpoint
+    pub y1: f64,
+    /// Direction in degrees from +x towards +y, 0 to 180
+    pub angle: f32,
+    /// Width in pixels above the threshold
+    pub width: f32,
+    /// Mean level above the background in ADU
+    pub brightness: f32,
+    /// Pixels above the threshold along the trail
+    pub pixels: usize,
+}
+
+/// Thresholds for hot, cold and bad-column defects
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DefectConfig {
+    /// Pixels this many sigma above their surroundings are hot
+    pub hot_sigma: f32,
+    /// Pixels this many sigma below their surroundings are cold
+    pub cold_sigma: f32,
+    /// Columns whose median is this many sigma off the other columns are bad
+    pub column_sigma: f32,
+}
+
+/// Cosmetic defects of a sensor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DefectMap {
+    /// Image width in pixels
+    pub width: usize,
+    /// Image height in pixels
+    pub height: usize,
+    /// Hot pixels as `(x, y)`, outside bad columns
+    pub hot_pixels: Vec<(usize, usize)>,
+    /// Cold pixels as `(x, y)`, outside bad columns
+    pub cold_pixels: Vec<(usize, usize)>,
+    /// Bad columns by x
+    pub bad_columns: Vec<usize>,
 }
 
 /// Weights for calculating overall quality score
-#[derive(Debug, Clone, Copy, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub struct QualityWeights {
     /// Weight for FWHM score (default: 0.3)
     pub fwhm: f32,
@@ -119,7 +376,7 @@ impl Default for QualityWeights {
 
 /// Normalized quality scores for a frame
 /// All scores are normalized to a 0-1 scale where higher values are better
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct QualityScores {
     /// FWHM score (higher means better focus/seeing)
     pub fwhm: f32,
@@ -138,7 +395,7 @@ pub struct QualityScores {
 }
 
 /// Overall frame quality metrics
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FrameQualityMetrics {
     /// Frame identifier
     pub frame_id: String,
@@ -148,4 +405,10 @@ pub struct FrameQualityMetrics {
     pub background: BackgroundMetrics,
     /// Normalized quality scores (0-1 scale, higher is better)
     pub scores: QualityScores,
+    /// Trails crossing the frame; their fragments are left out of `star_stats`
+    #[serde(default)]
+    pub trails: Vec<Trail>,
+    /// Hot pixels found in the frame, a sensor-health metric
+    #[serde(default)]
+    pub hot_pixels: Option<usize>,
 }
@@ -1,4 +1,4 @@
-use ravensky_astro::{io, metrics};
+use ravensky_astro::{io, metadata, metrics};
 use std::path::Path;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -11,6 +11,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load the image
     println!("Loading image: {}", filepath.display());
     let (pixels, width, height) = io::fits::load_fits(filepath)?;
+    let frame_metadata = metadata::fits_parser::extract_metadata_from_path(filepath)?;
 
     println!("Image dimensions: {}x{}", width, height);
 
@@ -25,12 +26,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         pixels.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b))
     );
 
-    // Detect stars using SEP
+    // Detect stars using SEP, with the gain and saturation level from the headers
     println!("\nDetecting stars...");
-    let (star_stats, bg_metrics) = metrics::sep_detect::detect_stars_with_sep_background(
+    let inputs = metrics::DetectionInputs::from_metadata(&frame_metadata);
+    let (star_stats, bg_metrics) = metrics::sep_detect::detect_stars_with_inputs(
         &pixels,
         width,
         height,
+        &metrics::DetectionConfig::default(),
+        &inputs,
         Some(50), // Limit to top 50 stars
     )?;
 
@@ -48,6 +52,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "Background uniformity: {:.1}%",
         bg_metrics.uniformity * 100.0
     );
+    if let Some(gradient) = bg_metrics.gradient {
+        println!(
+            "Background gradient: {:.1} ADU towards {:.0}°, vignetting {:.1} ADU, lumpiness {:.1} ADU",
+            gradient.gradient, gradient.angle, gradient.vignetting, gradient.lumpiness
+        );
+    }
 
     Ok(())
 }
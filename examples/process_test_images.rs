@@ -1,7 +1,7 @@
 use anyhow::Result;
 use astro_io::{fits, xisf};
 use astro_metadata::{fits_parser, xisf_parser};
-use astro_metrics::sep_detect;
+use astro_metrics::{sep_detect, DetectionConfig, DetectionInputs};
 use std::fs;
 use std::path::Path;
 use std::time::Instant;
@@ -133,7 +133,16 @@ fn process_fits_file(path: &Path) -> Result<()> {
     if !has_nan && !has_inf {
         println!("Starting star detection...");
         let start = Instant::now();
-        match sep_detect::detect_stars_with_sep_background(&pixels, width, height, None) {
+        // Real e-/ADU gain and saturation level from the headers
+        let inputs = DetectionInputs::from_metadata(&metadata);
+        match sep_detect::detect_stars_with_inputs(
+            &pixels,
+            width,
+            height,
+            &DetectionConfig::default(),
+            &inputs,
+            None,
+        ) {
             Ok((star_stats, background)) => {
                 let detect_time = start.elapsed();
                 println!("Star detection time: {:?}", detect_time);
@@ -236,7 +245,16 @@ fn process_xisf_file(path: &Path) -> Result<()> {
     if !has_nan && !has_inf {
         println!("Starting star detection...");
         let start = Instant::now();
-        match sep_detect::detect_stars_with_sep_background(&pixels, width, height, None) {
+        // Real e-/ADU gain and saturation level from the headers
+        let inputs = DetectionInputs::from_metadata(&metadata);
+        match sep_detect::detect_stars_with_inputs(
+            &pixels,
+            width,
+            height,
+            &DetectionConfig::default(),
+            &inputs,
+            None,
+        ) {
             Ok((star_stats, background)) => {
                 let detect_time = start.elapsed();
                 println!("Star detection time: {:?}", detect_time);
@@ -82,7 +82,11 @@ fn print_metadata_report(
         println!("Exposure Time: {:.2} seconds", exp_time);
     }
     if let Some(frame_type) = &metadata.exposure.frame_type {
-        println!("Frame Type: {}", frame_type);
+        println!(
+            "Frame Type: {} ({})",
+            frame_type,
+            metadata.exposure.frame_kind()
+        );
     }
     if let Some(date_obs) = metadata.exposure.date_obs {
         println!(
@@ -98,6 +102,14 @@ fn print_metadata_report(
     if let Some(filter) = &metadata.filter.name {
         println!("Filter: {}", filter);
     }
+    if let Some(band) = &metadata.filter.band {
+        println!("Band: {}", band);
+    }
+    if let (Some(wavelength), Some(bandwidth)) =
+        (metadata.filter.wavelength, metadata.filter.bandwidth)
+    {
+        println!("Passband: {:.1} nm ({:.1} nm wide)", wavelength, bandwidth);
+    }
 
     if let Some(mount) = &metadata.mount {
         println!("\n=== Mount Information ===");
@@ -128,6 +140,15 @@ fn print_metadata_report(
         }
     }
 
+    if let Some(software) = &metadata.source_software {
+        println!("\n=== Source Software ===");
+        println!("Vendor: {:?}", software.vendor);
+        println!("Creator: {}", software.name);
+        if let Some(version) = &software.version {
+            println!("Version: {}", version);
+        }
+    }
+
     println!("\n=== Calculated Values ===");
     if let Some(plate_scale) = metadata.plate_scale() {
         println!("Plate Scale: {:.3} arcsec/pixel", plate_scale);
@@ -167,7 +188,7 @@ fn print_metadata_report(
             }
             if !display_function.parameters.is_empty() {
                 let mut parameters: Vec<_> = display_function.parameters.iter().collect();
-                parameters.sort_by(|(left, _), (right, _)| left.cmp(right));
+                parameters.sort_by_key(|(left, _)| *left);
                 let formatted = parameters
                     .into_iter()
                     .map(|(key, value)| format!("{}={}", key, value))
@@ -456,7 +456,8 @@ fn is_expected_to_vary_per_exposure(element: &str) -> bool {
             | "wcs.azimuth"
             | "xisf.creation_time"
             | "attachments[].checksum"
-    )
+    ) || (element.starts_with("derived.") && element != "derived.twilight")
+        || (element.starts_with("exposure.timing.") && element != "exposure.timing.time_scale")
 }
 
 impl FlattenedMetadata {
@@ -489,7 +490,7 @@ fn write_element_csv<W: Write>(writer: W, stats: &StatsAccumulator) -> Result<()
     csv_writer.write_record(["metadata_element", "percent_of_files"])?;
 
     let mut elements: Vec<_> = stats.elements.iter().collect();
-    elements.sort_unstable_by(|(left, _), (right, _)| left.cmp(right));
+    elements.sort_unstable_by_key(|(left, _)| *left);
 
     for (element, element_stats) in elements {
         let percentage = percentage(element_stats.files_with_element, stats.files_processed);
@@ -505,7 +506,7 @@ fn write_value_csv<W: Write>(writer: W, stats: &StatsAccumulator) -> Result<()>
     csv_writer.write_record(["metadata_element", "value", "percent_of_element"])?;
 
     let mut elements: Vec<_> = stats.elements.iter().collect();
-    elements.sort_unstable_by(|(left, _), (right, _)| left.cmp(right));
+    elements.sort_unstable_by_key(|(left, _)| *left);
 
     for (element, element_stats) in elements {
         if is_expected_to_vary_per_exposure(element)
@@ -516,7 +517,7 @@ fn write_value_csv<W: Write>(writer: W, stats: &StatsAccumulator) -> Result<()>
         }
 
         let mut values: Vec<_> = element_stats.value_counts.iter().collect();
-        values.sort_unstable_by(|(left, _), (right, _)| left.cmp(right));
+        values.sort_unstable_by_key(|(left, _)| *left);
 
         for (value, count) in values {
             let percentage = percentage(*count, element_stats.files_with_element);
@@ -705,7 +706,7 @@ mod tests {
         assert!(is_expected_to_vary_per_exposure("wcs.crval1"));
         assert!(is_expected_to_vary_per_exposure("attachments[].checksum"));
 
-        assert!(!is_expected_to_vary_per_exposure("detector.gain"));
+        assert!(!is_expected_to_vary_per_exposure("detector.gain_setting"));
         assert!(!is_expected_to_vary_per_exposure("detector.offset"));
         assert!(!is_expected_to_vary_per_exposure("filter.name"));
         assert!(!is_expected_to_vary_per_exposure("exposure.exposure_time"));
@@ -29,8 +29,9 @@
 //!
 //! // Extract star metrics
 //! let (image_data, width, height) = io::fits::load_fits(path)?;
-//! let (star_stats, background) = metrics::sep_detect::detect_stars_with_sep_background(
-//!     &image_data, width, height, None)?;
+//! let inputs = metrics::DetectionInputs::from_metadata(&metadata);
+//! let (star_stats, background) = metrics::sep_detect::detect_stars_with_inputs(
+//!     &image_data, width, height, &metrics::DetectionConfig::default(), &inputs, None)?;
 //!
 //! // Calculate quality scores
 //! let scores = metrics::quality_metrics::calculate_quality_scores(&star_stats, &background);